@@ -4,6 +4,7 @@ const DESCRIPTIONS = {
   'change-owners': 'Invite new crate owners or remove existing ones',
   'publish-new': 'Publish new crates',
   'publish-update': 'Publish new versions of existing crates',
+  'read-only': 'Read the index and download crates from registries that require authentication',
   yank: 'Yank and unyank crate versions',
 };
 
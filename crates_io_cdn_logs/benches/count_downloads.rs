@@ -1,6 +1,11 @@
-use crates_io_cdn_logs::{cloudfront, fastly};
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use crates_io_cdn_logs::{caddy, cloudfront, common, fastly};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use std::io::Cursor;
+use tokio::runtime::Runtime;
+
+/// The number of times the test data is repeated for the "large" benchmarks,
+/// resulting in log files with a couple of megabytes each.
+const REPETITIONS: usize = 2000;
 
 fn criterion_benchmark(c: &mut Criterion) {
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -23,7 +28,81 @@ fn criterion_benchmark(c: &mut Criterion) {
         b.to_async(&rt)
             .iter(|| fastly::count_downloads(black_box(Cursor::new(bytes))));
     });
+
+    let bytes = include_bytes!("../test_data/common/basic.log");
+    c.bench_function("common", |b| {
+        // Insert a call to `to_async` to convert the bencher to async mode.
+        // The timing loops are the same as with the normal bencher.
+        b.to_async(&rt)
+            .iter(|| common::count_downloads(black_box(Cursor::new(bytes))));
+    });
+
+    let bytes = include_bytes!("../test_data/caddy/basic.log");
+    c.bench_function("caddy", |b| {
+        // Insert a call to `to_async` to convert the bencher to async mode.
+        // The timing loops are the same as with the normal bencher.
+        b.to_async(&rt)
+            .iter(|| caddy::count_downloads(black_box(Cursor::new(bytes))));
+    });
+}
+
+/// Compares parsing large log files on a single thread with parsing them
+/// on all available threads.
+///
+/// The chunks of a log file are parsed on the blocking thread pool of the
+/// tokio runtime, so limiting the pool to a single thread results in the
+/// chunks being parsed one after the other.
+fn large_files_benchmark(c: &mut Criterion) {
+    let runtimes = [("sequential", runtime(1)), ("parallel", runtime(512))];
+
+    let mut group = c.benchmark_group("large");
+    group.sample_size(10);
+
+    let bytes = cloudfront_large();
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    for (name, rt) in &runtimes {
+        group.bench_with_input(BenchmarkId::new("cloudfront", name), &bytes, |b, bytes| {
+            b.to_async(rt)
+                .iter(|| cloudfront::count_downloads(black_box(Cursor::new(bytes))));
+        });
+    }
+
+    let bytes = include_bytes!("../test_data/fastly/basic.log").repeat(REPETITIONS);
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    for (name, rt) in &runtimes {
+        group.bench_with_input(BenchmarkId::new("fastly", name), &bytes, |b, bytes| {
+            b.to_async(rt)
+                .iter(|| fastly::count_downloads(black_box(Cursor::new(bytes))));
+        });
+    }
+
+    group.finish();
+}
+
+fn runtime(max_blocking_threads: usize) -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(max_blocking_threads)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Repeats the log lines of the CloudFront test data, without repeating its
+/// header lines.
+fn cloudfront_large() -> Vec<u8> {
+    let log = include_str!("../test_data/cloudfront/basic.log");
+    let (header, body): (Vec<_>, Vec<_>) = log.lines().partition(|line| line.starts_with('#'));
+
+    let mut output = header.join("\n");
+    for _ in 0..REPETITIONS {
+        for line in &body {
+            output.push('\n');
+            output.push_str(line);
+        }
+    }
+
+    output.into_bytes()
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, large_files_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,220 @@
+//! # Caddy access log parsing
+//!
+//! see <https://caddyserver.com/docs/caddyfile/directives/log#format-modules>.
+//!
+//! Only the `json` log encoder is supported, with the timestamps either in
+//! the default `unix_seconds_float` format or as RFC 3339 strings.
+
+use crate::chunks;
+use crate::paths::parse_path;
+use crate::{ClientClass, DownloadsMap};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+use tokio::io::AsyncBufRead;
+use tracing::{debug_span, instrument, warn};
+
+#[instrument(level = "debug", skip(reader))]
+pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Result<DownloadsMap> {
+    chunks::count_downloads(reader, |_chunk| Ok(()), |(), chunk| count_chunk(chunk)).await
+}
+
+/// Counts the downloads in a line-aligned chunk of the log file.
+#[instrument(level = "debug", skip_all)]
+fn count_chunk(chunk: &str) -> DownloadsMap {
+    let mut downloads = DownloadsMap::new();
+
+    for line in chunk.lines() {
+        let span = debug_span!("process_line");
+        let _guard = span.enter();
+
+        let json = match parse_json(line) {
+            Ok(json) => json,
+            Err(error) => {
+                warn!("Failed to parse JSON: {error}");
+                continue;
+            }
+        };
+
+        let (Some(request), Some(status)) = (json.request, json.status) else {
+            // Ignore log entries that are not access log entries.
+            continue;
+        };
+
+        if request.method != "GET" {
+            // Ignore non-GET requests.
+            continue;
+        }
+
+        if status != 200 {
+            // Ignore non-200 responses.
+            continue;
+        }
+
+        let uri = decode_uri(&request.uri);
+
+        let Some((name, version)) = parse_path(&uri) else {
+            continue;
+        };
+
+        let Some(date_time) = json.ts.date_time() else {
+            warn!(ts = ?json.ts, "Failed to parse timestamp");
+            continue;
+        };
+
+        let user_agent = request.headers.user_agent.first();
+        let client = ClientClass::from_user_agent(user_agent.map_or("", |ua| ua.as_ref()));
+
+        downloads.add(name, version, date_time.date_naive(), client);
+    }
+
+    downloads
+}
+
+/// The fields of a Caddy log entry that are needed to count downloads.
+///
+/// The `request` and `status` fields are only present in access log
+/// entries, and missing in the other log entries that Caddy writes to the
+/// same log file, if it has been configured to do so.
+#[derive(Debug, Deserialize)]
+struct LogLine<'a> {
+    ts: Timestamp,
+    #[serde(borrow)]
+    request: Option<Request<'a>>,
+    status: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request<'a> {
+    #[serde(borrow)]
+    method: Cow<'a, str>,
+    #[serde(borrow)]
+    uri: Cow<'a, str>,
+    #[serde(borrow, default)]
+    headers: Headers<'a>,
+}
+
+/// The request headers that are needed to count downloads. Caddy logs
+/// each header as a list of values.
+#[derive(Debug, Default, Deserialize)]
+struct Headers<'a> {
+    #[serde(borrow, default, rename = "User-Agent")]
+    user_agent: Vec<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    /// Seconds since the Unix epoch, which is the default format.
+    Seconds(f64),
+    /// A RFC 3339 timestamp, e.g. when using `time_format rfc3339`.
+    DateTime(DateTime<Utc>),
+}
+
+impl Timestamp {
+    fn date_time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Timestamp::Seconds(seconds) => {
+                let nanos = (seconds.fract() * 1e9) as u32;
+                DateTime::from_timestamp(seconds.trunc() as i64, nanos)
+            }
+            Timestamp::DateTime(date_time) => Some(*date_time),
+        }
+    }
+}
+
+#[instrument(level = "debug", skip(json))]
+fn parse_json(json: &str) -> Result<LogLine<'_>, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Deal with URIs like `/crates/tikv-jemalloc-sys/tikv-jemalloc-sys-0.5.4%2B5.3.0-patched.crate`.
+///
+/// Caddy logs the raw request URI, so a single round of percent-decoding is
+/// needed here.
+#[instrument(level = "debug", skip(uri))]
+fn decode_uri(uri: &str) -> Cow<'_, str> {
+    percent_encoding::percent_decode_str(uri).decode_utf8_lossy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use claims::{assert_ok, assert_some};
+    use insta::assert_debug_snapshot;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_timestamp() {
+        let ts = assert_ok!(serde_json::from_str::<Timestamp>("1705420984.5"));
+        let date_time = assert_some!(ts.date_time());
+        assert_eq!(date_time.to_string(), "2024-01-16 16:03:04.500 UTC");
+
+        let ts = assert_ok!(serde_json::from_str::<Timestamp>(
+            r#""2024-01-16T16:03:04Z""#
+        ));
+        let date_time = assert_some!(ts.date_time());
+        assert_eq!(date_time.to_string(), "2024-01-16 16:03:04 UTC");
+    }
+
+    #[tokio::test]
+    async fn test_basic() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/caddy/basic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (cargo/1.75) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.75) .. 2
+            2024-01-17  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (cargo/1.75) .. 1
+            2024-01-17  tracing-core@0.1.32 (cargo/1.75) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_percent_encoding() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/caddy/percent-encoding.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (cargo/1.75) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_unrelated_traffic() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/caddy/unrelated-traffic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  strsim@0.10.0 (cargo/1.75) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_recoverable_errors() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/caddy/recoverable-errors.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  strsim@0.10.0 (cargo/1.75) .. 1
+        }
+        "###);
+    }
+}
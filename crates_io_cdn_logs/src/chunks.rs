@@ -0,0 +1,120 @@
+//! Parallel parsing of log files in line-aligned chunks.
+//!
+//! The decompressed input is read in chunks of roughly [CHUNK_SIZE] bytes,
+//! which are extended to the end of their last line. Each chunk is then
+//! parsed on the blocking thread pool of the tokio runtime, producing a
+//! partial [DownloadsMap], and the partial maps are merged at the end.
+
+use crate::DownloadsMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tokio::task::JoinHandle;
+use tracing::{instrument, Span};
+
+/// The minimum size of a chunk in bytes.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Reads the log file from `reader` and counts the downloads of all chunks
+/// in parallel.
+///
+/// Before a chunk is handed to the thread pool, `prepare` is called with the
+/// chunk in file order. It returns the state that `count` needs to parse the
+/// chunk, which allows formats like CloudFront to carry information from
+/// header lines over to the following chunks. Since `prepare` runs
+/// sequentially, it should only do cheap work.
+#[instrument(level = "debug", skip_all)]
+pub(crate) async fn count_downloads<R, S, P, C>(
+    mut reader: R,
+    mut prepare: P,
+    count: C,
+) -> anyhow::Result<DownloadsMap>
+where
+    R: AsyncBufRead + Unpin,
+    S: Send + 'static,
+    P: FnMut(&str) -> anyhow::Result<S>,
+    C: Fn(S, &str) -> DownloadsMap + Send + Sync + 'static,
+{
+    let count = Arc::new(count);
+    let max_pending = std::thread::available_parallelism().map_or(1, |n| n.get()) * 2;
+
+    let mut downloads = DownloadsMap::new();
+    let mut pending: VecDeque<JoinHandle<DownloadsMap>> = VecDeque::new();
+    while let Some(chunk) = read_chunk(&mut reader).await? {
+        let state = prepare(&chunk)?;
+
+        if pending.len() >= max_pending {
+            if let Some(handle) = pending.pop_front() {
+                downloads.merge(handle.await?);
+            }
+        }
+
+        let count = count.clone();
+        let span = Span::current();
+        pending.push_back(tokio::task::spawn_blocking(move || {
+            span.in_scope(|| count(state, &chunk))
+        }));
+    }
+
+    for handle in pending {
+        downloads.merge(handle.await?);
+    }
+
+    Ok(downloads)
+}
+
+/// Reads the next chunk of at least [CHUNK_SIZE] bytes, or less at the end of
+/// the file, that ends with a complete line.
+///
+/// Returns `None` once the end of the file has been reached.
+async fn read_chunk<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<Option<String>> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    (&mut *reader)
+        .take(CHUNK_SIZE as u64)
+        .read_to_end(&mut chunk)
+        .await?;
+
+    if chunk.is_empty() {
+        return Ok(None);
+    }
+
+    if !chunk.ends_with(b"\n") {
+        reader.read_until(b'\n', &mut chunk).await?;
+    }
+
+    Ok(Some(String::from_utf8(chunk)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok, assert_some};
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_read_chunk() {
+        let line = "a".repeat(1000);
+        let lines = (0..2000).map(|_| line.as_str()).collect::<Vec<_>>();
+        let input = lines.join("\n");
+        let mut cursor = Cursor::new(input.as_bytes());
+
+        let first = assert_some!(assert_ok!(read_chunk(&mut cursor).await));
+        assert!(first.len() > CHUNK_SIZE);
+        assert!(first.ends_with(&format!("\n{line}\n")));
+
+        let second = assert_some!(assert_ok!(read_chunk(&mut cursor).await));
+        assert!(second.starts_with(&format!("{line}\n")));
+        assert!(second.ends_with(&line));
+
+        assert_eq!(first.len() + second.len(), input.len());
+        assert_eq!(first.lines().count() + second.lines().count(), 2000);
+
+        assert_eq!(assert_ok!(read_chunk(&mut cursor).await), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8() {
+        let mut cursor = Cursor::new(b"foo\n\xff\xfe\n");
+        assert_err!(read_chunk(&mut cursor).await);
+    }
+}
@@ -0,0 +1,143 @@
+use semver::Version;
+use std::fmt::{Display, Formatter};
+use tracing::instrument;
+
+/// User agent prefixes of known registry mirroring tools, which download
+/// crate files in bulk and should not be counted as regular cargo downloads.
+///
+/// The prefixes are matched case-insensitively against the start of the
+/// user agent, to avoid matching e.g. a "Nexus" device name in a browser
+/// user agent.
+const MIRROR_TOOLS: &[&str] = &["artifactory", "kellnr", "nexus", "panamax", "romt"];
+
+/// The kind of client that downloaded a crate file, derived from the
+/// `User-Agent` header of the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClientClass {
+    /// A regular download by cargo, with the release of cargo.
+    Cargo(CargoRelease),
+    /// A download by one of the known registry mirroring tools.
+    Mirror(&'static str),
+    /// Any other client, like browsers, scanners or custom scripts, or
+    /// requests without a user agent.
+    Other,
+}
+
+impl ClientClass {
+    #[instrument(level = "debug")]
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        // e.g. `cargo 1.74.0 (ecb9851af 2023-10-18)`
+        if let Some(rest) = user_agent.strip_prefix("cargo ") {
+            let version = rest.split(' ').next().unwrap_or_default();
+            return match Version::parse(version) {
+                Ok(version) => Self::Cargo(CargoRelease::from_version(&version)),
+                Err(_) => Self::Other,
+            };
+        }
+
+        MIRROR_TOOLS
+            .iter()
+            .find(|tool| {
+                let prefix = user_agent.get(..tool.len());
+                prefix.is_some_and(|prefix| prefix.eq_ignore_ascii_case(tool))
+            })
+            .map(|tool| Self::Mirror(tool))
+            .unwrap_or(Self::Other)
+    }
+}
+
+impl Display for ClientClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cargo(release) => write!(f, "cargo/{release}"),
+            Self::Mirror(tool) => write!(f, "mirror/{tool}"),
+            Self::Other => f.write_str("other"),
+        }
+    }
+}
+
+/// The release of cargo that downloaded a crate file.
+///
+/// Stable releases are bucketed by their minor version, and pre-releases by
+/// their release channel, so that every new nightly or patch release does not
+/// add another client to the per-client breakdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CargoRelease {
+    /// A stable release, with the major and minor version, e.g. `1.74`.
+    Stable(u64, u64),
+    Beta,
+    /// A nightly release, or any other pre-release like local `-dev` builds.
+    Nightly,
+}
+
+impl CargoRelease {
+    pub fn from_version(version: &Version) -> Self {
+        if version.pre.is_empty() {
+            Self::Stable(version.major, version.minor)
+        } else if version.pre.starts_with("beta") {
+            Self::Beta
+        } else {
+            Self::Nightly
+        }
+    }
+}
+
+impl Display for CargoRelease {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stable(major, minor) => write!(f, "{major}.{minor}"),
+            Self::Beta => f.write_str("beta"),
+            Self::Nightly => f.write_str("nightly"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(user_agent: &str) -> String {
+        ClientClass::from_user_agent(user_agent).to_string()
+    }
+
+    #[test]
+    fn test_from_user_agent() {
+        assert_eq!(
+            classify("cargo 1.74.0 (ecb9851af 2023-10-18)"),
+            "cargo/1.74"
+        );
+        assert_eq!(
+            classify("cargo 1.74.1 (ecb9851af 2023-11-22)"),
+            "cargo/1.74"
+        );
+        assert_eq!(
+            classify("cargo 1.78.0-nightly (7b7af3077 2024-02-17)"),
+            "cargo/nightly"
+        );
+        assert_eq!(
+            classify("cargo 1.77.0-beta.5 (e52e36006 2024-02-18)"),
+            "cargo/beta"
+        );
+        assert_eq!(classify("cargo 1.78.0-dev"), "cargo/nightly");
+        assert_eq!(classify("cargo 1.74"), "other");
+        assert_eq!(classify("cargo"), "other");
+
+        assert_eq!(classify("Panamax/1.0.3"), "mirror/panamax");
+        assert_eq!(
+            classify("Artifactory/7.71.11 77111900"),
+            "mirror/artifactory"
+        );
+        assert_eq!(
+            classify("Nexus/3.64.0-04 (OSS; Linux; 5.15.0)"),
+            "mirror/nexus"
+        );
+
+        assert_eq!(
+            classify("Mozilla/5.0 (Linux; Android 6.0; Nexus 5 Build/MRA58N)"),
+            "other"
+        );
+        assert_eq!(classify("curl/8.5.0"), "other");
+        assert_eq!(classify(""), "other");
+        assert_eq!(classify("-"), "other");
+    }
+}
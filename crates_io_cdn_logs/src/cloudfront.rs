@@ -3,11 +3,12 @@
 //! see <https://docs.aws.amazon.com/AmazonCloudFront/latest/DeveloperGuide/AccessLogs.html#LogFileFormat>
 //! and <https://www.w3.org/TR/WD-logfile.html>.
 
+use crate::chunks;
 use crate::paths::parse_path;
-use crate::DownloadsMap;
+use crate::{ClientClass, DownloadsMap};
 use chrono::NaiveDate;
 use std::borrow::Cow;
-use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::io::AsyncBufRead;
 use tracing::{instrument, warn};
 
 const HEADER_PREFIX: char = '#';
@@ -18,36 +19,76 @@ const FIELD_DATE: &str = "date";
 const FIELD_METHOD: &str = "cs-method";
 const FIELD_PATH: &str = "cs-uri-stem";
 const FIELD_STATUS: &str = "sc-status";
+const FIELD_USER_AGENT: &str = "cs(User-Agent)";
 
 #[instrument(level = "debug", skip(reader))]
 pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Result<DownloadsMap> {
-    let mut num_fields = 0;
-    let mut date_index = None;
-    let mut method_index = None;
-    let mut path_index = None;
-    let mut status_index = None;
+    // The `#Fields` header lines apply to all following lines, so the
+    // fields that are in effect at the start of each chunk are determined
+    // before the chunks are counted in parallel.
+    let mut fields = Fields::default();
+    let prepare = move |chunk: &str| {
+        let initial_fields = fields.clone();
+
+        let header_lines = chunk.lines().filter(|line| line.starts_with(HEADER_PREFIX));
+        for line in header_lines {
+            if let Some(version) = line.strip_prefix(HEADER_VERSION) {
+                let version = version.trim();
+                if version != "1.0" {
+                    anyhow::bail!("Unsupported version: {}", version);
+                }
+            } else if let Some(fields_str) = line.strip_prefix(HEADER_FIELDS) {
+                fields = Fields::parse(fields_str);
+            }
+        }
+
+        Ok(initial_fields)
+    };
+
+    chunks::count_downloads(reader, prepare, count_chunk).await
+}
+
+/// The positions of the relevant fields in a log line, as declared by the
+/// last `#Fields` header line.
+#[derive(Debug, Clone, Default)]
+struct Fields {
+    num_fields: usize,
+    date_index: Option<usize>,
+    method_index: Option<usize>,
+    path_index: Option<usize>,
+    status_index: Option<usize>,
+    user_agent_index: Option<usize>,
+}
+
+impl Fields {
+    fn parse(fields_str: &str) -> Self {
+        let fields = fields_str.trim().split(' ').collect::<Vec<_>>();
+
+        Self {
+            num_fields: fields.len(),
+            date_index: fields.iter().position(|f| f == &FIELD_DATE),
+            method_index: fields.iter().position(|f| f == &FIELD_METHOD),
+            path_index: fields.iter().position(|f| f == &FIELD_PATH),
+            status_index: fields.iter().position(|f| f == &FIELD_STATUS),
+            user_agent_index: fields.iter().position(|f| f == &FIELD_USER_AGENT),
+        }
+    }
+}
 
+/// Counts the downloads in a line-aligned chunk of the log file, starting
+/// with the given [Fields].
+#[instrument(level = "debug", skip_all)]
+fn count_chunk(mut fields: Fields, chunk: &str) -> DownloadsMap {
     let mut downloads = DownloadsMap::new();
 
-    let mut lines = reader.lines();
-    while let Some(line) = lines.next_line().await? {
-        if let Some(version) = line.strip_prefix(HEADER_VERSION) {
-            let version = version.trim();
-            if version != "1.0" {
-                anyhow::bail!("Unsupported version: {}", version);
-            }
+    for line in chunk.lines() {
+        if line.starts_with(HEADER_VERSION) {
+            // The version has already been checked while preparing the chunk.
             continue;
         }
 
         if let Some(fields_str) = line.strip_prefix(HEADER_FIELDS) {
-            let fields = fields_str.trim().split(' ').collect::<Vec<_>>();
-
-            num_fields = fields.len();
-            date_index = fields.iter().position(|f| f == &FIELD_DATE);
-            method_index = fields.iter().position(|f| f == &FIELD_METHOD);
-            path_index = fields.iter().position(|f| f == &FIELD_PATH);
-            status_index = fields.iter().position(|f| f == &FIELD_STATUS);
-
+            fields = Fields::parse(fields_str);
             continue;
         }
 
@@ -59,24 +100,25 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
         let values = line.split('\t').collect::<Vec<_>>();
 
         let num_values = values.len();
-        if num_values != num_fields {
+        if num_values != fields.num_fields {
+            let num_fields = fields.num_fields;
             warn!("Expected {num_fields} fields, but found {num_values}");
             continue;
         }
 
-        let method = get_value(&values, method_index, FIELD_METHOD);
+        let method = get_value(&values, fields.method_index, FIELD_METHOD);
         if method != "GET" {
             // Ignore non-GET requests.
             continue;
         }
 
-        let status = get_value(&values, status_index, FIELD_STATUS);
+        let status = get_value(&values, fields.status_index, FIELD_STATUS);
         if status != "200" {
             // Ignore non-200 responses.
             continue;
         }
 
-        let path = get_value(&values, path_index, FIELD_PATH);
+        let path = get_value(&values, fields.path_index, FIELD_PATH);
 
         // Deal with paths like `/crates/tikv-jemalloc-sys/tikv-jemalloc-sys-0.5.4%252B5.3.0-patched.crate`.
         //
@@ -90,7 +132,7 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
             continue;
         };
 
-        let date = get_value(&values, date_index, FIELD_DATE);
+        let date = get_value(&values, fields.date_index, FIELD_DATE);
         let date = match date.parse::<NaiveDate>() {
             Ok(date) => date,
             Err(error) => {
@@ -99,10 +141,15 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
             }
         };
 
-        downloads.add(name, version, date);
+        // The user agent is percent-encoded too, e.g. `cargo%201.74.0%20(ecb9851af%202023-10-18)`.
+        let user_agent = get_value(&values, fields.user_agent_index, FIELD_USER_AGENT);
+        let user_agent = percent_encoding::percent_decode_str(user_agent).decode_utf8_lossy();
+        let client = ClientClass::from_user_agent(&user_agent);
+
+        downloads.add(name, version, date, client);
     }
 
-    Ok(downloads)
+    downloads
 }
 
 #[instrument(level = "debug", skip(path))]
@@ -137,25 +184,25 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  bindgen@0.65.1 .. 1
-            2024-01-16  cumulus-primitives-core@0.4.0 .. 1
-            2024-01-16  derive_more@0.99.17 .. 1
-            2024-01-16  hash-db@0.15.2 .. 1
-            2024-01-16  hyper-rustls@0.24.2 .. 1
-            2024-01-16  jsonrpsee-server@0.16.3 .. 1
-            2024-01-16  peeking_take_while@0.1.2 .. 1
-            2024-01-16  quick-error@1.2.3 .. 2
-            2024-01-16  tracing-core@0.1.32 .. 1
-            2024-01-17  flatbuffers@23.1.21 .. 1
-            2024-01-17  jemallocator@0.5.4 .. 1
-            2024-01-17  leveldb-sys@2.0.9 .. 1
-            2024-01-17  num_cpus@1.15.0 .. 1
-            2024-01-17  paste@1.0.12 .. 1
-            2024-01-17  quick-error@1.2.3 .. 1
-            2024-01-17  rand@0.8.5 .. 1
-            2024-01-17  serde_derive@1.0.163 .. 1
-            2024-01-17  smallvec@1.10.0 .. 1
-            2024-01-17  tar@0.4.38 .. 1
+            2024-01-16  bindgen@0.65.1 (cargo/1.74) .. 1
+            2024-01-16  cumulus-primitives-core@0.4.0 (cargo/1.74) .. 1
+            2024-01-16  derive_more@0.99.17 (cargo/1.74) .. 1
+            2024-01-16  hash-db@0.15.2 (cargo/1.74) .. 1
+            2024-01-16  hyper-rustls@0.24.2 (cargo/1.74) .. 1
+            2024-01-16  jsonrpsee-server@0.16.3 (cargo/1.74) .. 1
+            2024-01-16  peeking_take_while@0.1.2 (cargo/1.74) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.74) .. 2
+            2024-01-16  tracing-core@0.1.32 (cargo/1.74) .. 1
+            2024-01-17  flatbuffers@23.1.21 (cargo/1.71) .. 1
+            2024-01-17  jemallocator@0.5.4 (cargo/1.71) .. 1
+            2024-01-17  leveldb-sys@2.0.9 (cargo/1.71) .. 1
+            2024-01-17  num_cpus@1.15.0 (cargo/1.71) .. 1
+            2024-01-17  paste@1.0.12 (cargo/1.71) .. 1
+            2024-01-17  quick-error@1.2.3 (cargo/1.74) .. 1
+            2024-01-17  rand@0.8.5 (cargo/1.71) .. 1
+            2024-01-17  serde_derive@1.0.163 (cargo/1.71) .. 1
+            2024-01-17  smallvec@1.10.0 (cargo/1.71) .. 1
+            2024-01-17  tar@0.4.38 (cargo/1.71) .. 1
         }
         "###);
     }
@@ -171,7 +218,7 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-17  zstd-sys@2.0.8+zstd.1.5.5 .. 3
+            2024-01-17  zstd-sys@2.0.8+zstd.1.5.5 (cargo/1.71) .. 3
         }
         "###);
     }
@@ -187,7 +234,7 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  bindgen@0.65.1 .. 2
+            2024-01-16  bindgen@0.65.1 (cargo/1.74) .. 2
         }
         "###);
     }
@@ -203,7 +250,22 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  bindgen@0.65.1 .. 1
+            2024-01-16  bindgen@0.65.1 (other) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_chunks() {
+        // The header lines of each copy of the file are spread over
+        // multiple chunks, which must not affect the result.
+        let bytes = include_bytes!("../test_data/cloudfront/recoverable-errors.log");
+        let bytes = bytes.repeat(3000);
+        let downloads = assert_ok!(count_downloads(Cursor::new(bytes)).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (other) .. 3000
         }
         "###);
     }
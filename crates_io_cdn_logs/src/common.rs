@@ -0,0 +1,235 @@
+//! # Common and Combined Log Format parsing
+//!
+//! This is the default access log format of web servers like nginx and
+//! Apache, which can be used by self-hosted deployments.
+//!
+//! see <https://httpd.apache.org/docs/current/logs.html#common>
+//! and <https://nginx.org/en/docs/http/ngx_http_log_module.html#log_format>.
+
+use crate::chunks;
+use crate::paths::parse_path;
+use crate::{ClientClass, DownloadsMap};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::borrow::Cow;
+use tokio::io::AsyncBufRead;
+use tracing::{debug_span, instrument, warn};
+
+const TIMESTAMP_FORMAT: &str = "%d/%b/%Y:%H:%M:%S %z";
+
+#[instrument(level = "debug", skip(reader))]
+pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Result<DownloadsMap> {
+    chunks::count_downloads(reader, |_chunk| Ok(()), |(), chunk| count_chunk(chunk)).await
+}
+
+/// Counts the downloads in a line-aligned chunk of the log file.
+#[instrument(level = "debug", skip_all)]
+fn count_chunk(chunk: &str) -> DownloadsMap {
+    let mut downloads = DownloadsMap::new();
+
+    for line in chunk.lines() {
+        let span = debug_span!("process_line");
+        let _guard = span.enter();
+
+        let Some(line) = parse_line(line) else {
+            warn!("Failed to parse log line");
+            continue;
+        };
+
+        if line.method != "GET" {
+            // Ignore non-GET requests.
+            continue;
+        }
+
+        if line.status != "200" {
+            // Ignore non-200 responses.
+            continue;
+        }
+
+        let path = decode_path(line.path);
+
+        let Some((name, version)) = parse_path(&path) else {
+            continue;
+        };
+
+        let date = match parse_date(line.timestamp) {
+            Ok(date) => date,
+            Err(error) => {
+                warn!(timestamp = %line.timestamp, %error, "Failed to parse timestamp");
+                continue;
+            }
+        };
+
+        let client = ClientClass::from_user_agent(line.user_agent.unwrap_or_default());
+
+        downloads.add(name, version, date, client);
+    }
+
+    downloads
+}
+
+/// The fields of a log line that are needed to count downloads.
+#[derive(Debug, PartialEq, Eq)]
+struct LogLine<'a> {
+    timestamp: &'a str,
+    method: &'a str,
+    path: &'a str,
+    status: &'a str,
+    /// Only available in the combined log format.
+    user_agent: Option<&'a str>,
+}
+
+/// Parses a line like
+/// `127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326`,
+/// optionally followed by the quoted referer and user agent fields of the
+/// combined log format.
+#[instrument(level = "debug", skip(line))]
+fn parse_line(line: &str) -> Option<LogLine<'_>> {
+    // A regex could also be used here, but plain string searches are
+    // significantly faster.
+    let (_, rest) = line.split_once(" [")?;
+    let (timestamp, rest) = rest.split_once("] \"")?;
+    let (request, rest) = rest.split_once("\" ")?;
+
+    let mut request = request.split(' ');
+    let method = request.next()?;
+    let path = request.next()?;
+
+    let mut rest = rest.splitn(3, ' ');
+    let status = rest.next()?;
+
+    // The combined log format appends `"<referer>" "<user agent>"`.
+    let user_agent = rest
+        .nth(1)
+        .and_then(|rest| rest.strip_prefix('"'))
+        .and_then(|rest| rest.split_once("\" \""))
+        .and_then(|(_referer, user_agent)| user_agent.strip_suffix('"'));
+
+    Some(LogLine {
+        timestamp,
+        method,
+        path,
+        status,
+        user_agent,
+    })
+}
+
+/// Parses timestamps like `10/Oct/2000:13:55:36 -0700` and returns the
+/// corresponding UTC date, to be consistent with the other log formats.
+#[instrument(level = "debug", skip(timestamp))]
+fn parse_date(timestamp: &str) -> chrono::ParseResult<NaiveDate> {
+    let date_time = DateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)?;
+    Ok(date_time.with_timezone(&Utc).date_naive())
+}
+
+/// Deal with paths like `/crates/tikv-jemalloc-sys/tikv-jemalloc-sys-0.5.4%2B5.3.0-patched.crate`.
+///
+/// Since the web server logs the raw request line, a single round of
+/// percent-decoding is needed here.
+#[instrument(level = "debug", skip(path))]
+fn decode_path(path: &str) -> Cow<'_, str> {
+    percent_encoding::percent_decode_str(path).decode_utf8_lossy()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use claims::{assert_none, assert_ok, assert_some};
+    use insta::assert_debug_snapshot;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_line() {
+        let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08""#;
+        let line = assert_some!(parse_line(line));
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: "10/Oct/2000:13:55:36 -0700",
+                method: "GET",
+                path: "/apache_pb.gif",
+                status: "200",
+                user_agent: Some("Mozilla/4.08"),
+            }
+        );
+
+        let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326"#;
+        let line = assert_some!(parse_line(line));
+        assert_eq!(line.status, "200");
+        assert_eq!(line.user_agent, None);
+
+        assert_none!(parse_line(""));
+        assert_none!(parse_line("127.0.0.1 - - [10/Oct/2000:13:55:36 -0700]"));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let date = assert_ok!(parse_date("10/Oct/2000:13:55:36 -0700"));
+        assert_eq!(date.to_string(), "2000-10-10");
+
+        // The date is converted to UTC
+        let date = assert_ok!(parse_date("10/Oct/2000:21:55:36 -0700"));
+        assert_eq!(date.to_string(), "2000-10-11");
+    }
+
+    #[tokio::test]
+    async fn test_basic() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/common/basic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (cargo/1.75) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.74) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (other) .. 1
+            2024-01-17  tracing-core@0.1.32 (cargo/1.75) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_percent_encoding() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/common/percent-encoding.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (cargo/1.75) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_unrelated_traffic() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/common/unrelated-traffic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  strsim@0.10.0 (cargo/1.75) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_recoverable_errors() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/common/recoverable-errors.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  strsim@0.10.0 (cargo/1.75) .. 1
+        }
+        "###);
+    }
+}
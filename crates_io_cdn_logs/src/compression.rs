@@ -4,9 +4,13 @@ use std::task::{Context, Poll};
 use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
 
 /// A wrapper for the compression formats that CDN logs are currently stored in.
+///
+/// Access logs of self-hosted deployments (e.g. nginx or Caddy) are usually
+/// stored uncompressed, and are passed through as they are.
 pub enum Decompressor<T> {
     Gzip(GzipDecoder<T>),
     Zstd(ZstdDecoder<T>),
+    Plain(T),
 }
 
 impl<T: AsyncBufRead> Decompressor<T> {
@@ -14,6 +18,7 @@ impl<T: AsyncBufRead> Decompressor<T> {
         match extension {
             Some("gz") => Ok(Decompressor::gzip(inner)),
             Some("zst") => Ok(Decompressor::zstd(inner)),
+            Some("log" | "json") => Ok(Decompressor::Plain(inner)),
             Some(ext) => anyhow::bail!("Unexpected file extension: {}", ext),
             None => anyhow::bail!("Unexpected missing file extension"),
         }
@@ -37,6 +42,7 @@ impl<T: AsyncBufRead + Unpin> AsyncRead for Decompressor<T> {
         match &mut *self {
             Decompressor::Gzip(inner) => Pin::new(inner).poll_read(cx, buf),
             Decompressor::Zstd(inner) => Pin::new(inner).poll_read(cx, buf),
+            Decompressor::Plain(inner) => Pin::new(inner).poll_read(cx, buf),
         }
     }
 }
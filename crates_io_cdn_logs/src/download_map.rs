@@ -1,3 +1,4 @@
+use crate::ClientClass;
 use chrono::NaiveDate;
 use derive_deref::Deref;
 use semver::Version;
@@ -5,21 +6,41 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
 #[derive(Clone, Default, Deref)]
-pub struct DownloadsMap(HashMap<(String, Version, NaiveDate), u64>);
+pub struct DownloadsMap(HashMap<(String, Version, NaiveDate, ClientClass), u64>);
 
 impl DownloadsMap {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
 
-    /// Increments the download count for the given crate version on the given date.
-    pub fn add(&mut self, name: String, version: Version, date: NaiveDate) {
-        *self.0.entry((name, version, date)).or_default() += 1;
+    /// Increments the download count for the given crate version on the
+    /// given date by the given kind of client.
+    pub fn add(&mut self, name: String, version: Version, date: NaiveDate, client: ClientClass) {
+        *self.0.entry((name, version, date, client)).or_default() += 1;
+    }
+
+    /// Adds all download counts of the `other` map to this map.
+    pub fn merge(&mut self, other: DownloadsMap) {
+        for (key, downloads) in other.0 {
+            *self.0.entry(key).or_default() += downloads;
+        }
     }
 
     /// Returns a [HashSet] of all crate names in the map.
     pub fn unique_crates(&self) -> HashSet<&str> {
-        self.0.keys().map(|(krate, _, _)| krate.as_str()).collect()
+        self.0
+            .keys()
+            .map(|(krate, _, _, _)| krate.as_str())
+            .collect()
+    }
+
+    /// Returns a [HashSet] of all crate version and date combinations in the
+    /// map, regardless of the client.
+    pub fn unique_version_dates(&self) -> HashSet<(&str, &Version, NaiveDate)> {
+        self.0
+            .keys()
+            .map(|(krate, version, date, _)| (krate.as_str(), version, *date))
+            .collect()
     }
 
     /// Returns the total number of downloads across all crates and versions.
@@ -27,11 +48,13 @@ impl DownloadsMap {
         self.0.values().sum()
     }
 
-    /// Converts the map into a vector of `(crate, version, date, downloads)` tuples.
-    pub fn into_vec(self) -> Vec<(String, Version, NaiveDate, u64)> {
+    /// Converts the map into a vector of `(crate, version, date, client, downloads)` tuples.
+    pub fn into_vec(self) -> Vec<(String, Version, NaiveDate, ClientClass, u64)> {
         self.0
             .into_iter()
-            .map(|((name, version, date), downloads)| (name, version, date, downloads))
+            .map(|((name, version, date, client), downloads)| {
+                (name, version, date, client, downloads)
+            })
             .collect()
     }
 }
@@ -41,15 +64,19 @@ impl Debug for DownloadsMap {
         let mut downloads = self
             .0
             .iter()
-            .map(|((krate, version, date), downloads)| (date, krate, version, downloads))
+            .map(|((krate, version, date, client), downloads)| {
+                (date, krate, version, client, downloads)
+            })
             .collect::<Vec<_>>();
 
         downloads.sort();
 
         f.write_str("DownloadsMap {\n")?;
-        for (date, krate, version, downloads) in downloads {
+        for (date, krate, version, client, downloads) in downloads {
             f.write_str("    ")?;
-            f.write_fmt(format_args!("{date}  {krate}@{version} .. {downloads}"))?;
+            f.write_fmt(format_args!(
+                "{date}  {krate}@{version} ({client}) .. {downloads}"
+            ))?;
             f.write_str("\n")?;
         }
         f.write_str("}")?;
@@ -65,11 +92,14 @@ mod tests {
     use insta::assert_debug_snapshot;
     use semver::Version;
 
-    fn add(downloads: &mut DownloadsMap, name: &str, version: &str, date: &str) {
+    const CARGO: &str = "cargo 1.74.0 (ecb9851af 2023-10-18)";
+
+    fn add(downloads: &mut DownloadsMap, name: &str, version: &str, date: &str, agent: &str) {
         downloads.add(
             name.to_string(),
             version.parse::<Version>().unwrap(),
             date.parse::<NaiveDate>().unwrap(),
+            ClientClass::from_user_agent(agent),
         );
     }
 
@@ -78,31 +108,77 @@ mod tests {
         let mut downloads = DownloadsMap::new();
 
         // Add an entry to the map
-        add(&mut downloads, "xmas", "2.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "2.0.0", "2023-12-25", CARGO);
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2023-12-25  xmas@2.0.0 .. 1
+            2023-12-25  xmas@2.0.0 (cargo/1.74) .. 1
         }
         "###);
 
         // Add the same entry again
-        add(&mut downloads, "xmas", "2.0.0", "2023-12-25");
+        add(&mut downloads, "xmas", "2.0.0", "2023-12-25", CARGO);
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2023-12-25  xmas@2.0.0 .. 2
+            2023-12-25  xmas@2.0.0 (cargo/1.74) .. 2
         }
         "###);
 
         // Add other entries
-        add(&mut downloads, "foo", "2.0.0", "2023-12-25");
-        add(&mut downloads, "xmas", "1.0.0", "2023-12-25");
-        add(&mut downloads, "xmas", "2.0.0", "2023-12-26");
+        add(&mut downloads, "foo", "2.0.0", "2023-12-25", CARGO);
+        add(&mut downloads, "xmas", "1.0.0", "2023-12-25", CARGO);
+        add(&mut downloads, "xmas", "2.0.0", "2023-12-26", CARGO);
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  foo@2.0.0 (cargo/1.74) .. 1
+            2023-12-25  xmas@1.0.0 (cargo/1.74) .. 1
+            2023-12-25  xmas@2.0.0 (cargo/1.74) .. 2
+            2023-12-26  xmas@2.0.0 (cargo/1.74) .. 1
+        }
+        "###);
+
+        // Add the same entry by other clients
+        add(
+            &mut downloads,
+            "xmas",
+            "2.0.0",
+            "2023-12-25",
+            "Panamax/1.0.3",
+        );
+        add(&mut downloads, "xmas", "2.0.0", "2023-12-25", "curl/8.5.0");
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2023-12-25  foo@2.0.0 (cargo/1.74) .. 1
+            2023-12-25  xmas@1.0.0 (cargo/1.74) .. 1
+            2023-12-25  xmas@2.0.0 (cargo/1.74) .. 2
+            2023-12-25  xmas@2.0.0 (mirror/panamax) .. 1
+            2023-12-25  xmas@2.0.0 (other) .. 1
+            2023-12-26  xmas@2.0.0 (cargo/1.74) .. 1
+        }
+        "###);
+
+        assert_eq!(downloads.len(), 6);
+        assert_eq!(downloads.unique_version_dates().len(), 4);
+        assert_eq!(downloads.sum_downloads(), 7);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut downloads = DownloadsMap::new();
+        add(&mut downloads, "foo", "1.0.0", "2023-12-25", CARGO);
+        add(&mut downloads, "xmas", "2.0.0", "2023-12-25", CARGO);
+
+        let mut other = DownloadsMap::new();
+        add(&mut other, "xmas", "2.0.0", "2023-12-25", CARGO);
+        add(&mut other, "xmas", "2.0.0", "2023-12-25", "curl/8.5.0");
+        add(&mut other, "xmas", "2.0.0", "2023-12-26", CARGO);
+
+        downloads.merge(other);
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2023-12-25  foo@2.0.0 .. 1
-            2023-12-25  xmas@1.0.0 .. 1
-            2023-12-25  xmas@2.0.0 .. 2
-            2023-12-26  xmas@2.0.0 .. 1
+            2023-12-25  foo@1.0.0 (cargo/1.74) .. 1
+            2023-12-25  xmas@2.0.0 (cargo/1.74) .. 2
+            2023-12-25  xmas@2.0.0 (other) .. 1
+            2023-12-26  xmas@2.0.0 (cargo/1.74) .. 1
         }
         "###);
     }
@@ -37,6 +37,12 @@ impl LogLine<'_> {
             LogLine::V1(line) => line.status,
         }
     }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        match self {
+            LogLine::V1(line) => line.user_agent.as_deref(),
+        }
+    }
 }
 
 /// This struct corresponds to the `"version": "1"` variant of the [LogLine] enum.
@@ -52,6 +58,8 @@ impl LogLine<'_> {
 ///   crates.io codebase.
 /// - The `method` and `url` fields are using `Cow` to avoid
 ///   unnecessary allocations.
+/// - The optional `user_agent` field is included, so that downloads can be
+///   classified by client. It is missing in older log lines.
 #[derive(Debug, Deserialize)]
 pub struct LogLineV1<'a> {
     pub date_time: DateTime<Utc>,
@@ -60,6 +68,8 @@ pub struct LogLineV1<'a> {
     #[serde(borrow)]
     pub url: Cow<'a, str>,
     pub status: u16,
+    #[serde(borrow, default)]
+    pub user_agent: Option<Cow<'a, str>>,
 }
 
 #[cfg(test)]
@@ -79,6 +89,7 @@ mod tests {
                 method: "GET",
                 url: "https://static.staging.crates.io/?1705420437",
                 status: 403,
+                user_agent: None,
             },
         )
         "###);
@@ -4,22 +4,28 @@
 
 mod json;
 
+use crate::chunks;
 use crate::paths::parse_path;
-use crate::DownloadsMap;
+use crate::{ClientClass, DownloadsMap};
 use std::borrow::Cow;
-use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::io::AsyncBufRead;
 use tracing::{debug_span, instrument, warn};
 
 #[instrument(level = "debug", skip(reader))]
 pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Result<DownloadsMap> {
+    chunks::count_downloads(reader, |_chunk| Ok(()), |(), chunk| count_chunk(chunk)).await
+}
+
+/// Counts the downloads in a line-aligned chunk of the log file.
+#[instrument(level = "debug", skip_all)]
+fn count_chunk(chunk: &str) -> DownloadsMap {
     let mut downloads = DownloadsMap::new();
 
-    let mut lines = reader.lines();
-    while let Some(line) = lines.next_line().await? {
+    for line in chunk.lines() {
         let span = debug_span!("process_line");
         let _guard = span.enter();
 
-        let Some(json) = parse_line(&line) else {
+        let Some(json) = parse_line(line) else {
             warn!("Failed to find JSON start");
             continue;
         };
@@ -54,10 +60,13 @@ pub async fn count_downloads(reader: impl AsyncBufRead + Unpin) -> anyhow::Resul
 
         let date = json.date_time().date_naive();
 
-        downloads.add(name, version, date);
+        let user_agent = json.user_agent().unwrap_or_default();
+        let client = ClientClass::from_user_agent(user_agent);
+
+        downloads.add(name, version, date, client);
     }
 
-    Ok(downloads)
+    downloads
 }
 
 #[instrument(level = "debug", skip(line))]
@@ -98,28 +107,28 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  strsim@0.10.0 .. 1
-            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched .. 1
-            2024-01-16  tinyvec@1.6.0 .. 1
-            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 .. 1
-            2024-01-16  windows_x86_64_gnu@0.48.0 .. 1
-            2024-01-16  windows_x86_64_gnullvm@0.42.2 .. 1
-            2024-01-16  winnow@0.5.4 .. 1
-            2024-01-17  anstyle@1.0.1 .. 1
-            2024-01-17  cast@0.3.0 .. 1
-            2024-01-17  cc@1.0.73 .. 1
-            2024-01-17  croaring-sys@1.1.0 .. 1
-            2024-01-17  half@1.8.2 .. 1
-            2024-01-17  jemalloc-sys@0.3.2 .. 1
-            2024-01-17  lazy_static@1.4.0 .. 1
-            2024-01-17  libc@0.2.126 .. 1
-            2024-01-17  lzma-sys@0.1.20 .. 1
-            2024-01-17  sqlparser@0.40.0 .. 1
-            2024-01-17  synchronized-writer@1.1.11 .. 1
-            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched .. 1
-            2024-01-17  windows_x86_64_gnu@0.48.0 .. 2
-            2024-01-17  xz2@0.1.7 .. 1
-            2024-01-17  zstd-safe@7.0.0 .. 1
+            2024-01-16  strsim@0.10.0 (other) .. 1
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (other) .. 1
+            2024-01-16  tinyvec@1.6.0 (other) .. 1
+            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnu@0.48.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnullvm@0.42.2 (other) .. 1
+            2024-01-16  winnow@0.5.4 (other) .. 1
+            2024-01-17  anstyle@1.0.1 (other) .. 1
+            2024-01-17  cast@0.3.0 (other) .. 1
+            2024-01-17  cc@1.0.73 (other) .. 1
+            2024-01-17  croaring-sys@1.1.0 (other) .. 1
+            2024-01-17  half@1.8.2 (other) .. 1
+            2024-01-17  jemalloc-sys@0.3.2 (other) .. 1
+            2024-01-17  lazy_static@1.4.0 (other) .. 1
+            2024-01-17  libc@0.2.126 (other) .. 1
+            2024-01-17  lzma-sys@0.1.20 (other) .. 1
+            2024-01-17  sqlparser@0.40.0 (other) .. 1
+            2024-01-17  synchronized-writer@1.1.11 (other) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (other) .. 1
+            2024-01-17  windows_x86_64_gnu@0.48.0 (other) .. 2
+            2024-01-17  xz2@0.1.7 (other) .. 1
+            2024-01-17  zstd-safe@7.0.0 (other) .. 1
         }
         "###);
     }
@@ -135,7 +144,7 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched .. 2
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (other) .. 2
         }
         "###);
     }
@@ -151,7 +160,7 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  strsim@0.10.0 .. 2
+            2024-01-16  strsim@0.10.0 (other) .. 2
         }
         "###);
     }
@@ -167,7 +176,7 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  strsim@0.10.0 .. 1
+            2024-01-16  strsim@0.10.0 (other) .. 1
         }
         "###);
     }
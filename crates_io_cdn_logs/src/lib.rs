@@ -1,4 +1,8 @@
+pub mod caddy;
+mod chunks;
+mod client;
 pub mod cloudfront;
+pub mod common;
 mod compression;
 mod download_map;
 pub mod fastly;
@@ -6,6 +10,7 @@ mod paths;
 #[cfg(test)]
 mod test_utils;
 
+pub use crate::client::{CargoRelease, ClientClass};
 pub use crate::compression::Decompressor;
 pub use crate::download_map::DownloadsMap;
 use std::io::Cursor;
@@ -35,6 +40,25 @@ where
             let reader = Cursor::new(b"<").chain(reader);
             fastly::count_downloads(reader).await
         }
+        // Caddy JSON log lines start with a `{` character.
+        b'{' => {
+            // We can't use `AsyncSeek` here because `async-compression` does
+            // not support it, but we can use `Cursor` to prepend the `{` back
+            // onto the reader.
+            let reader = Cursor::new(b"{").chain(reader);
+            caddy::count_downloads(reader).await
+        }
+        // Common/Combined Log Format lines start with the IP address of the
+        // client, which is either an IPv4 address or an IPv6 address in
+        // its compressed or uncompressed form. IPv6 addresses can start with
+        // any hexadecimal digit, e.g. `fd00::1`.
+        byte @ (b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' | b':') => {
+            // We can't use `AsyncSeek` here because `async-compression` does
+            // not support it, but we can use `Cursor` to prepend the byte
+            // back onto the reader.
+            let reader = Cursor::new([byte]).chain(reader);
+            common::count_downloads(reader).await
+        }
         // Anything else is rejected.
         byte => {
             anyhow::bail!("Failed to determine log file format. Unrecognized first byte: {byte:?}.")
@@ -60,25 +84,25 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  bindgen@0.65.1 .. 1
-            2024-01-16  cumulus-primitives-core@0.4.0 .. 1
-            2024-01-16  derive_more@0.99.17 .. 1
-            2024-01-16  hash-db@0.15.2 .. 1
-            2024-01-16  hyper-rustls@0.24.2 .. 1
-            2024-01-16  jsonrpsee-server@0.16.3 .. 1
-            2024-01-16  peeking_take_while@0.1.2 .. 1
-            2024-01-16  quick-error@1.2.3 .. 2
-            2024-01-16  tracing-core@0.1.32 .. 1
-            2024-01-17  flatbuffers@23.1.21 .. 1
-            2024-01-17  jemallocator@0.5.4 .. 1
-            2024-01-17  leveldb-sys@2.0.9 .. 1
-            2024-01-17  num_cpus@1.15.0 .. 1
-            2024-01-17  paste@1.0.12 .. 1
-            2024-01-17  quick-error@1.2.3 .. 1
-            2024-01-17  rand@0.8.5 .. 1
-            2024-01-17  serde_derive@1.0.163 .. 1
-            2024-01-17  smallvec@1.10.0 .. 1
-            2024-01-17  tar@0.4.38 .. 1
+            2024-01-16  bindgen@0.65.1 (cargo/1.74) .. 1
+            2024-01-16  cumulus-primitives-core@0.4.0 (cargo/1.74) .. 1
+            2024-01-16  derive_more@0.99.17 (cargo/1.74) .. 1
+            2024-01-16  hash-db@0.15.2 (cargo/1.74) .. 1
+            2024-01-16  hyper-rustls@0.24.2 (cargo/1.74) .. 1
+            2024-01-16  jsonrpsee-server@0.16.3 (cargo/1.74) .. 1
+            2024-01-16  peeking_take_while@0.1.2 (cargo/1.74) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.74) .. 2
+            2024-01-16  tracing-core@0.1.32 (cargo/1.74) .. 1
+            2024-01-17  flatbuffers@23.1.21 (cargo/1.71) .. 1
+            2024-01-17  jemallocator@0.5.4 (cargo/1.71) .. 1
+            2024-01-17  leveldb-sys@2.0.9 (cargo/1.71) .. 1
+            2024-01-17  num_cpus@1.15.0 (cargo/1.71) .. 1
+            2024-01-17  paste@1.0.12 (cargo/1.71) .. 1
+            2024-01-17  quick-error@1.2.3 (cargo/1.74) .. 1
+            2024-01-17  rand@0.8.5 (cargo/1.71) .. 1
+            2024-01-17  serde_derive@1.0.163 (cargo/1.71) .. 1
+            2024-01-17  smallvec@1.10.0 (cargo/1.71) .. 1
+            2024-01-17  tar@0.4.38 (cargo/1.71) .. 1
         }
         "###);
     }
@@ -96,25 +120,25 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  bindgen@0.65.1 .. 1
-            2024-01-16  cumulus-primitives-core@0.4.0 .. 1
-            2024-01-16  derive_more@0.99.17 .. 1
-            2024-01-16  hash-db@0.15.2 .. 1
-            2024-01-16  hyper-rustls@0.24.2 .. 1
-            2024-01-16  jsonrpsee-server@0.16.3 .. 1
-            2024-01-16  peeking_take_while@0.1.2 .. 1
-            2024-01-16  quick-error@1.2.3 .. 2
-            2024-01-16  tracing-core@0.1.32 .. 1
-            2024-01-17  flatbuffers@23.1.21 .. 1
-            2024-01-17  jemallocator@0.5.4 .. 1
-            2024-01-17  leveldb-sys@2.0.9 .. 1
-            2024-01-17  num_cpus@1.15.0 .. 1
-            2024-01-17  paste@1.0.12 .. 1
-            2024-01-17  quick-error@1.2.3 .. 1
-            2024-01-17  rand@0.8.5 .. 1
-            2024-01-17  serde_derive@1.0.163 .. 1
-            2024-01-17  smallvec@1.10.0 .. 1
-            2024-01-17  tar@0.4.38 .. 1
+            2024-01-16  bindgen@0.65.1 (cargo/1.74) .. 1
+            2024-01-16  cumulus-primitives-core@0.4.0 (cargo/1.74) .. 1
+            2024-01-16  derive_more@0.99.17 (cargo/1.74) .. 1
+            2024-01-16  hash-db@0.15.2 (cargo/1.74) .. 1
+            2024-01-16  hyper-rustls@0.24.2 (cargo/1.74) .. 1
+            2024-01-16  jsonrpsee-server@0.16.3 (cargo/1.74) .. 1
+            2024-01-16  peeking_take_while@0.1.2 (cargo/1.74) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.74) .. 2
+            2024-01-16  tracing-core@0.1.32 (cargo/1.74) .. 1
+            2024-01-17  flatbuffers@23.1.21 (cargo/1.71) .. 1
+            2024-01-17  jemallocator@0.5.4 (cargo/1.71) .. 1
+            2024-01-17  leveldb-sys@2.0.9 (cargo/1.71) .. 1
+            2024-01-17  num_cpus@1.15.0 (cargo/1.71) .. 1
+            2024-01-17  paste@1.0.12 (cargo/1.71) .. 1
+            2024-01-17  quick-error@1.2.3 (cargo/1.74) .. 1
+            2024-01-17  rand@0.8.5 (cargo/1.71) .. 1
+            2024-01-17  serde_derive@1.0.163 (cargo/1.71) .. 1
+            2024-01-17  smallvec@1.10.0 (cargo/1.71) .. 1
+            2024-01-17  tar@0.4.38 (cargo/1.71) .. 1
         }
         "###);
     }
@@ -128,28 +152,28 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  strsim@0.10.0 .. 1
-            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched .. 1
-            2024-01-16  tinyvec@1.6.0 .. 1
-            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 .. 1
-            2024-01-16  windows_x86_64_gnu@0.48.0 .. 1
-            2024-01-16  windows_x86_64_gnullvm@0.42.2 .. 1
-            2024-01-16  winnow@0.5.4 .. 1
-            2024-01-17  anstyle@1.0.1 .. 1
-            2024-01-17  cast@0.3.0 .. 1
-            2024-01-17  cc@1.0.73 .. 1
-            2024-01-17  croaring-sys@1.1.0 .. 1
-            2024-01-17  half@1.8.2 .. 1
-            2024-01-17  jemalloc-sys@0.3.2 .. 1
-            2024-01-17  lazy_static@1.4.0 .. 1
-            2024-01-17  libc@0.2.126 .. 1
-            2024-01-17  lzma-sys@0.1.20 .. 1
-            2024-01-17  sqlparser@0.40.0 .. 1
-            2024-01-17  synchronized-writer@1.1.11 .. 1
-            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched .. 1
-            2024-01-17  windows_x86_64_gnu@0.48.0 .. 2
-            2024-01-17  xz2@0.1.7 .. 1
-            2024-01-17  zstd-safe@7.0.0 .. 1
+            2024-01-16  strsim@0.10.0 (other) .. 1
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (other) .. 1
+            2024-01-16  tinyvec@1.6.0 (other) .. 1
+            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnu@0.48.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnullvm@0.42.2 (other) .. 1
+            2024-01-16  winnow@0.5.4 (other) .. 1
+            2024-01-17  anstyle@1.0.1 (other) .. 1
+            2024-01-17  cast@0.3.0 (other) .. 1
+            2024-01-17  cc@1.0.73 (other) .. 1
+            2024-01-17  croaring-sys@1.1.0 (other) .. 1
+            2024-01-17  half@1.8.2 (other) .. 1
+            2024-01-17  jemalloc-sys@0.3.2 (other) .. 1
+            2024-01-17  lazy_static@1.4.0 (other) .. 1
+            2024-01-17  libc@0.2.126 (other) .. 1
+            2024-01-17  lzma-sys@0.1.20 (other) .. 1
+            2024-01-17  sqlparser@0.40.0 (other) .. 1
+            2024-01-17  synchronized-writer@1.1.11 (other) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (other) .. 1
+            2024-01-17  windows_x86_64_gnu@0.48.0 (other) .. 2
+            2024-01-17  xz2@0.1.7 (other) .. 1
+            2024-01-17  zstd-safe@7.0.0 (other) .. 1
         }
         "###);
     }
@@ -167,28 +191,98 @@ mod tests {
 
         assert_debug_snapshot!(downloads, @r###"
         DownloadsMap {
-            2024-01-16  strsim@0.10.0 .. 1
-            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched .. 1
-            2024-01-16  tinyvec@1.6.0 .. 1
-            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 .. 1
-            2024-01-16  windows_x86_64_gnu@0.48.0 .. 1
-            2024-01-16  windows_x86_64_gnullvm@0.42.2 .. 1
-            2024-01-16  winnow@0.5.4 .. 1
-            2024-01-17  anstyle@1.0.1 .. 1
-            2024-01-17  cast@0.3.0 .. 1
-            2024-01-17  cc@1.0.73 .. 1
-            2024-01-17  croaring-sys@1.1.0 .. 1
-            2024-01-17  half@1.8.2 .. 1
-            2024-01-17  jemalloc-sys@0.3.2 .. 1
-            2024-01-17  lazy_static@1.4.0 .. 1
-            2024-01-17  libc@0.2.126 .. 1
-            2024-01-17  lzma-sys@0.1.20 .. 1
-            2024-01-17  sqlparser@0.40.0 .. 1
-            2024-01-17  synchronized-writer@1.1.11 .. 1
-            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched .. 1
-            2024-01-17  windows_x86_64_gnu@0.48.0 .. 2
-            2024-01-17  xz2@0.1.7 .. 1
-            2024-01-17  zstd-safe@7.0.0 .. 1
+            2024-01-16  strsim@0.10.0 (other) .. 1
+            2024-01-16  tikv-jemalloc-sys@0.5.2+5.3.0-patched (other) .. 1
+            2024-01-16  tinyvec@1.6.0 (other) .. 1
+            2024-01-16  winapi-x86_64-pc-windows-gnu@0.4.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnu@0.48.0 (other) .. 1
+            2024-01-16  windows_x86_64_gnullvm@0.42.2 (other) .. 1
+            2024-01-16  winnow@0.5.4 (other) .. 1
+            2024-01-17  anstyle@1.0.1 (other) .. 1
+            2024-01-17  cast@0.3.0 (other) .. 1
+            2024-01-17  cc@1.0.73 (other) .. 1
+            2024-01-17  croaring-sys@1.1.0 (other) .. 1
+            2024-01-17  half@1.8.2 (other) .. 1
+            2024-01-17  jemalloc-sys@0.3.2 (other) .. 1
+            2024-01-17  lazy_static@1.4.0 (other) .. 1
+            2024-01-17  libc@0.2.126 (other) .. 1
+            2024-01-17  lzma-sys@0.1.20 (other) .. 1
+            2024-01-17  sqlparser@0.40.0 (other) .. 1
+            2024-01-17  synchronized-writer@1.1.11 (other) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (other) .. 1
+            2024-01-17  windows_x86_64_gnu@0.48.0 (other) .. 2
+            2024-01-17  xz2@0.1.7 (other) .. 1
+            2024-01-17  zstd-safe@7.0.0 (other) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_common() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/common/basic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (cargo/1.75) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.74) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (other) .. 1
+            2024-01-17  tracing-core@0.1.32 (cargo/1.75) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_common_ipv6() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(
+            br#"::1 - - [16/Jan/2024:16:03:04 +0000] "GET /crates/bindgen/bindgen-0.65.1.crate HTTP/1.1" 200 212451"#,
+        );
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (other) .. 1
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_common_ipv6_hex_letter() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(
+            br#"fd00::1 - - [16/Jan/2024:16:03:04 +0000] "GET /crates/bindgen/bindgen-0.65.1.crate HTTP/1.1" 200 212451
+FE80::1 - - [16/Jan/2024:16:03:05 +0000] "GET /crates/bindgen/bindgen-0.65.1.crate HTTP/1.1" 200 212451"#,
+        );
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (other) .. 2
+        }
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_caddy() {
+        let _guard = enable_tracing_output();
+
+        let mut cursor = Cursor::new(include_bytes!("../test_data/caddy/basic.log"));
+        let downloads = assert_ok!(count_downloads(&mut cursor).await);
+
+        assert_debug_snapshot!(downloads, @r###"
+        DownloadsMap {
+            2024-01-16  bindgen@0.65.1 (cargo/1.75) .. 1
+            2024-01-16  quick-error@1.2.3 (cargo/1.75) .. 2
+            2024-01-17  quick-error@1.2.3 (cargo/1.75) .. 1
+            2024-01-17  tikv-jemalloc-sys@0.5.4+5.3.0-patched (cargo/1.75) .. 1
+            2024-01-17  tracing-core@0.1.32 (cargo/1.75) .. 1
         }
         "###);
     }
@@ -197,8 +291,8 @@ mod tests {
     async fn test_unknown() {
         let _guard = enable_tracing_output();
 
-        let mut cursor = Cursor::new(b"foo");
+        let mut cursor = Cursor::new(b"xyz");
         let error = assert_err!(count_downloads(&mut cursor).await);
-        assert_display_snapshot!(error, @"Failed to determine log file format. Unrecognized first byte: 102.");
+        assert_display_snapshot!(error, @"Failed to determine log file format. Unrecognized first byte: 120.");
     }
 }
@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Crate {
     pub name: String,
     pub vers: String,
@@ -24,6 +24,14 @@ pub struct Crate {
     pub links: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rust_version: Option<String>,
+    /// The time at which this version was published, as an RFC 3339
+    /// timestamp in UTC (e.g. `2024-02-08T12:34:56Z`).
+    ///
+    /// Unknown fields are ignored by all cargo versions, so unlike
+    /// `features2` this field does not require a new schema version `v`.
+    /// Bumping `v` would instead cause cargo to ignore the entries entirely.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubtime: Option<String>,
     /// The schema version for this entry.
     ///
     /// If this is None, it defaults to version 1. Entries with unknown
@@ -47,6 +55,22 @@ pub struct Crate {
     pub v: Option<u32>,
 }
 
+impl Crate {
+    /// Returns the features of this version with the entries of `features2`
+    /// merged into `features`, the same way cargo does it when reading the
+    /// index.
+    pub fn merged_features(&self) -> BTreeMap<String, Vec<String>> {
+        let mut features = self.features.clone();
+        if let Some(features2) = &self.features2 {
+            for (name, values) in features2 {
+                let entry = features.entry(name.clone()).or_default();
+                entry.extend(values.iter().cloned());
+            }
+        }
+        features
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Dependency {
     pub name: String,
@@ -5,12 +5,16 @@ extern crate tracing;
 
 mod credentials;
 mod data;
+mod read;
 mod repo;
 mod ser;
+mod snapshot;
 #[cfg(feature = "testing")]
 pub mod testing;
 
 pub use crate::credentials::Credentials;
 pub use crate::data::{Crate, Dependency, DependencyKind};
-pub use crate::repo::{Repository, RepositoryConfig};
+pub use crate::read::{index_files, parse_crates, read_crates, IndexFile, ParseError};
+pub use crate::repo::{MirrorConfig, Repository, RepositoryConfig};
 pub use crate::ser::write_crates;
+pub use crate::snapshot::RepositorySnapshot;
@@ -0,0 +1,188 @@
+use crate::{Crate, Repository};
+use anyhow::Context;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// An error that occurred while parsing a single line of an index file.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to parse line {line} of the index file: {source}")]
+pub struct ParseError {
+    /// The (1-based) number of the line that could not be parsed.
+    pub line: usize,
+    pub source: serde_json::Error,
+}
+
+/// Parses the content of an index file line by line.
+///
+/// Empty lines are skipped. Since every line of an index file is parsed
+/// independently, callers can decide whether to skip invalid entries or to
+/// abort on the first one.
+pub fn parse_crates(content: &str) -> impl Iterator<Item = Result<Crate, ParseError>> + '_ {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|source| ParseError {
+                line: i + 1,
+                source,
+            })
+        })
+}
+
+/// Parses the content of an index file, failing on the first invalid line.
+pub fn read_crates(content: &str) -> Result<Vec<Crate>, ParseError> {
+    parse_crates(content).collect()
+}
+
+/// The index file of a single crate in a local directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexFile {
+    /// The (lowercase) crate name, as used for the file name.
+    pub name: String,
+    /// The absolute path of the file.
+    pub path: PathBuf,
+}
+
+impl IndexFile {
+    /// Reads and parses all entries of this index file.
+    pub fn read(&self) -> anyhow::Result<Vec<Crate>> {
+        let path = self.path.display();
+
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read index file {path}"))?;
+
+        read_crates(&content).with_context(|| format!("Failed to parse index file {path}"))
+    }
+}
+
+/// Returns all crate index files in the given directory, ordered by path.
+///
+/// This works for both a checkout of the git index and a local copy of the
+/// sparse index, since both use the same directory layout. Files that do not
+/// match the layout of [Repository::relative_index_file] (e.g. `config.json`
+/// or anything in the `.git` directory) are skipped.
+pub fn index_files(root: &Path) -> anyhow::Result<Vec<IndexFile>> {
+    let walker = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git");
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let Some(name) = entry.file_name().to_str() else {
+            continue;
+        };
+
+        let relative_path = entry.path().strip_prefix(root)?;
+        if Repository::relative_index_file(name) != relative_path {
+            continue;
+        }
+
+        let name = name.to_string();
+        let path = entry.into_path();
+        files.push(IndexFile { name, path });
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::*;
+
+    const FOO: &str =
+        r#"{"name":"foo","vers":"1.0.0","deps":[],"cksum":"0123","features":{},"yanked":false}"#;
+    const BAR: &str = r#"{"name":"bar","vers":"0.1.0","deps":[],"cksum":"4567","features":{"a":["b"]},"features2":{"c":["dep:d"]},"yanked":true,"v":2}"#;
+
+    #[test]
+    fn test_read_crates() {
+        let content = format!("{FOO}\n\n{BAR}\n");
+        let crates = assert_ok!(read_crates(&content));
+        assert_eq!(crates.len(), 2);
+        assert_eq!(crates[0].name, "foo");
+        assert_eq!(crates[1].name, "bar");
+        assert_some_eq!(crates[1].v, 2);
+    }
+
+    #[test]
+    fn test_parse_crates_reports_line_numbers() {
+        let content = format!("{FOO}\n{{\"name\":\"foo\"}}\n\n{BAR}\nnot json\n");
+        let results = parse_crates(&content).collect::<Vec<_>>();
+        assert_eq!(results.len(), 4);
+        assert_ok!(&results[0]);
+        assert_eq!(assert_err!(&results[1]).line, 2);
+        assert_ok!(&results[2]);
+        assert_eq!(assert_err!(&results[3]).line, 5);
+
+        let error = assert_err!(read_crates(&content));
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn test_merged_features() {
+        let crates = assert_ok!(read_crates(BAR));
+        let features = crates[0].merged_features();
+        assert_eq!(features.len(), 2);
+        assert_some_eq!(features.get("a"), &vec!["b".to_string()]);
+        assert_some_eq!(features.get("c"), &vec!["dep:d".to_string()]);
+    }
+
+    #[test]
+    fn test_index_files() {
+        let dir = assert_ok!(tempfile::tempdir());
+        let root = dir.path();
+
+        for (path, content) in [
+            ("config.json", "{}"),
+            ("3/f/foo", FOO),
+            ("3/b/bar", BAR),
+            ("3/b/baz/qux", FOO),
+            (".git/3/f/foo", FOO),
+        ] {
+            let path = root.join(path);
+            assert_ok!(fs::create_dir_all(path.parent().unwrap()));
+            assert_ok!(fs::write(path, content));
+        }
+
+        let files = assert_ok!(index_files(root));
+        let names = files.iter().map(|file| &file.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["bar", "foo"]);
+
+        let crates = assert_ok!(files[1].read());
+        assert_eq!(crates.len(), 1);
+        assert_eq!(crates[0].vers, "1.0.0");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_index_files_of_git_checkout() {
+        use crate::testing::UpstreamIndex;
+
+        let upstream = assert_ok!(UpstreamIndex::new());
+        assert_ok!(upstream.write_index_file("foo", &format!("{FOO}\n")));
+        assert_ok!(upstream.write_index_file("bar", &format!("{BAR}\n")));
+
+        let dir = assert_ok!(tempfile::tempdir());
+        assert_ok!(git2::Repository::clone(upstream.url().as_str(), dir.path()));
+
+        let files = assert_ok!(index_files(dir.path()));
+        let names = files.iter().map(|file| &file.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["bar", "foo"]);
+
+        for file in files {
+            let crates = assert_ok!(file.read());
+            assert_eq!(
+                crates,
+                assert_ok!(upstream.crates_from_index_head(&file.name))
+            );
+        }
+    }
+}
@@ -1,61 +1,122 @@
 use crate::credentials::Credentials;
+use crate::snapshot::RepositorySnapshot;
 use anyhow::{anyhow, Context};
 use base64::{engine::general_purpose, Engine};
-use crates_io_env_vars::{required_var, required_var_parsed, var};
+use crates_io_env_vars::{list, required_var, required_var_parsed, var};
 use secrecy::{ExposeSecret, SecretString};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 use url::Url;
 
+/// The maximum time that a `git fetch` or `git push` of the index may take.
+/// The `git` process is killed afterwards, since a hung process would
+/// otherwise block the index checkout indefinitely.
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
 pub struct RepositoryConfig {
     pub index_location: Url,
     pub credentials: Credentials,
+    /// Additional remotes that the index is mirrored to.
+    pub mirrors: Vec<MirrorConfig>,
 }
 
 impl RepositoryConfig {
+    /// Loads the repository configuration from the environment.
+    ///
+    /// The primary repository is configured via `GIT_REPO_URL`, `GIT_HTTP_USER`,
+    /// `GIT_HTTP_PWD` and `GIT_SSH_KEY`. Mirrors are configured via the
+    /// comma-separated list of mirror names in `GIT_MIRRORS`, with the
+    /// corresponding `GIT_MIRROR_{NAME}_URL`, `GIT_MIRROR_{NAME}_HTTP_USER`,
+    /// `GIT_MIRROR_{NAME}_HTTP_PWD` and `GIT_MIRROR_{NAME}_SSH_KEY` variables.
     pub fn from_environment() -> anyhow::Result<Self> {
         let repo_url: Url = required_var_parsed("GIT_REPO_URL")?;
-        let is_ssh = repo_url.scheme() == "ssh";
-
-        let username = var("GIT_HTTP_USER")?;
-        let password = var("GIT_HTTP_PWD")?.map(SecretString::from);
-
-        match (is_ssh, username, password) {
-            (true, username, password) => {
-                let ssh_key = SecretString::from(required_var("GIT_SSH_KEY")?);
-
-                if username.is_some() || password.is_some() {
-                    warn!("both http and ssh credentials to authenticate with git are set");
-                    info!("note: ssh credentials will take precedence over the http ones");
-                }
-
-                let key = general_purpose::STANDARD
-                    .decode(ssh_key.expose_secret())
-                    .expect("failed to base64 decode the ssh key");
-                let key =
-                    String::from_utf8(key).expect("failed to convert the ssh key to a string");
-                let credentials = Credentials::Ssh { key: key.into() };
-
-                Ok(Self {
-                    index_location: repo_url,
-                    credentials,
-                })
-            }
-            (false, Some(username), Some(password)) => {
-                let credentials = Credentials::Http { username, password };
+        let credentials = credentials_from_environment(&repo_url, "GIT")?;
+
+        let mirrors = list("GIT_MIRRORS")?
+            .into_iter()
+            .map(|name| MirrorConfig::from_environment(&name))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            index_location: repo_url,
+            credentials,
+            mirrors,
+        })
+    }
+}
+
+/// A remote that the index is mirrored to, in addition to the primary
+/// `origin` remote.
+#[derive(Clone)]
+pub struct MirrorConfig {
+    /// The name of the mirror, which is used to refer to it from background
+    /// jobs and log messages.
+    pub name: String,
+    pub url: Url,
+    pub credentials: Credentials,
+}
+
+impl MirrorConfig {
+    fn from_environment(name: &str) -> anyhow::Result<Self> {
+        let prefix = format!("GIT_MIRROR_{}", name.to_uppercase().replace('-', "_"));
+        let url: Url = required_var_parsed(&format!("{prefix}_URL"))?;
+        let credentials = credentials_from_environment(&url, &prefix)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            url,
+            credentials,
+        })
+    }
 
-                Ok(Self {
-                    index_location: repo_url,
-                    credentials,
-                })
+    /// Returns the [Object ID](git2::Oid) of the `master` branch of this
+    /// mirror, or `None` if the branch does not exist.
+    #[instrument(skip_all, fields(mirror = %self.name))]
+    pub fn head_oid(&self) -> anyhow::Result<Option<git2::Oid>> {
+        let mut command = Command::new("git");
+        command.args(["ls-remote", self.url.as_str(), "refs/heads/master"]);
+
+        let output = run_via_cli_with_output(&mut command, &self.credentials)?;
+        let Some(oid) = output.split_whitespace().next() else {
+            return Ok(None);
+        };
+
+        let oid = git2::Oid::from_str(oid).context("Failed to parse `git ls-remote` output")?;
+        Ok(Some(oid))
+    }
+}
+
+/// Loads the credentials for the repository at `url` from the
+/// `{prefix}_HTTP_USER`, `{prefix}_HTTP_PWD` and `{prefix}_SSH_KEY`
+/// environment variables.
+fn credentials_from_environment(url: &Url, prefix: &str) -> anyhow::Result<Credentials> {
+    let is_ssh = url.scheme() == "ssh";
+
+    let username = var(&format!("{prefix}_HTTP_USER"))?;
+    let password = var(&format!("{prefix}_HTTP_PWD"))?.map(SecretString::from);
+
+    match (is_ssh, username, password) {
+        (true, username, password) => {
+            let ssh_key = SecretString::from(required_var(&format!("{prefix}_SSH_KEY"))?);
+
+            if username.is_some() || password.is_some() {
+                warn!("both http and ssh credentials to authenticate with git are set");
+                info!("note: ssh credentials will take precedence over the http ones");
             }
 
-            (false, _, _) => Ok(Self {
-                index_location: repo_url,
-                credentials: Credentials::Missing,
-            }),
+            let key = general_purpose::STANDARD
+                .decode(ssh_key.expose_secret())
+                .expect("failed to base64 decode the ssh key");
+            let key = String::from_utf8(key).expect("failed to convert the ssh key to a string");
+
+            Ok(Credentials::Ssh { key: key.into() })
         }
+        (false, Some(username), Some(password)) => Ok(Credentials::Http { username, password }),
+        (false, _, _) => Ok(Credentials::Missing),
     }
 }
 
@@ -63,6 +124,7 @@ pub struct Repository {
     checkout_path: TempDir,
     repository: git2::Repository,
     credentials: Credentials,
+    mirrors: Vec<MirrorConfig>,
 }
 
 impl Repository {
@@ -120,6 +182,7 @@ impl Repository {
             checkout_path,
             repository,
             credentials: repository_config.credentials.clone(),
+            mirrors: repository_config.mirrors.clone(),
         })
     }
 
@@ -176,20 +239,29 @@ impl Repository {
         Ok(head.target().unwrap())
     }
 
-    /// Commits the specified file with the specified commit message and pushes
+    /// Creates a read-only [RepositorySnapshot] of the currently checked out
+    /// commit, which can be used after the lock on this repository has been
+    /// released.
+    pub fn snapshot(&self) -> anyhow::Result<RepositorySnapshot> {
+        RepositorySnapshot::create(self.checkout_path.path(), self.head_oid()?)
+    }
+
+    /// Commits the specified files with the specified commit message and pushes
     /// the commit to the `master` branch on the `origin` remote.
     ///
-    /// Note that `modified_file` expects a file path **relative** to the
+    /// Note that `modified_files` expects file paths **relative** to the
     /// repository working folder!
     #[instrument(skip_all, fields(message = %msg))]
-    fn perform_commit_and_push(&self, msg: &str, modified_file: &Path) -> anyhow::Result<()> {
-        // git add $file
+    fn perform_commit_and_push(&self, msg: &str, modified_files: &[&Path]) -> anyhow::Result<()> {
+        // git add $files
         let mut index = self.repository.index()?;
 
-        if self.checkout_path.path().join(modified_file).exists() {
-            index.add_path(modified_file)?;
-        } else {
-            index.remove_path(modified_file)?;
+        for modified_file in modified_files {
+            if self.checkout_path.path().join(modified_file).exists() {
+                index.add_path(modified_file)?;
+            } else {
+                index.remove_path(modified_file)?;
+            }
         }
 
         index.write()?;
@@ -254,7 +326,9 @@ impl Repository {
     /// Push the current branch to the provided refname
     #[instrument(skip_all)]
     fn push(&self) -> anyhow::Result<()> {
-        self.run_command(Command::new("git").args(["push", "origin", "HEAD:master"]))
+        let mut command = Command::new("git");
+        command.args(["push", "origin", "HEAD:master"]);
+        self.run_remote_command(&mut command)
     }
 
     /// Commits the specified file with the specified commit message and pushes
@@ -265,10 +339,30 @@ impl Repository {
     /// This function also prints the commit message and a success or failure
     /// message to the console.
     pub fn commit_and_push(&self, message: &str, modified_file: &Path) -> anyhow::Result<()> {
+        self.commit_files_and_push(message, &[modified_file])
+    }
+
+    /// Commits all of the specified files in a single commit with the
+    /// specified commit message and pushes the commit to the `master` branch
+    /// on the `origin` remote.
+    ///
+    /// Note that `modified_files` expects **absolute** file paths!
+    ///
+    /// This function also prints the commit message and a success or failure
+    /// message to the console.
+    pub fn commit_files_and_push(
+        &self,
+        message: &str,
+        modified_files: &[&Path],
+    ) -> anyhow::Result<()> {
         info!("Committing and pushing \"{message}\"");
 
-        let relative_path = modified_file.strip_prefix(self.checkout_path.path())?;
-        self.perform_commit_and_push(message, relative_path)
+        let relative_paths = modified_files
+            .iter()
+            .map(|path| path.strip_prefix(self.checkout_path.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.perform_commit_and_push(message, &relative_paths)
             .map(|_| info!("Commit and push finished for \"{message}\""))
             .map_err(|err| {
                 error!(?err, "Commit and push for \"{message}\" errored");
@@ -276,13 +370,18 @@ impl Repository {
             })
     }
 
+    /// Returns the configured mirrors of the index.
+    pub fn mirrors(&self) -> &[MirrorConfig] {
+        &self.mirrors
+    }
+
     /// Fetches any changes from the `origin` remote and performs a hard reset
     /// to the tip of the `origin/master` branch.
     #[instrument(skip_all)]
     pub fn reset_head(&self) -> anyhow::Result<()> {
         let original_head = self.head_oid()?;
 
-        self.run_command(Command::new("git").args(["fetch", "origin", "master"]))?;
+        self.run_remote_command(Command::new("git").args(["fetch", "origin", "master"]))?;
         self.run_command(Command::new("git").args(["reset", "--hard", "origin/master"]))?;
 
         let head = self.head_oid()?;
@@ -323,6 +422,16 @@ impl Repository {
 
         run_via_cli(command, &self.credentials)
     }
+
+    /// Runs the specified `git` command that talks to the `origin` remote
+    /// like [Self::run_command], but kills it once it has taken longer than
+    /// [REMOTE_TIMEOUT].
+    fn run_remote_command(&self, command: &mut Command) -> anyhow::Result<()> {
+        let checkout_path = self.checkout_path.path();
+        command.current_dir(checkout_path);
+
+        run_via_cli_with_timeout(command, &self.credentials, Some(REMOTE_TIMEOUT)).map(|_| ())
+    }
 }
 
 /// Runs the specified `git` command through the `git` CLI.
@@ -331,6 +440,26 @@ impl Repository {
 /// variable to ensure that `git push` commands are able to succeed.
 #[instrument(skip_all)]
 pub fn run_via_cli(command: &mut Command, credentials: &Credentials) -> anyhow::Result<()> {
+    run_via_cli_with_output(command, credentials).map(|_| ())
+}
+
+/// Runs the specified `git` command through the `git` CLI like [run_via_cli],
+/// and returns its `stdout` output.
+fn run_via_cli_with_output(
+    command: &mut Command,
+    credentials: &Credentials,
+) -> anyhow::Result<String> {
+    run_via_cli_with_timeout(command, credentials, None)
+}
+
+/// Runs the specified `git` command through the `git` CLI like
+/// [run_via_cli_with_output], and kills it if it has not finished within
+/// the given `timeout`.
+fn run_via_cli_with_timeout(
+    command: &mut Command,
+    credentials: &Credentials,
+    timeout: Option<Duration>,
+) -> anyhow::Result<String> {
     let temp_key_path = credentials
         .ssh_key()
         .map(|_| credentials.write_temporary_ssh_key())
@@ -344,7 +473,11 @@ pub fn run_via_cli(command: &mut Command, credentials: &Credentials) -> anyhow::
     }
 
     debug!(?command);
-    let output = command.output()?;
+    let output = match timeout {
+        Some(timeout) => output_with_timeout(command, timeout)?,
+        None => command.output()?,
+    };
+
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -355,5 +488,78 @@ pub fn run_via_cli(command: &mut Command, credentials: &Credentials) -> anyhow::
         ));
     }
 
-    Ok(())
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs the command like [Command::output], but kills the process if it has
+/// not exited within the given `timeout`.
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> anyhow::Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // The pipes are drained in the background, so that the process can't
+    // block on a full pipe buffer while we are waiting for it to exit.
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+
+    let stdout = read_pipe(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read_pipe(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            warn!(?timeout, "git command timed out, killing it…");
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("Running git command timed out after {timeout:?}"));
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn test_output_with_timeout() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo foo"]);
+        let output = output_with_timeout(&mut command, Duration::from_secs(10)).unwrap();
+        assert!(output.status.success());
+        assert_ok_eq!(String::from_utf8(output.stdout), "foo\n");
+
+        let started_at = Instant::now();
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        assert_err!(output_with_timeout(
+            &mut command,
+            Duration::from_millis(100)
+        ));
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+    }
 }
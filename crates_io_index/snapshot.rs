@@ -0,0 +1,144 @@
+use crate::credentials::Credentials;
+use crate::repo::{run_via_cli, MirrorConfig, Repository};
+use anyhow::{anyhow, Context};
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+/// A read-only copy of the crate index at a fixed commit.
+///
+/// Snapshots are created via [Repository::snapshot] and are independent of
+/// the [Repository] they were created from, so that long-running read-only
+/// work does not have to hold on to the primary checkout while new commits
+/// are being pushed.
+pub struct RepositorySnapshot {
+    /// Removes the snapshot from disk once it is dropped.
+    _checkout_path: TempDir,
+    repository: git2::Repository,
+    head: git2::Oid,
+}
+
+impl RepositorySnapshot {
+    /// Creates a bare local clone of the repository at `source`, pinned to
+    /// the `head` commit.
+    ///
+    /// Local clones hardlink the object files instead of copying them, so
+    /// this is cheap even for large repositories.
+    #[instrument(skip_all, fields(%head))]
+    pub(crate) fn create(source: &Path, head: git2::Oid) -> anyhow::Result<Self> {
+        let checkout_path = tempfile::Builder::new()
+            .prefix("git-snapshot")
+            .tempdir()
+            .context("Failed to create temporary directory")?;
+
+        let mut command = Command::new("git");
+        command
+            .args(["clone", "--bare", "--local", "--quiet"])
+            .arg(source)
+            .arg(checkout_path.path());
+
+        run_via_cli(&mut command, &Credentials::Missing)
+            .context("Failed to create index snapshot")?;
+
+        let repository = git2::Repository::open_bare(checkout_path.path())
+            .context("Failed to open index snapshot")?;
+
+        Ok(Self {
+            _checkout_path: checkout_path,
+            repository,
+            head,
+        })
+    }
+
+    /// Returns the [Object ID](git2::Oid) of the commit that this snapshot
+    /// was taken at.
+    pub fn head_oid(&self) -> git2::Oid {
+        self.head
+    }
+
+    fn head_tree(&self) -> anyhow::Result<git2::Tree<'_>> {
+        let commit = self
+            .repository
+            .find_commit(self.head)
+            .context("Failed to find snapshot commit")?;
+
+        commit.tree().context("Failed to find snapshot tree")
+    }
+
+    /// Returns the (lowercase) names of all crates that have an index file
+    /// in this snapshot.
+    ///
+    /// Files that are not located at the expected path for their name (e.g.
+    /// `config.json`) are ignored.
+    pub fn crate_names(&self) -> anyhow::Result<Vec<String>> {
+        let tree = self.head_tree()?;
+
+        let mut names = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() != Some(git2::ObjectType::Blob) {
+                return git2::TreeWalkResult::Ok;
+            }
+
+            if let Some(name) = entry.name() {
+                let path = format!("{dir}{name}");
+                if Repository::relative_index_file_for_url(name) == path {
+                    names.push(name.to_string());
+                }
+            }
+
+            git2::TreeWalkResult::Ok
+        })
+        .context("Failed to walk snapshot tree")?;
+
+        Ok(names)
+    }
+
+    /// Returns the content of the index file of the given crate, or `None`
+    /// if the file does not exist in this snapshot.
+    pub fn read_index_file(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let tree = self.head_tree()?;
+
+        let path = Repository::relative_index_file(name);
+        let entry = match tree.get_path(&path) {
+            Ok(entry) => entry,
+            Err(error) if error.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        let object = entry.to_object(&self.repository)?;
+        let blob = object
+            .as_blob()
+            .ok_or_else(|| anyhow!("Index file of `{name}` is not a blob"))?;
+
+        Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+    }
+
+    /// Force-pushes the commit of this snapshot to the `master` branch of the
+    /// given mirror.
+    ///
+    /// The push is forced since the mirrors are supposed to be exact copies
+    /// of the primary repository, which might have been squashed in the
+    /// meantime.
+    #[instrument(skip_all, fields(mirror = %mirror.name, head = %self.head))]
+    pub fn push_to_mirror(&self, mirror: &MirrorConfig) -> anyhow::Result<()> {
+        let refspec = format!("{}:refs/heads/master", self.head);
+
+        let mut command = Command::new("git");
+        command
+            .args(["push", "--force", mirror.url.as_str(), &refspec])
+            .current_dir(self.repository.path());
+
+        run_via_cli(&mut command, &mirror.credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<RepositorySnapshot>();
+    }
+}
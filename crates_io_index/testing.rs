@@ -96,14 +96,44 @@ impl UpstreamIndex {
         let tree = head.peel_to_tree()?;
         let blob = tree.get_path(&path)?.to_object(&repo)?.peel_to_blob()?;
 
-        let content = blob.content();
+        let content = std::str::from_utf8(blob.content())?;
+        Ok(crate::read_crates(content)?)
+    }
+
+    /// Commits the given content as the index file of the given crate,
+    /// bypassing the regular index sync jobs.
+    pub fn write_index_file(&self, crate_name: &str, content: &str) -> anyhow::Result<()> {
+        let repo = self.repository.lock().unwrap();
 
-        // The index format consists of one JSON object per line
-        // It is not a JSON array
-        let lines = std::str::from_utf8(content)?.lines();
-        let versions = lines.map(serde_json::from_str).collect::<Result<_, _>>()?;
+        let path = crate::Repository::relative_index_file_for_url(crate_name);
+        let blob = repo.blob(content.as_bytes())?;
 
-        Ok(versions)
+        let head = repo.head()?;
+        let parent = head.peel_to_commit()?;
+
+        let mut index = git2::Index::new()?;
+        index.read_tree(&parent.tree()?)?;
+        index.add(&git2::IndexEntry {
+            ctime: git2::IndexTime::new(0, 0),
+            mtime: git2::IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: content.len() as u32,
+            id: blob,
+            flags: 0,
+            flags_extended: 0,
+            path: path.into_bytes(),
+        })?;
+
+        let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+        let sig = repo.signature()?;
+        let message = format!("Write index file of `{crate_name}`");
+        repo.commit(Some("HEAD"), &sig, &sig, &message, &tree, &[&parent])?;
+
+        Ok(())
     }
 
     pub fn create_empty_commit(&self) -> anyhow::Result<()> {
@@ -1,10 +1,11 @@
 use crate::errors::EnqueueError;
-use crate::schema::background_jobs;
-use diesel::prelude::*;
-use diesel::PgConnection;
+use crate::storage;
+use chrono::{DateTime, Utc};
+use diesel::{Connection, PgConnection};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::future::Future;
+use std::time::Duration;
 use tracing::instrument;
 
 pub const DEFAULT_QUEUE: &str = "default";
@@ -23,6 +24,14 @@ pub trait BackgroundJob: Serialize + DeserializeOwned + Send + Sync + 'static {
     /// Job queue where this job will be executed.
     const QUEUE: &'static str = DEFAULT_QUEUE;
 
+    /// Maximum duration that a single execution of the task may take.
+    ///
+    /// If the task takes longer than this it is aborted, its
+    /// [cancellation token](crate::cancellation_token) is cancelled and the
+    /// job is marked as failed, so that it will be retried later.
+    /// `None` means that the task may run indefinitely.
+    const TIMEOUT: Option<Duration> = None;
+
     /// The application data provided to this job at runtime.
     type Context: Clone + Send + 'static;
 
@@ -40,14 +49,64 @@ pub trait BackgroundJob: Serialize + DeserializeOwned + Send + Sync + 'static {
         job_priority: i16,
     ) -> Result<i64, EnqueueError> {
         let job_data = serde_json::to_value(self)?;
-        let id = diesel::insert_into(background_jobs::table)
-            .values((
-                background_jobs::job_type.eq(Self::JOB_NAME),
-                background_jobs::data.eq(job_data),
-                background_jobs::priority.eq(job_priority),
-            ))
-            .returning(background_jobs::id)
-            .get_result(conn)?;
+        let id = storage::insert_job(conn, Self::JOB_NAME, job_data, job_priority, None)?;
         Ok(id)
     }
+
+    /// Enqueue the task, but do not run it before the given point in time.
+    #[instrument(name = "swirl.enqueue", skip(self, conn), fields(message = Self::JOB_NAME))]
+    fn enqueue_at(
+        &self,
+        conn: &mut PgConnection,
+        run_at: DateTime<Utc>,
+    ) -> Result<i64, EnqueueError> {
+        let job_data = serde_json::to_value(self)?;
+        let run_at = Some(run_at.naive_utc());
+        let id = storage::insert_job(conn, Self::JOB_NAME, job_data, Self::PRIORITY, run_at)?;
+        Ok(id)
+    }
+
+    /// Enqueue the task, but do not run it before the given `delay` has passed.
+    fn enqueue_after(&self, conn: &mut PgConnection, delay: Duration) -> Result<i64, EnqueueError> {
+        let delay = chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::max_value());
+        let run_at = Utc::now()
+            .checked_add_signed(delay)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        self.enqueue_at(conn, run_at)
+    }
+
+    /// Enqueue the task, but do not run it before all of the jobs with the
+    /// given IDs have completed successfully.
+    ///
+    /// Dependencies that have already completed are ignored. Note that the
+    /// task will never run if any of the dependencies keeps failing.
+    fn enqueue_with_dependencies(
+        &self,
+        conn: &mut PgConnection,
+        dependencies: &[i64],
+    ) -> Result<i64, EnqueueError> {
+        conn.transaction(|conn| {
+            let id = self.enqueue(conn)?;
+            storage::add_dependencies(conn, id, dependencies)?;
+            Ok(id)
+        })
+    }
+
+    /// Enqueue the task as an additional dependency of the job with the given
+    /// ID, which will then not run before this task has completed successfully.
+    ///
+    /// The dependent job must not be running yet, which is usually ensured by
+    /// letting it depend on the [current job](crate::current_job_id) too.
+    fn enqueue_as_dependency_of(
+        &self,
+        conn: &mut PgConnection,
+        job_id: i64,
+    ) -> Result<i64, EnqueueError> {
+        conn.transaction(|conn| {
+            let id = self.enqueue(conn)?;
+            storage::add_dependencies(conn, job_id, &[id])?;
+            Ok(id)
+        })
+    }
 }
@@ -0,0 +1,30 @@
+use std::future::Future;
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    static CANCELLATION_TOKEN: CancellationToken;
+}
+
+/// Returns the [CancellationToken] of the currently running job.
+///
+/// The token is cancelled once the job has exceeded its
+/// [`TIMEOUT`](crate::BackgroundJob::TIMEOUT), or when the job future is
+/// dropped for any other reason. Jobs that perform long-running blocking work
+/// (e.g. via `spawn_blocking()`) should retrieve the token before moving into
+/// the blocking context and check it between individual steps, since dropping
+/// the job future does not stop such work.
+///
+/// Outside of a running job this returns a token that is never cancelled.
+pub fn cancellation_token() -> CancellationToken {
+    CANCELLATION_TOKEN
+        .try_with(Clone::clone)
+        .unwrap_or_default()
+}
+
+/// Runs the given future with `token` available via [cancellation_token].
+pub(crate) async fn with_cancellation_token<F: Future>(
+    token: CancellationToken,
+    future: F,
+) -> F::Output {
+    CANCELLATION_TOKEN.scope(token, future).await
+}
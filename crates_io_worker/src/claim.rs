@@ -0,0 +1,60 @@
+use crate::storage;
+use crate::BackgroundJob;
+use diesel::pg::data_types::PgInterval;
+use diesel::prelude::*;
+use std::time::Duration;
+use tracing::instrument;
+
+/// Claims up to `limit` pending jobs of type `J` that would otherwise be run
+/// by a worker right now, so that the current job can process them instead,
+/// e.g. as part of a single batch. Returns the IDs and data of the claimed
+/// jobs.
+///
+/// Jobs that are locked by another worker, scheduled for later, still
+/// waiting for dependencies, or backing off after a failure are skipped.
+///
+/// Instead of keeping the rows locked until the current job has finished,
+/// the claimed jobs are rescheduled to run after the `lease` has expired, so
+/// the `lease` needs to be longer than the [TIMEOUT](BackgroundJob::TIMEOUT)
+/// of the current job. Once they have been processed, the jobs should be
+/// removed with [complete_claimed_jobs], or made runnable again with
+/// [release_claimed_jobs] if processing them failed. If the worker dies
+/// before either happens, they are picked up again once the lease has
+/// expired.
+#[instrument(name = "swirl.claim", skip(conn), fields(message = J::JOB_NAME))]
+pub fn claim_jobs<J: BackgroundJob>(
+    conn: &mut PgConnection,
+    limit: usize,
+    lease: Duration,
+) -> anyhow::Result<Vec<(i64, J)>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let lease = PgInterval::from_microseconds(lease.as_micros().try_into()?);
+    let jobs = storage::claim_jobs(conn, J::JOB_NAME, limit.try_into()?, lease)?;
+
+    jobs.into_iter()
+        .map(|job| Ok((job.id, serde_json::from_value(job.data)?)))
+        .collect()
+}
+
+/// Removes the given jobs claimed via [claim_jobs] from the queue, since they
+/// have been completed by the current job.
+pub fn complete_claimed_jobs(conn: &mut PgConnection, job_ids: &[i64]) -> QueryResult<()> {
+    if job_ids.is_empty() {
+        return Ok(());
+    }
+
+    storage::delete_jobs(conn, job_ids)
+}
+
+/// Makes the given jobs claimed via [claim_jobs] runnable again, so that they
+/// don't have to wait for their lease to expire.
+pub fn release_claimed_jobs(conn: &mut PgConnection, job_ids: &[i64]) -> QueryResult<()> {
+    if job_ids.is_empty() {
+        return Ok(());
+    }
+
+    storage::release_jobs(conn, job_ids)
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// An error occurred queueing the job
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -10,3 +12,13 @@ pub enum EnqueueError {
     #[error(transparent)]
     DatabaseError(#[from] diesel::result::Error),
 }
+
+/// The job panicked while it was running
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub(crate) struct PanicError(pub(crate) String);
+
+/// The job did not finish within its [`TIMEOUT`](crate::BackgroundJob::TIMEOUT)
+#[derive(Debug, thiserror::Error)]
+#[error("job timed out after {0:?}")]
+pub(crate) struct TimeoutError(pub(crate) Duration);
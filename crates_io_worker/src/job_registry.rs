@@ -1,8 +1,11 @@
+use crate::cancellation::with_cancellation_token;
+use crate::errors::TimeoutError;
 use crate::BackgroundJob;
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 type RunTaskFnReturn = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
 type RunTaskFn<Context> = dyn Fn(Context, serde_json::Value) -> RunTaskFnReturn + Send + Sync;
@@ -39,7 +42,19 @@ impl<Context: Clone + Send + Sync + 'static> JobRegistry<Context> {
 fn runnable<J: BackgroundJob>(ctx: J::Context, payload: serde_json::Value) -> RunTaskFnReturn {
     Box::pin(async move {
         let job: J = serde_json::from_value(payload)?;
-        job.run(ctx).await
+
+        // The guard cancels the token once the job has finished, timed out
+        // or panicked, so that any leftover blocking work can stop early.
+        let token = CancellationToken::new();
+        let _guard = token.clone().drop_guard();
+
+        let future = with_cancellation_token(token, job.run(ctx));
+        match J::TIMEOUT {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .map_err(|_| TimeoutError(timeout))?,
+            None => future.await,
+        }
     })
 }
 
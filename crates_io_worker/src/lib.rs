@@ -1,12 +1,21 @@
 mod background_job;
+mod cancellation;
+mod claim;
 mod errors;
 mod job_registry;
+mod metrics;
 mod runner;
 pub mod schema;
 mod storage;
 mod util;
 mod worker;
+mod workflow;
 
 pub use self::background_job::BackgroundJob;
+pub use self::cancellation::cancellation_token;
+pub use self::claim::{claim_jobs, complete_claimed_jobs, release_claimed_jobs};
 pub use self::errors::EnqueueError;
+pub use self::metrics::WorkerMetrics;
 pub use self::runner::Runner;
+pub use self::workflow::current_job_id;
+pub use tokio_util::sync::CancellationToken;
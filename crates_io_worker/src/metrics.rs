@@ -0,0 +1,117 @@
+use prometheus::proto::MetricFamily;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use std::time::Duration;
+
+const NAMESPACE: &str = "cratesio_worker";
+
+/// Background jobs take anywhere from a few milliseconds (e.g. syncing a
+/// single sparse index file) to more than an hour (e.g. the database dump),
+/// so the buckets are spread out much wider than the ones used for the
+/// response times of the web server.
+const HISTOGRAM_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0,
+];
+
+/// The outcome of a single job execution, used as the `outcome` label of the
+/// [WorkerMetrics::job_outcomes] counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobOutcome {
+    Success,
+    Failure,
+    Panic,
+    Timeout,
+}
+
+impl JobOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobOutcome::Success => "success",
+            JobOutcome::Failure => "failure",
+            JobOutcome::Panic => "panic",
+            JobOutcome::Timeout => "timeout",
+        }
+    }
+}
+
+/// Instance-level metrics of the background workers of a [Runner](crate::Runner).
+///
+/// The metrics are updated by the workers whenever a job is run, and can be
+/// exported by calling [WorkerMetrics::gather].
+#[derive(Clone)]
+pub struct WorkerMetrics {
+    registry: Registry,
+    /// Time it took to execute a job, per job type
+    pub job_duration: HistogramVec,
+    /// Number of executed jobs, per job type and outcome
+    pub job_outcomes: IntCounterVec,
+    /// Time a job waited in the queue before it was picked up, per job type
+    pub job_queue_time: HistogramVec,
+}
+
+impl WorkerMetrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+
+        let job_duration = HistogramVec::new(
+            histogram_opts("job_duration_seconds", "Time it took to execute a job"),
+            &["job"],
+        )?;
+        registry.register(Box::new(job_duration.clone()))?;
+
+        let job_outcomes = IntCounterVec::new(
+            Opts::new("job_outcomes_total", "Number of executed jobs by outcome")
+                .namespace(NAMESPACE),
+            &["job", "outcome"],
+        )?;
+        registry.register(Box::new(job_outcomes.clone()))?;
+
+        let job_queue_time = HistogramVec::new(
+            histogram_opts(
+                "job_queue_time_seconds",
+                "Time a job waited in the queue before it was started",
+            ),
+            &["job"],
+        )?;
+        registry.register(Box::new(job_queue_time.clone()))?;
+
+        Ok(Self {
+            registry,
+            job_duration,
+            job_outcomes,
+            job_queue_time,
+        })
+    }
+
+    /// Returns the current values of all metrics.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+
+    pub(crate) fn observe_queue_time(&self, job_type: &str, queue_time: Duration) {
+        self.job_queue_time
+            .with_label_values(&[job_type])
+            .observe(queue_time.as_secs_f64());
+    }
+
+    pub(crate) fn observe_job(&self, job_type: &str, duration: Duration, outcome: JobOutcome) {
+        self.job_duration
+            .with_label_values(&[job_type])
+            .observe(duration.as_secs_f64());
+
+        self.job_outcomes
+            .with_label_values(&[job_type, outcome.as_str()])
+            .inc();
+    }
+}
+
+impl std::fmt::Debug for WorkerMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WorkerMetrics")
+    }
+}
+
+fn histogram_opts(name: &str, help: &str) -> HistogramOpts {
+    HistogramOpts::new(name, help)
+        .namespace(NAMESPACE)
+        .buckets(HISTOGRAM_BUCKETS.to_vec())
+}
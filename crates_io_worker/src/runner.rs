@@ -1,6 +1,7 @@
 use crate::background_job::DEFAULT_QUEUE;
 use crate::job_registry::JobRegistry;
 use crate::worker::Worker;
+use crate::WorkerMetrics;
 use crate::{storage, BackgroundJob};
 use anyhow::anyhow;
 use diesel::prelude::*;
@@ -11,9 +12,12 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Handle;
 use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, info_span, warn, Instrument};
 
 const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(25);
 
 pub type ConnectionPool = Pool<ConnectionManager<PgConnection>>;
 pub type PooledConn = PooledConnection<ConnectionManager<PgConnection>>;
@@ -25,6 +29,8 @@ pub struct Runner<Context> {
     queues: HashMap<String, Queue<Context>>,
     context: Context,
     shutdown_when_queue_empty: bool,
+    shutdown_grace_period: Duration,
+    metrics: WorkerMetrics,
 }
 
 impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
@@ -35,6 +41,8 @@ impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
             queues: HashMap::new(),
             context,
             shutdown_when_queue_empty: false,
+            shutdown_grace_period: DEFAULT_SHUTDOWN_GRACE_PERIOD,
+            metrics: WorkerMetrics::new().expect("Failed to initialize worker metrics"),
         }
     }
 
@@ -69,10 +77,23 @@ impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
         self
     }
 
+    /// Set the maximum duration that running jobs are given to finish after
+    /// [RunHandle::shutdown] has been called.
+    ///
+    /// Jobs that are still running after this duration are aborted and
+    /// released, so that they can be picked up again by another process.
+    pub fn shutdown_grace_period(mut self, grace_period: Duration) -> Self {
+        self.shutdown_grace_period = grace_period;
+        self
+    }
+
     /// Start the background workers.
     ///
     /// This returns a `RunningRunner` which can be used to wait for the workers to shutdown.
     pub fn start(&self) -> RunHandle {
+        let shutdown = CancellationToken::new();
+        let abort = CancellationToken::new();
+
         let mut handles = Vec::new();
         for (queue_name, queue) in &self.queues {
             for i in 1..=queue.num_workers {
@@ -85,6 +106,9 @@ impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
                     job_registry: Arc::new(queue.job_registry.clone()),
                     shutdown_when_queue_empty: self.shutdown_when_queue_empty,
                     poll_interval: queue.poll_interval,
+                    metrics: self.metrics.clone(),
+                    shutdown: shutdown.clone(),
+                    abort: abort.clone(),
                 };
 
                 let span = info_span!("worker", worker.name = %name);
@@ -96,7 +120,17 @@ impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
             }
         }
 
-        RunHandle { handles }
+        RunHandle {
+            handles,
+            shutdown,
+            abort,
+            shutdown_grace_period: self.shutdown_grace_period,
+        }
+    }
+
+    /// Returns the metrics that are recorded by the background workers.
+    pub fn metrics(&self) -> &WorkerMetrics {
+        &self.metrics
     }
 
     pub fn connection(&self) -> Result<PooledConn, PoolError> {
@@ -119,16 +153,42 @@ impl<Context: Clone + Send + Sync + 'static> Runner<Context> {
 
 pub struct RunHandle {
     handles: Vec<JoinHandle<()>>,
+    shutdown: CancellationToken,
+    abort: CancellationToken,
+    shutdown_grace_period: Duration,
 }
 
 impl RunHandle {
+    /// Signal all background workers to stop picking up new jobs.
+    ///
+    /// Jobs that are currently running are given the configured
+    /// [grace period](Runner::shutdown_grace_period) to finish. After that
+    /// they are aborted, and their database transactions are rolled back so
+    /// that the jobs can be picked up again by another process.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
     /// Wait for all background workers to shut down.
     pub async fn wait_for_shutdown(self) {
+        let shutdown = self.shutdown.clone();
+        let abort = self.abort.clone();
+        let grace_period = self.shutdown_grace_period;
+        let abort_after_grace_period = tokio::spawn(async move {
+            shutdown.cancelled().await;
+            sleep(grace_period).await;
+
+            warn!("Shutdown grace period has elapsed. Aborting running jobs…");
+            abort.cancel();
+        });
+
         join_all(self.handles).await.into_iter().for_each(|result| {
             if let Err(error) = result {
                 warn!(%error, "Background worker task panicked");
             }
         });
+
+        abort_after_grace_period.abort();
     }
 }
 
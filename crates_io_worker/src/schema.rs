@@ -1,3 +1,10 @@
+diesel::table! {
+    background_job_dependencies (job_id, dependency_id) {
+        job_id -> Int8,
+        dependency_id -> Int8,
+    }
+}
+
 diesel::table! {
     background_jobs (id) {
         id -> Int8,
@@ -7,5 +14,8 @@ diesel::table! {
         last_retry -> Timestamp,
         created_at -> Timestamp,
         priority -> Int2,
+        run_at -> Timestamp,
     }
 }
+
+diesel::allow_tables_to_appear_in_same_query!(background_job_dependencies, background_jobs);
@@ -1,8 +1,10 @@
-use crate::schema::background_jobs;
-use diesel::dsl::now;
+use crate::schema::{background_job_dependencies, background_jobs};
+use chrono::NaiveDateTime;
+use diesel::dsl::{exists, not, now};
+use diesel::pg::data_types::PgInterval;
 use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::sql_types::{Bool, Integer, Interval};
+use diesel::sql_types::{BigInt, Bool, Integer, Interval};
 use diesel::{delete, update};
 
 #[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
@@ -10,8 +12,11 @@ pub(super) struct BackgroundJob {
     pub(super) id: i64,
     pub(super) job_type: String,
     pub(super) data: serde_json::Value,
+    pub(super) run_at: NaiveDateTime,
 }
 
+/// Returns a filter expression for jobs that have either never failed, or
+/// whose exponential backoff since the last failed attempt has expired.
 fn retriable() -> Box<dyn BoxableExpression<background_jobs::table, Pg, SqlType = Bool>> {
     use diesel::dsl::*;
 
@@ -23,8 +28,28 @@ fn retriable() -> Box<dyn BoxableExpression<background_jobs::table, Pg, SqlType
     )
 }
 
-/// Finds the next job that is unlocked, and ready to be retried. If a row is
-/// found, it will be locked.
+/// Returns a filter expression for jobs that are scheduled to run, have no
+/// pending dependencies, and are ready to be retried.
+fn runnable() -> Box<dyn BoxableExpression<background_jobs::table, Pg, SqlType = Bool>> {
+    let dependencies = background_job_dependencies::table
+        .filter(background_job_dependencies::job_id.eq(background_jobs::id));
+
+    Box::new(
+        background_jobs::run_at
+            .le(now)
+            .and(not(exists(dependencies)))
+            .and(retriable()),
+    )
+}
+
+/// Finds the next job that is unlocked, scheduled to run, has no pending
+/// dependencies, and is ready to be retried. If a row is found, it will be
+/// locked.
+///
+/// The row is locked with `FOR NO KEY UPDATE` instead of `FOR UPDATE`, so that
+/// the running job can still enqueue other jobs that depend on it. The foreign
+/// key checks of these inserts would otherwise block until the job has
+/// finished.
 pub(super) fn find_next_unlocked_job(
     conn: &mut PgConnection,
     job_types: &[String],
@@ -32,13 +57,99 @@ pub(super) fn find_next_unlocked_job(
     background_jobs::table
         .select(BackgroundJob::as_select())
         .filter(background_jobs::job_type.eq_any(job_types))
-        .filter(retriable())
+        .filter(runnable())
         .order((background_jobs::priority.desc(), background_jobs::id))
-        .for_update()
+        .for_no_key_update()
         .skip_locked()
         .first::<BackgroundJob>(conn)
 }
 
+/// Finds up to `limit` unlocked jobs of the given type that could run right
+/// now, like [find_next_unlocked_job], and reschedules them to run after the
+/// `lease` has expired.
+pub(super) fn claim_jobs(
+    conn: &mut PgConnection,
+    job_type: &str,
+    limit: i64,
+    lease: PgInterval,
+) -> QueryResult<Vec<BackgroundJob>> {
+    conn.transaction(|conn| {
+        let jobs = background_jobs::table
+            .select(BackgroundJob::as_select())
+            .filter(background_jobs::job_type.eq(job_type))
+            .filter(runnable())
+            .order((background_jobs::priority.desc(), background_jobs::id))
+            .limit(limit)
+            .for_no_key_update()
+            .skip_locked()
+            .load::<BackgroundJob>(conn)?;
+
+        let ids = jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+        update(background_jobs::table)
+            .filter(background_jobs::id.eq_any(ids))
+            .set(background_jobs::run_at.eq(now + lease.into_sql::<Interval>()))
+            .execute(conn)?;
+
+        Ok(jobs)
+    })
+}
+
+/// Makes the given jobs runnable again right away.
+pub(super) fn release_jobs(conn: &mut PgConnection, job_ids: &[i64]) -> QueryResult<()> {
+    update(background_jobs::table)
+        .filter(background_jobs::id.eq_any(job_ids))
+        .set(background_jobs::run_at.eq(now))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Inserts a new job and returns its ID. Without a `run_at` time, the job
+/// is scheduled to run immediately.
+pub(super) fn insert_job(
+    conn: &mut PgConnection,
+    job_type: &str,
+    data: serde_json::Value,
+    priority: i16,
+    run_at: Option<NaiveDateTime>,
+) -> QueryResult<i64> {
+    diesel::insert_into(background_jobs::table)
+        .values((
+            background_jobs::job_type.eq(job_type),
+            background_jobs::data.eq(data),
+            background_jobs::priority.eq(priority),
+            run_at.map(|run_at| background_jobs::run_at.eq(run_at)),
+        ))
+        .returning(background_jobs::id)
+        .get_result(conn)
+}
+
+/// Registers the jobs with the `dependencies` IDs as dependencies of the job
+/// with the `job_id` ID. Dependencies that have already completed are ignored.
+pub(super) fn add_dependencies(
+    conn: &mut PgConnection,
+    job_id: i64,
+    dependencies: &[i64],
+) -> QueryResult<()> {
+    // Lock the dependency rows, so that they can not be deleted between
+    // checking for their existence and inserting the new rows.
+    let existing_dependencies = background_jobs::table
+        .select((job_id.into_sql::<BigInt>(), background_jobs::id))
+        .filter(background_jobs::id.eq_any(dependencies))
+        .for_key_share();
+
+    diesel::insert_into(background_job_dependencies::table)
+        .values(existing_dependencies)
+        .into_columns((
+            background_job_dependencies::job_id,
+            background_job_dependencies::dependency_id,
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    Ok(())
+}
+
 /// The number of jobs that have failed at least once
 pub(super) fn failed_job_count(conn: &mut PgConnection) -> QueryResult<i64> {
     background_jobs::table
@@ -53,6 +164,12 @@ pub(super) fn delete_successful_job(conn: &mut PgConnection, job_id: i64) -> Que
     Ok(())
 }
 
+/// Deletes the given jobs, which have been completed by another job
+pub(super) fn delete_jobs(conn: &mut PgConnection, job_ids: &[i64]) -> QueryResult<()> {
+    delete(background_jobs::table.filter(background_jobs::id.eq_any(job_ids))).execute(conn)?;
+    Ok(())
+}
+
 /// Marks that we just tried and failed to run a job.
 ///
 /// Ignores any database errors that may have occurred. If the DB has gone away,
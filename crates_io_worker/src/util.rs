@@ -1,4 +1,5 @@
-use anyhow::anyhow;
+use crate::errors::{PanicError, TimeoutError};
+use sentry_core::protocol::SpanStatus;
 use sentry_core::Hub;
 use std::any::Any;
 use std::future::Future;
@@ -21,13 +22,13 @@ where
         .and_then(std::convert::identity)
 }
 
-pub async fn with_sentry_transaction<F, R, E, Fut>(
+pub async fn with_sentry_transaction<F, R, Fut>(
     transaction_name: &str,
     callback: F,
-) -> Result<R, E>
+) -> anyhow::Result<R>
 where
     F: FnOnce() -> Fut,
-    Fut: Future<Output = Result<R, E>>,
+    Fut: Future<Output = anyhow::Result<R>>,
 {
     let hub = Hub::new_from_top(Hub::current());
     let _scope_guard = hub.push_scope();
@@ -39,9 +40,10 @@ where
 
     let result = callback().await;
 
-    tx.set_status(match result.is_ok() {
-        true => sentry_core::protocol::SpanStatus::Ok,
-        false => sentry_core::protocol::SpanStatus::UnknownError,
+    tx.set_status(match &result {
+        Ok(_) => SpanStatus::Ok,
+        Err(error) if error.is::<TimeoutError>() => SpanStatus::DeadlineExceeded,
+        Err(_) => SpanStatus::UnknownError,
     });
     tx.finish();
 
@@ -55,13 +57,15 @@ where
 /// documented as "commonly but not always `&'static str` or `String`". So we can try all of those,
 /// and give up if we didn't get one of those three types.
 pub fn try_to_extract_panic_info(info: &(dyn Any + Send + 'static)) -> anyhow::Error {
-    if let Some(x) = info.downcast_ref::<PanicInfo<'_>>() {
-        anyhow!("job panicked: {x}")
+    let message = if let Some(x) = info.downcast_ref::<PanicInfo<'_>>() {
+        format!("job panicked: {x}")
     } else if let Some(x) = info.downcast_ref::<&'static str>() {
-        anyhow!("job panicked: {x}")
+        format!("job panicked: {x}")
     } else if let Some(x) = info.downcast_ref::<String>() {
-        anyhow!("job panicked: {x}")
+        format!("job panicked: {x}")
     } else {
-        anyhow!("job panicked")
-    }
+        "job panicked".to_string()
+    };
+
+    PanicError(message).into()
 }
@@ -1,16 +1,21 @@
+use crate::errors::{PanicError, TimeoutError};
 use crate::job_registry::JobRegistry;
+use crate::metrics::JobOutcome;
 use crate::runner::ConnectionPool;
-use crate::storage;
 use crate::util::{spawn_blocking, try_to_extract_panic_info, with_sentry_transaction};
+use crate::workflow::with_current_job_id;
+use crate::{storage, WorkerMetrics};
 use anyhow::anyhow;
+use chrono::Utc;
 use diesel::prelude::*;
 use futures_util::FutureExt;
 use sentry_core::{Hub, SentryFutureExt};
 use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info_span, warn};
 
 pub struct Worker<Context> {
@@ -19,12 +24,23 @@ pub struct Worker<Context> {
     pub(crate) job_registry: Arc<JobRegistry<Context>>,
     pub(crate) shutdown_when_queue_empty: bool,
     pub(crate) poll_interval: Duration,
+    pub(crate) metrics: WorkerMetrics,
+    pub(crate) shutdown: CancellationToken,
+    pub(crate) abort: CancellationToken,
 }
 
 impl<Context: Clone + Send + Sync + 'static> Worker<Context> {
     /// Run background jobs forever, or until the queue is empty if `shutdown_when_queue_empty` is set.
+    ///
+    /// The loop is also stopped once a shutdown has been requested via the
+    /// `shutdown` token.
     pub async fn run(&self) {
         loop {
+            if self.shutdown.is_cancelled() {
+                debug!("Shutdown requested. Shutting down the worker…");
+                break;
+            }
+
             match self.run_next_job().await {
                 Ok(Some(_)) => {}
                 Ok(None) if self.shutdown_when_queue_empty => {
@@ -36,16 +52,24 @@ impl<Context: Clone + Send + Sync + 'static> Worker<Context> {
                         "No pending background worker jobs found. Polling again in {:?}…",
                         self.poll_interval
                     );
-                    sleep(self.poll_interval).await;
+                    self.sleep_until_next_poll().await;
                 }
                 Err(error) => {
                     error!(%error, "Failed to run job");
-                    sleep(self.poll_interval).await;
+                    self.sleep_until_next_poll().await;
                 }
             }
         }
     }
 
+    /// Sleep for the configured `poll_interval`, or until a shutdown has been requested.
+    async fn sleep_until_next_poll(&self) {
+        tokio::select! {
+            _ = sleep(self.poll_interval) => {}
+            _ = self.shutdown.cancelled() => {}
+        }
+    }
+
     /// Run the next job in the queue, if there is one.
     ///
     /// Returns:
@@ -56,6 +80,8 @@ impl<Context: Clone + Send + Sync + 'static> Worker<Context> {
         let context = self.context.clone();
         let job_registry = self.job_registry.clone();
         let pool = self.connection_pool.clone();
+        let metrics = self.metrics.clone();
+        let abort = self.abort.clone();
 
         spawn_blocking(move || {
             let job_types = job_registry.job_types();
@@ -71,14 +97,23 @@ impl<Context: Clone + Send + Sync + 'static> Worker<Context> {
                 let _enter = span.enter();
 
                 let job_id = job.id;
+                let job_type = job.job_type.clone();
+
+                // `run_at` defaults to `created_at`, but also accounts for
+                // jobs that were scheduled to run at a later point in time.
+                let queue_time = Utc::now().naive_utc() - job.run_at;
+                metrics.observe_queue_time(&job_type, queue_time.to_std().unwrap_or_default());
+
                 debug!("Running job…");
+                let started_at = Instant::now();
 
                 let future = with_sentry_transaction(&job.job_type, || async {
                     let run_task_fn = job_registry
                         .get(&job.job_type)
                         .ok_or_else(|| anyhow!("Unknown job type {}", job.job_type))?;
 
-                    AssertUnwindSafe(run_task_fn(context, job.data))
+                    let future = with_current_job_id(job_id, run_task_fn(context, job.data));
+                    AssertUnwindSafe(future)
                         .catch_unwind()
                         .await
                         .map_err(|e| try_to_extract_panic_info(&e))
@@ -86,18 +121,50 @@ impl<Context: Clone + Send + Sync + 'static> Worker<Context> {
                         .and_then(std::convert::identity)
                 });
 
-                let result = Handle::current().block_on(future.bind_hub(Hub::current()));
+                let future = future.bind_hub(Hub::current());
+                let result = Handle::current().block_on(async {
+                    tokio::select! {
+                        result = future => Some(result),
+                        _ = abort.cancelled() => None,
+                    }
+                });
+
+                // Returning an error here rolls back the transaction, which
+                // releases the lock on the job without counting it as a
+                // failed attempt, so that another process can pick it up.
+                let Some(result) = result else {
+                    warn!("Job was aborted during shutdown. Releasing it…");
+                    return Err(anyhow!("Job {job_id} was aborted during shutdown"));
+                };
 
-                match result {
+                let duration = started_at.elapsed();
+
+                let outcome = match result {
                     Ok(_) => {
                         debug!("Deleting successful job…");
-                        storage::delete_successful_job(conn, job_id)?
+                        storage::delete_successful_job(conn, job_id)?;
+                        JobOutcome::Success
+                    }
+                    Err(error) if error.is::<TimeoutError>() => {
+                        // Timeouts usually point at a hung external process or
+                        // request, so they are logged as errors instead of the
+                        // warnings for regular failures.
+                        let timeout = error.downcast_ref::<TimeoutError>().map(|error| error.0);
+                        error!(?timeout, "Job exceeded its timeout and was cancelled");
+                        storage::update_failed_job(conn, job_id);
+                        JobOutcome::Timeout
                     }
                     Err(error) => {
                         warn!(%error, "Failed to run job");
                         storage::update_failed_job(conn, job_id);
+                        match error.is::<PanicError>() {
+                            true => JobOutcome::Panic,
+                            false => JobOutcome::Failure,
+                        }
                     }
-                }
+                };
+
+                metrics.observe_job(&job_type, duration, outcome);
 
                 Ok(Some(job_id))
             })
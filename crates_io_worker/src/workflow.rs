@@ -0,0 +1,27 @@
+use std::future::Future;
+
+tokio::task_local! {
+    static CURRENT_JOB_ID: i64;
+}
+
+/// Returns the ID of the currently running job, or `None` when called outside
+/// of a running job.
+///
+/// This can be used to fan out into multiple jobs, with a follow-up job that
+/// only runs once the current job and all of its children have completed:
+///
+/// ```ignore
+/// let parent_id = current_job_id().unwrap();
+/// let follow_up_id = FollowUpJob.enqueue_with_dependencies(conn, &[parent_id])?;
+/// for child in children {
+///     child.enqueue_as_dependency_of(conn, follow_up_id)?;
+/// }
+/// ```
+pub fn current_job_id() -> Option<i64> {
+    CURRENT_JOB_ID.try_with(|id| *id).ok()
+}
+
+/// Runs the given future with `job_id` available via [current_job_id].
+pub(crate) async fn with_current_job_id<F: Future>(job_id: i64, future: F) -> F::Output {
+    CURRENT_JOB_ID.scope(job_id, future).await
+}
@@ -1,12 +1,17 @@
+use chrono::Utc;
 use crates_io_test_db::TestDatabase;
 use crates_io_worker::schema::background_jobs;
-use crates_io_worker::{BackgroundJob, Runner};
+use crates_io_worker::{
+    cancellation_token, claim_jobs, complete_claimed_jobs, current_job_id, release_claimed_jobs,
+    BackgroundJob, Runner,
+};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::runtime::Handle;
-use tokio::sync::Barrier;
+use tokio::sync::{Barrier, Notify};
 
 fn job_exists(id: i64, conn: &mut PgConnection) -> bool {
     background_jobs::table
@@ -206,6 +211,573 @@ async fn panicking_in_jobs_updates_retry_counter() {
     assert_eq!(tries, 1);
 }
 
+#[tokio::test]
+async fn jobs_exceeding_their_timeout_are_cancelled_and_marked_as_failed() {
+    #[derive(Clone)]
+    struct TestContext {
+        cancelled: Arc<Notify>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestJob;
+
+    impl BackgroundJob for TestJob {
+        const JOB_NAME: &'static str = "test";
+        const TIMEOUT: Option<Duration> = Some(Duration::from_millis(100));
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            let token = cancellation_token();
+            tokio::spawn(async move {
+                token.cancelled().await;
+                ctx.cancelled.notify_one();
+            });
+
+            std::future::pending().await
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let test_context = TestContext {
+        cancelled: Arc::new(Notify::new()),
+    };
+
+    let runner = runner(test_database.url(), test_context.clone()).register_job_type::<TestJob>();
+
+    let mut conn = test_database.connect();
+    let job_id = TestJob.enqueue(&mut conn).unwrap();
+
+    let metrics = runner.metrics().clone();
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    let timeouts = metrics.job_outcomes.with_label_values(&["test", "timeout"]);
+    assert_eq!(timeouts.get(), 1);
+
+    let tries = background_jobs::table
+        .find(job_id)
+        .select(background_jobs::retries)
+        .for_update()
+        .first::<i32>(&mut *conn)
+        .unwrap();
+    assert_eq!(tries, 1);
+
+    let cancelled = test_context.cancelled.notified();
+    assert!(tokio::time::timeout(Duration::from_secs(1), cancelled)
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn jobs_are_not_run_before_their_scheduled_time() {
+    #[derive(Serialize, Deserialize)]
+    struct TestJob;
+
+    impl BackgroundJob for TestJob {
+        const JOB_NAME: &'static str = "test";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let runner = runner(test_database.url(), ()).register_job_type::<TestJob>();
+
+    let mut conn = test_database.connect();
+    let delayed_job_id = TestJob
+        .enqueue_after(&mut conn, Duration::from_secs(60 * 60))
+        .unwrap();
+    let scheduled_job_id = TestJob
+        .enqueue_at(&mut conn, Utc::now() - chrono::Duration::seconds(1))
+        .unwrap();
+
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    assert!(job_exists(delayed_job_id, &mut conn));
+    assert!(!job_exists(scheduled_job_id, &mut conn));
+}
+
+#[tokio::test]
+async fn job_outcomes_are_recorded_in_metrics() {
+    #[derive(Serialize, Deserialize)]
+    struct SuccessfulJob;
+
+    impl BackgroundJob for SuccessfulJob {
+        const JOB_NAME: &'static str = "successful";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct FailingJob;
+
+    impl BackgroundJob for FailingJob {
+        const JOB_NAME: &'static str = "failing";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("failed"))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct PanickingJob;
+
+    impl BackgroundJob for PanickingJob {
+        const JOB_NAME: &'static str = "panicking";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            panic!()
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let runner = runner(test_database.url(), ())
+        .register_job_type::<SuccessfulJob>()
+        .register_job_type::<FailingJob>()
+        .register_job_type::<PanickingJob>();
+
+    let mut conn = test_database.connect();
+    SuccessfulJob.enqueue(&mut conn).unwrap();
+    SuccessfulJob.enqueue(&mut conn).unwrap();
+    FailingJob.enqueue(&mut conn).unwrap();
+    PanickingJob.enqueue(&mut conn).unwrap();
+
+    let metrics = runner.metrics().clone();
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    let outcomes = |job, outcome| {
+        metrics
+            .job_outcomes
+            .with_label_values(&[job, outcome])
+            .get()
+    };
+    assert_eq!(outcomes("successful", "success"), 2);
+    assert_eq!(outcomes("successful", "failure"), 0);
+    assert_eq!(outcomes("failing", "failure"), 1);
+    assert_eq!(outcomes("panicking", "panic"), 1);
+
+    let queue_times = |job| {
+        let histogram = metrics.job_queue_time.with_label_values(&[job]);
+        histogram.get_sample_count()
+    };
+    assert_eq!(queue_times("successful"), 2);
+
+    let durations = |job| {
+        let histogram = metrics.job_duration.with_label_values(&[job]);
+        histogram.get_sample_count()
+    };
+    assert_eq!(durations("successful"), 2);
+    assert_eq!(durations("failing"), 1);
+}
+
+#[tokio::test]
+async fn running_jobs_finish_but_no_new_jobs_are_started_after_shutdown() {
+    #[derive(Clone)]
+    struct TestContext {
+        job_started_barrier: Arc<Barrier>,
+        shutdown_requested_barrier: Arc<Barrier>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestJob;
+
+    impl BackgroundJob for TestJob {
+        const JOB_NAME: &'static str = "test";
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            ctx.job_started_barrier.wait().await;
+            ctx.shutdown_requested_barrier.wait().await;
+            Ok(())
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let test_context = TestContext {
+        job_started_barrier: Arc::new(Barrier::new(2)),
+        shutdown_requested_barrier: Arc::new(Barrier::new(2)),
+    };
+
+    let runner = runner(test_database.url(), test_context.clone())
+        .configure_default_queue(|queue| queue.num_workers(1))
+        .register_job_type::<TestJob>();
+
+    let mut conn = test_database.connect();
+    let first_job_id = TestJob.enqueue(&mut conn).unwrap();
+    let second_job_id = TestJob.enqueue(&mut conn).unwrap();
+
+    let runner = runner.start();
+    test_context.job_started_barrier.wait().await;
+
+    runner.shutdown();
+    test_context.shutdown_requested_barrier.wait().await;
+    runner.wait_for_shutdown().await;
+
+    assert!(!job_exists(first_job_id, &mut conn));
+    assert!(job_exists(second_job_id, &mut conn));
+}
+
+#[tokio::test]
+async fn running_jobs_are_released_after_the_shutdown_grace_period() {
+    #[derive(Clone)]
+    struct TestContext {
+        job_started_barrier: Arc<Barrier>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestJob;
+
+    impl BackgroundJob for TestJob {
+        const JOB_NAME: &'static str = "test";
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            ctx.job_started_barrier.wait().await;
+            std::future::pending().await
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let test_context = TestContext {
+        job_started_barrier: Arc::new(Barrier::new(2)),
+    };
+
+    let runner = runner(test_database.url(), test_context.clone())
+        .shutdown_grace_period(Duration::from_millis(100))
+        .register_job_type::<TestJob>();
+
+    let mut conn = test_database.connect();
+    let job_id = TestJob.enqueue(&mut conn).unwrap();
+
+    let runner = runner.start();
+    test_context.job_started_barrier.wait().await;
+
+    runner.shutdown();
+    runner.wait_for_shutdown().await;
+
+    assert!(job_exists(job_id, &mut conn));
+    assert!(!job_is_locked(job_id, &mut conn));
+
+    let tries = background_jobs::table
+        .find(job_id)
+        .select(background_jobs::retries)
+        .first::<i32>(&mut *conn)
+        .unwrap();
+    assert_eq!(tries, 0);
+}
+
+#[tokio::test]
+async fn jobs_are_not_run_before_their_dependencies_have_succeeded() {
+    #[derive(Serialize, Deserialize)]
+    struct FailingJob;
+
+    impl BackgroundJob for FailingJob {
+        const JOB_NAME: &'static str = "failing";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("failed"))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct FollowUpJob;
+
+    impl BackgroundJob for FollowUpJob {
+        const JOB_NAME: &'static str = "follow_up";
+        type Context = ();
+
+        async fn run(&self, _ctx: Self::Context) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let runner = runner(test_database.url(), ())
+        .register_job_type::<FailingJob>()
+        .register_job_type::<FollowUpJob>();
+
+    let mut conn = test_database.connect();
+    let dependency_id = FailingJob.enqueue(&mut conn).unwrap();
+    let follow_up_id = FollowUpJob
+        .enqueue_with_dependencies(&mut conn, &[dependency_id])
+        .unwrap();
+
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    assert!(job_exists(dependency_id, &mut conn));
+    assert!(job_exists(follow_up_id, &mut conn));
+}
+
+#[tokio::test]
+async fn follow_up_jobs_run_after_parent_and_all_children_have_succeeded() {
+    #[derive(Clone)]
+    struct TestContext {
+        database_url: String,
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl TestContext {
+        fn record(&self, event: &'static str) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct ParentJob;
+
+    impl BackgroundJob for ParentJob {
+        const JOB_NAME: &'static str = "parent";
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            let conn = &mut PgConnection::establish(&ctx.database_url)?;
+
+            let parent_id = current_job_id().unwrap();
+            let follow_up_id = FollowUpJob.enqueue_with_dependencies(conn, &[parent_id])?;
+            ChildJob.enqueue_as_dependency_of(conn, follow_up_id)?;
+            ChildJob.enqueue_as_dependency_of(conn, follow_up_id)?;
+
+            ctx.record("parent");
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct ChildJob;
+
+    impl BackgroundJob for ChildJob {
+        const JOB_NAME: &'static str = "child";
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            ctx.record("child");
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct FollowUpJob;
+
+    impl BackgroundJob for FollowUpJob {
+        const JOB_NAME: &'static str = "follow_up";
+        type Context = TestContext;
+
+        async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+            ctx.record("follow_up");
+            Ok(())
+        }
+    }
+
+    let test_database = TestDatabase::new();
+
+    let test_context = TestContext {
+        database_url: test_database.url().to_string(),
+        events: Arc::new(Mutex::new(Vec::new())),
+    };
+
+    let runner = runner(test_database.url(), test_context.clone())
+        .register_job_type::<ParentJob>()
+        .register_job_type::<ChildJob>()
+        .register_job_type::<FollowUpJob>();
+
+    let mut conn = test_database.connect();
+    ParentJob.enqueue(&mut conn).unwrap();
+
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    let events = test_context.events.lock().unwrap().clone();
+    assert_eq!(events, vec!["parent", "child", "child", "follow_up"]);
+
+    let remaining_jobs: i64 = background_jobs::table
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(remaining_jobs, 0);
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClaimableJob {
+    name: String,
+}
+
+impl BackgroundJob for ClaimableJob {
+    const JOB_NAME: &'static str = "claimable";
+    type Context = ClaimTestContext;
+
+    async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+        ctx.events.lock().unwrap().push(self.name.clone());
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClaimingJob {
+    fail: bool,
+}
+
+impl BackgroundJob for ClaimingJob {
+    const JOB_NAME: &'static str = "claiming";
+    const PRIORITY: i16 = 10;
+    type Context = ClaimTestContext;
+
+    async fn run(&self, ctx: Self::Context) -> anyhow::Result<()> {
+        let conn = &mut *ctx.pool.get()?;
+
+        let claimed = claim_jobs::<ClaimableJob>(conn, 10, Duration::from_secs(60))?;
+        let ids = claimed.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let names = claimed.into_iter().map(|(_, job)| job.name);
+        let event = format!("claimed {}", names.collect::<Vec<_>>().join(", "));
+        ctx.events.lock().unwrap().push(event);
+
+        if self.fail {
+            release_claimed_jobs(conn, &ids)?;
+            return Err(anyhow::anyhow!("failed"));
+        }
+
+        complete_claimed_jobs(conn, &ids)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct ClaimTestContext {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl ClaimTestContext {
+    fn new(database_url: &str) -> Self {
+        let pool = Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .build_unchecked(ConnectionManager::new(database_url));
+
+        let events = Default::default();
+        Self { pool, events }
+    }
+}
+
+fn claimable_job(name: &str) -> ClaimableJob {
+    let name = name.to_string();
+    ClaimableJob { name }
+}
+
+#[tokio::test]
+async fn claimed_jobs_are_leased_until_released() {
+    let test_database = TestDatabase::new();
+    let mut conn = test_database.connect();
+
+    let claimed_names = |conn: &mut PgConnection, limit| {
+        let jobs = claim_jobs::<ClaimableJob>(conn, limit, Duration::from_secs(60)).unwrap();
+        jobs.into_iter()
+            .map(|(_, job)| job.name)
+            .collect::<Vec<_>>()
+    };
+
+    let foo_id = claimable_job("foo").enqueue(&mut conn).unwrap();
+    let bar_id = claimable_job("bar").enqueue(&mut conn).unwrap();
+    claimable_job("baz").enqueue(&mut conn).unwrap();
+
+    // Pretend that the `bar` job has just failed, so that it is still
+    // backing off
+    diesel::update(background_jobs::table.find(bar_id))
+        .set((
+            background_jobs::retries.eq(1),
+            background_jobs::last_retry.eq(diesel::dsl::now),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+
+    assert_eq!(claimed_names(&mut conn, 0), Vec::<String>::new());
+    assert_eq!(claimed_names(&mut conn, 10), ["foo", "baz"]);
+
+    // Claimed jobs are leased, and are not claimed a second time
+    assert_eq!(claimed_names(&mut conn, 10), Vec::<String>::new());
+
+    // Released jobs can be claimed again right away
+    release_claimed_jobs(&mut conn, &[foo_id]).unwrap();
+    assert_eq!(claimed_names(&mut conn, 10), ["foo"]);
+
+    // Completed jobs are removed from the queue
+    complete_claimed_jobs(&mut conn, &[foo_id]).unwrap();
+    assert!(!job_exists(foo_id, &mut conn));
+}
+
+#[tokio::test]
+async fn claimed_jobs_are_completed_by_the_claiming_job() {
+    let test_database = TestDatabase::new();
+    let test_context = ClaimTestContext::new(test_database.url());
+
+    let runner = runner(test_database.url(), test_context.clone())
+        .configure_default_queue(|queue| queue.num_workers(1))
+        .register_job_type::<ClaimableJob>()
+        .register_job_type::<ClaimingJob>();
+
+    let mut conn = test_database.connect();
+    claimable_job("foo").enqueue(&mut conn).unwrap();
+    claimable_job("bar").enqueue(&mut conn).unwrap();
+    ClaimingJob { fail: false }.enqueue(&mut conn).unwrap();
+
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    let events = test_context.events.lock().unwrap().clone();
+    assert_eq!(events, ["claimed foo, bar"]);
+
+    let remaining_jobs: i64 = background_jobs::table
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(remaining_jobs, 0);
+}
+
+#[tokio::test]
+async fn claimed_jobs_are_released_and_retried_if_the_claiming_job_fails() {
+    let test_database = TestDatabase::new();
+    let test_context = ClaimTestContext::new(test_database.url());
+
+    let runner = runner(test_database.url(), test_context.clone())
+        .configure_default_queue(|queue| queue.num_workers(1))
+        .register_job_type::<ClaimableJob>()
+        .register_job_type::<ClaimingJob>();
+
+    let mut conn = test_database.connect();
+    claimable_job("foo").enqueue(&mut conn).unwrap();
+    claimable_job("bar").enqueue(&mut conn).unwrap();
+    let claiming_job_id = ClaimingJob { fail: true }.enqueue(&mut conn).unwrap();
+
+    let runner = runner.start();
+    runner.wait_for_shutdown().await;
+
+    // The released jobs are run individually, without waiting for their lease
+    let events = test_context.events.lock().unwrap().clone();
+    assert_eq!(events, ["claimed foo, bar", "foo", "bar"]);
+
+    let remaining_jobs = background_jobs::table
+        .select((background_jobs::id, background_jobs::retries))
+        .load::<(i64, i32)>(&mut conn)
+        .unwrap();
+    assert_eq!(remaining_jobs, [(claiming_job_id, 1)]);
+}
+
 fn runner<Context: Clone + Send + Sync + 'static>(
     database_url: &str,
     context: Context,
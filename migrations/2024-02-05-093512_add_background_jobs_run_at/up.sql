@@ -0,0 +1 @@
+ALTER TABLE background_jobs ADD run_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
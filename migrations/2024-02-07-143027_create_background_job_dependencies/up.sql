@@ -0,0 +1,12 @@
+CREATE TABLE background_job_dependencies
+(
+    job_id        BIGINT NOT NULL REFERENCES background_jobs (id) ON DELETE CASCADE,
+    dependency_id BIGINT NOT NULL REFERENCES background_jobs (id) ON DELETE CASCADE,
+    PRIMARY KEY (job_id, dependency_id)
+);
+
+CREATE INDEX background_job_dependencies_dependency_id_index ON background_job_dependencies (dependency_id);
+
+COMMENT ON TABLE background_job_dependencies IS 'Jobs that have to complete successfully before another job can run. Rows are deleted automatically once the dependency job has completed.';
+COMMENT ON COLUMN background_job_dependencies.job_id IS 'The job that is waiting for the dependency to complete.';
+COMMENT ON COLUMN background_job_dependencies.dependency_id IS 'The job that has to complete successfully first.';
@@ -0,0 +1,3 @@
+DROP TABLE index_changes;
+DROP FUNCTION index_changes_set_id();
+DROP TABLE index_changes_counter;
@@ -0,0 +1,36 @@
+CREATE TABLE index_changes
+(
+    id         BIGINT    PRIMARY KEY,
+    crate_name VARCHAR   NOT NULL,
+    version    VARCHAR,
+    kind       INTEGER   NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+COMMENT ON TABLE index_changes IS 'Append-only log of all changes to the package index, used for the changes feed API of registry mirrors.';
+COMMENT ON COLUMN index_changes.id IS 'Gap-free identifier in commit order, used as the cursor of the changes feed. It is assigned from `index_changes_counter` by the `trigger_index_changes_set_id` trigger.';
+COMMENT ON COLUMN index_changes.crate_name IS 'Name of the crate that was changed. This is intentionally not a foreign key, since the crate might have been deleted.';
+COMMENT ON COLUMN index_changes.version IS 'Version that was changed, or NULL if the change affects the whole crate.';
+COMMENT ON COLUMN index_changes.kind IS 'Kind of the change (0 = publish, 1 = yank, 2 = unyank, 3 = delete version, 4 = delete crate).';
+COMMENT ON COLUMN index_changes.created_at IS 'Time at which the change was made.';
+
+CREATE TABLE index_changes_counter
+(
+    last_id BIGINT NOT NULL PRIMARY KEY
+);
+
+COMMENT ON TABLE index_changes_counter IS 'Single row holding the `id` of the last recorded index change. Updating the row locks it until the recording transaction finishes, so that concurrent changes get their `id` in commit order and a rolled back change does not leave a gap.';
+COMMENT ON COLUMN index_changes_counter.last_id IS 'The `id` of the last recorded index change.';
+
+INSERT INTO index_changes_counter (last_id) VALUES (0);
+
+CREATE FUNCTION index_changes_set_id() RETURNS trigger AS $$
+BEGIN
+    UPDATE index_changes_counter SET last_id = last_id + 1 RETURNING last_id INTO NEW.id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_index_changes_set_id
+    BEFORE INSERT ON index_changes
+    FOR EACH ROW EXECUTE PROCEDURE index_changes_set_id();
@@ -0,0 +1,15 @@
+CREATE TABLE storage_audit_failures
+(
+    version_id      INTEGER   NOT NULL PRIMARY KEY REFERENCES versions (id) ON DELETE CASCADE,
+    kind            INTEGER   NOT NULL,
+    actual_checksum CHAR(64),
+    actual_size     BIGINT,
+    detected_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+COMMENT ON TABLE storage_audit_failures IS 'Report of all crate files in the storage that did not match the recorded checksum during the last storage integrity audit.';
+COMMENT ON COLUMN storage_audit_failures.version_id IS 'Version whose crate file failed the audit. The row is removed once the crate file passes the audit again.';
+COMMENT ON COLUMN storage_audit_failures.kind IS 'Kind of the failure (0 = missing, 1 = truncated, 2 = mismatched).';
+COMMENT ON COLUMN storage_audit_failures.actual_checksum IS 'SHA256 checksum of the stored crate file, or NULL if the file is missing.';
+COMMENT ON COLUMN storage_audit_failures.actual_size IS 'Size of the stored crate file in bytes, or NULL if the file is missing.';
+COMMENT ON COLUMN storage_audit_failures.detected_at IS 'Time at which the failure was last detected.';
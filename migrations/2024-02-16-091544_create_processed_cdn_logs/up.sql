@@ -0,0 +1,22 @@
+CREATE TABLE processed_cdn_logs
+(
+    bucket        VARCHAR   NOT NULL,
+    path          VARCHAR   NOT NULL,
+    region        VARCHAR   NOT NULL,
+    status        INTEGER   NOT NULL,
+    checksum      VARCHAR,
+    num_downloads BIGINT    NOT NULL,
+    num_inserts   INTEGER   NOT NULL,
+    processed_at  TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (bucket, path)
+);
+
+COMMENT ON TABLE processed_cdn_logs IS 'Ledger of all CDN log files whose downloads have been counted, to make sure that every file is only counted once.';
+COMMENT ON COLUMN processed_cdn_logs.bucket IS 'Name of the bucket that contains the log file.';
+COMMENT ON COLUMN processed_cdn_logs.path IS 'Path of the log file within the bucket.';
+COMMENT ON COLUMN processed_cdn_logs.region IS 'Region of the bucket, which is needed to re-process the log file.';
+COMMENT ON COLUMN processed_cdn_logs.status IS 'Result of the processing (0 = counted, 1 = empty).';
+COMMENT ON COLUMN processed_cdn_logs.checksum IS 'ETag of the log file as reported by the object store, or NULL if the object store did not report one.';
+COMMENT ON COLUMN processed_cdn_logs.num_downloads IS 'Total number of downloads that were counted in the log file.';
+COMMENT ON COLUMN processed_cdn_logs.num_inserts IS 'Number of crate, version and date combinations that were counted in the log file.';
+COMMENT ON COLUMN processed_cdn_logs.processed_at IS 'Time at which the log file was last processed.';
@@ -0,0 +1,14 @@
+CREATE TABLE version_downloads_by_client
+(
+    version_id INTEGER NOT NULL REFERENCES versions (id) ON DELETE CASCADE,
+    date       DATE    NOT NULL,
+    client     VARCHAR NOT NULL,
+    downloads  INTEGER NOT NULL,
+    PRIMARY KEY (version_id, date, client)
+);
+
+COMMENT ON TABLE version_downloads_by_client IS 'Breakdown of the daily downloads of each version by the kind of client, as counted from the CDN logs.';
+COMMENT ON COLUMN version_downloads_by_client.version_id IS 'Version that was downloaded.';
+COMMENT ON COLUMN version_downloads_by_client.date IS 'Date of the downloads (UTC).';
+COMMENT ON COLUMN version_downloads_by_client.client IS 'Kind of client that downloaded the version, derived from its user agent (e.g. `cargo/1.76`, `cargo/nightly`, `mirror/panamax` or `other`). Stable cargo releases are bucketed by their minor version, and pre-releases by their release channel.';
+COMMENT ON COLUMN version_downloads_by_client.downloads IS 'Number of downloads by this kind of client on this date.';
@@ -0,0 +1,2 @@
+DROP TABLE version_downloads_monthly;
+DROP TABLE version_downloads_weekly;
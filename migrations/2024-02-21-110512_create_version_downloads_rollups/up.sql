@@ -0,0 +1,30 @@
+CREATE TABLE version_downloads_weekly
+(
+    version_id INTEGER NOT NULL REFERENCES versions (id) ON DELETE CASCADE,
+    date       DATE    NOT NULL,
+    downloads  BIGINT  NOT NULL,
+    PRIMARY KEY (version_id, date)
+);
+
+COMMENT ON TABLE version_downloads_weekly IS 'Number of downloads of each version per week, rolled up from `version_downloads`.';
+COMMENT ON COLUMN version_downloads_weekly.version_id IS 'Version that was downloaded.';
+COMMENT ON COLUMN version_downloads_weekly.date IS 'First day (Monday) of the week (UTC).';
+COMMENT ON COLUMN version_downloads_weekly.downloads IS 'Number of downloads in this week.';
+
+CREATE TABLE version_downloads_monthly
+(
+    version_id INTEGER NOT NULL REFERENCES versions (id) ON DELETE CASCADE,
+    date       DATE    NOT NULL,
+    downloads  BIGINT  NOT NULL,
+    PRIMARY KEY (version_id, date)
+);
+
+COMMENT ON TABLE version_downloads_monthly IS 'Number of downloads of each version per month, rolled up from `version_downloads`.';
+COMMENT ON COLUMN version_downloads_monthly.version_id IS 'Version that was downloaded.';
+COMMENT ON COLUMN version_downloads_monthly.date IS 'First day of the month (UTC).';
+COMMENT ON COLUMN version_downloads_monthly.downloads IS 'Number of downloads in this month.';
+
+-- The rollups of the existing daily downloads are backfilled one month at a
+-- time with `crates-admin backfill-download-rollups`, instead of within this
+-- migration. From here on they are kept up to date by the `update_downloads`
+-- background job.
@@ -0,0 +1,17 @@
+CREATE TABLE version_downloads_archives
+(
+    month           DATE      NOT NULL PRIMARY KEY,
+    path            VARCHAR   NOT NULL,
+    num_rows        BIGINT    NOT NULL,
+    archived_at     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    client_path     VARCHAR,
+    num_client_rows BIGINT    NOT NULL DEFAULT 0
+);
+
+COMMENT ON TABLE version_downloads_archives IS 'Months of daily downloads that have been moved from `version_downloads` and `version_downloads_by_client` to compressed CSV files in the storage bucket.';
+COMMENT ON COLUMN version_downloads_archives.month IS 'First day of the archived month.';
+COMMENT ON COLUMN version_downloads_archives.path IS 'Path of the archive file of the daily downloads in the storage bucket (e.g. `archive/version-downloads/2023-01/20230501T120000-1a2b3c4d.csv.gz`). Every archival run writes new files, and the paths are only switched once the archived rows have been deleted.';
+COMMENT ON COLUMN version_downloads_archives.num_rows IS 'Number of daily download rows in the archive file.';
+COMMENT ON COLUMN version_downloads_archives.archived_at IS 'Date and time when the month was archived.';
+COMMENT ON COLUMN version_downloads_archives.client_path IS 'Path of the archive file of the per-client breakdown of the daily downloads in the storage bucket, or NULL if there were no per-client downloads.';
+COMMENT ON COLUMN version_downloads_archives.num_client_rows IS 'Number of per-client download rows in the archive file at `client_path`.';
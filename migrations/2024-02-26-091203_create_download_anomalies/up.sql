@@ -0,0 +1,44 @@
+CREATE TABLE download_anomalies
+(
+    version_id         INTEGER          NOT NULL REFERENCES versions (id) ON DELETE CASCADE,
+    date               DATE             NOT NULL,
+    kind               INTEGER          NOT NULL,
+    downloads          INTEGER          NOT NULL,
+    expected_downloads INTEGER          NOT NULL,
+    score              DOUBLE PRECISION NOT NULL,
+    excluded           BOOLEAN          NOT NULL DEFAULT FALSE,
+    detected_at        TIMESTAMP        NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    dismissed_at       TIMESTAMP,
+    PRIMARY KEY (version_id, date)
+);
+
+COMMENT ON TABLE download_anomalies IS 'Daily downloads of versions that have been flagged as statistical outliers by the download anomaly detection, for review by the crates.io team.';
+COMMENT ON COLUMN download_anomalies.version_id IS 'Version whose daily downloads have been flagged.';
+COMMENT ON COLUMN download_anomalies.date IS 'Date of the flagged daily downloads.';
+COMMENT ON COLUMN download_anomalies.kind IS 'Kind of the anomaly (0 = z-score outlier against the history of the version, 1 = single-day spike against the history of the crate).';
+COMMENT ON COLUMN download_anomalies.downloads IS 'Number of downloads of the version on that date when the anomaly was detected.';
+COMMENT ON COLUMN download_anomalies.expected_downloads IS 'Number of downloads that would have been expected on that date based on the history.';
+COMMENT ON COLUMN download_anomalies.score IS 'Z-score of the downloads (kind 0), or ratio of the downloads to the average daily downloads of the crate (kind 1).';
+COMMENT ON COLUMN download_anomalies.excluded IS 'If true, only the expected downloads of that date are counted in `recent_crate_downloads`, which is used for the search ranking.';
+COMMENT ON COLUMN download_anomalies.detected_at IS 'Time at which the anomaly was last detected.';
+COMMENT ON COLUMN download_anomalies.dismissed_at IS 'Time at which the anomaly has been reviewed and found to be legitimate, or NULL if it has not been dismissed. Dismissed anomalies are kept, so that they are not flagged again by the next detection run.';
+
+-- Only count the expected downloads of excluded anomalies.
+DROP MATERIALIZED VIEW recent_crate_downloads;
+CREATE MATERIALIZED VIEW recent_crate_downloads (crate_id, downloads) AS
+  SELECT crate_id, SUM(
+    CASE WHEN download_anomalies.excluded
+      THEN LEAST(version_downloads.downloads, download_anomalies.expected_downloads)
+      ELSE version_downloads.downloads
+    END
+  ) FROM version_downloads
+    INNER JOIN versions
+      ON version_downloads.version_id = versions.id
+    LEFT JOIN download_anomalies
+      ON version_downloads.version_id = download_anomalies.version_id
+      AND version_downloads.date = download_anomalies.date
+    WHERE version_downloads.date > date(CURRENT_TIMESTAMP - INTERVAL '90 days')
+    GROUP BY crate_id;
+CREATE UNIQUE INDEX recent_crate_downloads_crate_id ON recent_crate_downloads (crate_id);
+CREATE INDEX index_recent_crate_downloads_by_downloads
+  ON recent_crate_downloads USING btree (downloads);
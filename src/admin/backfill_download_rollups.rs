@@ -0,0 +1,52 @@
+use crate::db;
+use crate::models::DownloadGranularity;
+use crate::schema::version_downloads;
+use crate::worker::jobs;
+use anyhow::Context;
+use chrono::{Months, NaiveDate};
+use diesel::dsl::{max, min};
+use diesel::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "backfill-download-rollups",
+    about = "Insert the missing weekly and monthly download rollups from the daily downloads"
+)]
+pub struct Opts {
+    /// The first month to backfill, e.g. `2023-01`. Defaults to the month of
+    /// the oldest daily downloads.
+    #[arg(long, value_parser = parse_month)]
+    from: Option<NaiveDate>,
+}
+
+fn parse_month(month: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{month}-01"), "%F")
+        .with_context(|| format!("Invalid month `{month}`, expected e.g. `2023-01`"))
+}
+
+pub fn run(opts: Opts) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    let (oldest, newest) = version_downloads::table
+        .select((min(version_downloads::date), max(version_downloads::date)))
+        .first::<(Option<NaiveDate>, Option<NaiveDate>)>(conn)?;
+
+    let (Some(oldest), Some(newest)) = (oldest, newest) else {
+        println!("No daily downloads to backfill");
+        return Ok(());
+    };
+
+    // Every month is backfilled by separate statements, so that no lock is
+    // held for long, and an interrupted backfill can be resumed with `--from`.
+    let mut month = DownloadGranularity::Month.period_start(opts.from.unwrap_or(oldest));
+    while month <= newest {
+        jobs::backfill_rollups(conn, month)?;
+        println!(
+            "Backfilled the download rollups of {}",
+            month.format("%Y-%m")
+        );
+        month = month + Months::new(1);
+    }
+
+    Ok(())
+}
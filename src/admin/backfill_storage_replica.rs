@@ -0,0 +1,42 @@
+use crate::storage::{Storage, StorageBucket};
+use anyhow::Context;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "backfill-storage-replica",
+    about = "Copy all objects that are missing from the secondary storage"
+)]
+pub struct Opts {
+    /// Only list the objects that would be copied
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub fn run(opts: Opts) -> anyhow::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to initialize tokio runtime")?;
+
+    let storage = Storage::from_environment();
+    if storage.as_replica_inner().is_none() {
+        anyhow::bail!("Storage replication is not configured");
+    }
+
+    rt.block_on(async {
+        for bucket in [StorageBucket::Default, StorageBucket::Index] {
+            println!("looking for unreplicated objects in the {bucket} bucket");
+            let paths = storage.list_unreplicated_objects(bucket).await?;
+            println!("found {} unreplicated objects", paths.len());
+
+            for path in paths {
+                println!("copying {bucket}/{path}");
+                if !opts.dry_run {
+                    storage.replicate_object(bucket, &path).await?;
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,76 @@
+use crate::db;
+use crate::models::ProcessedCdnLog;
+use crate::worker::jobs;
+use anyhow::anyhow;
+use crates_io_worker::BackgroundJob;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "cdn-log-ledger",
+    about = "Inspect the ledger of processed CDN log files"
+)]
+pub enum Command {
+    /// List the most recently processed log files
+    List {
+        /// Only list log files in this bucket
+        #[arg(long)]
+        bucket: Option<String>,
+        /// Maximum number of log files to list
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Process a log file again, even if its downloads have already been
+    /// counted. This counts the downloads a second time!
+    Reprocess {
+        bucket: String,
+        path: String,
+        /// Region of the bucket, which is required if the log file is not
+        /// in the ledger yet
+        #[arg(long)]
+        region: Option<String>,
+    },
+}
+
+pub fn run(command: Command) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    match command {
+        Command::List { bucket, limit } => {
+            let processed = ProcessedCdnLog::recent(conn, bucket.as_deref(), limit)?;
+            for log in processed {
+                let ProcessedCdnLog {
+                    bucket,
+                    path,
+                    status,
+                    num_downloads,
+                    processed_at,
+                    ..
+                } = log;
+
+                println!("{processed_at}  {bucket}/{path}  {status} ({num_downloads} downloads)");
+            }
+        }
+        Command::Reprocess {
+            bucket,
+            path,
+            region,
+        } => {
+            let processed = ProcessedCdnLog::find(conn, &bucket, &path)?;
+            let region = match (region, processed) {
+                (Some(region), _) => region,
+                (None, Some(processed)) => processed.region,
+                (None, None) => {
+                    return Err(anyhow!(
+                        "`{bucket}/{path}` has not been processed yet, please pass `--region`"
+                    ));
+                }
+            };
+
+            let job = jobs::ProcessCdnLog::new(region, bucket, path).forced();
+            println!("Enqueueing background job: {job:?}");
+            job.enqueue(conn)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::models::{IndexChangeKind, NewIndexChange};
 use crate::schema::{crate_owners, teams, users};
 use crate::storage::Storage;
 use crate::worker::jobs;
@@ -89,7 +90,8 @@ pub fn run(opts: Opts) -> anyhow::Result<()> {
         };
 
         info!(%name, "Enqueuing index sync jobs");
-        if let Err(error) = jobs::enqueue_sync_to_index(name, conn) {
+        let change = NewIndexChange::new(name, None, IndexChangeKind::DeleteCrate);
+        if let Err(error) = jobs::enqueue_index_change(&change, conn) {
             warn!(%name, ?error, "Failed to enqueue index sync jobs");
         }
 
@@ -1,3 +1,4 @@
+use crate::models::{IndexChangeKind, NewIndexChange};
 use crate::schema::crates;
 use crate::storage::Storage;
 use crate::worker::jobs;
@@ -71,8 +72,12 @@ pub fn run(opts: Opts) -> anyhow::Result<()> {
     }
 
     info!(%crate_name, "Enqueuing index sync jobs");
-    if let Err(error) = jobs::enqueue_sync_to_index(crate_name, conn) {
-        warn!(%crate_name, ?error, "Failed to enqueue index sync jobs");
+    for version in &opts.versions {
+        let kind = IndexChangeKind::DeleteVersion;
+        let change = NewIndexChange::new(crate_name, Some(version), kind);
+        if let Err(error) = jobs::enqueue_index_change(&change, conn) {
+            warn!(%crate_name, %version, ?error, "Failed to enqueue index sync jobs");
+        }
     }
 
     let rt = tokio::runtime::Builder::new_current_thread()
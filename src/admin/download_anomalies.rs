@@ -0,0 +1,139 @@
+use crate::db;
+use crate::models::DownloadAnomaly;
+use crate::schema::{crates, download_anomalies, versions};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use diesel::prelude::*;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "download-anomalies",
+    about = "Review the daily downloads that have been flagged as anomalies"
+)]
+pub enum Command {
+    /// List the most recently flagged anomalies
+    List {
+        /// Only list anomalies of this crate
+        #[arg(long)]
+        krate: Option<String>,
+        /// Maximum number of anomalies to list
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        /// Also list anomalies that have been dismissed
+        #[arg(long)]
+        dismissed: bool,
+    },
+    /// Only count the expected downloads of an anomaly in the recent
+    /// downloads, which are used for the search ranking
+    Exclude(AnomalyArgs),
+    /// Count all downloads of a previously excluded anomaly again
+    Include(AnomalyArgs),
+    /// Dismiss an anomaly that has been found to be legitimate, so that it
+    /// is not flagged again
+    Dismiss(AnomalyArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AnomalyArgs {
+    /// Name of the crate
+    krate: String,
+    /// Version of the crate
+    version: String,
+    /// Date of the anomaly, e.g. `2024-02-26`
+    date: NaiveDate,
+}
+
+pub fn run(command: Command) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    match command {
+        Command::List {
+            krate,
+            limit,
+            dismissed,
+        } => {
+            let mut query = download_anomalies::table
+                .inner_join(versions::table.inner_join(crates::table))
+                .select((crates::name, versions::num, DownloadAnomaly::as_select()))
+                .order((download_anomalies::date.desc(), crates::name, versions::num))
+                .limit(limit)
+                .into_boxed();
+
+            if let Some(krate) = krate {
+                query = query.filter(crates::name.eq(krate));
+            }
+
+            if !dismissed {
+                query = query.filter(download_anomalies::dismissed_at.is_null());
+            }
+
+            for (name, num, anomaly) in query.load::<(String, String, DownloadAnomaly)>(conn)? {
+                let DownloadAnomaly {
+                    date,
+                    kind,
+                    downloads,
+                    expected_downloads,
+                    score,
+                    excluded,
+                    dismissed_at,
+                    ..
+                } = anomaly;
+
+                let status = match (excluded, dismissed_at) {
+                    (_, Some(_)) => " [dismissed]",
+                    (true, None) => " [excluded]",
+                    (false, None) => "",
+                };
+                println!(
+                    "{date}  {name} v{num}  {kind} ({downloads} downloads, expected {expected_downloads}, score {score:.1}){status}"
+                );
+            }
+        }
+        Command::Exclude(args) => {
+            let version_id = find_version_id(conn, &args)?;
+            if !DownloadAnomaly::set_excluded(conn, version_id, args.date, true)? {
+                return Err(not_found(&args));
+            }
+            println!("Excluded anomaly, the recent downloads are updated by the next `update_downloads` job");
+        }
+        Command::Include(args) => {
+            let version_id = find_version_id(conn, &args)?;
+            if !DownloadAnomaly::set_excluded(conn, version_id, args.date, false)? {
+                return Err(not_found(&args));
+            }
+            println!("Included anomaly, the recent downloads are updated by the next `update_downloads` job");
+        }
+        Command::Dismiss(args) => {
+            let version_id = find_version_id(conn, &args)?;
+            if !DownloadAnomaly::dismiss(conn, version_id, args.date)? {
+                return Err(not_found(&args).context("The anomaly may have been dismissed already"));
+            }
+            println!("Dismissed anomaly, the recent downloads are updated by the next `update_downloads` job");
+        }
+    }
+
+    Ok(())
+}
+
+fn find_version_id(conn: &mut PgConnection, args: &AnomalyArgs) -> anyhow::Result<i32> {
+    let AnomalyArgs { krate, version, .. } = args;
+
+    versions::table
+        .inner_join(crates::table)
+        .select(versions::id)
+        .filter(crates::name.eq(krate))
+        .filter(versions::num.eq(version))
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| anyhow!("`{krate}` v{version} does not exist"))
+}
+
+fn not_found(args: &AnomalyArgs) -> anyhow::Error {
+    let AnomalyArgs {
+        krate,
+        version,
+        date,
+    } = args;
+
+    anyhow!("No anomaly of `{krate}` v{version} on {date} has been flagged")
+}
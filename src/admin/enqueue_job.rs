@@ -15,6 +15,10 @@ use secrecy::{ExposeSecret, SecretString};
 )]
 pub enum Command {
     UpdateDownloads,
+    /// Move old months of daily downloads to archive files in the storage
+    ArchiveVersionDownloads,
+    /// Scan the most recent daily downloads for anomalies
+    DetectDownloadAnomalies,
     DumpDb {
         #[arg(env = "READ_ONLY_REPLICA_URL")]
         database_url: SecretString,
@@ -27,16 +31,51 @@ pub enum Command {
         #[arg(long = "dry-run")]
         dry_run: bool,
     },
+    BackfillIndexPubtime {
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Compare the git index mirrors with the primary repository and sync
+    /// the ones that are out of date
+    CheckGitMirrors,
     CheckTyposquat {
         #[arg()]
         name: String,
     },
+    /// Count the downloads of a single log file, e.g. an access log that
+    /// was dropped into the local CDN log storage of a self-hosted deployment
+    ProcessCdnLog {
+        /// Path of the log file in the CDN log storage
+        path: String,
+        /// Bucket of the log file, which is ignored for local storage
+        #[arg(long, default_value = "local")]
+        bucket: String,
+        /// Region of the bucket, which is ignored for local storage
+        #[arg(long, default_value = "local")]
+        region: String,
+    },
     ProcessCdnLogQueue(jobs::ProcessCdnLogQueue),
+    /// Re-sign the metadata documents of the sparse index
+    ResignIndexMetadata,
     SyncAdmins {
         /// Force a sync even if one is already in progress
         #[arg(long)]
         force: bool,
     },
+    VerifyIndex {
+        /// Enqueue index sync jobs for all crates with discrepancies
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Verify the stored crate files against their recorded checksums
+    VerifyStorage {
+        /// Only verify a random sample of this many versions
+        #[arg(long)]
+        sample: Option<i64>,
+        /// Resume the audit after the version with this id
+        #[arg(long, default_value_t = 0, conflicts_with = "sample")]
+        after: i32,
+    },
 }
 
 pub fn run(command: Command) -> Result<()> {
@@ -87,9 +126,22 @@ pub fn run(command: Command) -> Result<()> {
 
             jobs::SyncAdmins.enqueue(conn)?;
         }
+        Command::ArchiveVersionDownloads => {
+            jobs::ArchiveVersionDownloads.enqueue(conn)?;
+        }
+        Command::DetectDownloadAnomalies => {
+            jobs::DetectDownloadAnomalies.enqueue(conn)?;
+        }
         Command::DailyDbMaintenance => {
             jobs::DailyDbMaintenance.enqueue(conn)?;
         }
+        Command::ProcessCdnLog {
+            path,
+            bucket,
+            region,
+        } => {
+            jobs::ProcessCdnLog::new(region, bucket, path).enqueue(conn)?;
+        }
         Command::ProcessCdnLogQueue(job) => {
             job.enqueue(conn)?;
         }
@@ -99,6 +151,25 @@ pub fn run(command: Command) -> Result<()> {
         Command::NormalizeIndex { dry_run } => {
             jobs::NormalizeIndex::new(dry_run).enqueue(conn)?;
         }
+        Command::BackfillIndexPubtime { dry_run } => {
+            jobs::BackfillIndexPubtime::new(dry_run).enqueue(conn)?;
+        }
+        Command::CheckGitMirrors => {
+            jobs::CheckGitMirrors.enqueue(conn)?;
+        }
+        Command::ResignIndexMetadata => {
+            jobs::ResignIndexMetadata.enqueue(conn)?;
+        }
+        Command::VerifyIndex { repair } => {
+            jobs::VerifyIndex::new(repair).enqueue(conn)?;
+        }
+        Command::VerifyStorage { sample, after } => {
+            let job = match sample {
+                Some(size) => jobs::VerifyStorage::sample(size),
+                None => jobs::VerifyStorage::all(after),
+            };
+            job.enqueue(conn)?;
+        }
         Command::CheckTyposquat { name } => {
             // The job will fail if the crate doesn't actually exist, so let's check that up front.
             if crates::table
@@ -1,6 +1,10 @@
+pub mod backfill_download_rollups;
+pub mod backfill_storage_replica;
+pub mod cdn_log_ledger;
 pub mod delete_crate;
 pub mod delete_version;
 pub mod dialoguer;
+pub mod download_anomalies;
 pub mod enqueue_job;
 pub mod git_import;
 pub mod migrate;
@@ -10,5 +14,8 @@ pub mod render_readmes;
 pub mod test_pagerduty;
 pub mod transfer_crates;
 pub mod upload_index;
+pub mod verify_index;
+pub mod verify_storage;
 pub mod verify_token;
+pub mod version_downloads_archive;
 pub mod yank_version;
@@ -0,0 +1,68 @@
+use crate::db;
+use crate::storage::Storage;
+use crate::tasks::block_on_runtime;
+use crate::worker::jobs::verify_index::{
+    enqueue_repair_jobs, verify_index, GitIndexFiles, IndexFiles, SparseIndexFiles,
+};
+use crates_io_index::{Repository, RepositoryConfig};
+use crates_io_worker::CancellationToken;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "verify-index",
+    about = "Compare the git and sparse indexes against the database"
+)]
+pub struct Opts {
+    /// Enqueue index sync jobs for all crates with discrepancies
+    #[arg(long)]
+    repair: bool,
+
+    /// Skip the comparison of the git index
+    #[arg(long)]
+    skip_git: bool,
+
+    /// Skip the comparison of the sparse index
+    #[arg(long)]
+    skip_sparse: bool,
+}
+
+pub fn run(opts: Opts) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    let rt = block_on_runtime()?;
+
+    let snapshot = if opts.skip_git {
+        None
+    } else {
+        println!("fetching git repo");
+        let config = RepositoryConfig::from_environment()?;
+        let repo = Repository::open(&config)?;
+        repo.reset_head()?;
+        println!("HEAD is at {}", repo.head_oid()?);
+        Some(repo.snapshot()?)
+    };
+
+    let storage = Storage::from_environment();
+
+    let mut git = snapshot.as_ref().map(GitIndexFiles::new);
+    let mut sparse =
+        (!opts.skip_sparse).then(|| SparseIndexFiles::new(&storage, rt.handle().clone()));
+
+    let mut sources: Vec<&mut dyn IndexFiles> = Vec::new();
+    if let Some(git) = git.as_mut() {
+        sources.push(git);
+    }
+    if let Some(sparse) = sparse.as_mut() {
+        sources.push(sparse);
+    }
+
+    let report = verify_index(conn, &mut sources, &CancellationToken::new())?;
+    report.print("crates");
+
+    if opts.repair && !report.findings.is_empty() {
+        let num_jobs = enqueue_repair_jobs(conn, &report.findings)?;
+        println!("enqueued {num_jobs} index sync jobs");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::db;
+use crate::storage::Storage;
+use crate::tasks::block_on_runtime;
+use crate::worker::jobs::audit::AuditReport;
+use crate::worker::jobs::verify_storage::{load_versions, verify_storage, Selection, BATCH_SIZE};
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "verify-storage",
+    about = "Verify the stored crate files against their recorded checksums"
+)]
+pub struct Opts {
+    /// Only verify a random sample of this many versions
+    #[arg(long)]
+    sample: Option<i64>,
+
+    /// Resume the audit after the version with this id
+    #[arg(long, default_value_t = 0, conflicts_with = "sample")]
+    after: i32,
+
+    /// Number of versions that are loaded from the database at once
+    #[arg(long, default_value_t = BATCH_SIZE)]
+    batch_size: i64,
+}
+
+pub fn run(opts: Opts) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    let rt = block_on_runtime()?;
+
+    let storage = Storage::from_environment();
+
+    let mut selection = match opts.sample {
+        Some(size) => Selection::Sample(size),
+        None => Selection::After(opts.after, opts.batch_size),
+    };
+
+    let mut total = AuditReport::default();
+    loop {
+        let versions = load_versions(conn, selection)?;
+        let report = verify_storage(conn, &storage, rt.handle(), &versions)?;
+        total.num_checked += report.num_checked;
+        total.findings.extend(report.findings);
+
+        let last_version_id = versions.last().map(|version| version.id);
+        let (Selection::After(_, batch_size), Some(last_version_id)) = (selection, last_version_id)
+        else {
+            break;
+        };
+
+        println!("verified crate files up to version id {last_version_id}");
+        if (versions.len() as i64) < batch_size {
+            break;
+        }
+
+        selection = Selection::After(last_version_id, batch_size);
+    }
+
+    total.print("crate files");
+
+    Ok(())
+}
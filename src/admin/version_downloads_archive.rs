@@ -0,0 +1,65 @@
+use crate::db;
+use crate::models::VersionDownloadsArchive;
+use crate::storage::Storage;
+use crate::tasks::block_on_runtime;
+use crate::worker::jobs;
+use anyhow::Context;
+use chrono::NaiveDate;
+
+#[derive(clap::Parser, Debug)]
+#[command(
+    name = "version-downloads-archive",
+    about = "Inspect and restore the archived months of daily downloads"
+)]
+pub enum Command {
+    /// List the archived months
+    List,
+    /// Restore the daily downloads of an archived month into the
+    /// `version_downloads` and `version_downloads_by_client` tables. The
+    /// month is archived again by the next run of the
+    /// `archive_version_downloads` job.
+    Rehydrate {
+        /// The month to restore, e.g. `2023-01`
+        #[arg(value_parser = parse_month)]
+        month: NaiveDate,
+    },
+}
+
+fn parse_month(month: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{month}-01"), "%F")
+        .with_context(|| format!("Invalid month `{month}`, expected e.g. `2023-01`"))
+}
+
+pub fn run(command: Command) -> anyhow::Result<()> {
+    let conn = &mut db::oneoff_connection()?;
+
+    match command {
+        Command::List => {
+            for archive in VersionDownloadsArchive::all(conn)? {
+                let VersionDownloadsArchive {
+                    month,
+                    path,
+                    num_rows,
+                    archived_at,
+                    client_path,
+                    num_client_rows,
+                } = archive;
+
+                let month = month.format("%Y-%m");
+                println!("{month}  {path}  {num_rows} rows (archived at {archived_at})");
+                if let Some(client_path) = client_path {
+                    println!("         {client_path}  {num_client_rows} rows");
+                }
+            }
+        }
+        Command::Rehydrate { month } => {
+            let rt = block_on_runtime()?;
+
+            let storage = Storage::from_environment();
+            let num_rows = jobs::rehydrate_month(conn, &storage, rt.handle(), month)?;
+            println!("Restored {num_rows} rows of {}", month.format("%Y-%m"));
+        }
+    }
+
+    Ok(())
+}
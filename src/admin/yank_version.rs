@@ -1,6 +1,6 @@
 use crate::admin::dialoguer;
 use crate::db;
-use crate::models::{Crate, Version};
+use crate::models::{Crate, IndexChangeKind, NewIndexChange, Version};
 use crate::schema::versions;
 use crate::worker::jobs;
 use diesel::prelude::*;
@@ -57,7 +57,8 @@ fn yank(opts: Opts, conn: &mut PgConnection) -> anyhow::Result<()> {
         .set(versions::yanked.eq(true))
         .execute(conn)?;
 
-    jobs::enqueue_sync_to_index(&krate.name, conn)?;
+    let change = NewIndexChange::new(&krate.name, Some(&v.num), IndexChangeKind::Yank);
+    jobs::enqueue_index_change(&change, conn)?;
 
     Ok(())
 }
@@ -11,6 +11,8 @@ use crate::email::Emails;
 use crate::metrics::{InstanceMetrics, ServiceMetrics};
 use crate::rate_limiter::RateLimiter;
 use crate::storage::Storage;
+use crate::upstream_registry::{UpstreamRegistry, UpstreamRegistryClient};
+use crate::worker::jobs::EnqueueReplicateStorageObject;
 use axum::extract::{FromRef, FromRequestParts, State};
 use crates_io_github::GitHubClient;
 use diesel::r2d2;
@@ -62,6 +64,9 @@ pub struct App {
 
     /// Rate limit select actions.
     pub rate_limiter: RateLimiter,
+
+    /// The upstream registry of the pull-through mirror mode, if enabled.
+    pub upstream_registry: Option<UpstreamRegistry>,
 }
 
 impl App {
@@ -72,7 +77,13 @@ impl App {
     /// - GitHub OAuth
     /// - Database connection pools
     /// - A `git2::Repository` instance from the index repo checkout (that server.rs ensures exists)
-    pub fn new(config: config::Server, emails: Emails, github: Box<dyn GitHubClient>) -> App {
+    /// - The upstream registry of the pull-through mirror mode, if configured
+    pub fn new(
+        config: config::Server,
+        emails: Emails,
+        github: Box<dyn GitHubClient>,
+        upstream_registry_client: Box<dyn UpstreamRegistryClient>,
+    ) -> App {
         use oauth2::{AuthUrl, TokenUrl};
 
         let instance_metrics =
@@ -141,10 +152,19 @@ impl App {
             None
         };
 
+        let repair_handler = EnqueueReplicateStorageObject::new(primary_database.clone());
+        let storage =
+            Storage::from_config(&config.storage).with_replication_failure_handler(repair_handler);
+
         let version_id_cacher = CacheBuilder::new(config.version_id_cache_size)
             .time_to_live(config.version_id_cache_ttl)
             .build();
 
+        let upstream_registry = config
+            .upstream_registry
+            .clone()
+            .map(|config| UpstreamRegistry::new(config, upstream_registry_client));
+
         App {
             primary_database,
             read_only_replica_database: replica_database,
@@ -153,11 +173,12 @@ impl App {
             version_id_cacher,
             downloads_counter: DownloadsCounter::new(),
             emails,
-            storage: Arc::new(Storage::from_config(&config.storage)),
+            storage: Arc::new(storage),
             service_metrics: ServiceMetrics::new().expect("could not initialize service metrics"),
             instance_metrics,
             balance_capacity: Default::default(),
             rate_limiter: RateLimiter::new(config.rate_limiter.clone()),
+            upstream_registry,
             config: Arc::new(config),
         }
     }
@@ -95,6 +95,10 @@ impl AuthCheck {
             // The token is NOT a legacy token, and the endpoint only allows legacy tokens.
             (Some(_), None) => false,
 
+            // Every token can be used to read from the registry, but tokens
+            // with only the `read-only` scope can't be used for anything else.
+            (Some(_), Some(EndpointScope::ReadOnly)) => true,
+
             // The token is NOT a legacy token, and the endpoint allows a certain endpoint scope or a legacy token.
             (Some(token_scopes), Some(endpoint_scope)) => token_scopes.contains(endpoint_scope),
         }
@@ -108,6 +112,10 @@ impl AuthCheck {
             // The token does not have any crate scopes.
             (Some(token_scopes), _) if token_scopes.is_empty() => true,
 
+            // Crate scopes only restrict write access, and reading the index
+            // of all crates is required to resolve dependencies.
+            (Some(_), _) if self.endpoint_scope == Some(EndpointScope::ReadOnly) => true,
+
             // The token has crate scopes, but the endpoint does not deal with crates.
             (Some(_), None) => false,
 
@@ -279,6 +287,7 @@ mod tests {
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
 
         assert!(auth_check.crate_scope_matches(None));
         assert!(!auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
@@ -296,6 +305,7 @@ mod tests {
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
 
         assert!(auth_check.crate_scope_matches(None));
         assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
@@ -315,6 +325,7 @@ mod tests {
         assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
 
         assert!(auth_check.crate_scope_matches(None));
         assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
@@ -334,6 +345,7 @@ mod tests {
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
         assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
 
         assert!(auth_check.crate_scope_matches(None));
         assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
@@ -353,6 +365,7 @@ mod tests {
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
         assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
         assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(!auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
 
         assert!(auth_check.crate_scope_matches(None));
         assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
@@ -360,4 +373,21 @@ mod tests {
         assert!(!auth_check.crate_scope_matches(Some(&vec![cs("anyhow")])));
         assert!(!auth_check.crate_scope_matches(Some(&vec![cs("actix-*")])));
     }
+
+    #[test]
+    fn read_only_endpoint() {
+        let auth_check = AuthCheck::default().with_endpoint_scope(EndpointScope::ReadOnly);
+
+        assert!(auth_check.endpoint_scope_matches(None));
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishNew])));
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::PublishUpdate])));
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::Yank])));
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ChangeOwners])));
+        assert!(auth_check.endpoint_scope_matches(Some(&vec![EndpointScope::ReadOnly])));
+
+        assert!(auth_check.crate_scope_matches(None));
+        assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-console")])));
+        assert!(auth_check.crate_scope_matches(Some(&vec![cs("tokio-*")])));
+        assert!(auth_check.crate_scope_matches(Some(&vec![cs("anyhow")])));
+    }
 }
@@ -7,6 +7,10 @@
 //! the worker thread), we will rebuild the runner and try again up to 5 times.
 //! After the 5th occurrence, we will panic.
 //!
+//! On `SIGINT` or `SIGTERM` the workers stop picking up new jobs, and running
+//! jobs are given `WORKER_SHUTDOWN_GRACE_PERIOD_SECONDS` (default: 25) to
+//! finish before they are aborted and released for other processes.
+//!
 //! Usage:
 //!      cargo run --bin background-worker
 
@@ -14,24 +18,31 @@
 extern crate tracing;
 
 use anyhow::Context;
+use crates_io::cdn_invalidation::InvalidationQueue;
 use crates_io::cloudfront::CloudFront;
 use crates_io::db::DieselPool;
 use crates_io::fastly::Fastly;
+use crates_io::index_signing::IndexSigner;
+use crates_io::metrics::LogEncoder;
 use crates_io::storage::Storage;
 use crates_io::team_repo::TeamRepoImpl;
+use crates_io::worker::jobs::{EnqueueReplicateStorageObject, GIT_MIRRORS_QUEUE};
 use crates_io::worker::{Environment, RunnerExt};
 use crates_io::{config, Emails};
 use crates_io::{db, ssh};
-use crates_io_env_vars::var;
+use crates_io_env_vars::{var, var_parsed};
 use crates_io_index::RepositoryConfig;
-use crates_io_worker::Runner;
+use crates_io_worker::{Runner, WorkerMetrics};
 use diesel::r2d2;
 use diesel::r2d2::ConnectionManager;
+use prometheus::Encoder;
 use reqwest::Client;
 use secrecy::ExposeSecret;
+use std::io::Write;
 use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 
 fn main() -> anyhow::Result<()> {
     let _sentry = crates_io::sentry::init();
@@ -68,8 +79,8 @@ fn main() -> anyhow::Result<()> {
 
     let repository_config = RepositoryConfig::from_environment()?;
 
-    let cloudfront = CloudFront::from_environment();
-    let storage = Arc::new(Storage::from_config(&config.storage));
+    let cloudfront = CloudFront::from_environment().map(InvalidationQueue::new);
+    let index_signer = IndexSigner::from_environment()?;
 
     let client = Client::builder()
         .timeout(Duration::from_secs(45))
@@ -77,7 +88,14 @@ fn main() -> anyhow::Result<()> {
         .expect("Couldn't build client");
 
     let emails = Emails::from_environment(&config);
-    let fastly = Fastly::from_environment(client.clone());
+    let metrics_log_interval = config
+        .instance_metrics_log_every_seconds
+        .map(Duration::from_secs);
+
+    let shutdown_grace_period = var_parsed("WORKER_SHUTDOWN_GRACE_PERIOD_SECONDS")?
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(25));
+    let fastly = Fastly::from_environment(client.clone()).map(InvalidationQueue::new);
     let team_repo = TeamRepoImpl::default();
 
     let connection_pool = r2d2::Pool::builder()
@@ -85,13 +103,21 @@ fn main() -> anyhow::Result<()> {
         .min_idle(Some(0))
         .build_unchecked(ConnectionManager::new(db_url));
 
+    let diesel_pool = DieselPool::new_background_worker(connection_pool.clone());
+
+    let repair_handler = EnqueueReplicateStorageObject::new(diesel_pool.clone());
+    let storage =
+        Storage::from_config(&config.storage).with_replication_failure_handler(repair_handler);
+    let storage = Arc::new(storage);
+
     let environment = Environment::builder()
         .config(Arc::new(config))
         .repository_config(repository_config)
         .cloudfront(cloudfront)
+        .index_signer(index_signer)
         .fastly(fastly)
         .storage(storage)
-        .connection_pool(DieselPool::new_background_worker(connection_pool.clone()))
+        .connection_pool(diesel_pool)
         .emails(emails)
         .team_repo(Box::new(team_repo))
         .build()?;
@@ -110,11 +136,69 @@ fn main() -> anyhow::Result<()> {
     let runner = Runner::new(runtime.handle(), connection_pool, environment.clone())
         .configure_default_queue(|queue| queue.num_workers(5))
         .configure_queue("repository", |queue| queue.num_workers(1))
-        .register_crates_io_job_types()
-        .start();
+        .configure_queue(GIT_MIRRORS_QUEUE, |queue| queue.num_workers(1))
+        .shutdown_grace_period(shutdown_grace_period)
+        .register_crates_io_job_types();
+
+    if let Some(interval) = metrics_log_interval {
+        log_worker_metrics_thread(runner.metrics().clone(), interval);
+    }
+
+    let runner = runner.start();
 
     info!("Runner booted, running jobs");
-    runtime.block_on(runner.wait_for_shutdown());
+    runtime.block_on(async {
+        shutdown_signal().await;
+
+        info!("Shutdown requested, waiting for running jobs to finish…");
+        runner.shutdown();
+        runner.wait_for_shutdown().await;
+    });
+
+    // Blocking tasks of aborted jobs might still be running at this point, but
+    // their jobs have already been released, so we don't wait for them.
+    runtime.shutdown_background();
+
+    info!("Runner has gracefully shutdown!");
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let interrupt = async {
+        signal(SignalKind::interrupt())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    let terminate = async {
+        signal(SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+}
+
+fn log_worker_metrics_thread(metrics: WorkerMetrics, interval: Duration) {
+    std::thread::spawn(move || loop {
+        if let Err(err) = log_worker_metrics_inner(&metrics) {
+            error!(?err, "log_worker_metrics error");
+        }
+        sleep(interval);
+    });
+}
+
+fn log_worker_metrics_inner(metrics: &WorkerMetrics) -> anyhow::Result<()> {
+    let families = metrics.gather();
+
+    let mut stdout = std::io::stdout();
+    LogEncoder::new().encode(&families, &mut stdout)?;
+    stdout.flush()?;
 
     Ok(())
 }
@@ -2,8 +2,10 @@
 extern crate tracing;
 
 use crates_io::admin::{
-    delete_crate, delete_version, enqueue_job, git_import, migrate, populate, render_readmes,
-    test_pagerduty, transfer_crates, upload_index, verify_token, yank_version,
+    backfill_download_rollups, backfill_storage_replica, cdn_log_ledger, delete_crate,
+    delete_version, download_anomalies, enqueue_job, git_import, migrate, populate, render_readmes,
+    test_pagerduty, transfer_crates, upload_index, verify_index, verify_storage, verify_token,
+    version_downloads_archive, yank_version,
 };
 
 #[derive(clap::Parser, Debug)]
@@ -18,10 +20,20 @@ enum Command {
     VerifyToken(verify_token::Opts),
     Migrate(migrate::Opts),
     UploadIndex(upload_index::Opts),
+    VerifyIndex(verify_index::Opts),
+    VerifyStorage(verify_storage::Opts),
     YankVersion(yank_version::Opts),
     GitImport(git_import::Opts),
+    BackfillStorageReplica(backfill_storage_replica::Opts),
+    BackfillDownloadRollups(backfill_download_rollups::Opts),
     #[clap(subcommand)]
     EnqueueJob(enqueue_job::Command),
+    #[clap(subcommand)]
+    CdnLogLedger(cdn_log_ledger::Command),
+    #[clap(subcommand)]
+    VersionDownloadsArchive(version_downloads_archive::Command),
+    #[clap(subcommand)]
+    DownloadAnomalies(download_anomalies::Command),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -46,9 +58,16 @@ fn main() -> anyhow::Result<()> {
         Command::VerifyToken(opts) => verify_token::run(opts),
         Command::Migrate(opts) => migrate::run(opts),
         Command::UploadIndex(opts) => upload_index::run(opts),
+        Command::VerifyIndex(opts) => verify_index::run(opts),
+        Command::VerifyStorage(opts) => verify_storage::run(opts),
         Command::YankVersion(opts) => yank_version::run(opts),
         Command::GitImport(opts) => git_import::run(opts),
+        Command::BackfillStorageReplica(opts) => backfill_storage_replica::run(opts),
+        Command::BackfillDownloadRollups(opts) => backfill_download_rollups::run(opts),
         Command::EnqueueJob(command) => enqueue_job::run(command),
+        Command::CdnLogLedger(command) => cdn_log_ledger::run(command),
+        Command::VersionDownloadsArchive(command) => version_downloads_archive::run(command),
+        Command::DownloadAnomalies(command) => download_anomalies::run(command),
     }
 }
 
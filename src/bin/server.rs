@@ -2,6 +2,7 @@
 extern crate tracing;
 
 use crates_io::middleware::normalize_path::normalize_path;
+use crates_io::upstream_registry::RealUpstreamRegistryClient;
 use crates_io::{metrics::LogEncoder, App, Emails};
 use std::{sync::Arc, time::Duration};
 
@@ -17,6 +18,12 @@ use tower::Layer;
 
 const CORE_THREADS: usize = 4;
 
+/// The timeouts of the requests to the upstream registry in mirror mode.
+/// These requests block sparse index and download requests, so a slow or
+/// hung upstream must fail quickly to fall back to the stale copy.
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(15);
+
 fn main() -> anyhow::Result<()> {
     let _sentry = crates_io::sentry::init();
 
@@ -30,10 +37,17 @@ fn main() -> anyhow::Result<()> {
     let emails = Emails::from_environment(&config);
 
     let client = Client::new();
-    let github = RealGitHubClient::new(client);
+    let github = RealGitHubClient::new(client.clone());
     let github = Box::new(github);
 
-    let app = Arc::new(App::new(config, emails, github));
+    let upstream_client = Client::builder()
+        .connect_timeout(UPSTREAM_CONNECT_TIMEOUT)
+        .timeout(UPSTREAM_TIMEOUT)
+        .build()?;
+    let upstream_registry = RealUpstreamRegistryClient::new(upstream_client);
+    let upstream_registry = Box::new(upstream_registry);
+
+    let app = Arc::new(App::new(config, emails, github, upstream_registry));
 
     // Start the background thread periodically persisting download counts to the database.
     downloads_counter_thread(app.clone());
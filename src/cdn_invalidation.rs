@@ -0,0 +1,412 @@
+//! Batched and coalesced cache invalidations for the CDNs in front of the
+//! storage buckets.
+//!
+//! Every background job that changes a file on a CDN-backed bucket needs to
+//! invalidate the cached copy of that file. Sending one invalidation request
+//! per file quickly runs into the rate limits of the CDN providers when many
+//! crates are published at the same time, so the [InvalidationQueue] collects
+//! the paths of all callers over a short window, deduplicates them, and sends
+//! them to the [CdnInvalidator] in as few batches as possible.
+//!
+//! The invalidations are sent in the background, so that throttled requests
+//! and their retries don't hold up the background jobs that caused them.
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// The default time that the [InvalidationQueue] waits for more paths
+/// before the collected paths are invalidated.
+const DEFAULT_WINDOW: Duration = Duration::from_secs(2);
+
+/// The number of times a throttled batch is sent again before giving up.
+const MAX_RETRIES: u32 = 5;
+
+/// The default of [CdnInvalidator::initial_retry_delay].
+const DEFAULT_INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two attempts to send a throttled batch.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// The error of a failed invalidation request.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidationError {
+    /// The provider rejected the request because of too many requests or
+    /// too many invalidations in progress, so it can be retried later.
+    ///
+    /// `completed` is the number of paths at the start of the batch that
+    /// were invalidated before the request was throttled. These are not sent
+    /// again when the batch is retried.
+    #[error("invalidation request was throttled")]
+    Throttled {
+        retry_after: Option<Duration>,
+        completed: usize,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A CDN provider that can invalidate the cached copies of files.
+#[async_trait]
+pub trait CdnInvalidator: Send + Sync {
+    /// The name of the provider, which is used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// The maximum number of paths that can be sent in a single call to
+    /// [CdnInvalidator::invalidate_batch].
+    fn max_batch_size(&self) -> usize;
+
+    /// The delay before a throttled batch is sent again for the first time,
+    /// unless the provider asked for a different delay. The delay is doubled
+    /// for every further retry, up to [MAX_RETRY_DELAY].
+    fn initial_retry_delay(&self) -> Duration {
+        DEFAULT_INITIAL_RETRY_DELAY
+    }
+
+    /// Invalidates the given paths, which do not start with a `/`.
+    async fn invalidate_batch(&self, paths: &[String]) -> Result<(), InvalidationError>;
+}
+
+/// The result of invalidating the paths of a window, which is shared by all
+/// callers whose paths were part of the window.
+type BatchResult = Option<Result<(), Arc<anyhow::Error>>>;
+
+/// The paths that have been collected since the current window started.
+struct PendingPaths {
+    paths: BTreeSet<String>,
+    result: watch::Sender<BatchResult>,
+}
+
+/// Collects paths that should be invalidated on a CDN, and sends them to
+/// the [CdnInvalidator] in deduplicated batches.
+///
+/// The first call to [InvalidationQueue::invalidate] starts a window, during
+/// which the paths of all further calls are collected. Once the window has
+/// passed, a background task splits the collected paths into batches of at
+/// most [CdnInvalidator::max_batch_size] paths, which are sent one after
+/// another, and throttled batches are retried with an exponential backoff
+/// starting at [CdnInvalidator::initial_retry_delay]. Failures are logged
+/// by the background task.
+pub struct InvalidationQueue {
+    invalidator: Arc<dyn CdnInvalidator>,
+    window: Duration,
+    pending: Arc<Mutex<Option<PendingPaths>>>,
+}
+
+impl InvalidationQueue {
+    pub fn new(invalidator: impl CdnInvalidator + 'static) -> Self {
+        Self {
+            invalidator: Arc::new(invalidator),
+            window: DEFAULT_WINDOW,
+            pending: Default::default(),
+        }
+    }
+
+    /// Sets the time that the queue waits for more paths before the
+    /// collected paths are invalidated.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Queues the given paths, such as `config.json` or `re/ge/regex`, to be
+    /// invalidated together with the paths of all other calls within the
+    /// same window, and returns immediately.
+    ///
+    /// The returned [PendingInvalidation] can be used to wait for the result,
+    /// but dropping it does not cancel the invalidation.
+    pub fn invalidate<I, S>(&self, paths: I) -> PendingInvalidation
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut pending = self.pending.lock().unwrap();
+        let pending = pending.get_or_insert_with(|| self.start_window());
+
+        for path in paths {
+            let path = path.as_ref().trim_start_matches('/');
+            pending.paths.insert(path.to_string());
+        }
+
+        let receiver = pending.result.subscribe();
+        PendingInvalidation { receiver }
+    }
+
+    /// Creates the pending paths of a new window, and spawns a task that
+    /// invalidates them once the window has passed.
+    fn start_window(&self) -> PendingPaths {
+        let (sender, _) = watch::channel(None);
+
+        let invalidator = self.invalidator.clone();
+        let pending = self.pending.clone();
+        let window = self.window;
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+
+            let Some(PendingPaths { paths, result }) = pending.lock().unwrap().take() else {
+                return;
+            };
+
+            let paths = paths.into_iter().collect::<Vec<_>>();
+            let outcome = invalidate_all(invalidator.as_ref(), &paths).await;
+            if let Err(error) = &outcome {
+                error!("{error:#}");
+            }
+
+            result.send_replace(Some(outcome.map_err(Arc::new)));
+        });
+
+        PendingPaths {
+            paths: BTreeSet::new(),
+            result: sender,
+        }
+    }
+}
+
+/// The invalidation of the paths of a call to [InvalidationQueue::invalidate].
+pub struct PendingInvalidation {
+    receiver: watch::Receiver<BatchResult>,
+}
+
+impl PendingInvalidation {
+    /// Waits until all batches of the window have been sent, and fails if
+    /// any of them failed.
+    pub async fn wait(mut self) -> anyhow::Result<()> {
+        let result = self.receiver.wait_for(Option::is_some).await?.clone();
+        match result {
+            Some(Err(error)) => Err(anyhow!("{error:#}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Sends the paths to the invalidator in batches of at most
+/// [CdnInvalidator::max_batch_size] paths.
+async fn invalidate_all(invalidator: &dyn CdnInvalidator, paths: &[String]) -> anyhow::Result<()> {
+    let name = invalidator.name();
+    let num_paths = paths.len();
+    info!(num_paths, "Invalidating paths on {name}");
+
+    let mut last_error = None;
+    for batch in paths.chunks(invalidator.max_batch_size().max(1)) {
+        if let Err(error) = invalidate_with_retries(invalidator, batch).await {
+            warn!(
+                num_paths = batch.len(),
+                "Failed to invalidate paths on {name}: {error:#}"
+            );
+            last_error = Some(error);
+        }
+    }
+
+    match last_error {
+        Some(error) => Err(error.context(format!("Failed to invalidate paths on {name}"))),
+        None => Ok(()),
+    }
+}
+
+/// Sends a batch to the invalidator, and retries throttled requests with
+/// the paths that have not been invalidated yet.
+async fn invalidate_with_retries(
+    invalidator: &dyn CdnInvalidator,
+    mut paths: &[String],
+) -> anyhow::Result<()> {
+    let initial_delay = invalidator.initial_retry_delay();
+    let mut retries = 0;
+    loop {
+        match invalidator.invalidate_batch(paths).await {
+            Ok(()) => return Ok(()),
+            Err(InvalidationError::Throttled {
+                retry_after,
+                completed,
+            }) if retries < MAX_RETRIES => {
+                paths = &paths[completed.min(paths.len())..];
+
+                let retry_after =
+                    retry_after.unwrap_or_else(|| retry_delay(initial_delay, retries));
+                debug!(
+                    ?retry_after,
+                    completed, "Invalidation request was throttled, retrying…"
+                );
+                tokio::time::sleep(retry_after).await;
+
+                retries += 1;
+            }
+            Err(InvalidationError::Throttled { .. }) => {
+                return Err(anyhow!(
+                    "Invalidation request was still throttled after {MAX_RETRIES} retries"
+                ));
+            }
+            Err(InvalidationError::Other(error)) => return Err(error),
+        }
+    }
+}
+
+/// Returns the delay before the retry after `retries` previous retries.
+fn retry_delay(initial_delay: Duration, retries: u32) -> Duration {
+    initial_delay
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claims::{assert_err, assert_ok};
+    use tokio::sync::Mutex as AsyncMutex;
+
+    /// A [CdnInvalidator] that records all batches, and fails the first
+    /// `throttled` requests after invalidating `completed` paths.
+    #[derive(Default)]
+    struct RecordingInvalidator {
+        batches: AsyncMutex<Vec<Vec<String>>>,
+        throttled: AsyncMutex<usize>,
+        completed: usize,
+        failing: bool,
+    }
+
+    #[async_trait]
+    impl CdnInvalidator for Arc<RecordingInvalidator> {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn max_batch_size(&self) -> usize {
+            2
+        }
+
+        async fn invalidate_batch(&self, paths: &[String]) -> Result<(), InvalidationError> {
+            let mut throttled = self.throttled.lock().await;
+            if *throttled > 0 {
+                *throttled -= 1;
+                let completed = self.completed.min(paths.len());
+                self.batches.lock().await.push(paths[..completed].to_vec());
+                let retry_after = Some(Duration::from_millis(1));
+                return Err(InvalidationError::Throttled {
+                    retry_after,
+                    completed,
+                });
+            }
+
+            if self.failing {
+                return Err(anyhow!("invalidation failed").into());
+            }
+
+            self.batches.lock().await.push(paths.to_vec());
+            Ok(())
+        }
+    }
+
+    fn queue(invalidator: &Arc<RecordingInvalidator>) -> InvalidationQueue {
+        InvalidationQueue::new(invalidator.clone()).with_window(Duration::from_millis(50))
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_and_batch() {
+        let invalidator = Arc::new(RecordingInvalidator::default());
+        let queue = queue(&invalidator);
+
+        let (a, b, c) = tokio::join!(
+            queue.invalidate(["/config.json", "re/ge/regex"]).wait(),
+            queue.invalidate(["se/rd/serde"]).wait(),
+            queue
+                .invalidate(["/re/ge/regex", "config.json", "3/s/syn"])
+                .wait(),
+        );
+        assert_ok!(a);
+        assert_ok!(b);
+        assert_ok!(c);
+
+        assert_eq!(
+            *invalidator.batches.lock().await,
+            vec![
+                vec!["3/s/syn".to_string(), "config.json".to_string()],
+                vec!["re/ge/regex".to_string(), "se/rd/serde".to_string()],
+            ]
+        );
+
+        // a later call starts a new window
+        assert_ok!(queue.invalidate(["config.json"]).wait().await);
+        assert_eq!(invalidator.batches.lock().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_throttled() {
+        let invalidator = Arc::new(RecordingInvalidator::default());
+        *invalidator.throttled.lock().await = 2;
+        let queue = queue(&invalidator);
+
+        assert_ok!(queue.invalidate(["config.json"]).wait().await);
+        assert_eq!(
+            *invalidator.batches.lock().await,
+            vec![vec![], vec![], vec!["config.json".to_string()]]
+        );
+
+        *invalidator.throttled.lock().await = MAX_RETRIES as usize + 1;
+        assert_err!(queue.invalidate(["config.json"]).wait().await);
+    }
+
+    #[tokio::test]
+    async fn test_retry_throttled_resumes() {
+        let invalidator = Arc::new(RecordingInvalidator {
+            completed: 1,
+            ..Default::default()
+        });
+        *invalidator.throttled.lock().await = 1;
+        let queue = queue(&invalidator);
+
+        assert_ok!(queue.invalidate(["3/s/syn", "config.json"]).wait().await);
+        assert_eq!(
+            *invalidator.batches.lock().await,
+            vec![vec!["3/s/syn".to_string()], vec!["config.json".to_string()]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_in_background() {
+        let invalidator = Arc::new(RecordingInvalidator::default());
+        let queue = queue(&invalidator);
+
+        // The paths are invalidated even if nobody waits for the result
+        queue.invalidate(["config.json"]);
+        assert!(invalidator.batches.lock().await.is_empty());
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            *invalidator.batches.lock().await,
+            vec![vec!["config.json".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let delays = (0..MAX_RETRIES)
+            .map(|retries| retry_delay(Duration::from_secs(60), retries).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![60, 120, 240, 300, 300]);
+
+        assert_eq!(retry_delay(DEFAULT_INITIAL_RETRY_DELAY, 3).as_secs(), 8);
+        assert_eq!(
+            retry_delay(DEFAULT_INITIAL_RETRY_DELAY, 64),
+            MAX_RETRY_DELAY
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure() {
+        let invalidator = Arc::new(RecordingInvalidator {
+            failing: true,
+            ..Default::default()
+        });
+        let queue = queue(&invalidator);
+
+        let (a, b) = tokio::join!(
+            queue.invalidate(["config.json"]).wait(),
+            queue.invalidate(["re/ge/regex"]).wait(),
+        );
+        assert_err!(a);
+        assert_err!(b);
+    }
+}
@@ -1,8 +1,27 @@
+use crate::cdn_invalidation::{CdnInvalidator, InvalidationError};
+use anyhow::Context;
+use async_trait::async_trait;
 use aws_credential_types::Credentials;
 use aws_sdk_cloudfront::config::retry::RetryConfig;
 use aws_sdk_cloudfront::config::{BehaviorVersion, Region};
 use aws_sdk_cloudfront::types::{InvalidationBatch, Paths};
 use aws_sdk_cloudfront::{Client, Config};
+use std::time::Duration;
+
+/// The maximum number of file paths that can be invalidated at the same time.
+const MAX_PATHS_IN_PROGRESS: usize = 3000;
+
+/// The maximum number of file paths per invalidation batch, which allows
+/// several batches to be in progress at the same time, so that a single large
+/// batch does not block all further invalidations until it is completed.
+const MAX_BATCH_SIZE: usize = MAX_PATHS_IN_PROGRESS / 6;
+
+/// The delay before a throttled batch is sent again for the first time.
+///
+/// CloudFront only accepts new invalidations once the paths of earlier ones
+/// have been invalidated, which usually takes a few minutes, so retrying
+/// within seconds would only be throttled again.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 pub struct CloudFront {
     client: Client,
@@ -31,26 +50,42 @@ impl CloudFront {
             distribution_id,
         })
     }
+}
 
-    /// Invalidate a file on CloudFront
+#[async_trait]
+impl CdnInvalidator for CloudFront {
+    fn name(&self) -> &'static str {
+        "CloudFront"
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn initial_retry_delay(&self) -> Duration {
+        INITIAL_RETRY_DELAY
+    }
+
+    /// Invalidate files on CloudFront
     ///
-    /// `path` is the path to the file to invalidate, such as `config.json`, or `re/ge/regex`
-    #[instrument(skip(self))]
-    pub async fn invalidate(&self, path: &str) -> anyhow::Result<()> {
-        let path = if path.starts_with('/') {
-            path.to_string()
-        } else {
-            format!("/{path}")
-        };
+    /// `paths` are the paths to the files to invalidate, such as `config.json`, or `re/ge/regex`
+    #[instrument(skip_all, fields(num_paths = paths.len()))]
+    async fn invalidate_batch(&self, paths: &[String]) -> Result<(), InvalidationError> {
+        let items = paths.iter().map(|path| format!("/{path}")).collect();
 
         let now = chrono::offset::Utc::now().timestamp_micros();
 
-        let paths = Paths::builder().quantity(1).items(path).build()?;
+        let paths = Paths::builder()
+            .quantity(paths.len() as i32)
+            .set_items(Some(items))
+            .build()
+            .context("Failed to build invalidation paths")?;
 
         let invalidation_batch = InvalidationBatch::builder()
             .caller_reference(format!("{now}"))
             .paths(paths)
-            .build()?;
+            .build()
+            .context("Failed to build invalidation batch")?;
 
         let invalidation_request = self
             .client
@@ -67,7 +102,20 @@ impl CloudFront {
             }
             Err(error) => {
                 warn!(?error, "Invalidation request failed");
-                Err(error.into())
+
+                let throttled = error
+                    .as_service_error()
+                    .is_some_and(|error| error.is_too_many_invalidations_in_progress());
+
+                if throttled {
+                    // A throttled invalidation batch is rejected as a whole.
+                    return Err(InvalidationError::Throttled {
+                        retry_after: None,
+                        completed: 0,
+                    });
+                }
+
+                Err(anyhow::Error::from(error).into())
             }
         }
     }
@@ -5,6 +5,8 @@ mod cdn_log_storage;
 mod database_pools;
 mod sentry;
 mod server;
+mod sparse_index;
+mod upstream_registry;
 
 pub use self::balance_capacity::BalanceCapacityConfig;
 pub use self::base::Base;
@@ -13,3 +15,5 @@ pub use self::cdn_log_storage::CdnLogStorageConfig;
 pub use self::database_pools::{DatabasePools, DbPoolConfig};
 pub use self::sentry::SentryConfig;
 pub use self::server::Server;
+pub use self::sparse_index::SparseIndexConfig;
+pub use self::upstream_registry::UpstreamRegistryConfig;
@@ -9,7 +9,7 @@ use super::base::Base;
 use super::database_pools::DatabasePools;
 use crate::config::balance_capacity::BalanceCapacityConfig;
 use crate::config::cdn_log_storage::CdnLogStorageConfig;
-use crate::config::CdnLogQueueConfig;
+use crate::config::{CdnLogQueueConfig, SparseIndexConfig, UpstreamRegistryConfig};
 use crate::middleware::cargo_compat::StatusCodeConfig;
 use crate::storage::StorageConfig;
 use crates_io_env_vars::{list, list_parsed, required_var, var, var_parsed};
@@ -73,6 +73,21 @@ pub struct Server {
     pub cdn_user_agent: String,
     pub balance_capacity: BalanceCapacityConfig,
 
+    /// Maximum number of pending crates that are synced to the git index in a
+    /// single commit and push by the `SyncToGitIndex` background job.
+    pub git_index_sync_batch_size: usize,
+
+    pub sparse_index: SparseIndexConfig,
+
+    /// Configuration of the pull-through mirror mode. `None` if crates are
+    /// never fetched from an upstream registry.
+    pub upstream_registry: Option<UpstreamRegistryConfig>,
+
+    /// Should all read requests to the API and the sparse index require
+    /// authentication? If enabled, `config.json` advertises `auth-required`
+    /// so that cargo sends credentials for every request to the registry.
+    pub auth_required: bool,
+
     /// Instructs the `cargo_compat` middleware whether to adjust response
     /// status codes to `200 OK` for all endpoints that are relevant for cargo.
     pub cargo_compat_status_code_config: StatusCodeConfig,
@@ -114,11 +129,20 @@ impl Server {
     /// - `WEB_PAGE_OFFSET_CIDR_BLOCKLIST`: A comma separated list of CIDR blocks that will be used
     ///   to block IP addresses, e.g. `192.168.1.0/24`. If not set or empty, no blocking will occur.
     /// - `INSTANCE_METRICS_LOG_EVERY_SECONDS`: How frequently should instance metrics be logged.
-    ///   If the environment variable is not present instance metrics are not logged.
+    ///   This also applies to the job metrics of the background worker. If the environment
+    ///   variable is not present instance metrics are not logged.
     /// - `FORCE_UNCONDITIONAL_REDIRECTS`: Whether to force unconditional redirects in the download
     ///   endpoint even with a healthy database pool.
     /// - `BLOCKED_ROUTES`: A comma separated list of HTTP route patterns that are manually blocked
     ///   by an operator (e.g. `/crates/:crate_id/:version/download`).
+    /// - `GIT_INDEX_SYNC_BATCH_SIZE`: Maximum number of pending crates that are synced to the
+    ///   git index in a single commit. Defaults to 1, which disables batching.
+    /// - `SERVE_SPARSE_INDEX`, `INDEX_API_URL` and `INDEX_DL_URL`: See
+    ///   [SparseIndexConfig::from_environment].
+    /// - `UPSTREAM_REGISTRY_INDEX_URL`, `UPSTREAM_REGISTRY_ALLOWED_CRATES` and
+    ///   `UPSTREAM_REGISTRY_INDEX_TTL`: See [UpstreamRegistryConfig::from_environment].
+    /// - `AUTH_REQUIRED`: Require authentication for all read requests to the API and the sparse
+    ///   index, turning this into a private registry.
     ///
     /// # Panics
     ///
@@ -167,6 +191,9 @@ impl Server {
 
         let storage = StorageConfig::from_environment();
 
+        let domain_name = dotenvy::var("DOMAIN_NAME").unwrap_or_else(|_| "crates.io".into());
+        let sparse_index = SparseIndexConfig::from_environment(base.env, &domain_name, port)?;
+
         // `sha256-dbf9FMl76C7BnK1CC3eWb3pvsQAUaTYSHAlBy9tNTG0=` refers to
         // the `script` in `public/github-redirect.html`
         let content_security_policy = format!(
@@ -205,7 +232,7 @@ impl Server {
             page_offset_ua_blocklist,
             page_offset_cidr_blocklist,
             excluded_crate_names,
-            domain_name: dotenvy::var("DOMAIN_NAME").unwrap_or_else(|_| "crates.io".into()),
+            domain_name,
             allowed_origins,
             downloads_persist_interval: var_parsed("DOWNLOADS_PERSIST_INTERVAL_MS")?
                 .map(Duration::from_millis)
@@ -223,6 +250,10 @@ impl Server {
             cdn_user_agent: var("WEB_CDN_USER_AGENT")?
                 .unwrap_or_else(|| "Amazon CloudFront".into()),
             balance_capacity: BalanceCapacityConfig::from_environment()?,
+            git_index_sync_batch_size: var_parsed("GIT_INDEX_SYNC_BATCH_SIZE")?.unwrap_or(1),
+            sparse_index,
+            upstream_registry: UpstreamRegistryConfig::from_environment()?,
+            auth_required: var("AUTH_REQUIRED")?.is_some(),
             cargo_compat_status_code_config: var_parsed("CARGO_COMPAT_STATUS_CODES")?
                 .unwrap_or(StatusCodeConfig::AdjustAll),
             serve_dist: true,
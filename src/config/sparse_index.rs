@@ -0,0 +1,51 @@
+use crate::Env;
+use crates_io_env_vars::var;
+
+/// Configuration of the sparse index that is served by the server itself,
+/// instead of by a CDN in front of the index bucket.
+pub struct SparseIndexConfig {
+    /// Should the server serve the sparse index at `/index/`?
+    ///
+    /// This is always enabled in development mode, and can be enabled for
+    /// self-hosted deployments via the `SERVE_SPARSE_INDEX` environment
+    /// variable.
+    pub serve: bool,
+
+    /// The `api` URL that is advertised in the `config.json` file.
+    pub api_url: String,
+
+    /// The `dl` URL that is advertised in the `config.json` file.
+    pub dl_url: String,
+}
+
+impl SparseIndexConfig {
+    /// Load the sparse index configuration from the environment.
+    ///
+    /// - `SERVE_SPARSE_INDEX`: Serve the sparse index at `/index/`, even if
+    ///   not running in development mode.
+    /// - `INDEX_API_URL`: The `api` URL in `config.json`. Defaults to
+    ///   `http://localhost:{port}` in development mode and
+    ///   `https://{domain_name}` otherwise.
+    /// - `INDEX_DL_URL`: The `dl` URL in `config.json`. Defaults to the
+    ///   download endpoint of the API.
+    pub fn from_environment(env: Env, domain_name: &str, port: u16) -> anyhow::Result<Self> {
+        let serve = env == Env::Development || var("SERVE_SPARSE_INDEX")?.is_some();
+
+        let api_url = match var("INDEX_API_URL")? {
+            Some(api_url) => api_url,
+            None if env == Env::Development => format!("http://localhost:{port}"),
+            None => format!("https://{domain_name}"),
+        };
+
+        let dl_url = match var("INDEX_DL_URL")? {
+            Some(dl_url) => dl_url,
+            None => format!("{}/api/v1/crates", api_url.trim_end_matches('/')),
+        };
+
+        Ok(Self {
+            serve,
+            api_url,
+            dl_url,
+        })
+    }
+}
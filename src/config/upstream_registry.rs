@@ -0,0 +1,61 @@
+use crate::models::token::CrateScope;
+use anyhow::anyhow;
+use crates_io_env_vars::{list, var, var_parsed};
+use std::time::Duration;
+
+const DEFAULT_INDEX_TTL: u64 = 10 * 60;
+
+/// Configuration of the pull-through mirror mode, in which crates that have
+/// not been published to this registry are fetched from an upstream registry
+/// on demand.
+#[derive(Debug, Clone)]
+pub struct UpstreamRegistryConfig {
+    /// The URL of the sparse index of the upstream registry (e.g.
+    /// `https://index.crates.io`).
+    pub index_url: String,
+
+    /// Only crates matching one of these patterns are fetched from the
+    /// upstream registry.
+    pub allowed_crates: Vec<CrateScope>,
+
+    /// How long a mirrored index file is served before it is fetched from
+    /// the upstream registry again.
+    pub index_ttl: Duration,
+}
+
+impl UpstreamRegistryConfig {
+    /// Load the upstream registry configuration from the environment.
+    ///
+    /// Returns `None` if `UPSTREAM_REGISTRY_INDEX_URL` is not set, which
+    /// disables the pull-through mirror mode.
+    ///
+    /// - `UPSTREAM_REGISTRY_INDEX_URL`: The URL of the sparse index of the
+    ///   upstream registry.
+    /// - `UPSTREAM_REGISTRY_ALLOWED_CRATES`: A comma separated list of crate
+    ///   name patterns (e.g. `serde,tokio-*`) that may be fetched from the
+    ///   upstream registry. Use `*` to allow all crates.
+    /// - `UPSTREAM_REGISTRY_INDEX_TTL`: How long mirrored index files are
+    ///   cached, in seconds. Defaults to 10 minutes.
+    pub fn from_environment() -> anyhow::Result<Option<Self>> {
+        let Some(index_url) = var("UPSTREAM_REGISTRY_INDEX_URL")? else {
+            return Ok(None);
+        };
+
+        let allowed_crates = list("UPSTREAM_REGISTRY_ALLOWED_CRATES")?
+            .into_iter()
+            .map(|pattern| {
+                CrateScope::try_from(pattern.as_str())
+                    .map_err(|error| anyhow!("{error}: {pattern}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let index_ttl = var_parsed("UPSTREAM_REGISTRY_INDEX_TTL")?.unwrap_or(DEFAULT_INDEX_TTL);
+        let index_ttl = Duration::from_secs(index_ttl);
+
+        Ok(Some(Self {
+            index_url,
+            allowed_crates,
+            index_ttl,
+        }))
+    }
+}
@@ -64,6 +64,7 @@ pub mod helpers;
 pub mod util;
 
 pub mod category;
+pub mod changes;
 pub mod crate_owner_invitation;
 pub mod git;
 pub mod github;
@@ -71,6 +72,7 @@ pub mod keyword;
 pub mod krate;
 pub mod metrics;
 pub mod site_metadata;
+pub mod sparse_index;
 pub mod team;
 pub mod token;
 pub mod user;
@@ -0,0 +1,69 @@
+use super::frontend_prelude::*;
+
+use crate::controllers::helpers::pagination::{encode_seek, Page, PaginationOptions};
+use crate::models::IndexChange;
+use crate::views::EncodableIndexChange;
+use axum::extract::Query;
+use indexmap::IndexMap;
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    since: Option<i64>,
+}
+
+/// Handles the `GET /changes` route.
+///
+/// Returns the changes to the index in the order in which they happened.
+/// Mirrors can pass the `id` of the last change they have processed as
+/// `?since=` to only receive subsequent changes, and follow the `next_page`
+/// link until it is `null`. If both `since` and `seek` are present, `seek`
+/// takes precedence.
+///
+/// A change only shows up once its transaction has committed, and all
+/// changes committed later get a greater `id`, so a cursor never skips a
+/// change.
+pub async fn list(app: AppState, query: Query<ListQuery>, req: Parts) -> AppResult<Json<Value>> {
+    spawn_blocking(move || {
+        let pagination: PaginationOptions = PaginationOptions::builder()
+            .enable_pages(false)
+            .enable_seek(true)
+            .gather(&req)?;
+
+        let since = match pagination.page {
+            Page::Unspecified => query.since.unwrap_or(0),
+            Page::Seek(s) => s.decode::<i64>()?,
+            Page::Numeric(_) => unreachable!("page-based pagination is disabled"),
+        };
+
+        let conn = &mut *app.db_read()?;
+
+        // We fetch one element over the page limit to then detect whether there is a next page.
+        let mut changes = IndexChange::since(conn, since, pagination.per_page + 1)?;
+
+        let next_page = if changes.len() > pagination.per_page as usize {
+            changes.pop();
+
+            match changes.last() {
+                Some(last) => {
+                    let mut params = IndexMap::new();
+                    params.insert("seek".into(), encode_seek(last.id)?);
+                    Some(req.query_with_params(params))
+                }
+                None => None,
+            }
+        } else {
+            None
+        };
+
+        let changes = changes
+            .into_iter()
+            .map(EncodableIndexChange::from)
+            .collect::<Vec<_>>();
+
+        Ok(Json(json!({
+            "changes": changes,
+            "meta": { "next_page": next_page },
+        })))
+    })
+    .await
+}
@@ -4,17 +4,28 @@
 //! download counts are located in `version::downloads`.
 
 use std::cmp;
+use std::fmt::Write;
 
 use crate::controllers::frontend_prelude::*;
 
-use crate::models::{Crate, CrateVersions, Version, VersionDownload};
+use crate::models::{Crate, CrateVersions, DownloadGranularity, Version, VersionDownload};
 use crate::schema::version_downloads;
 use crate::sql::to_char;
 use crate::util::errors::crate_not_found;
 use crate::views::EncodableVersionDownload;
+use chrono::{NaiveDate, Utc};
 
 /// Handles the `GET /crates/:crate_id/downloads` route.
-pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppResult<Json<Value>> {
+///
+/// Without query parameters, the daily downloads of the last 90 days are
+/// returned. With `?granularity=week` or `?granularity=month`, the weekly or
+/// monthly downloads of the whole history of the crate are returned instead,
+/// see [rollup_downloads].
+pub async fn downloads(
+    state: AppState,
+    Path(crate_name): Path<String>,
+    req: Parts,
+) -> AppResult<Response> {
     spawn_blocking(move || {
         use diesel::dsl::*;
         use diesel::sql_types::BigInt;
@@ -25,6 +36,11 @@ pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppRe
             .optional()?
             .ok_or_else(|| crate_not_found(&crate_name))?;
 
+        if let Some(granularity) = req.query().get("granularity") {
+            let granularity = granularity.parse().map_err(bad_request)?;
+            return rollup_downloads(conn, &krate, granularity, &req);
+        }
+
         let mut versions: Vec<Version> = krate.all_versions().load(conn)?;
         versions
             .sort_by_cached_key(|version| cmp::Reverse(semver::Version::parse(&version.num).ok()));
@@ -63,7 +79,77 @@ pub async fn downloads(state: AppState, Path(crate_name): Path<String>) -> AppRe
             "meta": {
                 "extra_downloads": extra,
             },
-        })))
+        }))
+        .into_response())
     })
     .await
 }
+
+/// Returns the downloads of all versions of the crate per week or month.
+///
+/// The optional `from` and `to` query parameters (`YYYY-MM-DD`) limit the
+/// periods that are returned, and default to the creation date of the crate
+/// and today. With `?format=csv`, the downloads are returned as CSV instead
+/// of JSON.
+fn rollup_downloads(
+    conn: &mut PgConnection,
+    krate: &Crate,
+    granularity: DownloadGranularity,
+    req: &Parts,
+) -> AppResult<Response> {
+    let query = req.query();
+
+    let parse_date = |name: &str| {
+        query
+            .get(name)
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%F").map_err(|_| {
+                    bad_request(format!(
+                        "invalid `{name}` parameter `{date}`, expected a date like `2024-01-31`"
+                    ))
+                })
+            })
+            .transpose()
+    };
+
+    let from = parse_date("from")?.unwrap_or_else(|| krate.created_at.date());
+    let to = parse_date("to")?.unwrap_or_else(|| Utc::now().date_naive());
+    if from > to {
+        return Err(bad_request("`from` must not be after `to`"));
+    }
+
+    let from = granularity.period_start(from);
+    let downloads = granularity.crate_downloads(conn, krate.id, from, to)?;
+
+    match query.get("format").map(String::as_str) {
+        None | Some("json") => {
+            let downloads = downloads
+                .into_iter()
+                .map(|(date, downloads)| json!({ "date": date, "downloads": downloads }))
+                .collect::<Vec<_>>();
+
+            Ok(Json(json!({
+                "downloads": downloads,
+                "meta": {
+                    "granularity": granularity.to_string(),
+                    "from": from,
+                    "to": to,
+                },
+            }))
+            .into_response())
+        }
+        Some("csv") => {
+            let mut csv = String::from("date,downloads\n");
+            for (date, downloads) in downloads {
+                let _ = writeln!(csv, "{date},{downloads}");
+            }
+
+            let content_type = [(header::CONTENT_TYPE, "text/csv; charset=utf-8")];
+            Ok((content_type, csv).into_response())
+        }
+        Some(format) => {
+            let detail = format!("invalid `format` parameter `{format}`, expected `json` or `csv`");
+            Err(bad_request(detail))
+        }
+    }
+}
@@ -17,8 +17,8 @@ use url::Url;
 
 use crate::controllers::cargo_prelude::*;
 use crate::models::{
-    insert_version_owner_action, Category, Crate, DependencyKind, Keyword, NewCrate, NewVersion,
-    Rights, VersionAction,
+    insert_version_owner_action, Category, Crate, DependencyKind, IndexChangeKind, Keyword,
+    NewCrate, NewIndexChange, NewVersion, Rights, VersionAction,
 };
 
 use crate::licenses::parse_license_expr;
@@ -395,7 +395,9 @@ pub async fn publish(app: AppState, req: BytesRequest) -> AppResult<Json<GoodCra
                 ))
                 .map_err(|e| internal(format!("failed to upload crate: {e}")))?;
 
-            jobs::enqueue_sync_to_index(&krate.name, conn)?;
+            let kind = IndexChangeKind::Publish;
+            let change = NewIndexChange::new(&krate.name, Some(&version_string), kind);
+            jobs::enqueue_index_change(&change, conn)?;
 
             // Experiment: check new crates for potential typosquatting.
             if existing_crate.is_none() {
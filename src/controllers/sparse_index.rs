@@ -0,0 +1,170 @@
+use crate::app::AppState;
+use crate::models::Crate;
+use crate::schema::crates;
+use crate::tasks::spawn_blocking;
+use crate::util::errors::{internal, not_found, AppResult, BoxedAppError};
+use axum::extract::Path;
+use axum::response::{IntoResponse, Response};
+use axum_extra::headers::{
+    ContentType, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use axum_extra::TypedHeader;
+use chrono::{DateTime, Utc};
+use diesel::dsl::{exists, select};
+use diesel::prelude::*;
+use http::{header, HeaderMap, StatusCode};
+use hyper::body::Bytes;
+use sha2::{Digest, Sha256};
+use std::time::SystemTime;
+
+/// The `config.json` file of the sparse index, as described in
+/// <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration>.
+#[derive(Serialize)]
+struct IndexConfig<'a> {
+    dl: &'a str,
+    api: &'a str,
+    #[serde(rename = "auth-required", skip_serializing_if = "std::ops::Not::not")]
+    auth_required: bool,
+}
+
+/// Handles the `GET /index/*path` route.
+///
+/// Serves the sparse index files from the index bucket of the [Storage](crate::storage::Storage),
+/// and generates the `config.json` file from the
+/// [`sparse_index`](crate::config::Server::sparse_index) configuration.
+///
+/// Responses contain `ETag` and (except for `config.json`) `Last-Modified`
+/// headers, and conditional requests are answered with `304 Not Modified`,
+/// which allows cargo to use its local cache of the index.
+pub async fn serve(
+    state: AppState,
+    Path(path): Path<String>,
+    if_modified_since: Option<TypedHeader<IfModifiedSince>>,
+    req_headers: HeaderMap,
+) -> AppResult<Response> {
+    // `IfNoneMatch` successfully decodes from a missing header (as an empty
+    // list of tags), so it can't be used as an optional `TypedHeader`.
+    let if_none_match = req_headers
+        .contains_key(header::IF_NONE_MATCH)
+        .then(|| req_headers.typed_get::<IfNoneMatch>())
+        .flatten();
+
+    let (content, content_type, last_modified) = if path == "config.json" {
+        let config = &state.config.sparse_index;
+        let config = IndexConfig {
+            dl: &config.dl_url,
+            api: &config.api_url,
+            auth_required: state.config.auth_required,
+        };
+
+        let content = Bytes::from(serde_json::to_vec(&config)?);
+        (content, ContentType::json(), None)
+    } else {
+        let Some((content, last_modified)) = read_index_file(&state, &path).await? else {
+            return Err(not_found());
+        };
+
+        (content, ContentType::text(), Some(last_modified))
+    };
+
+    let etag = etag(&content);
+    let not_modified = match (if_none_match, if_modified_since) {
+        // `If-None-Match` takes precedence over `If-Modified-Since`, see
+        // https://www.rfc-editor.org/rfc/rfc9110#section-13.1.3
+        (Some(if_none_match), _) => !if_none_match.precondition_passes(&etag),
+        (None, Some(TypedHeader(if_modified_since))) => {
+            // HTTP dates only have a precision of one second
+            let since = DateTime::<Utc>::from(SystemTime::from(if_modified_since));
+            last_modified
+                .is_some_and(|last_modified| last_modified.timestamp() <= since.timestamp())
+        }
+        (None, None) => false,
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(etag);
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(LastModified::from(std::time::SystemTime::from(
+            last_modified,
+        )));
+    }
+
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    headers.typed_insert(content_type);
+    Ok((headers, content).into_response())
+}
+
+/// Reads a file of the sparse index from the [Storage](crate::storage::Storage).
+///
+/// If the pull-through mirror mode is enabled, the index files of crates
+/// that have not been published to this registry are fetched from the
+/// [upstream registry](crate::upstream_registry) if they are missing or
+/// older than the configured TTL. If the upstream registry is unavailable,
+/// the stale copy is returned instead.
+pub async fn read_index_file(
+    state: &AppState,
+    path: &str,
+) -> AppResult<Option<(Bytes, DateTime<Utc>)>> {
+    let file = state.storage.read_index_file(path).await;
+    let file = file.map_err(|e| internal(format!("failed to read index file: {e}")))?;
+
+    let Some(upstream) = &state.upstream_registry else {
+        return Ok(file);
+    };
+
+    let Some(name) = upstream.mirrored_crate_name(path) else {
+        return Ok(file);
+    };
+
+    let is_fresh = file
+        .as_ref()
+        .is_some_and(|(_, last_modified)| !upstream.is_expired(*last_modified));
+    if is_fresh {
+        return Ok(file);
+    }
+
+    let is_local = spawn_blocking({
+        let state = state.clone();
+        let name = name.to_string();
+        move || {
+            let conn = &mut *state.db_read()?;
+            let query = crates::table.filter(Crate::with_name(&name));
+            Ok::<_, BoxedAppError>(select(exists(query)).get_result::<bool>(conn)?)
+        }
+    })
+    .await?;
+
+    if is_local {
+        return Ok(file);
+    }
+
+    match upstream.fetch_index_file(path, name).await {
+        Ok(Some(content)) => {
+            let result = state
+                .storage
+                .sync_index_file(path, Some(content.clone()))
+                .await;
+            result.map_err(|e| internal(format!("failed to store index file: {e}")))?;
+            Ok(Some((Bytes::from(content), Utc::now())))
+        }
+        Ok(None) => Ok(file),
+        Err(error) => {
+            warn!(%path, "Failed to fetch index file from upstream registry: {error:#}");
+            Ok(file)
+        }
+    }
+}
+
+/// Returns a strong `ETag` that is derived from the SHA256 hash of `content`.
+///
+/// Unlike the `e_tag` of the object store metadata, this is stable across
+/// storage backends and is also available for the generated `config.json`.
+fn etag(content: &[u8]) -> ETag {
+    let hash = hex::encode(Sha256::digest(content));
+    format!("\"{hash}\"")
+        .parse()
+        .expect("hex encoded hash is a valid ETag")
+}
@@ -4,11 +4,12 @@
 
 use super::version_and_crate;
 use crate::controllers::prelude::*;
+use crate::controllers::sparse_index::read_index_file;
 use crate::db::PoolError;
 use crate::middleware::log_request::RequestLogExt;
-use crate::models::VersionDownload;
+use crate::models::{Crate, VersionClientDownload, VersionDownload};
 use crate::schema::*;
-use crate::util::errors::version_not_found;
+use crate::util::errors::{bad_request, internal, version_not_found};
 use crate::views::EncodableVersionDownload;
 use chrono::{Duration, NaiveDate, Utc};
 use tracing::Instrument;
@@ -103,7 +104,20 @@ pub async fn download(
                 }
             }
         })
-        .await?;
+        .await;
+
+        // Versions that have not been published to this registry might be
+        // fetched from the upstream registry in pull-through mirror mode.
+        let version_id = match version_id {
+            Err(error) if error.response().status() == StatusCode::NOT_FOUND => {
+                if !mirror_crate_file(&app, &crate_name, &version).await? {
+                    return Err(error);
+                }
+
+                None
+            }
+            result => result?,
+        };
 
         if let Some(version_id) = version_id {
             let span = info_span!("cache.write", ?cache_key);
@@ -122,6 +136,63 @@ pub async fn download(
     }
 }
 
+/// Fetches the `.crate` file of a version that has not been published to
+/// this registry from the upstream registry, unless it has already been
+/// stored before.
+///
+/// Returns `false` if the pull-through mirror mode is disabled, the crate is
+/// not on the allow-list or has an invalid name, or the version does not
+/// exist upstream.
+async fn mirror_crate_file(app: &AppState, crate_name: &str, version: &str) -> AppResult<bool> {
+    let Some(upstream) = &app.upstream_registry else {
+        return Ok(false);
+    };
+
+    if !upstream.is_allowed(crate_name) {
+        return Ok(false);
+    }
+
+    if Crate::validate_crate_name("crate", crate_name).is_err() {
+        return Ok(false);
+    }
+
+    let exists = app.storage.crate_file_exists(crate_name, version).await;
+    if exists.map_err(|e| internal(format!("failed to check crate file: {e}")))? {
+        return Ok(true);
+    }
+
+    let path = crates_io_index::Repository::relative_index_file_for_url(crate_name);
+    let Some((index, _)) = read_index_file(app, &path).await? else {
+        return Ok(false);
+    };
+
+    let index = String::from_utf8_lossy(&index);
+    let crates = crates_io_index::read_crates(&index)
+        .map_err(|e| internal(format!("failed to parse index file: {e}")))?;
+
+    // Cargo always uses the canonical crate name for downloads
+    let krate = crates
+        .into_iter()
+        .find(|krate| krate.name == crate_name && krate.vers == version);
+    let Some(krate) = krate else {
+        return Ok(false);
+    };
+
+    let bytes = upstream.fetch_crate_file(&krate).await;
+    let bytes = bytes.map_err(|e| internal(format!("failed to fetch crate file: {e:#}")))?;
+    let Some(bytes) = bytes else {
+        return Ok(false);
+    };
+
+    let result = app
+        .storage
+        .upload_crate_file(crate_name, version, bytes)
+        .await;
+    result.map_err(|e| internal(format!("failed to store crate file: {e}")))?;
+
+    Ok(true)
+}
+
 #[instrument("db.query", skip(conn), fields(message = "SELECT ... FROM versions"))]
 fn get_version_id(krate: &str, version: &str, conn: &mut PgConnection) -> QueryResult<i32> {
     versions::table
@@ -133,6 +204,9 @@ fn get_version_id(krate: &str, version: &str, conn: &mut PgConnection) -> QueryR
 }
 
 /// Handles the `GET /crates/:crate_id/:version/downloads` route.
+///
+/// With `?by=client`, the daily downloads are broken down by the kind of
+/// client that downloaded the version, as counted from the CDN logs.
 pub async fn downloads(
     app: AppState,
     Path((crate_name, version)): Path<(String, String)>,
@@ -153,13 +227,32 @@ pub async fn downloads(
             .unwrap_or_else(|| Utc::now().date_naive());
         let cutoff_start_date = cutoff_end_date - Duration::days(89);
 
-        let downloads = VersionDownload::belonging_to(&version)
-            .filter(version_downloads::date.between(cutoff_start_date, cutoff_end_date))
-            .order(version_downloads::date)
-            .load(conn)?
-            .into_iter()
-            .map(VersionDownload::into)
-            .collect::<Vec<EncodableVersionDownload>>();
+        let downloads = match req.query().get("by").map(String::as_str) {
+            None => VersionDownload::belonging_to(&version)
+                .filter(version_downloads::date.between(cutoff_start_date, cutoff_end_date))
+                .order(version_downloads::date)
+                .load(conn)?
+                .into_iter()
+                .map(VersionDownload::into)
+                .collect::<Vec<EncodableVersionDownload>>(),
+            Some("client") => VersionClientDownload::belonging_to(&version)
+                .filter(
+                    version_downloads_by_client::date.between(cutoff_start_date, cutoff_end_date),
+                )
+                .order((
+                    version_downloads_by_client::date,
+                    version_downloads_by_client::client,
+                ))
+                .select(VersionClientDownload::as_select())
+                .load(conn)?
+                .into_iter()
+                .map(VersionClientDownload::into)
+                .collect::<Vec<EncodableVersionDownload>>(),
+            Some(by) => {
+                let detail = format!("invalid `by` parameter `{by}`, expected `client`");
+                return Err(bad_request(detail));
+            }
+        };
 
         Ok(Json(json!({ "version_downloads": downloads })))
     })
@@ -5,7 +5,7 @@ use crate::auth::AuthCheck;
 use crate::controllers::cargo_prelude::*;
 use crate::models::token::EndpointScope;
 use crate::models::Rights;
-use crate::models::{insert_version_owner_action, VersionAction};
+use crate::models::{insert_version_owner_action, IndexChangeKind, NewIndexChange, VersionAction};
 use crate::rate_limiter::LimitedAction;
 use crate::schema::versions;
 use crate::util::errors::{custom, version_not_found};
@@ -93,15 +93,16 @@ fn modify_yank(
         .set(versions::yanked.eq(yanked))
         .execute(conn)?;
 
-    let action = if yanked {
-        VersionAction::Yank
+    let (action, kind) = if yanked {
+        (VersionAction::Yank, IndexChangeKind::Yank)
     } else {
-        VersionAction::Unyank
+        (VersionAction::Unyank, IndexChangeKind::Unyank)
     };
 
     insert_version_owner_action(conn, version.id, user.id, api_token_id, action)?;
 
-    jobs::enqueue_sync_to_index(&krate.name, conn)?;
+    let change = NewIndexChange::new(&krate.name, Some(&version.num), kind);
+    jobs::enqueue_index_change(&change, conn)?;
 
     ok_true()
 }
@@ -1,7 +1,13 @@
+use crate::cdn_invalidation::{CdnInvalidator, InvalidationError};
 use anyhow::{anyhow, Context};
-use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::Client;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, SecretString};
+use std::time::Duration;
+
+/// The maximum number of paths that are purged by a single batch.
+const MAX_PATHS_PER_BATCH: usize = 256;
 
 #[derive(Debug)]
 pub struct Fastly {
@@ -22,44 +28,11 @@ impl Fastly {
         })
     }
 
-    /// Invalidate a path on Fastly
-    ///
-    /// This method takes a path and invalidates the cached content on Fastly. The path must not
-    /// contain a wildcard, since the Fastly API does not support wildcard invalidations. Paths are
-    /// invalidated for both domains that are associated with the Fastly service.
-    ///
-    /// Requests are authenticated using a token that is sent in a header. The token is passed to
-    /// the application as an environment variable.
-    ///
-    /// More information on Fastly's APIs for cache invalidations can be found here:
-    /// <https://developer.fastly.com/reference/api/purging/>
-    #[instrument(skip(self))]
-    pub async fn invalidate(&self, path: &str) -> anyhow::Result<()> {
-        if path.contains('*') {
-            return Err(anyhow!(
-                "wildcard invalidations are not supported for Fastly"
-            ));
-        }
-
-        let domains = [
-            &self.static_domain_name,
-            &format!("fastly-{}", self.static_domain_name),
-        ];
-        let path = path.trim_start_matches('/');
-
-        for domain in domains.iter() {
-            let url = format!("https://api.fastly.com/purge/{}/{}", domain, path);
-            self.purge_url(&url).await?;
-        }
-
-        Ok(())
-    }
-
-    async fn purge_url(&self, url: &str) -> anyhow::Result<()> {
+    async fn purge_url(&self, url: &str) -> Result<(), InvalidationError> {
         trace!(?url);
 
         let api_token = self.api_token.expose_secret();
-        let mut api_token = HeaderValue::try_from(api_token)?;
+        let mut api_token = HeaderValue::try_from(api_token).map_err(anyhow::Error::from)?;
         api_token.set_sensitive(true);
 
         let mut headers = HeaderMap::new();
@@ -91,8 +64,76 @@ impl Fastly {
                     "invalidation request to Fastly failed"
                 );
 
-                Err(error).with_context(|| format!("failed to purge {url}"))
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    let retry_after = headers
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok()?.parse().ok())
+                        .map(Duration::from_secs);
+
+                    return Err(InvalidationError::Throttled {
+                        retry_after,
+                        completed: 0,
+                    });
+                }
+
+                let error = anyhow::Error::from(error).context(format!("failed to purge {url}"));
+                Err(error.into())
             }
         }
     }
 }
+
+#[async_trait]
+impl CdnInvalidator for Fastly {
+    fn name(&self) -> &'static str {
+        "Fastly"
+    }
+
+    /// Fastly has no batch endpoint for URL purges, so this only limits the
+    /// number of purge requests that are sent one after another.
+    fn max_batch_size(&self) -> usize {
+        MAX_PATHS_PER_BATCH
+    }
+
+    /// Invalidate paths on Fastly
+    ///
+    /// This method takes paths and invalidates the cached content on Fastly. The paths must not
+    /// contain a wildcard, since the Fastly API does not support wildcard invalidations. Paths are
+    /// invalidated for both domains that are associated with the Fastly service.
+    ///
+    /// Requests are authenticated using a token that is sent in a header. The token is passed to
+    /// the application as an environment variable.
+    ///
+    /// More information on Fastly's APIs for cache invalidations can be found here:
+    /// <https://developer.fastly.com/reference/api/purging/>
+    #[instrument(skip_all, fields(num_paths = paths.len()))]
+    async fn invalidate_batch(&self, paths: &[String]) -> Result<(), InvalidationError> {
+        if paths.iter().any(|path| path.contains('*')) {
+            return Err(anyhow!("wildcard invalidations are not supported for Fastly").into());
+        }
+
+        let domains = [
+            &self.static_domain_name,
+            &format!("fastly-{}", self.static_domain_name),
+        ];
+
+        for (i, path) in paths.iter().enumerate() {
+            for domain in domains.iter() {
+                let url = format!("https://api.fastly.com/purge/{}/{}", domain, path);
+                match self.purge_url(&url).await {
+                    // The paths before the throttled one have already been
+                    // purged, so a retry resumes from the throttled path.
+                    Err(InvalidationError::Throttled { retry_after, .. }) => {
+                        return Err(InvalidationError::Throttled {
+                            retry_after,
+                            completed: i,
+                        });
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
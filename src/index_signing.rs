@@ -0,0 +1,533 @@
+//! Signed metadata for the sparse index, loosely following the roles and
+//! document layout of [The Update Framework](https://theupdateframework.io/)
+//! (TUF).
+//!
+//! For every sparse index file a `targets` document is uploaded to
+//! `_tuf/targets/{path}.json`, which contains the length and SHA256 hash of
+//! the index file. The `snapshot` document at [SNAPSHOT_PATH] lists the
+//! versions of all `targets` documents, and the `timestamp` document at
+//! [TIMESTAMP_PATH] references the current `snapshot` document by its hash.
+//! The `root` document at [ROOT_PATH] advertises the public key that all
+//! documents are signed with.
+//!
+//! Whenever an index file is synced, its `targets` document is regenerated
+//! and the `snapshot` and `timestamp` documents are updated accordingly. All
+//! documents are additionally re-signed periodically by the
+//! [ResignIndexMetadata](crate::worker::jobs::ResignIndexMetadata) job.
+//!
+//! Every regenerated document gets the version of the document it replaces
+//! plus one, so the documents have to be updated while holding
+//! [Environment::lock_index_metadata](crate::worker::Environment::lock_index_metadata).
+//!
+//! All documents are signed with a single Ed25519 registry key, and the
+//! signatures are calculated over the compact JSON serialization of the
+//! `signed` object.
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use crates_io_env_vars::var;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// The path of the `root` document, relative to the root of the index.
+pub const ROOT_PATH: &str = "_tuf/root.json";
+
+/// The path of the `snapshot` document, relative to the root of the index.
+pub const SNAPSHOT_PATH: &str = "_tuf/snapshot.json";
+
+/// The path of the `timestamp` document, relative to the root of the index.
+pub const TIMESTAMP_PATH: &str = "_tuf/timestamp.json";
+
+const SPEC_VERSION: &str = "1.0.0";
+
+/// The roles that are assigned to the registry key in the `root` document.
+const ROLES: [&str; 4] = ["root", "snapshot", "targets", "timestamp"];
+
+/// How long the `root` document is valid.
+const ROOT_EXPIRY_DAYS: i64 = 365;
+
+/// How long before its expiry the `root` document is regenerated. Until
+/// then, the existing document is kept as-is, so that clients don't have
+/// to re-fetch it.
+const ROOT_RENEWAL_DAYS: i64 = 30;
+
+/// How long the `targets` document of an index file is valid. The documents
+/// of crates that don't change are re-signed by the periodic job, so this
+/// only needs to cover a couple of missed runs.
+const TARGETS_EXPIRY_DAYS: i64 = 7;
+
+/// How long the `snapshot` and `timestamp` documents are valid. This limits
+/// how long a stale copy of the index can be served to a client without
+/// being detected, and needs to be longer than the interval of the periodic
+/// job.
+const TIMESTAMP_EXPIRY_DAYS: i64 = 2;
+
+/// Returns the path of the `targets` document for the index file at `path`,
+/// relative to the root of the index.
+pub fn targets_path(path: &str) -> String {
+    format!("_tuf/targets/{path}.json")
+}
+
+/// Signs the metadata documents of the sparse index with the registry key.
+pub struct IndexSigner {
+    key_pair: Ed25519KeyPair,
+    key_id: String,
+}
+
+impl IndexSigner {
+    /// Loads the registry key from the `INDEX_SIGNING_KEY` environment
+    /// variable, which contains the base64-encoded 32 byte Ed25519 seed.
+    ///
+    /// Returns `None` if signing is not configured.
+    pub fn from_environment() -> anyhow::Result<Option<Self>> {
+        let Some(key) = var("INDEX_SIGNING_KEY")?.map(SecretString::from) else {
+            return Ok(None);
+        };
+
+        let seed = general_purpose::STANDARD
+            .decode(key.expose_secret())
+            .context("Failed to base64 decode INDEX_SIGNING_KEY")?;
+
+        Self::from_seed(&seed).map(Some)
+    }
+
+    pub fn from_seed(seed: &[u8]) -> anyhow::Result<Self> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|error| anyhow!("Invalid index signing key: {error}"))?;
+
+        let key_id = hex::encode(Sha256::digest(key_pair.public_key().as_ref()));
+
+        Ok(Self { key_pair, key_id })
+    }
+
+    /// The ID of the registry key, which is the hex-encoded SHA256 hash of
+    /// the public key.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The hex-encoded public key of the registry key.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    /// Generates the signed `root` document with the given `version`, which
+    /// lists the registry key and assigns it to all roles.
+    pub fn root(&self, version: i64, now: DateTime<Utc>) -> anyhow::Result<String> {
+        let key = Key {
+            keytype: "ed25519",
+            keyval: KeyValue {
+                public: self.public_key(),
+            },
+            scheme: "ed25519",
+        };
+
+        let role = || Role {
+            keyids: vec![self.key_id.clone()],
+            threshold: 1,
+        };
+
+        let root = Root {
+            _type: "root",
+            expires: format_expiry(now + Duration::days(ROOT_EXPIRY_DAYS)),
+            keys: BTreeMap::from([(self.key_id.clone(), key)]),
+            roles: ROLES.into_iter().map(|name| (name, role())).collect(),
+            spec_version: SPEC_VERSION,
+            version,
+        };
+
+        self.sign(root)
+    }
+
+    /// Generates the signed `targets` document with the given `version` for
+    /// the index file at `path` with the given `content`.
+    pub fn targets(
+        &self,
+        path: &str,
+        content: &[u8],
+        version: i64,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<String> {
+        let target = Target {
+            hashes: BTreeMap::from([("sha256", hex::encode(Sha256::digest(content)))]),
+            length: content.len(),
+        };
+
+        let targets = Targets {
+            _type: "targets",
+            expires: format_expiry(now + Duration::days(TARGETS_EXPIRY_DAYS)),
+            spec_version: SPEC_VERSION,
+            targets: BTreeMap::from([(path.to_string(), target)]),
+            version,
+        };
+
+        self.sign(targets)
+    }
+
+    /// Returns whether the given existing `root` document needs to be
+    /// replaced, because it does not exist, lists a different key or set of
+    /// roles, or is about to expire.
+    pub fn root_needs_update(&self, existing: Option<&[u8]>, now: DateTime<Utc>) -> bool {
+        #[derive(Deserialize)]
+        struct Document {
+            signed: ExistingRoot,
+        }
+
+        #[derive(Deserialize)]
+        struct ExistingRoot {
+            expires: DateTime<Utc>,
+            keys: BTreeMap<String, serde::de::IgnoredAny>,
+            roles: BTreeMap<String, serde::de::IgnoredAny>,
+        }
+
+        let Some(existing) = existing else {
+            return true;
+        };
+
+        let Ok(Document { signed: root }) = serde_json::from_slice(existing) else {
+            return true;
+        };
+
+        let renew_at = now + Duration::days(ROOT_RENEWAL_DAYS);
+        root.keys.len() != 1
+            || !root.keys.contains_key(&self.key_id)
+            || !ROLES.iter().all(|role| root.roles.contains_key(*role))
+            || root.expires < renew_at
+    }
+
+    /// Generates the signed `snapshot` document with the given `version`,
+    /// which lists the given versions of all `targets` documents. The keys of
+    /// `targets` are the paths of the documents relative to the `_tuf`
+    /// directory (e.g. `targets/3/f/foo.json`).
+    pub fn snapshot(
+        &self,
+        targets: BTreeMap<String, i64>,
+        version: i64,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<String> {
+        let meta = targets
+            .into_iter()
+            .map(|(path, version)| (path, SnapshotMeta { version }))
+            .collect();
+
+        let snapshot = Snapshot {
+            _type: "snapshot",
+            expires: format_expiry(now + Duration::days(TIMESTAMP_EXPIRY_DAYS)),
+            meta,
+            spec_version: SPEC_VERSION,
+            version,
+        };
+
+        self.sign(snapshot)
+    }
+
+    /// Generates the signed `timestamp` document with the given `version`,
+    /// which references the given `snapshot` document by its length, hash
+    /// and version.
+    pub fn timestamp(
+        &self,
+        snapshot: &str,
+        snapshot_version: i64,
+        version: i64,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<String> {
+        let snapshot_meta = TimestampMeta {
+            hashes: BTreeMap::from([("sha256", hex::encode(Sha256::digest(snapshot)))]),
+            length: snapshot.len(),
+            version: snapshot_version,
+        };
+
+        let timestamp = Timestamp {
+            _type: "timestamp",
+            expires: format_expiry(now + Duration::days(TIMESTAMP_EXPIRY_DAYS)),
+            meta: BTreeMap::from([("snapshot.json", snapshot_meta)]),
+            spec_version: SPEC_VERSION,
+            version,
+        };
+
+        self.sign(timestamp)
+    }
+
+    fn sign<T: serde::Serialize>(&self, signed: T) -> anyhow::Result<String> {
+        let message = serde_json::to_vec(&signed)?;
+        let signature = self.key_pair.sign(&message);
+
+        let document = Signed {
+            signatures: vec![Signature {
+                keyid: &self.key_id,
+                sig: hex::encode(signature.as_ref()),
+            }],
+            signed,
+        };
+
+        Ok(serde_json::to_string(&document)?)
+    }
+}
+
+/// Returns the version of the given existing document, or `0` if it does not
+/// exist or can not be parsed. The regenerated document gets this version
+/// plus one.
+pub fn existing_version(existing: Option<&[u8]>) -> i64 {
+    #[derive(Deserialize)]
+    struct Document {
+        signed: Versioned,
+    }
+
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: i64,
+    }
+
+    existing
+        .and_then(|existing| serde_json::from_slice(existing).ok())
+        .map(|Document { signed }| signed.version)
+        .unwrap_or_default()
+}
+
+/// Returns the versions of the `targets` documents that are listed in the
+/// given existing `snapshot` document, or an empty map if it does not exist
+/// or can not be parsed.
+pub fn existing_snapshot_targets(existing: Option<&[u8]>) -> BTreeMap<String, i64> {
+    #[derive(Deserialize)]
+    struct Document {
+        signed: ExistingSnapshot,
+    }
+
+    #[derive(Deserialize)]
+    struct ExistingSnapshot {
+        meta: BTreeMap<String, ExistingSnapshotMeta>,
+    }
+
+    #[derive(Deserialize)]
+    struct ExistingSnapshotMeta {
+        version: i64,
+    }
+
+    existing
+        .and_then(|existing| serde_json::from_slice(existing).ok())
+        .map(|Document { signed }| signed.meta)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(path, meta)| (path, meta.version))
+        .collect()
+}
+
+fn format_expiry(expires: DateTime<Utc>) -> String {
+    expires.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// The fields of the following structs are sorted alphabetically, so that
+// the serialized documents have a canonical key order.
+
+#[derive(Serialize)]
+struct Signed<'a, T> {
+    signatures: Vec<Signature<'a>>,
+    signed: T,
+}
+
+#[derive(Serialize)]
+struct Signature<'a> {
+    keyid: &'a str,
+    sig: String,
+}
+
+#[derive(Serialize)]
+struct Root {
+    _type: &'static str,
+    expires: String,
+    keys: BTreeMap<String, Key>,
+    roles: BTreeMap<&'static str, Role>,
+    spec_version: &'static str,
+    version: i64,
+}
+
+#[derive(Serialize)]
+struct Key {
+    keytype: &'static str,
+    keyval: KeyValue,
+    scheme: &'static str,
+}
+
+#[derive(Serialize)]
+struct KeyValue {
+    public: String,
+}
+
+#[derive(Serialize)]
+struct Role {
+    keyids: Vec<String>,
+    threshold: u32,
+}
+
+#[derive(Serialize)]
+struct Targets {
+    _type: &'static str,
+    expires: String,
+    spec_version: &'static str,
+    targets: BTreeMap<String, Target>,
+    version: i64,
+}
+
+#[derive(Serialize)]
+struct Target {
+    hashes: BTreeMap<&'static str, String>,
+    length: usize,
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+    _type: &'static str,
+    expires: String,
+    meta: BTreeMap<String, SnapshotMeta>,
+    spec_version: &'static str,
+    version: i64,
+}
+
+#[derive(Serialize)]
+struct SnapshotMeta {
+    version: i64,
+}
+
+#[derive(Serialize)]
+struct Timestamp {
+    _type: &'static str,
+    expires: String,
+    meta: BTreeMap<&'static str, TimestampMeta>,
+    spec_version: &'static str,
+    version: i64,
+}
+
+#[derive(Serialize)]
+struct TimestampMeta {
+    hashes: BTreeMap<&'static str, String>,
+    length: usize,
+    version: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use insta::assert_json_snapshot;
+    use ring::signature::{UnparsedPublicKey, ED25519};
+    use serde_json::Value;
+
+    fn signer() -> IndexSigner {
+        IndexSigner::from_seed(&[42; 32]).unwrap()
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 2, 13, 12, 0, 0).unwrap()
+    }
+
+    /// Checks the signature of the `signed` object in the given document.
+    fn verify(signer: &IndexSigner, document: &str) -> Value {
+        let document: Value = serde_json::from_str(document).unwrap();
+
+        let signature = &document["signatures"][0];
+        assert_eq!(signature["keyid"], signer.key_id());
+
+        let signature = hex::decode(signature["sig"].as_str().unwrap()).unwrap();
+        let message = serde_json::to_vec(&document["signed"]).unwrap();
+
+        let public_key = hex::decode(signer.public_key()).unwrap();
+        let public_key = UnparsedPublicKey::new(&ED25519, public_key);
+        public_key.verify(&message, &signature).unwrap();
+
+        document["signed"].clone()
+    }
+
+    #[test]
+    fn test_root() {
+        let signer = signer();
+        let root = signer.root(2, now()).unwrap();
+        assert_json_snapshot!(verify(&signer, &root));
+    }
+
+    #[test]
+    fn test_targets() {
+        let signer = signer();
+        let targets = signer.targets("3/f/foo", b"{}\n", 1, now()).unwrap();
+        assert_json_snapshot!(verify(&signer, &targets));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let signer = signer();
+        let targets = BTreeMap::from([("targets/3/f/foo.json".to_string(), 3)]);
+        let snapshot = signer.snapshot(targets, 5, now()).unwrap();
+        assert_json_snapshot!(verify(&signer, &snapshot));
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let signer = signer();
+        let timestamp = signer.timestamp("{}", 5, 6, now()).unwrap();
+        assert_json_snapshot!(verify(&signer, &timestamp));
+    }
+
+    #[test]
+    fn test_root_needs_update() {
+        let signer = signer();
+        let root = signer.root(1, now()).unwrap();
+        let root = Some(root.as_bytes());
+
+        assert!(signer.root_needs_update(None, now()));
+        assert!(signer.root_needs_update(Some(b"{}"), now()));
+        assert!(!signer.root_needs_update(root, now()));
+        assert!(!signer.root_needs_update(root, now() + Duration::days(300)));
+        assert!(signer.root_needs_update(root, now() + Duration::days(340)));
+
+        let other_signer = IndexSigner::from_seed(&[7; 32]).unwrap();
+        assert!(other_signer.root_needs_update(root, now()));
+    }
+
+    #[test]
+    fn test_existing_version() {
+        let signer = signer();
+        let root = signer.root(3, now()).unwrap();
+        assert_eq!(existing_version(Some(root.as_bytes())), 3);
+        assert_eq!(existing_version(Some(b"{}")), 0);
+        assert_eq!(existing_version(None), 0);
+    }
+
+    #[test]
+    fn test_existing_snapshot_targets() {
+        let signer = signer();
+        let targets = BTreeMap::from([
+            ("targets/3/f/foo.json".to_string(), 3),
+            ("targets/3/b/bar.json".to_string(), 1),
+        ]);
+        let snapshot = signer.snapshot(targets.clone(), 5, now()).unwrap();
+        assert_eq!(
+            existing_snapshot_targets(Some(snapshot.as_bytes())),
+            targets
+        );
+        assert_eq!(existing_snapshot_targets(Some(b"{}")), BTreeMap::new());
+        assert_eq!(existing_snapshot_targets(None), BTreeMap::new());
+    }
+
+    #[test]
+    fn test_tampered_document() {
+        let signer = signer();
+        let targets = signer.targets("3/f/foo", b"{}\n", 1, now()).unwrap();
+        let tampered = targets.replace("\"length\":3", "\"length\":4");
+        assert_ne!(targets, tampered);
+
+        let document: Value = serde_json::from_str(&tampered).unwrap();
+        let signature = hex::decode(document["signatures"][0]["sig"].as_str().unwrap()).unwrap();
+        let message = serde_json::to_vec(&document["signed"]).unwrap();
+
+        let public_key = hex::decode(signer.public_key()).unwrap();
+        let public_key = UnparsedPublicKey::new(&ED25519, public_key);
+        assert!(public_key.verify(&message, &signature).is_err());
+    }
+
+    #[test]
+    fn test_targets_path() {
+        assert_eq!(targets_path("3/f/foo"), "_tuf/targets/3/f/foo.json");
+    }
+}
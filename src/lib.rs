@@ -32,6 +32,7 @@ pub mod admin;
 mod app;
 pub mod auth;
 pub mod boot;
+pub mod cdn_invalidation;
 pub mod certs;
 pub mod ci;
 pub mod cloudfront;
@@ -43,6 +44,7 @@ pub mod email;
 pub mod external_urls;
 pub mod fastly;
 pub mod headers;
+pub mod index_signing;
 mod licenses;
 pub mod metrics;
 pub mod middleware;
@@ -60,6 +62,7 @@ pub mod tasks;
 pub mod team_repo;
 mod test_util;
 pub mod typosquat;
+pub mod upstream_registry;
 pub mod util;
 pub mod views;
 pub mod worker;
@@ -11,7 +11,10 @@
 //! instance-level metric, and you should add it to `src/metrics/instance.rs`.
 
 use crate::metrics::macros::metrics;
-use crate::schema::{background_jobs, crates, versions};
+use crate::models::{DownloadAnomalyKind, StorageAuditFailureKind};
+use crate::schema::{
+    background_jobs, crates, download_anomalies, storage_audit_failures, versions,
+};
 use crate::util::errors::AppResult;
 use diesel::{dsl::count_star, prelude::*, PgConnection};
 use prometheus::{proto::MetricFamily, IntGauge, IntGaugeVec};
@@ -24,6 +27,10 @@ metrics! {
         versions_total: IntGauge,
         /// Number of queued up background jobs
         background_jobs: IntGaugeVec["priority", "job"],
+        /// Number of crate files that failed the last storage integrity audit
+        storage_audit_failures: IntGaugeVec["kind"],
+        /// Number of flagged download anomalies that have not been dismissed
+        download_anomalies: IntGaugeVec["kind", "excluded"],
     }
 
     // All service metrics will be prefixed with this namespace.
@@ -52,6 +59,43 @@ impl ServiceMetrics {
                 .set(count);
         }
 
+        let storage_audit_failures = storage_audit_failures::table
+            .group_by(storage_audit_failures::kind)
+            .select((storage_audit_failures::kind, count_star()))
+            .load::<(StorageAuditFailureKind, i64)>(conn)?;
+        for kind in StorageAuditFailureKind::VARIANTS {
+            let count = storage_audit_failures
+                .iter()
+                .find(|(k, _)| k == kind)
+                .map_or(0, |(_, count)| *count);
+
+            self.storage_audit_failures
+                .get_metric_with_label_values(&[kind.as_str()])?
+                .set(count);
+        }
+
+        let download_anomalies = download_anomalies::table
+            .filter(download_anomalies::dismissed_at.is_null())
+            .group_by((download_anomalies::kind, download_anomalies::excluded))
+            .select((
+                download_anomalies::kind,
+                download_anomalies::excluded,
+                count_star(),
+            ))
+            .load::<(DownloadAnomalyKind, bool, i64)>(conn)?;
+        for kind in DownloadAnomalyKind::VARIANTS {
+            for excluded in [false, true] {
+                let count = download_anomalies
+                    .iter()
+                    .find(|(k, e, _)| k == kind && *e == excluded)
+                    .map_or(0, |(_, _, count)| *count);
+
+                self.download_anomalies
+                    .get_metric_with_label_values(&[&kind.to_string(), &excluded.to_string()])?
+                    .set(count);
+            }
+        }
+
         Ok(self.registry.gather())
     }
 }
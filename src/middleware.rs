@@ -8,6 +8,7 @@ mod ember_html;
 pub mod log_request;
 pub mod normalize_path;
 pub mod real_ip;
+mod require_auth;
 mod require_user_agent;
 pub mod session;
 mod static_or_continue;
@@ -64,7 +65,10 @@ pub fn apply_axum_middleware(state: AppState, router: Router<()>) -> Router {
 
     let middlewares_2 = tower::ServiceBuilder::new()
         .layer(from_fn_with_state(
-            state.config.cargo_compat_status_code_config,
+            cargo_compat::CargoCompatConfig {
+                status_code_config: state.config.cargo_compat_status_code_config,
+                auth_required: state.config.auth_required,
+            },
             cargo_compat::middleware,
         ))
         .layer(from_fn_with_state(state.clone(), session::attach_session))
@@ -95,7 +99,10 @@ pub fn apply_axum_middleware(state: AppState, router: Router<()>) -> Router {
         // Because such a large portion of production traffic is for download requests (which update
         // download counts), we consider only the primary pool here.
         .layer(conditional_layer(capacity >= 10, || {
-            from_fn_with_state(state, balance_capacity::balance_capacity)
+            from_fn_with_state(state.clone(), balance_capacity::balance_capacity)
+        }))
+        .layer(conditional_layer(config.auth_required, || {
+            from_fn_with_state(state, require_auth::require_auth)
         }));
 
     router
@@ -2,7 +2,7 @@ use axum::extract::{MatchedPath, Request, State};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
-use http::{header, Method, StatusCode};
+use http::{header, HeaderValue, Method, StatusCode};
 use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug)]
@@ -33,9 +33,18 @@ impl FromStr for StatusCodeConfig {
     }
 }
 
+/// The configuration of the cargo compatibility [middleware].
+#[derive(Clone, Copy, Debug)]
+pub struct CargoCompatConfig {
+    pub status_code_config: StatusCodeConfig,
+    /// Whether the registry requires authentication for all requests, in
+    /// which case `401 Unauthorized` responses are passed through to cargo.
+    pub auth_required: bool,
+}
+
 /// Convert plain text errors into JSON errors and adjust status codes.
 pub async fn middleware(
-    State(config): State<StatusCodeConfig>,
+    State(config): State<CargoCompatConfig>,
     matched_path: Option<Extension<MatchedPath>>,
     req: Request,
     next: Next,
@@ -49,6 +58,14 @@ pub async fn middleware(
     if is_api_request {
         res = ensure_json_errors(res).await;
     }
+    if config.auth_required && res.status() == StatusCode::UNAUTHORIZED {
+        // cargo only retries a request with the credentials of the registry
+        // if it receives a `401 Unauthorized` response, so the status code
+        // must not be adjusted for registries that require authentication
+        // (see https://rust-lang.github.io/rfcs/3139-cargo-alternative-registry-auth.html).
+        ensure_www_authenticate(&mut res);
+        return res;
+    }
     if is_cargo_endpoint {
         // cargo until 1.34.0 expected crates.io to always return 200 OK for
         // all requests, even if they failed. If a different status code was
@@ -61,7 +78,7 @@ pub async fn middleware(
         // but for backwards compatibility we still return "200 OK" for now for
         // all endpoints that are relevant for cargo.
         let adjust_status_code = matches!(
-            (config, res.status().is_success()),
+            (config.status_code_config, res.status().is_success()),
             (StatusCodeConfig::AdjustAll, _) | (StatusCodeConfig::AdjustSuccess, true)
         );
         if adjust_status_code {
@@ -88,6 +105,14 @@ fn is_cargo_endpoint(method: &Method, path: &str) -> bool {
         .any(|(m, p)| m == method && p == &path)
 }
 
+/// Add the `WWW-Authenticate: Cargo` challenge that cargo expects for
+/// `401 Unauthorized` responses, unless the response already contains one.
+fn ensure_www_authenticate(res: &mut Response) {
+    res.headers_mut()
+        .entry(header::WWW_AUTHENTICATE)
+        .or_insert(HeaderValue::from_static("Cargo"));
+}
+
 /// Convert plain text errors into JSON errors.
 ///
 /// The built-in extractors in [axum] return plain text errors, but our API
@@ -141,6 +166,15 @@ mod tests {
     use tower::ServiceExt;
 
     fn build_app() -> Router {
+        build_app_with_auth_required(true)
+    }
+
+    fn build_app_with_auth_required(auth_required: bool) -> Router {
+        let config = CargoCompatConfig {
+            status_code_config: StatusCodeConfig::AdjustAll,
+            auth_required,
+        };
+
         let okay = get(|| async { "Everything is okay" });
         let teapot = get(|| async { (StatusCode::IM_A_TEAPOT, "I'm a teapot") });
         let internal =
@@ -157,7 +191,11 @@ mod tests {
                 "/api/v1/crates/:crate_id/owners",
                 get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
             )
-            .layer(from_fn_with_state(StatusCodeConfig::AdjustAll, middleware))
+            .route(
+                "/api/v1/crates",
+                get(|| async { (StatusCode::UNAUTHORIZED, "Unauthorized") }),
+            )
+            .layer(from_fn_with_state(config, middleware))
     }
 
     async fn request(path: &str) -> anyhow::Result<(Parts, Bytes)> {
@@ -169,11 +207,19 @@ mod tests {
     }
 
     async fn request_inner(method: Method, path: &str) -> anyhow::Result<(Parts, Bytes)> {
+        request_with_app(build_app(), method, path).await
+    }
+
+    async fn request_with_app(
+        app: Router,
+        method: Method,
+        path: &str,
+    ) -> anyhow::Result<(Parts, Bytes)> {
         let request = Request::builder()
             .method(method)
             .uri(path)
             .body(Body::empty())?;
-        let response = build_app().oneshot(request).await?;
+        let response = app.oneshot(request).await?;
         let (parts, body) = response.into_parts();
         let bytes = axum::body::to_bytes(body, usize::MAX).await?;
         Ok((parts, bytes))
@@ -252,4 +298,39 @@ mod tests {
         let (parts, _bytes) = request("/api/v1/crates/foo/owners").await.unwrap();
         assert_eq!(parts.status, StatusCode::OK);
     }
+
+    /// Check that `401 Unauthorized` responses keep their status code, even
+    /// for cargo endpoints, and contain the challenge that cargo expects.
+    #[tokio::test]
+    async fn test_unauthorized() {
+        let (parts, bytes) = request("/api/v1/crates").await.unwrap();
+        assert_eq!(parts.status, StatusCode::UNAUTHORIZED);
+        assert_debug_snapshot!(parts.headers, @r###"
+        {
+            "content-type": "application/json",
+            "www-authenticate": "Cargo",
+            "content-length": "38",
+        }
+        "###);
+        assert_debug_snapshot!(bytes, @r###"b"{\"errors\":[{\"detail\":\"Unauthorized\"}]}""###);
+    }
+
+    /// Check that `401 Unauthorized` responses of cargo endpoints are
+    /// adjusted like any other error if the registry does not require
+    /// authentication, since older cargo versions expect `200 OK`.
+    #[tokio::test]
+    async fn test_unauthorized_without_auth_required() {
+        let app = build_app_with_auth_required(false);
+        let (parts, bytes) = request_with_app(app, Method::GET, "/api/v1/crates")
+            .await
+            .unwrap();
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_debug_snapshot!(parts.headers, @r###"
+        {
+            "content-type": "application/json",
+            "content-length": "38",
+        }
+        "###);
+        assert_debug_snapshot!(bytes, @r###"b"{\"errors\":[{\"detail\":\"Unauthorized\"}]}""###);
+    }
 }
@@ -0,0 +1,61 @@
+//! Middleware that requires authentication for all read requests to the API
+//! and the sparse index, if the registry is configured with `AUTH_REQUIRED`.
+//!
+//! Requests without any credentials are rejected with `401 Unauthorized`,
+//! which makes cargo retry them with the token of the registry (see
+//! [RFC 3139](https://rust-lang.github.io/rfcs/3139-cargo-alternative-registry-auth.html)).
+//! All other requests go through an [AuthCheck] with the
+//! [read-only](EndpointScope::ReadOnly) endpoint scope. Write requests are
+//! not affected, since their controllers already perform their own checks.
+
+use crate::app::AppState;
+use crate::auth::AuthCheck;
+use crate::middleware::log_request::RequestLogExt;
+use crate::middleware::session::RequestSession;
+use crate::models::token::EndpointScope;
+use crate::tasks::spawn_blocking;
+use crate::util::errors::{unauthorized, BoxedAppError};
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::{header, Method};
+
+pub async fn require_auth(state: AppState, req: Request, next: Next) -> Response {
+    if !is_read_request(&req) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+
+    let has_credentials = parts.headers.contains_key(header::AUTHORIZATION)
+        || parts.session().get("user_id").is_some();
+
+    if !has_credentials {
+        parts
+            .request_log()
+            .add("cause", "no credentials for authenticated registry");
+        return unauthorized().into_response();
+    }
+
+    let result = spawn_blocking(move || {
+        let conn = &mut *state.db_read_prefer_primary()?;
+
+        AuthCheck::default()
+            .with_endpoint_scope(EndpointScope::ReadOnly)
+            .check(&parts, conn)?;
+
+        Ok::<_, BoxedAppError>(parts)
+    })
+    .await;
+
+    match result {
+        Ok(parts) => next.run(Request::from_parts(parts, body)).await,
+        Err(error) => error.into_response(),
+    }
+}
+
+fn is_read_request(req: &Request) -> bool {
+    let path = req.uri().path();
+    let is_read_method = req.method() == Method::GET || req.method() == Method::HEAD;
+    is_read_method && (path.starts_with("/api/v1/") || path.starts_with("/index/"))
+}
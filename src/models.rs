@@ -2,13 +2,22 @@ pub use self::action::{insert_version_owner_action, VersionAction, VersionOwnerA
 pub use self::category::{Category, CrateCategory, NewCategory};
 pub use self::crate_owner_invitation::{CrateOwnerInvitation, NewCrateOwnerInvitationOutcome};
 pub use self::dependency::{Dependency, DependencyKind, ReverseDependency};
-pub use self::download::VersionDownload;
+pub use self::download::{
+    DownloadGranularity, NewVersionDownloadsArchive, VersionClientDownload, VersionDownload,
+    VersionDownloadsArchive,
+};
+pub use self::download_anomaly::{DownloadAnomaly, DownloadAnomalyKind, NewDownloadAnomaly};
 pub use self::email::{Email, NewEmail};
 pub use self::follow::Follow;
+pub use self::index_change::{IndexChange, IndexChangeKind, NewIndexChange};
 pub use self::keyword::{CrateKeyword, Keyword};
 pub use self::krate::{Crate, CrateVersions, NewCrate, RecentCrateDownloads};
 pub use self::owner::{CrateOwner, Owner, OwnerKind};
+pub use self::processed_cdn_log::{NewProcessedCdnLog, ProcessedCdnLog, ProcessedCdnLogStatus};
 pub use self::rights::Rights;
+pub use self::storage_audit_failure::{
+    NewStorageAuditFailure, StorageAuditFailure, StorageAuditFailureKind,
+};
 pub use self::team::{NewTeam, Team};
 pub use self::token::{ApiToken, CreatedApiToken};
 pub use self::user::{NewUser, User};
@@ -21,12 +30,16 @@ pub mod category;
 mod crate_owner_invitation;
 pub mod dependency;
 mod download;
+mod download_anomaly;
 mod email;
 mod follow;
+mod index_change;
 mod keyword;
 pub mod krate;
 mod owner;
+mod processed_cdn_log;
 mod rights;
+mod storage_audit_failure;
 mod team;
 pub mod token;
 pub mod user;
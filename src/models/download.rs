@@ -1,6 +1,14 @@
 use crate::models::Version;
-use crate::schema::version_downloads;
-use chrono::NaiveDate;
+use crate::schema::{
+    version_downloads, version_downloads_archives, version_downloads_by_client,
+    version_downloads_monthly, version_downloads_weekly, versions,
+};
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
 #[diesel(primary_key(version_id, date), belongs_to(Version))]
@@ -11,3 +19,183 @@ pub struct VersionDownload {
     pub date: NaiveDate,
     pub processed: bool,
 }
+
+/// The daily downloads of a version by a single kind of client, as counted
+/// from the CDN logs.
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, Clone)]
+#[diesel(
+    table_name = version_downloads_by_client,
+    primary_key(version_id, date, client),
+    belongs_to(Version),
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct VersionClientDownload {
+    pub version_id: i32,
+    pub date: NaiveDate,
+    pub client: String,
+    pub downloads: i32,
+}
+
+/// A month of daily downloads that has been moved from `version_downloads`
+/// and `version_downloads_by_client` to archive files in the storage bucket.
+#[derive(Queryable, Identifiable, Selectable, Debug, Clone)]
+#[diesel(
+    table_name = version_downloads_archives,
+    primary_key(month),
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct VersionDownloadsArchive {
+    pub month: NaiveDate,
+    pub path: String,
+    pub num_rows: i64,
+    pub archived_at: NaiveDateTime,
+    pub client_path: Option<String>,
+    pub num_client_rows: i64,
+}
+
+impl VersionDownloadsArchive {
+    /// Loads all archived months, oldest first.
+    pub fn all(conn: &mut PgConnection) -> QueryResult<Vec<Self>> {
+        version_downloads_archives::table
+            .select(Self::as_select())
+            .order(version_downloads_archives::month)
+            .load(conn)
+    }
+
+    /// Loads the archive of the month starting at `month`, if it has been
+    /// archived.
+    pub fn find(conn: &mut PgConnection, month: NaiveDate) -> QueryResult<Option<Self>> {
+        version_downloads_archives::table
+            .find(month)
+            .select(Self::as_select())
+            .first(conn)
+            .optional()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable, AsChangeset)]
+#[diesel(
+    table_name = version_downloads_archives,
+    treat_none_as_null = true,
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct NewVersionDownloadsArchive<'a> {
+    pub month: NaiveDate,
+    pub path: &'a str,
+    pub num_rows: i64,
+    pub client_path: Option<&'a str>,
+    pub num_client_rows: i64,
+}
+
+impl NewVersionDownloadsArchive<'_> {
+    /// Records that a month has been archived, replacing a previous record
+    /// of the same month.
+    pub fn record(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        diesel::insert_into(version_downloads_archives::table)
+            .values(self)
+            .on_conflict(version_downloads_archives::month)
+            .do_update()
+            .set((
+                self,
+                version_downloads_archives::archived_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// The granularity of the long-term download rollups in the
+/// `version_downloads_weekly` and `version_downloads_monthly` tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadGranularity {
+    Week,
+    Month,
+}
+
+impl DownloadGranularity {
+    pub const ALL: [Self; 2] = [Self::Week, Self::Month];
+
+    /// The name of the rollup table for this granularity.
+    pub fn table_name(self) -> &'static str {
+        match self {
+            Self::Week => "version_downloads_weekly",
+            Self::Month => "version_downloads_monthly",
+        }
+    }
+
+    /// Returns the first day of the period that contains `date`, matching
+    /// `date_trunc()` in Postgres.
+    pub fn period_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+            Self::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// Returns the first day of the period after the one starting at `start`.
+    pub fn next_period_start(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => start + Days::new(7),
+            Self::Month => start + Months::new(1),
+        }
+    }
+
+    /// Loads the downloads of all versions of a crate per period, for all
+    /// periods that start between `from` and `to` (inclusive).
+    pub fn crate_downloads(
+        self,
+        conn: &mut PgConnection,
+        crate_id: i32,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> QueryResult<Vec<(NaiveDate, i64)>> {
+        match self {
+            Self::Week => version_downloads_weekly::table
+                .inner_join(versions::table)
+                .filter(versions::crate_id.eq(crate_id))
+                .filter(version_downloads_weekly::date.between(from, to))
+                .group_by(version_downloads_weekly::date)
+                .select((
+                    version_downloads_weekly::date,
+                    sql::<BigInt>("SUM(version_downloads_weekly.downloads)::BIGINT"),
+                ))
+                .order(version_downloads_weekly::date)
+                .load(conn),
+            Self::Month => version_downloads_monthly::table
+                .inner_join(versions::table)
+                .filter(versions::crate_id.eq(crate_id))
+                .filter(version_downloads_monthly::date.between(from, to))
+                .group_by(version_downloads_monthly::date)
+                .select((
+                    version_downloads_monthly::date,
+                    sql::<BigInt>("SUM(version_downloads_monthly.downloads)::BIGINT"),
+                ))
+                .order(version_downloads_monthly::date)
+                .load(conn),
+        }
+    }
+}
+
+impl Display for DownloadGranularity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Week => f.write_str("week"),
+            Self::Month => f.write_str("month"),
+        }
+    }
+}
+
+impl FromStr for DownloadGranularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(format!(
+                "invalid `granularity` parameter `{s}`, expected `week` or `month`"
+            )),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::schema::download_anomalies;
+use crate::sql::pg_enum;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use std::fmt::{Display, Formatter};
+
+pg_enum! {
+    pub enum DownloadAnomalyKind {
+        ZScore = 0,
+        SingleDaySpike = 1,
+    }
+}
+
+impl Display for DownloadAnomalyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ZScore => f.write_str("z-score"),
+            Self::SingleDaySpike => f.write_str("single-day-spike"),
+        }
+    }
+}
+
+/// The daily downloads of a version that have been flagged as a statistical
+/// outlier by the `detect_download_anomalies` background job.
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable)]
+#[diesel(
+    table_name = download_anomalies,
+    primary_key(version_id, date),
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct DownloadAnomaly {
+    pub version_id: i32,
+    pub date: NaiveDate,
+    pub kind: DownloadAnomalyKind,
+    pub downloads: i32,
+    pub expected_downloads: i32,
+    pub score: f64,
+    pub excluded: bool,
+    pub detected_at: NaiveDateTime,
+    pub dismissed_at: Option<NaiveDateTime>,
+}
+
+impl DownloadAnomaly {
+    /// Sets whether the downloads above the expected downloads are excluded
+    /// from `recent_crate_downloads`, and returns `false` if the anomaly
+    /// does not exist.
+    ///
+    /// The materialized view is only refreshed by the next
+    /// `update_downloads` background job.
+    pub fn set_excluded(
+        conn: &mut PgConnection,
+        version_id: i32,
+        date: NaiveDate,
+        excluded: bool,
+    ) -> QueryResult<bool> {
+        let updated = diesel::update(download_anomalies::table.find((version_id, date)))
+            .set(download_anomalies::excluded.eq(excluded))
+            .execute(conn)?;
+
+        Ok(updated > 0)
+    }
+
+    /// Marks an anomaly as dismissed after it has been reviewed and found to
+    /// be legitimate, which also includes its downloads again, and returns
+    /// `false` if the anomaly does not exist or has been dismissed already.
+    ///
+    /// Dismissed anomalies are kept, so that the next
+    /// `detect_download_anomalies` background job does not flag them again.
+    pub fn dismiss(conn: &mut PgConnection, version_id: i32, date: NaiveDate) -> QueryResult<bool> {
+        let updated = diesel::update(download_anomalies::table.find((version_id, date)))
+            .filter(download_anomalies::dismissed_at.is_null())
+            .set((
+                download_anomalies::excluded.eq(false),
+                download_anomalies::dismissed_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+
+        Ok(updated > 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Insertable, AsChangeset)]
+#[diesel(table_name = download_anomalies, check_for_backend(diesel::pg::Pg))]
+pub struct NewDownloadAnomaly {
+    pub version_id: i32,
+    pub date: NaiveDate,
+    pub kind: DownloadAnomalyKind,
+    pub downloads: i32,
+    pub expected_downloads: i32,
+    pub score: f64,
+}
+
+impl NewDownloadAnomaly {
+    /// Records the anomaly, replacing a previously recorded anomaly of the
+    /// same version and date, but keeping whether it is excluded.
+    ///
+    /// Anomalies that have been dismissed are left untouched, in which case
+    /// `false` is returned.
+    pub fn upsert(&self, conn: &mut PgConnection) -> QueryResult<bool> {
+        use diesel::query_dsl::methods::FilterDsl;
+
+        let affected = diesel::insert_into(download_anomalies::table)
+            .values(self)
+            .on_conflict((download_anomalies::version_id, download_anomalies::date))
+            .do_update()
+            .set((self, download_anomalies::detected_at.eq(diesel::dsl::now)))
+            .filter(download_anomalies::dismissed_at.is_null())
+            .execute(conn)?;
+
+        Ok(affected > 0)
+    }
+}
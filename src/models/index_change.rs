@@ -0,0 +1,102 @@
+use crate::schema::index_changes;
+use crate::sql::pg_enum;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+pg_enum! {
+    pub enum IndexChangeKind {
+        Publish = 0,
+        Yank = 1,
+        Unyank = 2,
+        DeleteVersion = 3,
+        DeleteCrate = 4,
+    }
+}
+
+/// An entry of the append-only log of index changes, which backs the changes
+/// feed API for registry mirrors.
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable)]
+#[diesel(table_name = index_changes, check_for_backend(diesel::pg::Pg))]
+pub struct IndexChange {
+    pub id: i64,
+    pub crate_name: String,
+    pub version: Option<String>,
+    pub kind: IndexChangeKind,
+    pub created_at: NaiveDateTime,
+}
+
+impl IndexChange {
+    /// Returns up to `limit` changes with an `id` greater than `since`, in
+    /// the order in which they were recorded.
+    ///
+    /// The `id` of a change is taken from the `index_changes_counter` row,
+    /// which stays locked until the recording transaction finishes. The ids
+    /// are therefore assigned in commit order and without gaps, so a change
+    /// that is committed later can never end up behind a cursor.
+    pub fn since(conn: &mut PgConnection, since: i64, limit: i64) -> QueryResult<Vec<Self>> {
+        index_changes::table
+            .filter(index_changes::id.gt(since))
+            .order(index_changes::id)
+            .limit(limit)
+            .select(Self::as_select())
+            .load(conn)
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = index_changes, check_for_backend(diesel::pg::Pg))]
+pub struct NewIndexChange<'a> {
+    pub crate_name: &'a str,
+    pub version: Option<&'a str>,
+    pub kind: IndexChangeKind,
+}
+
+impl<'a> NewIndexChange<'a> {
+    pub fn new(crate_name: &'a str, version: Option<&'a str>, kind: IndexChangeKind) -> Self {
+        Self {
+            crate_name,
+            version,
+            kind,
+        }
+    }
+
+    pub fn insert(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        diesel::insert_into(index_changes::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_db_connection;
+    use diesel::result::Error;
+
+    #[test]
+    fn rolled_back_changes_leave_no_gap() {
+        let (_test_db, conn) = &mut test_db_connection();
+
+        let change = NewIndexChange::new("foo", Some("1.0.0"), IndexChangeKind::Publish);
+        change.insert(conn).unwrap();
+
+        let result = conn.transaction(|conn| {
+            let change = NewIndexChange::new("foo", Some("1.1.0"), IndexChangeKind::Publish);
+            change.insert(conn)?;
+            Err::<(), _>(Error::RollbackTransaction)
+        });
+        assert!(result.is_err());
+
+        let change = NewIndexChange::new("foo", Some("1.2.0"), IndexChangeKind::Publish);
+        change.insert(conn).unwrap();
+
+        let changes = IndexChange::since(conn, 0, 10).unwrap();
+        let changes = changes
+            .iter()
+            .map(|change| (change.id, change.version.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [(1, "1.0.0"), (2, "1.2.0")]);
+    }
+}
@@ -501,6 +501,8 @@ impl Crate {
 
                 deps.sort();
 
+                let pubtime = Some(version.pubtime());
+
                 let features: BTreeMap<String, Vec<String>> =
                     serde_json::from_value(version.features).unwrap_or_default();
                 let (features, features2): (BTreeMap<_, _>, BTreeMap<_, _>) =
@@ -525,6 +527,7 @@ impl Crate {
                     features,
                     links: version.links,
                     rust_version: version.rust_version,
+                    pubtime,
                     features2,
                     v,
                 };
@@ -0,0 +1,115 @@
+use crate::schema::processed_cdn_logs;
+use crate::sql::pg_enum;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use std::fmt::{Display, Formatter};
+
+pg_enum! {
+    pub enum ProcessedCdnLogStatus {
+        Counted = 0,
+        Empty = 1,
+    }
+}
+
+impl Display for ProcessedCdnLogStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Counted => f.write_str("counted"),
+            Self::Empty => f.write_str("empty"),
+        }
+    }
+}
+
+/// A CDN log file whose downloads have already been counted.
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable)]
+#[diesel(
+    table_name = processed_cdn_logs,
+    primary_key(bucket, path),
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct ProcessedCdnLog {
+    pub bucket: String,
+    pub path: String,
+    pub region: String,
+    pub status: ProcessedCdnLogStatus,
+    pub checksum: Option<String>,
+    pub num_downloads: i64,
+    pub num_inserts: i32,
+    pub processed_at: NaiveDateTime,
+}
+
+impl ProcessedCdnLog {
+    pub fn find(conn: &mut PgConnection, bucket: &str, path: &str) -> QueryResult<Option<Self>> {
+        processed_cdn_logs::table
+            .find((bucket, path))
+            .select(Self::as_select())
+            .first(conn)
+            .optional()
+    }
+
+    /// Loads the most recently processed log files, optionally only the ones
+    /// in the given bucket.
+    pub fn recent(
+        conn: &mut PgConnection,
+        bucket: Option<&str>,
+        limit: i64,
+    ) -> QueryResult<Vec<Self>> {
+        let mut query = processed_cdn_logs::table
+            .select(Self::as_select())
+            .order(processed_cdn_logs::processed_at.desc())
+            .limit(limit)
+            .into_boxed();
+
+        if let Some(bucket) = bucket {
+            query = query.filter(processed_cdn_logs::bucket.eq(bucket));
+        }
+
+        query.load(conn)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable, AsChangeset)]
+#[diesel(
+    table_name = processed_cdn_logs,
+    treat_none_as_null = true,
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct NewProcessedCdnLog<'a> {
+    pub bucket: &'a str,
+    pub path: &'a str,
+    pub region: &'a str,
+    pub status: ProcessedCdnLogStatus,
+    pub checksum: Option<&'a str>,
+    pub num_downloads: i64,
+    pub num_inserts: i32,
+}
+
+impl NewProcessedCdnLog<'_> {
+    /// Records the log file in the ledger, and returns `false` if it has
+    /// already been recorded before.
+    ///
+    /// Since the insert waits for concurrent transactions that recorded the
+    /// same log file, this can be used to make sure that the downloads of a
+    /// log file are only saved once.
+    pub fn insert(&self, conn: &mut PgConnection) -> QueryResult<bool> {
+        let num_inserted = diesel::insert_into(processed_cdn_logs::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok(num_inserted > 0)
+    }
+
+    /// Records the log file in the ledger, replacing a previous entry of the
+    /// same log file.
+    pub fn upsert(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        diesel::insert_into(processed_cdn_logs::table)
+            .values(self)
+            .on_conflict((processed_cdn_logs::bucket, processed_cdn_logs::path))
+            .do_update()
+            .set((self, processed_cdn_logs::processed_at.eq(diesel::dsl::now)))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::schema::storage_audit_failures;
+use crate::sql::pg_enum;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+pg_enum! {
+    pub enum StorageAuditFailureKind {
+        Missing = 0,
+        Truncated = 1,
+        Mismatched = 2,
+    }
+}
+
+impl StorageAuditFailureKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Truncated => "truncated",
+            Self::Mismatched => "mismatched",
+        }
+    }
+}
+
+/// A `.crate` file that did not match the checksum recorded in the
+/// `versions` table during the last storage integrity audit.
+#[derive(Debug, Clone, Queryable, Identifiable, Selectable)]
+#[diesel(
+    table_name = storage_audit_failures,
+    primary_key(version_id),
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct StorageAuditFailure {
+    pub version_id: i32,
+    pub kind: StorageAuditFailureKind,
+    pub actual_checksum: Option<String>,
+    pub actual_size: Option<i64>,
+    pub detected_at: NaiveDateTime,
+}
+
+impl StorageAuditFailure {
+    /// Removes the failures of the given versions, after their `.crate`
+    /// files have passed the audit again.
+    pub fn resolve(conn: &mut PgConnection, version_ids: &[i32]) -> QueryResult<usize> {
+        let failures = storage_audit_failures::table
+            .filter(storage_audit_failures::version_id.eq_any(version_ids));
+
+        diesel::delete(failures).execute(conn)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable, AsChangeset)]
+#[diesel(
+    table_name = storage_audit_failures,
+    treat_none_as_null = true,
+    check_for_backend(diesel::pg::Pg)
+)]
+pub struct NewStorageAuditFailure {
+    pub version_id: i32,
+    pub kind: StorageAuditFailureKind,
+    pub actual_checksum: Option<String>,
+    pub actual_size: Option<i64>,
+}
+
+impl NewStorageAuditFailure {
+    /// Records the failure, replacing a previously recorded failure of the
+    /// same version.
+    pub fn upsert(&self, conn: &mut PgConnection) -> QueryResult<()> {
+        diesel::insert_into(storage_audit_failures::table)
+            .values(self)
+            .on_conflict(storage_audit_failures::version_id)
+            .do_update()
+            .set((
+                self,
+                storage_audit_failures::detected_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
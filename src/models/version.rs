@@ -98,6 +98,12 @@ impl TopVersions {
 }
 
 impl Version {
+    /// Returns the publication time of this version in the RFC 3339 format
+    /// that is used for the `pubtime` field of the index.
+    pub fn pubtime(&self) -> String {
+        self.created_at.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+    }
+
     /// Returns (dependency, crate dependency name)
     pub fn dependencies(&self, conn: &mut PgConnection) -> QueryResult<Vec<(Dependency, String)>> {
         Dependency::belonging_to(self)
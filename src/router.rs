@@ -123,6 +123,7 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
             put(user::me::update_email_notifications),
         )
         .route("/api/v1/summary", get(krate::metadata::summary))
+        .route("/api/v1/changes", get(changes::list))
         .route(
             "/api/v1/confirm/:email_token",
             put(user::me::confirm_user_email),
@@ -166,6 +167,13 @@ pub fn build_axum_router(state: AppState) -> Router<()> {
         );
     }
 
+    // Serve the sparse index from the index bucket in development mode and for
+    // self-hosted deployments. For crates.io, the sparse index is served by
+    // the CDN at https://index.crates.io instead.
+    if state.config.sparse_index.serve {
+        router = router.route("/index/*path", get(sparse_index::serve));
+    }
+
     router
         .fallback(|method: Method| async move {
             match method {
@@ -84,6 +84,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Jobs that have to complete successfully before another job can run. Rows are deleted automatically once the dependency job has completed.
+    background_job_dependencies (job_id, dependency_id) {
+        /// The job that is waiting for the dependency to complete.
+        job_id -> Int8,
+        /// The job that has to complete successfully first.
+        dependency_id -> Int8,
+    }
+}
+
 diesel::table! {
     /// Representation of the `background_jobs` table.
     ///
@@ -131,6 +141,12 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         priority -> Int2,
+        /// The `run_at` column of the `background_jobs` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        run_at -> Timestamp,
     }
 }
 
@@ -496,6 +512,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Daily downloads of versions that have been flagged as statistical outliers by the download anomaly detection, for review by the crates.io team.
+    download_anomalies (version_id, date) {
+        /// Version whose daily downloads have been flagged.
+        version_id -> Int4,
+        /// Date of the flagged daily downloads.
+        date -> Date,
+        /// Kind of the anomaly (0 = z-score outlier against the history of the version, 1 = single-day spike against the history of the crate).
+        kind -> Int4,
+        /// Number of downloads of the version on that date when the anomaly was detected.
+        downloads -> Int4,
+        /// Number of downloads that would have been expected on that date based on the history.
+        expected_downloads -> Int4,
+        /// Z-score of the downloads (kind 0), or ratio of the downloads to the average daily downloads of the crate (kind 1).
+        score -> Float8,
+        /// If true, only the expected downloads of that date are counted in `recent_crate_downloads`, which is used for the search ranking.
+        excluded -> Bool,
+        /// Time at which the anomaly was last detected.
+        detected_at -> Timestamp,
+        /// Time at which the anomaly has been reviewed and found to be legitimate, or NULL if it has not been dismissed. Dismissed anomalies are kept, so that they are not flagged again by the next detection run.
+        dismissed_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     /// Representation of the `emails` table.
     ///
@@ -560,6 +600,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Append-only log of all changes to the package index, used for the changes feed API of registry mirrors.
+    index_changes (id) {
+        /// Gap-free identifier in commit order, used as the cursor of the changes feed. It is assigned from `index_changes_counter` by the `trigger_index_changes_set_id` trigger.
+        id -> Int8,
+        /// Name of the crate that was changed. This is intentionally not a foreign key, since the crate might have been deleted.
+        crate_name -> Varchar,
+        /// Version that was changed, or NULL if the change affects the whole crate.
+        version -> Nullable<Varchar>,
+        /// Kind of the change (0 = publish, 1 = yank, 2 = unyank, 3 = delete version, 4 = delete crate).
+        kind -> Int4,
+        /// Time at which the change was made.
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Single row holding the `id` of the last recorded index change. Updating the row locks it until the recording transaction finishes, so that concurrent changes get their `id` in commit order and a rolled back change does not leave a gap.
+    index_changes_counter (last_id) {
+        /// The `id` of the last recorded index change.
+        last_id -> Int8,
+    }
+}
+
 diesel::table! {
     /// Representation of the `keywords` table.
     ///
@@ -606,6 +670,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Ledger of all CDN log files whose downloads have been counted, to make sure that every file is only counted once.
+    processed_cdn_logs (bucket, path) {
+        /// Name of the bucket that contains the log file.
+        bucket -> Varchar,
+        /// Path of the log file within the bucket.
+        path -> Varchar,
+        /// Region of the bucket, which is needed to re-process the log file.
+        region -> Varchar,
+        /// Result of the processing (0 = counted, 1 = empty).
+        status -> Int4,
+        /// ETag of the log file as reported by the object store, or NULL if the object store did not report one.
+        checksum -> Nullable<Varchar>,
+        /// Total number of downloads that were counted in the log file.
+        num_downloads -> Int8,
+        /// Number of crate, version and date combinations that were counted in the log file.
+        num_inserts -> Int4,
+        /// Time at which the log file was last processed.
+        processed_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `publish_limit_buckets` table.
     ///
@@ -722,6 +808,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Report of all crate files in the storage that did not match the recorded checksum during the last storage integrity audit.
+    storage_audit_failures (version_id) {
+        /// Version whose crate file failed the audit. The row is removed once the crate file passes the audit again.
+        version_id -> Int4,
+        /// Kind of the failure (0 = missing, 1 = truncated, 2 = mismatched).
+        kind -> Int4,
+        /// SHA256 checksum of the stored crate file, or NULL if the file is missing.
+        actual_checksum -> Nullable<Bpchar>,
+        /// Size of the stored crate file in bytes, or NULL if the file is missing.
+        actual_size -> Nullable<Int8>,
+        /// Time at which the failure was last detected.
+        detected_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `teams` table.
     ///
@@ -854,6 +956,62 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Months of daily downloads that have been moved from `version_downloads` and `version_downloads_by_client` to compressed CSV files in the storage bucket.
+    version_downloads_archives (month) {
+        /// First day of the archived month.
+        month -> Date,
+        /// Path of the archive file of the daily downloads in the storage bucket (e.g. `archive/version-downloads/2023-01/20230501T120000-1a2b3c4d.csv.gz`). Every archival run writes new files, and the paths are only switched once the archived rows have been deleted.
+        path -> Varchar,
+        /// Number of daily download rows in the archive file.
+        num_rows -> Int8,
+        /// Date and time when the month was archived.
+        archived_at -> Timestamp,
+        /// Path of the archive file of the per-client breakdown of the daily downloads in the storage bucket, or NULL if there were no per-client downloads.
+        client_path -> Nullable<Varchar>,
+        /// Number of per-client download rows in the archive file at `client_path`.
+        num_client_rows -> Int8,
+    }
+}
+
+diesel::table! {
+    /// Breakdown of the daily downloads of each version by the kind of client, as counted from the CDN logs.
+    version_downloads_by_client (version_id, date, client) {
+        /// Version that was downloaded.
+        version_id -> Int4,
+        /// Date of the downloads (UTC).
+        date -> Date,
+        /// Kind of client that downloaded the version, derived from its user agent (e.g. `cargo/1.76`, `cargo/nightly`, `mirror/panamax` or `other`). Stable cargo releases are bucketed by their minor version, and pre-releases by their release channel.
+        client -> Varchar,
+        /// Number of downloads by this kind of client on this date.
+        downloads -> Int4,
+    }
+}
+
+diesel::table! {
+    /// Number of downloads of each version per month, rolled up from `version_downloads`.
+    version_downloads_monthly (version_id, date) {
+        /// Version that was downloaded.
+        version_id -> Int4,
+        /// First day of the month (UTC).
+        date -> Date,
+        /// Number of downloads in this month.
+        downloads -> Int8,
+    }
+}
+
+diesel::table! {
+    /// Number of downloads of each version per week, rolled up from `version_downloads`.
+    version_downloads_weekly (version_id, date) {
+        /// Version that was downloaded.
+        version_id -> Int4,
+        /// First day (Monday) of the week (UTC).
+        date -> Date,
+        /// Number of downloads in this week.
+        downloads -> Int8,
+    }
+}
+
 diesel::table! {
     /// Representation of the `version_owner_actions` table.
     ///
@@ -1032,6 +1190,7 @@ diesel::joinable!(crates_keywords -> crates (crate_id));
 diesel::joinable!(crates_keywords -> keywords (keyword_id));
 diesel::joinable!(dependencies -> crates (crate_id));
 diesel::joinable!(dependencies -> versions (version_id));
+diesel::joinable!(download_anomalies -> versions (version_id));
 diesel::joinable!(emails -> users (user_id));
 diesel::joinable!(follows -> crates (crate_id));
 diesel::joinable!(follows -> users (user_id));
@@ -1039,7 +1198,11 @@ diesel::joinable!(publish_limit_buckets -> users (user_id));
 diesel::joinable!(publish_rate_overrides -> users (user_id));
 diesel::joinable!(readme_renderings -> versions (version_id));
 diesel::joinable!(recent_crate_downloads -> crates (crate_id));
+diesel::joinable!(storage_audit_failures -> versions (version_id));
 diesel::joinable!(version_downloads -> versions (version_id));
+diesel::joinable!(version_downloads_by_client -> versions (version_id));
+diesel::joinable!(version_downloads_monthly -> versions (version_id));
+diesel::joinable!(version_downloads_weekly -> versions (version_id));
 diesel::joinable!(version_owner_actions -> api_tokens (api_token_id));
 diesel::joinable!(version_owner_actions -> users (user_id));
 diesel::joinable!(version_owner_actions -> versions (version_id));
@@ -1049,6 +1212,7 @@ diesel::joinable!(versions_published_by -> versions (version_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     api_tokens,
+    background_job_dependencies,
     background_jobs,
     badges,
     categories,
@@ -1058,18 +1222,27 @@ diesel::allow_tables_to_appear_in_same_query!(
     crates_categories,
     crates_keywords,
     dependencies,
+    download_anomalies,
     emails,
     follows,
+    index_changes,
+    index_changes_counter,
     keywords,
     metadata,
+    processed_cdn_logs,
     publish_limit_buckets,
     publish_rate_overrides,
     readme_renderings,
     recent_crate_downloads,
     reserved_crate_names,
+    storage_audit_failures,
     teams,
     users,
     version_downloads,
+    version_downloads_archives,
+    version_downloads_by_client,
+    version_downloads_monthly,
+    version_downloads_weekly,
     version_owner_actions,
     versions,
     versions_published_by,
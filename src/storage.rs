@@ -1,5 +1,7 @@
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use crates_io_env_vars::required_var;
+use futures_util::stream::BoxStream;
 use futures_util::{StreamExt, TryStreamExt};
 use hyper::body::Bytes;
 use object_store::aws::{AmazonS3, AmazonS3Builder};
@@ -15,10 +17,18 @@ use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use self::replicated::{ReplicatedStore, SharedFailureHandler};
+pub use self::replicated::{ReplicationFailureHandler, StorageBucket};
+
+mod replicated;
 
 const PREFIX_CRATES: &str = "crates";
 const PREFIX_READMES: &str = "readmes";
+const PREFIX_DB_DUMP: &str = "db-dump";
+const PREFIX_INDEX: &str = "index";
+const PREFIX_ARCHIVE: &str = "archive";
 const DEFAULT_REGION: &str = "us-west-1";
 const CONTENT_TYPE_CRATE: &str = "application/gzip";
 const CONTENT_TYPE_DB_DUMP: &str = "application/gzip";
@@ -33,6 +43,8 @@ type StdPath = std::path::Path;
 #[derive(Debug)]
 pub struct StorageConfig {
     backend: StorageBackend,
+    /// An optional secondary backend that all writes are replicated to.
+    replica: Option<StorageBackend>,
     pub cdn_prefix: Option<String>,
 }
 
@@ -56,11 +68,23 @@ impl StorageConfig {
     pub fn in_memory() -> Self {
         Self {
             backend: StorageBackend::InMemory,
+            replica: None,
+            cdn_prefix: None,
+        }
+    }
+
+    /// An in-memory storage that is replicated to a second in-memory store.
+    pub fn in_memory_replicated() -> Self {
+        Self {
+            backend: StorageBackend::InMemory,
+            replica: Some(StorageBackend::InMemory),
             cdn_prefix: None,
         }
     }
 
     pub fn from_environment() -> Self {
+        let replica = replica_from_environment();
+
         if let Ok(bucket) = dotenvy::var("S3_BUCKET") {
             let region = dotenvy::var("S3_REGION").ok();
             let cdn_prefix = dotenvy::var("S3_CDN").ok();
@@ -68,27 +92,11 @@ impl StorageConfig {
             let index_bucket = required_var("S3_INDEX_BUCKET").unwrap();
             let index_region = dotenvy::var("S3_INDEX_REGION").ok();
 
-            let access_key = required_var("AWS_ACCESS_KEY").unwrap();
-            let secret_key: SecretString = required_var("AWS_SECRET_KEY").unwrap().into();
-
-            let default = S3Config {
-                bucket,
-                region,
-                access_key: access_key.clone(),
-                secret_key: secret_key.clone(),
-            };
-
-            let index = S3Config {
-                bucket: index_bucket,
-                region: index_region,
-                access_key,
-                secret_key,
-            };
-
-            let backend = StorageBackend::S3 { default, index };
+            let backend = s3_backend(bucket, region, index_bucket, index_region);
 
             return Self {
                 backend,
+                replica,
                 cdn_prefix,
             };
         }
@@ -103,11 +111,60 @@ impl StorageConfig {
 
         Self {
             backend,
+            replica,
             cdn_prefix: None,
         }
     }
 }
 
+/// Load the optional secondary storage backend from the environment.
+///
+/// - `S3_REPLICA_BUCKET` and `S3_REPLICA_INDEX_BUCKET`: Replicate to these
+///   S3 buckets, optionally in the `S3_REPLICA_REGION` and
+///   `S3_REPLICA_INDEX_REGION` regions, using the same credentials as the
+///   primary buckets.
+/// - `STORAGE_REPLICA_PATH`: Replicate to this directory on the local file
+///   system instead.
+fn replica_from_environment() -> Option<StorageBackend> {
+    if let Ok(bucket) = dotenvy::var("S3_REPLICA_BUCKET") {
+        let region = dotenvy::var("S3_REPLICA_REGION").ok();
+
+        let index_bucket = required_var("S3_REPLICA_INDEX_BUCKET").unwrap();
+        let index_region = dotenvy::var("S3_REPLICA_INDEX_REGION").ok();
+
+        return Some(s3_backend(bucket, region, index_bucket, index_region));
+    }
+
+    let path = dotenvy::var("STORAGE_REPLICA_PATH").ok()?;
+    Some(StorageBackend::LocalFileSystem { path: path.into() })
+}
+
+fn s3_backend(
+    bucket: String,
+    region: Option<String>,
+    index_bucket: String,
+    index_region: Option<String>,
+) -> StorageBackend {
+    let access_key = required_var("AWS_ACCESS_KEY").unwrap();
+    let secret_key: SecretString = required_var("AWS_SECRET_KEY").unwrap().into();
+
+    let default = S3Config {
+        bucket,
+        region,
+        access_key: access_key.clone(),
+        secret_key: secret_key.clone(),
+    };
+
+    let index = S3Config {
+        bucket: index_bucket,
+        region: index_region,
+        access_key,
+        secret_key,
+    };
+
+    StorageBackend::S3 { default, index }
+}
+
 pub struct Storage {
     cdn_prefix: Option<String>,
 
@@ -118,17 +175,33 @@ pub struct Storage {
 
     index_store: Box<dyn ObjectStore>,
     index_upload_store: Box<dyn ObjectStore>,
+
+    replica: Option<Replica>,
 }
 
-impl Storage {
-    pub fn from_environment() -> Self {
-        Self::from_config(&StorageConfig::from_environment())
-    }
+/// The unwrapped primary and secondary stores of a replicated [Storage],
+/// which are used to repair the secondary store.
+struct Replica {
+    primary: Stores,
+    secondary: Stores,
+    handler: SharedFailureHandler,
+}
 
-    pub fn from_config(config: &StorageConfig) -> Self {
-        let cdn_prefix = config.cdn_prefix.clone();
+/// The object stores of a single [StorageBackend].
+#[derive(Clone)]
+struct Stores {
+    store: Arc<dyn ObjectStore>,
+    crate_upload_store: Arc<dyn ObjectStore>,
+    readme_upload_store: Arc<dyn ObjectStore>,
+    db_dump_upload_store: Arc<dyn ObjectStore>,
+
+    index_store: Arc<dyn ObjectStore>,
+    index_upload_store: Arc<dyn ObjectStore>,
+}
 
-        match &config.backend {
+impl Stores {
+    fn from_backend(backend: &StorageBackend) -> Self {
+        match backend {
             StorageBackend::S3 { default, index } => {
                 let options = ClientOptions::default();
                 let store = build_s3(default, options);
@@ -149,25 +222,20 @@ impl Storage {
                 let options = client_options(CONTENT_TYPE_INDEX, CACHE_CONTROL_INDEX);
                 let index_upload_store = build_s3(index, options);
 
-                if cdn_prefix.is_none() {
-                    panic!("Missing S3_CDN environment variable");
-                }
-
                 Self {
-                    store: Box::new(store),
-                    crate_upload_store: Box::new(crate_upload_store),
-                    readme_upload_store: Box::new(readme_upload_store),
-                    db_dump_upload_store: Box::new(db_dump_upload_store),
-                    cdn_prefix,
-                    index_store: Box::new(index_store),
-                    index_upload_store: Box::new(index_upload_store),
+                    store: Arc::new(store),
+                    crate_upload_store: Arc::new(crate_upload_store),
+                    readme_upload_store: Arc::new(readme_upload_store),
+                    db_dump_upload_store: Arc::new(db_dump_upload_store),
+                    index_store: Arc::new(index_store),
+                    index_upload_store: Arc::new(index_upload_store),
                 }
             }
 
             StorageBackend::LocalFileSystem { path } => {
                 warn!(?path, "Using local file system for file storage");
 
-                let index_path = path.join("index");
+                let index_path = path.join(PREFIX_INDEX);
 
                 fs::create_dir_all(&index_path)
                     .context("Failed to create file storage directories")
@@ -185,33 +253,156 @@ impl Storage {
                 let index_store: Arc<dyn ObjectStore> = Arc::new(local_index);
 
                 Self {
-                    store: Box::new(store.clone()),
-                    crate_upload_store: Box::new(store.clone()),
-                    readme_upload_store: Box::new(store.clone()),
-                    db_dump_upload_store: Box::new(store),
-                    cdn_prefix,
-                    index_store: Box::new(index_store.clone()),
-                    index_upload_store: Box::new(index_store),
+                    store: store.clone(),
+                    crate_upload_store: store.clone(),
+                    readme_upload_store: store.clone(),
+                    db_dump_upload_store: store,
+                    index_store: index_store.clone(),
+                    index_upload_store: index_store,
                 }
             }
 
             StorageBackend::InMemory => {
                 warn!("Using in-memory file storage");
                 let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+                let index_store: Arc<dyn ObjectStore> =
+                    Arc::new(PrefixStore::new(store.clone(), PREFIX_INDEX));
 
                 Self {
-                    store: Box::new(store.clone()),
-                    crate_upload_store: Box::new(store.clone()),
-                    readme_upload_store: Box::new(store.clone()),
-                    db_dump_upload_store: Box::new(store.clone()),
-                    cdn_prefix,
-                    index_store: Box::new(PrefixStore::new(store.clone(), "index")),
-                    index_upload_store: Box::new(PrefixStore::new(store, "index")),
+                    store: store.clone(),
+                    crate_upload_store: store.clone(),
+                    readme_upload_store: store.clone(),
+                    db_dump_upload_store: store,
+                    index_store: index_store.clone(),
+                    index_upload_store: index_store,
                 }
             }
         }
     }
 
+    /// Wraps all stores in [ReplicatedStore]s, which mirror the writes to
+    /// the corresponding `secondary` stores.
+    fn replicated(&self, secondary: &Stores, handler: &SharedFailureHandler) -> Self {
+        let replicated = |primary: &Arc<dyn ObjectStore>, secondary, bucket| {
+            let store = ReplicatedStore::new(primary.clone(), secondary, bucket, handler.clone());
+            Arc::new(store) as Arc<dyn ObjectStore>
+        };
+
+        let secondary = secondary.clone();
+        Self {
+            store: replicated(&self.store, secondary.store, StorageBucket::Default),
+            crate_upload_store: replicated(
+                &self.crate_upload_store,
+                secondary.crate_upload_store,
+                StorageBucket::Default,
+            ),
+            readme_upload_store: replicated(
+                &self.readme_upload_store,
+                secondary.readme_upload_store,
+                StorageBucket::Default,
+            ),
+            db_dump_upload_store: replicated(
+                &self.db_dump_upload_store,
+                secondary.db_dump_upload_store,
+                StorageBucket::Default,
+            ),
+            index_store: replicated(
+                &self.index_store,
+                secondary.index_store,
+                StorageBucket::Index,
+            ),
+            index_upload_store: replicated(
+                &self.index_upload_store,
+                secondary.index_upload_store,
+                StorageBucket::Index,
+            ),
+        }
+    }
+
+    /// Returns the store that is used to read objects of the given bucket.
+    fn read_store(&self, bucket: StorageBucket) -> &Arc<dyn ObjectStore> {
+        match bucket {
+            StorageBucket::Default => &self.store,
+            StorageBucket::Index => &self.index_store,
+        }
+    }
+
+    /// Returns the store that is used to upload the object at `path`, so
+    /// that it gets the same content type and cache headers as the original.
+    fn upload_store(&self, bucket: StorageBucket, path: &Path) -> &Arc<dyn ObjectStore> {
+        let prefix = path.parts().next();
+        match (bucket, prefix.as_ref().map(|part| part.as_ref())) {
+            (StorageBucket::Index, _) => &self.index_upload_store,
+            (StorageBucket::Default, Some(PREFIX_CRATES)) => &self.crate_upload_store,
+            (StorageBucket::Default, Some(PREFIX_READMES)) => &self.readme_upload_store,
+            (StorageBucket::Default, Some(name)) if name.starts_with(PREFIX_DB_DUMP) => {
+                &self.db_dump_upload_store
+            }
+            (StorageBucket::Default, _) => &self.store,
+        }
+    }
+}
+
+impl Storage {
+    pub fn from_environment() -> Self {
+        Self::from_config(&StorageConfig::from_environment())
+    }
+
+    pub fn from_config(config: &StorageConfig) -> Self {
+        let cdn_prefix = config.cdn_prefix.clone();
+
+        if matches!(config.backend, StorageBackend::S3 { .. }) && cdn_prefix.is_none() {
+            panic!("Missing S3_CDN environment variable");
+        }
+
+        let primary = Stores::from_backend(&config.backend);
+
+        let (stores, replica) = match &config.replica {
+            Some(backend) => {
+                let secondary = Stores::from_backend(backend);
+                let handler = SharedFailureHandler::default();
+                let stores = primary.replicated(&secondary, &handler);
+
+                let replica = Replica {
+                    primary,
+                    secondary,
+                    handler,
+                };
+
+                (stores, Some(replica))
+            }
+            None => (primary, None),
+        };
+
+        Self {
+            cdn_prefix,
+            store: Box::new(stores.store),
+            crate_upload_store: Box::new(stores.crate_upload_store),
+            readme_upload_store: Box::new(stores.readme_upload_store),
+            db_dump_upload_store: Box::new(stores.db_dump_upload_store),
+            index_store: Box::new(stores.index_store),
+            index_upload_store: Box::new(stores.index_upload_store),
+            replica,
+        }
+    }
+
+    /// Sets the handler that gets notified whenever a write could not be
+    /// replicated to the secondary store.
+    ///
+    /// This has no effect if the storage is not replicated.
+    pub fn with_replication_failure_handler(
+        self,
+        handler: impl ReplicationFailureHandler + 'static,
+    ) -> Self {
+        if let Some(replica) = &self.replica {
+            if replica.handler.set(Box::new(handler)).is_err() {
+                warn!("Replication failure handler has already been set");
+            }
+        }
+
+        self
+    }
+
     /// Returns the URL of an uploaded crate's version archive.
     ///
     /// The function doesn't check for the existence of the file.
@@ -250,6 +441,37 @@ impl Storage {
         self.store.delete(&path).await
     }
 
+    /// Returns `true` if the `.crate` file of the given crate version has
+    /// been uploaded.
+    #[instrument(skip(self))]
+    pub async fn crate_file_exists(&self, name: &str, version: &str) -> Result<bool> {
+        let path = crate_file_path(name, version);
+        match self.store.head(&path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns a stream of the content of the `.crate` file of the given
+    /// crate version, or `None` if the file does not exist.
+    ///
+    /// This avoids buffering the whole file in memory, which is used by the
+    /// storage integrity audit to hash all stored files.
+    #[instrument(skip(self))]
+    pub async fn stream_crate_file(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<BoxStream<'static, Result<Bytes>>>> {
+        let path = crate_file_path(name, version);
+        match self.store.get(&path).await {
+            Ok(result) => Ok(Some(result.into_stream())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
     #[instrument(skip(self, bytes))]
     pub async fn upload_crate_file(&self, name: &str, version: &str, bytes: Bytes) -> Result<()> {
         let path = crate_file_path(name, version);
@@ -266,7 +488,18 @@ impl Storage {
 
     #[instrument(skip(self, content))]
     pub async fn sync_index(&self, name: &str, content: Option<String>) -> Result<()> {
-        let path = crates_io_index::Repository::relative_index_file_for_url(name).into();
+        let path = crates_io_index::Repository::relative_index_file_for_url(name);
+        self.sync_index_file(&path, content).await
+    }
+
+    /// Uploads or (if `content` is `None`) removes an arbitrary file in the
+    /// sparse index, like the signed metadata documents of the
+    /// [index_signing](crate::index_signing) module.
+    ///
+    /// `path` is relative to the root of the index (e.g. `_tuf/root.json`).
+    #[instrument(skip(self, content))]
+    pub async fn sync_index_file(&self, path: &str, content: Option<String>) -> Result<()> {
+        let path = path.into();
         if let Some(content) = content {
             self.index_upload_store.put(&path, content.into()).await?;
         } else {
@@ -276,11 +509,103 @@ impl Storage {
         Ok(())
     }
 
+    /// Returns the content of the sparse index file of the given crate, or
+    /// `None` if the file does not exist.
+    #[instrument(skip(self))]
+    pub async fn read_index(&self, name: &str) -> Result<Option<Bytes>> {
+        let path = crates_io_index::Repository::relative_index_file_for_url(name).into();
+        match self.index_store.get(&path).await {
+            Ok(result) => Ok(Some(result.bytes().await?)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the content and the last modification time of a file in the
+    /// sparse index, or `None` if the file does not exist.
+    ///
+    /// `path` is relative to the root of the index (e.g. `3/f/foo`).
+    #[instrument(skip(self))]
+    pub async fn read_index_file(&self, path: &str) -> Result<Option<(Bytes, DateTime<Utc>)>> {
+        // Invalid paths (e.g. containing `..`) can not refer to an index file.
+        let Ok(path) = Path::parse(path) else {
+            return Ok(None);
+        };
+
+        match self.index_store.get(&path).await {
+            Ok(result) => {
+                let last_modified = result.meta.last_modified;
+                Ok(Some((result.bytes().await?, last_modified)))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the paths of all files in the sparse index, relative to the
+    /// root of the index.
+    #[instrument(skip(self))]
+    pub async fn list_index_files(&self) -> Result<Vec<String>> {
+        let objects = self.index_store.list(None);
+        let locations = objects.map_ok(|meta| meta.location.to_string());
+        locations.try_collect().await
+    }
+
+    /// Uploads an archive file of the daily version downloads from the local
+    /// file at `local_path`, without buffering the file in memory.
+    #[instrument(skip(self))]
+    pub async fn upload_version_downloads_archive(
+        &self,
+        path: &str,
+        local_path: &StdPath,
+    ) -> anyhow::Result<()> {
+        self.upload_file(&*self.store, path, local_path).await
+    }
+
+    /// Downloads the archive file of the daily version downloads at `path` to
+    /// the local file at `local_path`, and returns `false` if the archive
+    /// file does not exist.
+    #[instrument(skip(self))]
+    pub async fn download_version_downloads_archive(
+        &self,
+        path: &str,
+        local_path: &StdPath,
+    ) -> anyhow::Result<bool> {
+        let mut stream = match self.store.get(&path.into()).await {
+            Ok(result) => result.into_stream(),
+            Err(object_store::Error::NotFound { .. }) => return Ok(false),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut local_file = File::create(local_path).await?;
+        while let Some(chunk) = stream.try_next().await? {
+            local_file.write_all(&chunk).await?;
+        }
+        local_file.flush().await?;
+
+        Ok(true)
+    }
+
+    /// Deletes the archive file of the daily version downloads at `path`.
+    #[instrument(skip(self))]
+    pub async fn delete_version_downloads_archive(&self, path: &str) -> Result<()> {
+        self.store.delete(&path.into()).await
+    }
+
     #[instrument(skip(self))]
     pub async fn upload_db_dump(&self, target: &str, local_path: &StdPath) -> anyhow::Result<()> {
-        let store = &self.db_dump_upload_store;
+        self.upload_file(&self.db_dump_upload_store, target, local_path)
+            .await
+    }
 
-        // Open the local tarball file
+    /// Uploads the local file at `local_path` to `target` via a multipart
+    /// upload, without buffering the file in memory.
+    async fn upload_file(
+        &self,
+        store: &dyn ObjectStore,
+        target: &str,
+        local_path: &StdPath,
+    ) -> anyhow::Result<()> {
         let mut local_file = File::open(local_path).await?;
 
         // Set up a multipart upload
@@ -297,14 +622,109 @@ impl Storage {
         // ... or finalize upload
         writer.shutdown().await?;
 
+        // Multipart uploads are not replicated by the `ReplicatedStore`, so
+        // the uploaded file needs to be copied to the secondary store.
+        if let Some(replica) = &self.replica {
+            if let Err(error) = self.replicate_object(StorageBucket::Default, target).await {
+                warn!("Failed to replicate {target} to secondary storage: {error}");
+                if let Some(handler) = replica.handler.get() {
+                    handler.on_failure(StorageBucket::Default, target).await;
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Copies the object at `path` from the primary to the secondary store,
+    /// or deletes it from the secondary store if it does not exist in the
+    /// primary store anymore.
+    ///
+    /// This has no effect if the storage is not replicated.
+    #[instrument(skip(self))]
+    pub async fn replicate_object(&self, bucket: StorageBucket, path: &str) -> anyhow::Result<()> {
+        let Some(replica) = &self.replica else {
+            return Ok(());
+        };
+
+        let path = Path::parse(path)?;
+        let secondary = replica.secondary.upload_store(bucket, &path);
+
+        let mut stream = match replica.primary.read_store(bucket).get(&path).await {
+            Ok(result) => result.into_stream(),
+            Err(object_store::Error::NotFound { .. }) => {
+                return match secondary.delete(&path).await {
+                    Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                    Err(error) => Err(error.into()),
+                };
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let (id, mut writer) = secondary.put_multipart(&path).await?;
+
+        // Upload the object contents
+        if let Err(error) = copy_stream(&mut stream, &mut writer).await {
+            // Abort the upload if something failed
+            secondary.abort_multipart(&path, &id).await?;
+            return Err(error);
+        }
+
+        // ... or finalize upload
+        writer.shutdown().await?;
+
+        Ok(())
+    }
+
+    /// Returns the paths of all objects in the given bucket that are missing
+    /// from the secondary store, or differ in size from the primary store.
+    ///
+    /// Returns an empty list if the storage is not replicated.
+    #[instrument(skip(self))]
+    pub async fn list_unreplicated_objects(&self, bucket: StorageBucket) -> Result<Vec<String>> {
+        let Some(replica) = &self.replica else {
+            return Ok(Vec::new());
+        };
+
+        let primary = replica.primary.read_store(bucket);
+        let secondary = replica.secondary.read_store(bucket);
+
+        let mut paths = Vec::new();
+        let mut objects = primary.list(None);
+        while let Some(meta) = objects.try_next().await? {
+            // The local and in-memory backends store the index inside of
+            // the default bucket.
+            let prefix = meta.location.parts().next();
+            let prefix = prefix.as_ref().map(|part| part.as_ref());
+            if bucket == StorageBucket::Default && prefix == Some(PREFIX_INDEX) {
+                continue;
+            }
+
+            let is_replicated = match secondary.head(&meta.location).await {
+                Ok(replica_meta) => replica_meta.size == meta.size,
+                Err(object_store::Error::NotFound { .. }) => false,
+                Err(error) => return Err(error),
+            };
+
+            if !is_replicated {
+                paths.push(meta.location.to_string());
+            }
+        }
+
+        Ok(paths)
+    }
+
     /// This should only be used for assertions in the test suite!
     pub fn as_inner(&self) -> &dyn ObjectStore {
         &self.store
     }
 
+    /// This should only be used for assertions in the test suite!
+    pub fn as_replica_inner(&self) -> Option<&dyn ObjectStore> {
+        let replica = self.replica.as_ref()?;
+        Some(&replica.secondary.store)
+    }
+
     async fn delete_all_with_prefix(&self, prefix: &Path) -> Result<()> {
         let objects = self.store.list(Some(prefix));
         let locations = objects.map(|meta| meta.map(|m| m.location)).boxed();
@@ -318,6 +738,17 @@ impl Storage {
     }
 }
 
+async fn copy_stream(
+    stream: &mut BoxStream<'static, Result<Bytes>>,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> anyhow::Result<()> {
+    while let Some(chunk) = stream.try_next().await? {
+        writer.write_all(&chunk).await?;
+    }
+
+    Ok(())
+}
+
 fn client_options(content_type: &str, cache_control: &'static str) -> ClientOptions {
     let mut headers = HeaderMap::new();
     headers.insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
@@ -347,6 +778,16 @@ fn readme_path(name: &str, version: &str) -> Path {
     format!("{PREFIX_READMES}/{name}/{name}-{version}.html").into()
 }
 
+/// Returns the path of an archive file of the daily version downloads of a
+/// month (`YYYY-MM`), relative to the root of the default bucket.
+///
+/// The `table` is the name of the archived table with dashes (e.g.
+/// `version-downloads`), and every archival run uses a new `run` identifier,
+/// so that existing archive files are never overwritten.
+pub fn version_downloads_archive_path(table: &str, month: &str, run: &str) -> String {
+    format!("{PREFIX_ARCHIVE}/{table}/{month}/{run}.csv.gz")
+}
+
 fn apply_cdn_prefix(cdn_prefix: &Option<String>, path: &Path) -> String {
     match cdn_prefix {
         Some(cdn_prefix) if !cdn_prefix.starts_with("https://") => {
@@ -570,6 +1011,21 @@ mod tests {
         assert!(stored_files(&s.store).await.is_empty());
     }
 
+    #[tokio::test]
+    async fn read_index() {
+        let s = Storage::from_config(&StorageConfig::in_memory());
+
+        assert_eq!(s.read_index("foo").await.unwrap(), None);
+        assert!(s.list_index_files().await.unwrap().is_empty());
+
+        let content = "foo".to_string();
+        s.sync_index("foo", Some(content)).await.unwrap();
+
+        let content = s.read_index("FOO").await.unwrap();
+        assert_eq!(content, Some(Bytes::from_static(b"foo")));
+        assert_eq!(s.list_index_files().await.unwrap(), vec!["3/f/foo"]);
+    }
+
     #[tokio::test]
     async fn upload_db_dump() {
         let s = Storage::from_config(&StorageConfig::in_memory());
@@ -583,4 +1039,86 @@ mod tests {
         let expected_files = vec![target];
         assert_eq!(stored_files(&s.store).await, expected_files);
     }
+
+    #[tokio::test]
+    async fn replicated_uploads() {
+        let s = Storage::from_config(&StorageConfig::in_memory_replicated());
+        let secondary = s.as_replica_inner().unwrap();
+
+        s.upload_crate_file("foo", "1.0.0", Bytes::new())
+            .await
+            .unwrap();
+        s.sync_index("foo", Some("foo".to_string())).await.unwrap();
+
+        let file = NamedTempFile::new().unwrap();
+        s.upload_db_dump("db-dump.tar.gz", file.path())
+            .await
+            .unwrap();
+
+        let expected_files = vec![
+            "crates/foo/foo-1.0.0.crate",
+            "db-dump.tar.gz",
+            "index/3/f/foo",
+        ];
+        assert_eq!(stored_files(&s.store).await, expected_files);
+        assert_eq!(stored_files(secondary).await, expected_files);
+
+        s.delete_crate_file("foo", "1.0.0").await.unwrap();
+        let expected_files = vec!["db-dump.tar.gz", "index/3/f/foo"];
+        assert_eq!(stored_files(secondary).await, expected_files);
+    }
+
+    #[tokio::test]
+    async fn replicate_object() {
+        let s = Storage::from_config(&StorageConfig::in_memory_replicated());
+        let replica = s.replica.as_ref().unwrap();
+
+        let path = "crates/foo/foo-1.0.0.crate".into();
+        let primary = &replica.primary.store;
+        primary.put(&path, Bytes::from("foo")).await.unwrap();
+
+        let path = "3/f/foo".into();
+        let primary = &replica.primary.index_store;
+        primary.put(&path, Bytes::from("foo")).await.unwrap();
+
+        let path = "3/b/bar".into();
+        let secondary = &replica.secondary.index_store;
+        secondary.put(&path, Bytes::from("bar")).await.unwrap();
+
+        let bucket = StorageBucket::Default;
+        let unreplicated = s.list_unreplicated_objects(bucket).await.unwrap();
+        assert_eq!(unreplicated, vec!["crates/foo/foo-1.0.0.crate"]);
+        for path in unreplicated {
+            s.replicate_object(bucket, &path).await.unwrap();
+        }
+        assert_eq!(
+            s.list_unreplicated_objects(bucket).await.unwrap(),
+            vec![""; 0]
+        );
+
+        let bucket = StorageBucket::Index;
+        let unreplicated = s.list_unreplicated_objects(bucket).await.unwrap();
+        assert_eq!(unreplicated, vec!["3/f/foo"]);
+        s.replicate_object(bucket, "3/f/foo").await.unwrap();
+
+        // Objects that are missing from the primary store are removed
+        s.replicate_object(bucket, "3/b/bar").await.unwrap();
+
+        let secondary = s.as_replica_inner().unwrap();
+        let expected_files = vec!["crates/foo/foo-1.0.0.crate", "index/3/f/foo"];
+        assert_eq!(stored_files(secondary).await, expected_files);
+    }
+
+    #[tokio::test]
+    async fn replicated_reads_fall_back_to_secondary() {
+        let s = Storage::from_config(&StorageConfig::in_memory_replicated());
+        let replica = s.replica.as_ref().unwrap();
+
+        let path = "3/f/foo".into();
+        let secondary = &replica.secondary.index_store;
+        secondary.put(&path, Bytes::from("foo")).await.unwrap();
+
+        assert_eq!(s.read_index("foo").await.unwrap().unwrap(), "foo");
+        assert!(s.read_index("bar").await.unwrap().is_none());
+    }
 }
@@ -0,0 +1,295 @@
+//! Replication of the [Storage](super::Storage) to a secondary object store
+//! for disaster recovery.
+//!
+//! All writes go to the primary store first, and only succeed if the primary
+//! store accepted them. Afterwards they are mirrored to the secondary store,
+//! and failures are reported to the configured [ReplicationFailureHandler],
+//! which usually enqueues a repair job. Reads fall back to the secondary
+//! store if an object can not be found in the primary store.
+
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use hyper::body::Bytes;
+use object_store::path::Path;
+use object_store::{
+    GetOptions, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore, PutOptions, PutResult,
+    Result,
+};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, OnceLock};
+use tokio::io::AsyncWrite;
+
+/// The bucket of the [Storage](super::Storage) that an object belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBucket {
+    /// The bucket with crate files, READMEs and database dumps.
+    Default,
+    /// The bucket with the sparse index.
+    Index,
+}
+
+impl Display for StorageBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageBucket::Default => f.write_str("default"),
+            StorageBucket::Index => f.write_str("index"),
+        }
+    }
+}
+
+/// Gets notified whenever a write could not be replicated to the secondary
+/// store, so that the object can be repaired later.
+#[async_trait]
+pub trait ReplicationFailureHandler: Send + Sync {
+    async fn on_failure(&self, bucket: StorageBucket, path: &str);
+}
+
+/// The [ReplicationFailureHandler] shared by all [ReplicatedStore]s of a
+/// [Storage](super::Storage), which is set after the storage has been
+/// created.
+pub(super) type SharedFailureHandler = Arc<OnceLock<Box<dyn ReplicationFailureHandler>>>;
+
+/// An [ObjectStore] that mirrors all writes of a primary store to a
+/// secondary store.
+///
+/// Multipart uploads only go to the primary store, and need to be
+/// replicated explicitly.
+pub(super) struct ReplicatedStore {
+    primary: Arc<dyn ObjectStore>,
+    secondary: Arc<dyn ObjectStore>,
+    bucket: StorageBucket,
+    handler: SharedFailureHandler,
+}
+
+impl ReplicatedStore {
+    pub(super) fn new(
+        primary: Arc<dyn ObjectStore>,
+        secondary: Arc<dyn ObjectStore>,
+        bucket: StorageBucket,
+        handler: SharedFailureHandler,
+    ) -> Self {
+        Self {
+            primary,
+            secondary,
+            bucket,
+            handler,
+        }
+    }
+
+    async fn on_secondary_failure(&self, location: &Path, error: object_store::Error) {
+        let bucket = self.bucket;
+        warn!(%bucket, %location, "Failed to replicate object to secondary storage: {error}");
+
+        if let Some(handler) = self.handler.get() {
+            handler.on_failure(bucket, location.as_ref()).await;
+        }
+    }
+}
+
+impl Debug for ReplicatedStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplicatedStore")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for ReplicatedStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Replicated({}, {})", self.primary, self.secondary)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for ReplicatedStore {
+    async fn put_opts(&self, location: &Path, bytes: Bytes, opts: PutOptions) -> Result<PutResult> {
+        let result = self
+            .primary
+            .put_opts(location, bytes.clone(), opts.clone())
+            .await?;
+
+        if let Err(error) = self.secondary.put_opts(location, bytes, opts).await {
+            self.on_secondary_failure(location, error).await;
+        }
+
+        Ok(result)
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.primary.put_multipart(location).await
+    }
+
+    async fn abort_multipart(&self, location: &Path, multipart_id: &MultipartId) -> Result<()> {
+        self.primary.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        match self
+            .primary
+            .get_opts(location, clone_options(&options))
+            .await
+        {
+            Err(object_store::Error::NotFound { .. }) => {
+                self.secondary.get_opts(location, options).await
+            }
+            result => result,
+        }
+    }
+
+    async fn delete(&self, location: &Path) -> Result<()> {
+        self.primary.delete(location).await?;
+
+        match self.secondary.delete(location).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(error) => self.on_secondary_failure(location, error).await,
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        self.primary.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        self.primary.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        self.primary.copy(from, to).await?;
+
+        if let Err(error) = self.secondary.copy(from, to).await {
+            self.on_secondary_failure(to, error).await;
+        }
+
+        Ok(())
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<()> {
+        self.primary.copy_if_not_exists(from, to).await?;
+
+        if let Err(error) = self.secondary.copy_if_not_exists(from, to).await {
+            self.on_secondary_failure(to, error).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// [GetOptions] does not implement [Clone] in this version of the
+/// `object_store` crate.
+fn clone_options(options: &GetOptions) -> GetOptions {
+    GetOptions {
+        if_match: options.if_match.clone(),
+        if_none_match: options.if_none_match.clone(),
+        if_modified_since: options.if_modified_since,
+        if_unmodified_since: options.if_unmodified_since,
+        range: options.range.clone(),
+        version: options.version.clone(),
+        head: options.head,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::local::LocalFileSystem;
+    use object_store::memory::InMemory;
+    use parking_lot::Mutex;
+    use tempfile::NamedTempFile;
+
+    type Failures = Arc<Mutex<Vec<(StorageBucket, String)>>>;
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        failures: Failures,
+    }
+
+    #[async_trait]
+    impl ReplicationFailureHandler for RecordingHandler {
+        async fn on_failure(&self, bucket: StorageBucket, path: &str) {
+            self.failures.lock().push((bucket, path.to_string()));
+        }
+    }
+
+    fn replicated(
+        secondary: Arc<dyn ObjectStore>,
+    ) -> (ReplicatedStore, Arc<dyn ObjectStore>, Failures) {
+        let primary: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+
+        let handler = RecordingHandler::default();
+        let failures = handler.failures.clone();
+        let shared_handler = SharedFailureHandler::default();
+        assert!(shared_handler.set(Box::new(handler)).is_ok());
+
+        let store = ReplicatedStore::new(
+            primary.clone(),
+            secondary,
+            StorageBucket::Default,
+            shared_handler,
+        );
+
+        (store, primary, failures)
+    }
+
+    /// A secondary store that fails all writes, because its root directory
+    /// is actually a file.
+    fn broken_store(file: &NamedTempFile) -> Arc<dyn ObjectStore> {
+        Arc::new(LocalFileSystem::new_with_prefix(file.path()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_writes_go_to_both_stores() {
+        let secondary: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let (store, primary, failures) = replicated(secondary.clone());
+
+        let path = "crates/foo/foo-1.0.0.crate".into();
+        store.put(&path, Bytes::from("foo")).await.unwrap();
+
+        let content = primary.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(content, "foo");
+        let content = secondary.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(content, "foo");
+
+        store.delete(&path).await.unwrap();
+        assert!(primary.head(&path).await.is_err());
+        assert!(secondary.head(&path).await.is_err());
+
+        assert!(failures.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reads_fall_back_to_secondary() {
+        let secondary: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let (store, _primary, _) = replicated(secondary.clone());
+
+        let path = "crates/foo/foo-1.0.0.crate".into();
+        secondary.put(&path, Bytes::from("foo")).await.unwrap();
+
+        let content = store.get(&path).await.unwrap().bytes().await.unwrap();
+        assert_eq!(content, "foo");
+
+        let path = "crates/bar/bar-1.0.0.crate".into();
+        let error = store.get(&path).await.unwrap_err();
+        assert!(matches!(error, object_store::Error::NotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_secondary_failures_are_reported() {
+        let file = NamedTempFile::new().unwrap();
+        let (store, primary, failures) = replicated(broken_store(&file));
+
+        let path = "crates/foo/foo-1.0.0.crate".into();
+        store.put(&path, Bytes::from("foo")).await.unwrap();
+        assert!(primary.head(&path).await.is_ok());
+
+        let expected = vec![(StorageBucket::Default, path.to_string())];
+        assert_eq!(*failures.lock(), expected);
+    }
+}
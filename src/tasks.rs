@@ -1,5 +1,7 @@
+use anyhow::Context;
 use sentry::Hub;
 use std::convert::identity;
+use tokio::runtime::Runtime;
 use tokio::task::JoinError;
 
 /// Runs the provided closure on a thread where blocking is acceptable.
@@ -25,6 +27,20 @@ where
         .and_then(identity)
 }
 
+/// Builds a runtime for synchronous code outside of the server and the
+/// background worker, e.g. admin commands, that needs to run async requests
+/// via [Handle::block_on](tokio::runtime::Handle::block_on).
+///
+/// [Handle::block_on](tokio::runtime::Handle::block_on) can only drive IO on
+/// a multi-threaded runtime, so a single worker thread is used.
+pub fn block_on_runtime() -> anyhow::Result<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .context("Failed to initialize tokio runtime")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,6 +21,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use diesel::prelude::*;
 
 mod account_lock;
+mod authenticated_registry;
 mod authentication;
 mod blocked_routes;
 mod builders;
@@ -38,9 +39,11 @@ mod routes;
 mod schema_details;
 mod server;
 mod server_binary;
+mod sparse_index;
 mod team;
 mod token;
 mod unhealthy_database;
+mod upstream_registry;
 mod user;
 mod util;
 mod version;
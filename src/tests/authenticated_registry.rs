@@ -0,0 +1,150 @@
+use crate::builders::{CrateBuilder, PublishBuilder};
+use crate::util::{MockRequestExt, RequestHelper, TestApp};
+use crates_io::models::token::{CrateScope, EndpointScope};
+use http::{header, StatusCode};
+use insta::assert_json_snapshot;
+
+#[test]
+fn anonymous_reads_are_unauthorized() {
+    let (_, anon) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .empty();
+
+    for url in [
+        "/api/v1/crates",
+        "/api/v1/summary",
+        "/api/v1/crates/foo/1.0.0/download",
+        "/index/config.json",
+        "/index/3/f/foo",
+    ] {
+        let response = anon.get::<()>(url);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{url}");
+        assert_some_eq!(response.headers().get(header::WWW_AUTHENTICATE), "Cargo");
+        assert_eq!(
+            response.json(),
+            json!({ "errors": [{ "detail": "this registry requires authentication" }] })
+        );
+    }
+}
+
+#[test]
+fn anonymous_reads_are_allowed_by_default() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.get::<()>("/api/v1/crates");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn config_json_advertises_auth_required() {
+    let (_, _, _, token) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .with_token();
+
+    let response = token.get::<()>("/index/config.json");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), @r###"
+    {
+      "api": "https://crates.io",
+      "auth-required": true,
+      "dl": "https://crates.io/api/v1/crates"
+    }
+    "###);
+}
+
+#[test]
+fn cookie_user_can_read() {
+    let (app, _, user) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let response = user.get::<()>("/api/v1/crates/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn read_only_token_can_read() {
+    let (app, _, user) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .with_user();
+    let token = user.db_new_scoped_token("read", None, Some(vec![EndpointScope::ReadOnly]), None);
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let response = token.get::<()>("/api/v1/crates/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    token
+        .get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+}
+
+#[test]
+fn read_only_token_cannot_publish() {
+    let (_, _, user) = TestApp::full()
+        .with_config(|config| config.auth_required = true)
+        .with_user();
+    let token = user.db_new_scoped_token("read", None, Some(vec![EndpointScope::ReadOnly]), None);
+
+    let response = token.publish_crate(PublishBuilder::new("foo", "1.0.0"));
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn crate_scoped_token_can_read_other_crates() {
+    let (app, _, user) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .with_user();
+    let crate_scopes = Some(vec![CrateScope::try_from("bar").unwrap()]);
+    let endpoint_scopes = Some(vec![EndpointScope::PublishUpdate]);
+    let token = user.db_new_scoped_token("bar", crate_scopes, endpoint_scopes, None);
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let response = token.get::<()>("/api/v1/crates/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn invalid_token_is_forbidden() {
+    let (_, anon) = TestApp::init()
+        .with_config(|config| config.auth_required = true)
+        .empty();
+
+    let mut request = anon.get_request("/api/v1/crates");
+    request.header(header::AUTHORIZATION, "cio1234567890123456789012345678901");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn sparse_index_requires_auth() {
+    let (app, anon, _, token) = TestApp::full()
+        .with_config(|config| config.auth_required = true)
+        .with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = token.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -19,7 +19,7 @@ fn new_krate() {
     });
 
     let crates = app.crates_from_index_head("foo_new");
-    assert_json_snapshot!(crates);
+    assert_json_snapshot!(crates, { "[].pubtime" => "[datetime]" });
 
     let expected_files = vec!["crates/foo_new/foo_new-1.0.0.crate", "index/fo/o_/foo_new"];
     assert_eq!(app.stored_files(), expected_files);
@@ -85,7 +85,7 @@ fn new_krate_twice() {
     });
 
     let crates = app.crates_from_index_head("foo_twice");
-    assert_json_snapshot!(crates);
+    assert_json_snapshot!(crates, { "[].pubtime" => "[datetime]" });
 
     let expected_files = vec![
         "crates/foo_twice/foo_twice-0.99.0.crate",
@@ -24,7 +24,7 @@ fn index_metadata() {
             .expect_build(conn);
 
         let metadata = fooo.index_metadata(conn).unwrap();
-        assert_json_snapshot!(metadata);
+        assert_json_snapshot!(metadata, { "[].pubtime" => "[datetime]" });
 
         let bar = CrateBuilder::new("bar", user.id)
             .version(
@@ -42,6 +42,9 @@ fn index_metadata() {
             .expect_build(conn);
 
         let metadata = bar.index_metadata(conn).unwrap();
-        assert_json_snapshot!(metadata);
+        assert_json_snapshot!(metadata, { "[].pubtime" => "[datetime]" });
+
+        let expected_pubtime = created_at_1.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        assert_eq!(metadata[0].pubtime, Some(expected_pubtime));
     });
 }
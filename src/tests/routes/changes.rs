@@ -0,0 +1,102 @@
+use crate::builders::PublishBuilder;
+use crate::routes::crates::versions::yank_unyank::YankRequestHelper;
+use crate::util::{RequestHelper, TestApp};
+use http::StatusCode;
+use insta::assert_json_snapshot;
+use serde_json::Value;
+
+#[test]
+fn changes_are_listed_in_order() {
+    let (_, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("bar", "0.1.0"))
+        .good();
+    token.yank("foo", "1.0.0").good();
+    token.unyank("foo", "1.0.0").good();
+
+    let response = anon.get::<()>("/api/v1/changes");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), {
+        ".changes[].id" => "[id]",
+        ".changes[].timestamp" => "[datetime]",
+    });
+}
+
+#[test]
+fn changes_can_be_paginated() {
+    let (_, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.2.0"))
+        .good();
+
+    let json = anon
+        .get_with_query::<Value>("/api/v1/changes", "per_page=2")
+        .good();
+    assert_eq!(versions(&json), ["1.0.0", "1.1.0"]);
+
+    let next_page = json["meta"]["next_page"].as_str().unwrap();
+    let json = anon
+        .get_with_query::<Value>("/api/v1/changes", next_page.trim_start_matches('?'))
+        .good();
+    assert_eq!(versions(&json), ["1.2.0"]);
+    assert_eq!(json["meta"]["next_page"], Value::Null);
+}
+
+#[test]
+fn changes_since_cursor() {
+    let (_, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0"))
+        .good();
+
+    let json = anon.get::<Value>("/api/v1/changes").good();
+    let cursor = json["changes"][0]["id"].as_i64().unwrap();
+
+    let query = format!("since={cursor}");
+    let json = anon
+        .get_with_query::<Value>("/api/v1/changes", &query)
+        .good();
+    assert_eq!(versions(&json), ["1.1.0"]);
+
+    let cursor = json["changes"][0]["id"].as_i64().unwrap();
+    let query = format!("since={cursor}");
+    let json = anon
+        .get_with_query::<Value>("/api/v1/changes", &query)
+        .good();
+    assert!(versions(&json).is_empty());
+}
+
+#[test]
+fn invalid_seek_parameter() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.get_with_query::<()>("/api/v1/changes", "seek=foo");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+fn versions(json: &Value) -> Vec<&str> {
+    json["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["version"].as_str().unwrap())
+        .collect()
+}
@@ -196,3 +196,211 @@ fn test_version_downloads() {
         @r###"{"errors":[{"detail":"crate `foo` does not have a version `invalid-version`"}]}"###
     );
 }
+
+#[test]
+fn test_version_downloads_by_client() {
+    use crates_io::schema::{version_downloads_by_client, versions};
+    use diesel::prelude::*;
+
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        let version_id: i32 = versions::table.select(versions::id).first(conn).unwrap();
+        let today = Utc::now().date_naive();
+        let yesterday = today - Duration::days(1);
+
+        let rows = [
+            (yesterday, "cargo/1.76", 3),
+            (today, "other", 1),
+            (today, "cargo/1.76", 5),
+            (today, "mirror/panamax", 2),
+        ];
+
+        for (date, client, downloads) in rows {
+            diesel::insert_into(version_downloads_by_client::table)
+                .values((
+                    version_downloads_by_client::version_id.eq(version_id),
+                    version_downloads_by_client::date.eq(date),
+                    version_downloads_by_client::client.eq(client),
+                    version_downloads_by_client::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/1.0.0/downloads", "by=client");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), {
+        ".version_downloads[].date" => "[date]",
+        ".version_downloads[].version" => "[version_id]",
+    }, @r###"
+    {
+      "version_downloads": [
+        {
+          "client": "cargo/1.76",
+          "date": "[date]",
+          "downloads": 3,
+          "version": "[version_id]"
+        },
+        {
+          "client": "cargo/1.76",
+          "date": "[date]",
+          "downloads": 5,
+          "version": "[version_id]"
+        },
+        {
+          "client": "mirror/panamax",
+          "date": "[date]",
+          "downloads": 2,
+          "version": "[version_id]"
+        },
+        {
+          "client": "other",
+          "date": "[date]",
+          "downloads": 1,
+          "version": "[version_id]"
+        }
+      ]
+    }
+    "###);
+
+    // the total downloads are not affected by the breakdown
+    assert_dl_count(&anon, "foo/1.0.0", None, 0);
+
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/1.0.0/downloads", "by=country");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_display_snapshot!(
+        response.text(),
+        @r###"{"errors":[{"detail":"invalid `by` parameter `country`, expected `client`"}]}"###
+    );
+}
+
+#[test]
+fn test_crate_downloads_rollups() {
+    use chrono::NaiveDate;
+    use crates_io::schema::{version_downloads_monthly, version_downloads_weekly, versions};
+    use diesel::prelude::*;
+
+    let (app, anon, cookie) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let user_id = cookie.as_model().id;
+        CrateBuilder::new("foo", user_id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+
+        let version_ids: Vec<i32> = versions::table
+            .select(versions::id)
+            .order(versions::num)
+            .load(conn)
+            .unwrap();
+        let (v1, v2) = (version_ids[0], version_ids[1]);
+
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%F").unwrap();
+        let rows = [
+            (v1, "2020-01-01", 10),
+            (v2, "2020-01-01", 5),
+            (v1, "2020-02-01", 7),
+            (v1, "2021-06-01", 3),
+        ];
+        for (version_id, day, downloads) in rows {
+            diesel::insert_into(version_downloads_monthly::table)
+                .values((
+                    version_downloads_monthly::version_id.eq(version_id),
+                    version_downloads_monthly::date.eq(date(day)),
+                    version_downloads_monthly::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        diesel::insert_into(version_downloads_weekly::table)
+            .values((
+                version_downloads_weekly::version_id.eq(v2),
+                version_downloads_weekly::date.eq(date("2020-01-06")),
+                version_downloads_weekly::downloads.eq(4),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    // `from` is rounded down to the start of its month
+    let query = "granularity=month&from=2020-01-15&to=2020-12-31";
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads", query);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), @r###"
+    {
+      "downloads": [
+        {
+          "date": "2020-01-01",
+          "downloads": 15
+        },
+        {
+          "date": "2020-02-01",
+          "downloads": 7
+        }
+      ],
+      "meta": {
+        "from": "2020-01-01",
+        "granularity": "month",
+        "to": "2020-12-31"
+      }
+    }
+    "###);
+
+    let query = "granularity=week&from=2020-01-08&to=2020-01-31";
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads", query);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), @r###"
+    {
+      "downloads": [
+        {
+          "date": "2020-01-06",
+          "downloads": 4
+        }
+      ],
+      "meta": {
+        "from": "2020-01-06",
+        "granularity": "week",
+        "to": "2020-01-31"
+      }
+    }
+    "###);
+
+    let query = "granularity=month&from=2020-01-01&format=csv";
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads", query);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "text/csv; charset=utf-8"
+    );
+    assert_display_snapshot!(response.text(), @r###"
+    date,downloads
+    2020-01-01,15
+    2020-02-01,7
+    2021-06-01,3
+    "###);
+
+    // the crate was created today, so nothing is returned by default
+    let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads", "granularity=month");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.json()["downloads"], serde_json::json!([]));
+
+    let invalid_queries = [
+        "granularity=day",
+        "granularity=month&from=2020-13-01",
+        "granularity=month&from=2021-01-01&to=2020-01-01",
+        "granularity=month&format=xml",
+    ];
+    for query in invalid_queries {
+        let response = anon.get_with_query::<()>("/api/v1/crates/foo/downloads", query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
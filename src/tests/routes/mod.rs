@@ -13,6 +13,7 @@
 
 pub mod categories;
 pub mod category_slugs;
+pub mod changes;
 pub mod crates;
 pub mod keywords;
 pub mod me;
@@ -0,0 +1,110 @@
+use crate::builders::PublishBuilder;
+use crate::util::{MockRequestExt, RequestHelper, TestApp};
+use http::{header, StatusCode};
+use insta::assert_json_snapshot;
+
+#[test]
+fn config_json() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.get::<()>("/index/config.json");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_json_snapshot!(response.json(), @r###"
+    {
+      "api": "https://crates.io",
+      "dl": "https://crates.io/api/v1/crates"
+    }
+    "###);
+}
+
+#[test]
+fn index_file() {
+    let (app, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_some_eq!(response.headers().get(header::CONTENT_TYPE), "text/plain");
+    assert_some!(response.headers().get(header::LAST_MODIFIED));
+
+    let crates = crates_io_index::read_crates(&response.text()).unwrap();
+    assert_eq!(crates.len(), 1);
+    assert_eq!(crates[0].name, "foo");
+    assert_eq!(crates[0].vers, "1.0.0");
+
+    anon.get::<()>("/index/3/b/bar").assert_not_found();
+    anon.get::<()>("/index/3/f/../f/foo").assert_not_found();
+}
+
+#[test]
+fn if_none_match() {
+    let (app, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = assert_some!(response.headers().get(header::ETAG)).clone();
+
+    let mut request = anon.get_request("/index/3/f/foo");
+    request.header(header::IF_NONE_MATCH, etag.to_str().unwrap());
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_some_eq!(response.headers().get(header::ETAG), &etag);
+    assert_eq!(response.text(), "");
+
+    // After a new publish the cached version is outdated
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.1.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let mut request = anon.get_request("/index/3/f/foo");
+    request.header(header::IF_NONE_MATCH, etag.to_str().unwrap());
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(assert_some!(response.headers().get(header::ETAG)), &etag);
+}
+
+#[test]
+fn if_none_match_config_json() {
+    let (_, anon) = TestApp::init().empty();
+
+    let response = anon.get::<()>("/index/config.json");
+    let etag = assert_some!(response.headers().get(header::ETAG)).clone();
+
+    let mut request = anon.get_request("/index/config.json");
+    request.header(header::IF_NONE_MATCH, etag.to_str().unwrap());
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[test]
+fn if_modified_since() {
+    let (app, anon, _, token) = TestApp::full().with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    let last_modified = assert_some!(response.headers().get(header::LAST_MODIFIED)).clone();
+
+    let mut request = anon.get_request("/index/3/f/foo");
+    request.header(header::IF_MODIFIED_SINCE, last_modified.to_str().unwrap());
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let mut request = anon.get_request("/index/3/f/foo");
+    request.header(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT");
+    let response = anon.run::<()>(request);
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,170 @@
+use crate::builders::CrateBuilder;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::models::token::CrateScope;
+use http::StatusCode;
+use std::time::Duration;
+
+const INDEX_URL: &str = "https://index.upstream.example/3/f/foo";
+const DOWNLOAD_URL: &str = "https://upstream.example/api/v1/crates/foo/1.0.0/download";
+const CONFIG_URL: &str = "https://index.upstream.example/config.json";
+
+#[test]
+fn index_file_is_fetched_from_upstream() {
+    let (app, anon) = TestApp::init().with_upstream_registry().empty();
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let crates = crates_io_index::read_crates(&response.text()).unwrap();
+    assert_eq!(crates.len(), 1);
+    assert_eq!(crates[0].name, "foo");
+    assert_eq!(crates[0].vers, "1.0.0");
+
+    assert!(app.stored_files().contains(&"index/3/f/foo".to_string()));
+
+    // The stored copy is served until the TTL expires
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.upstream_registry().requests(), vec![INDEX_URL]);
+
+    anon.get::<()>("/index/3/b/bar").assert_not_found();
+}
+
+#[test]
+fn expired_index_file_is_fetched_again() {
+    let (app, anon) = TestApp::init()
+        .with_upstream_registry()
+        .with_config(|config| {
+            config.upstream_registry.as_mut().unwrap().index_ttl = Duration::ZERO;
+        })
+        .empty();
+
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(
+        crates_io_index::read_crates(&response.text())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    app.upstream_registry().publish("foo", "1.1.0", b"foo");
+
+    let response = anon.get::<()>("/index/3/f/foo");
+    assert_eq!(
+        crates_io_index::read_crates(&response.text())
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        app.upstream_registry().requests(),
+        vec![INDEX_URL, INDEX_URL]
+    );
+}
+
+#[test]
+fn crates_must_be_on_the_allow_list() {
+    let (app, anon) = TestApp::init()
+        .with_upstream_registry()
+        .with_config(|config| {
+            let allowed_crates = vec![CrateScope::try_from("serde*").unwrap()];
+            config.upstream_registry.as_mut().unwrap().allowed_crates = allowed_crates;
+        })
+        .empty();
+
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+
+    anon.get::<()>("/index/3/f/foo").assert_not_found();
+    anon.get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_not_found();
+    assert_eq!(app.upstream_registry().requests(), Vec::<String>::new());
+}
+
+#[test]
+fn local_crates_are_not_fetched_from_upstream() {
+    let (app, anon, user) = TestApp::init()
+        .with_upstream_registry()
+        .with_config(|config| {
+            config.upstream_registry.as_mut().unwrap().index_ttl = Duration::ZERO;
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+
+    anon.get::<()>("/index/3/f/foo").assert_not_found();
+    assert_eq!(app.upstream_registry().requests(), Vec::<String>::new());
+}
+
+#[test]
+fn crate_file_is_fetched_from_upstream() {
+    let (app, anon) = TestApp::init().with_upstream_registry().empty();
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+
+    anon.get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+
+    let stored_files = app.stored_files();
+    assert!(stored_files.contains(&"crates/foo/foo-1.0.0.crate".to_string()));
+    assert!(stored_files.contains(&"index/3/f/foo".to_string()));
+
+    // The stored copy is served on subsequent downloads
+    anon.get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+
+    let requests = app.upstream_registry().requests();
+    let crate_requests = requests.iter().filter(|url| *url == DOWNLOAD_URL);
+    assert_eq!(crate_requests.count(), 1);
+
+    anon.get::<()>("/api/v1/crates/foo/2.0.0/download")
+        .assert_not_found();
+}
+
+#[test]
+fn upstream_config_is_cached() {
+    let (app, anon) = TestApp::init().with_upstream_registry().empty();
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+    app.upstream_registry().publish("foo", "1.1.0", b"foo");
+
+    anon.get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.0.0.crate");
+    anon.get::<()>("/api/v1/crates/foo/1.1.0/download")
+        .assert_redirect_ends_with("/crates/foo/foo-1.1.0.crate");
+
+    let requests = app.upstream_registry().requests();
+    let config_requests = requests.iter().filter(|url| *url == CONFIG_URL);
+    assert_eq!(config_requests.count(), 1);
+}
+
+#[test]
+fn invalid_crate_names_are_not_fetched_from_upstream() {
+    let (app, anon) = TestApp::init().with_upstream_registry().empty();
+
+    // `fée`, whose index file path would have to be split within the `é`
+    anon.get::<()>("/index/fe/e/f%C3%A9e").assert_not_found();
+
+    let url = "/api/v1/crates/f%C3%A9e/1.0.0/download";
+    anon.get::<()>(url).assert_not_found();
+    assert_eq!(app.upstream_registry().requests(), Vec::<String>::new());
+}
+
+#[test]
+fn crate_file_with_checksum_mismatch_is_rejected() {
+    let (app, anon) = TestApp::init().with_upstream_registry().empty();
+    app.upstream_registry().publish("foo", "1.0.0", b"foo");
+    app.upstream_registry().add_file(DOWNLOAD_URL, "tampered");
+
+    let response = anon.get::<()>("/api/v1/crates/foo/1.0.0/download");
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let stored_files = app.stored_files();
+    assert!(!stored_files.contains(&"crates/foo/foo-1.0.0.crate".to_string()));
+}
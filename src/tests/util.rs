@@ -47,6 +47,7 @@ pub mod matchers;
 mod mock_request;
 mod response;
 mod test_app;
+mod upstream_registry;
 
 pub(crate) use chaosproxy::ChaosProxy;
 use mock_request::MockRequest;
@@ -1,11 +1,13 @@
 use super::{MockAnonymousUser, MockCookieUser, MockTokenUser};
 use crate::util::chaosproxy::ChaosProxy;
 use crate::util::github::{MockGitHubClient, MOCK_GITHUB_DATA};
+use crate::util::upstream_registry::{MockUpstreamRegistry, UPSTREAM_INDEX_URL};
 use anyhow::Context;
 use crates_io::config::{
     self, BalanceCapacityConfig, Base, CdnLogQueueConfig, CdnLogStorageConfig, DatabasePools,
-    DbPoolConfig,
+    DbPoolConfig, SparseIndexConfig, UpstreamRegistryConfig,
 };
+use crates_io::index_signing::IndexSigner;
 use crates_io::middleware::cargo_compat::StatusCodeConfig;
 use crates_io::models::token::{CrateScope, EndpointScope};
 use crates_io::rate_limiter::{LimitedAction, RateLimiterConfig};
@@ -14,7 +16,7 @@ use crates_io::team_repo::MockTeamRepo;
 use crates_io::worker::{Environment, RunnerExt};
 use crates_io::{App, Emails, Env};
 use crates_io_index::testing::UpstreamIndex;
-use crates_io_index::{Credentials, RepositoryConfig};
+use crates_io_index::{Credentials, MirrorConfig, RepositoryConfig};
 use crates_io_test_db::TestDatabase;
 use crates_io_worker::Runner;
 use diesel::PgConnection;
@@ -34,6 +36,8 @@ struct TestAppInner {
     app: Arc<App>,
     router: axum::Router,
     index: Option<UpstreamIndex>,
+    git_mirrors: Vec<(String, UpstreamIndex)>,
+    upstream_registry: MockUpstreamRegistry,
     runner: Option<Runner<Arc<Environment>>>,
 
     primary_db_chaosproxy: Option<Arc<ChaosProxy>>,
@@ -87,6 +91,8 @@ impl TestApp {
             tracing_guard,
             config: simple_config(),
             index: None,
+            git_mirrors: Vec::new(),
+            index_signer: None,
             build_job_runner: false,
             use_chaos_proxy: false,
             team_repo: MockTeamRepo::new(),
@@ -149,6 +155,18 @@ impl TestApp {
         assert_some!(self.0.index.as_ref())
     }
 
+    /// Obtain a reference to the git index mirror with the given name
+    pub fn git_mirror(&self, name: &str) -> &UpstreamIndex {
+        let mirror = self.0.git_mirrors.iter().find(|(n, _)| n == name);
+        &assert_some!(mirror).1
+    }
+
+    /// Obtain a reference to the stub of the upstream registry that is used
+    /// in pull-through mirror mode
+    pub fn upstream_registry(&self) -> &MockUpstreamRegistry {
+        &self.0.upstream_registry
+    }
+
     /// Obtain a list of crates from the index HEAD
     pub fn crates_from_index_head(&self, crate_name: &str) -> Vec<crates_io_index::Crate> {
         self.upstream_index()
@@ -213,6 +231,8 @@ pub struct TestAppBuilder {
     tracing_guard: DefaultGuard,
     config: config::Server,
     index: Option<UpstreamIndex>,
+    git_mirrors: Vec<(String, UpstreamIndex)>,
+    index_signer: Option<IndexSigner>,
     build_job_runner: bool,
     use_chaos_proxy: bool,
     team_repo: MockTeamRepo,
@@ -259,7 +279,8 @@ impl TestAppBuilder {
             (primary_proxy, replica_proxy, Some(test_database))
         };
 
-        let (app, router) = build_app(self.config);
+        let upstream_registry = MockUpstreamRegistry::default();
+        let (app, router) = build_app(self.config, upstream_registry.clone());
 
         let runner = if self.build_job_runner {
             let index = self
@@ -267,14 +288,26 @@ impl TestAppBuilder {
                 .as_ref()
                 .expect("Index must be initialized to build a job runner");
 
+            let mirrors = self
+                .git_mirrors
+                .iter()
+                .map(|(name, mirror)| MirrorConfig {
+                    name: name.clone(),
+                    url: mirror.url(),
+                    credentials: Credentials::Missing,
+                })
+                .collect();
+
             let repository_config = RepositoryConfig {
                 index_location: index.url(),
                 credentials: Credentials::Missing,
+                mirrors,
             };
 
             let environment = Environment::builder()
                 .config(app.config.clone())
                 .repository_config(repository_config)
+                .index_signer(self.index_signer)
                 .storage(app.storage.clone())
                 .connection_pool(app.primary_database.clone())
                 .emails(app.emails.clone())
@@ -302,6 +335,8 @@ impl TestAppBuilder {
             test_database,
             router,
             index: self.index,
+            git_mirrors: self.git_mirrors,
+            upstream_registry,
             runner,
             primary_db_chaosproxy,
             replica_db_chaosproxy,
@@ -357,6 +392,32 @@ impl TestAppBuilder {
         self
     }
 
+    /// Add a git index mirror with the given name, which is available via
+    /// [TestApp::git_mirror]
+    pub fn with_git_mirror(mut self, name: &str) -> Self {
+        let mirror = UpstreamIndex::new().unwrap();
+        self.git_mirrors.push((name.to_string(), mirror));
+        self
+    }
+
+    /// Enable the pull-through mirror mode for all crates, using the
+    /// [TestApp::upstream_registry] stub as the upstream registry
+    pub fn with_upstream_registry(self) -> Self {
+        self.with_config(|config| {
+            config.upstream_registry = Some(UpstreamRegistryConfig {
+                index_url: UPSTREAM_INDEX_URL.into(),
+                allowed_crates: vec![CrateScope::try_from("*").unwrap()],
+                index_ttl: Duration::from_secs(10 * 60),
+            });
+        })
+    }
+
+    /// Sign the sparse index metadata with the given signer
+    pub fn with_index_signer(mut self, index_signer: IndexSigner) -> Self {
+        self.index_signer = Some(index_signer);
+        self
+    }
+
     pub fn with_job_runner(mut self) -> Self {
         self.build_job_runner = true;
         self
@@ -455,6 +516,14 @@ fn simple_config() -> config::Server {
         version_id_cache_ttl: Duration::from_secs(5 * 60),
         cdn_user_agent: "Amazon CloudFront".to_string(),
         balance_capacity,
+        git_index_sync_batch_size: 1,
+        sparse_index: SparseIndexConfig {
+            serve: true,
+            api_url: "https://crates.io".into(),
+            dl_url: "https://crates.io/api/v1/crates".into(),
+        },
+        upstream_registry: None,
+        auth_required: false,
 
         // The middleware has its own unit tests to verify its functionality.
         // Here, we can test what would happen if we toggled the status code
@@ -468,7 +537,10 @@ fn simple_config() -> config::Server {
     }
 }
 
-fn build_app(config: config::Server) -> (Arc<App>, axum::Router) {
+fn build_app(
+    config: config::Server,
+    upstream_registry: MockUpstreamRegistry,
+) -> (Arc<App>, axum::Router) {
     // Use the in-memory email backend for all tests, allowing tests to analyze the emails sent by
     // the application. This will also prevent cluttering the filesystem.
     let emails = Emails::new_in_memory();
@@ -477,7 +549,7 @@ fn build_app(config: config::Server) -> (Arc<App>, axum::Router) {
     // organizations without actually having to create GitHub accounts.
     let github = Box::new(MockGitHubClient::new(&MOCK_GITHUB_DATA));
 
-    let app = App::new(config, emails, github);
+    let app = App::new(config, emails, github, Box::new(upstream_registry));
 
     let app = Arc::new(app);
     let router = crates_io::build_handler(Arc::clone(&app));
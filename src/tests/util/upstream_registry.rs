@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use crates_io::upstream_registry::UpstreamRegistryClient;
+use crates_io_index::Repository;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const UPSTREAM_INDEX_URL: &str = "https://index.upstream.example";
+const UPSTREAM_DL_URL: &str = "https://upstream.example/api/v1/crates";
+
+/// A local stub of an upstream registry, which serves files from memory and
+/// records all requests.
+#[derive(Clone, Default)]
+pub struct MockUpstreamRegistry {
+    files: Arc<Mutex<HashMap<String, Bytes>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockUpstreamRegistry {
+    /// Serves `content` at the given URL.
+    pub fn add_file(&self, url: impl Into<String>, content: impl Into<Bytes>) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(url.into(), content.into());
+    }
+
+    /// Adds a crate version with the given `.crate` file content to the
+    /// sparse index of the upstream registry.
+    pub fn publish(&self, name: &str, version: &str, content: &'static [u8]) {
+        let config = json!({ "dl": UPSTREAM_DL_URL, "api": "https://upstream.example" });
+        self.add_file(
+            format!("{UPSTREAM_INDEX_URL}/config.json"),
+            config.to_string(),
+        );
+
+        let cksum = hex::encode(Sha256::digest(content));
+        let entry = json!({
+            "name": name,
+            "vers": version,
+            "deps": [],
+            "cksum": cksum,
+            "features": {},
+            "yanked": false,
+        });
+
+        let path = Repository::relative_index_file_for_url(name);
+        let url = format!("{UPSTREAM_INDEX_URL}/{path}");
+
+        let mut files = self.files.lock().unwrap();
+        let index = files.entry(url).or_default();
+        *index = format!("{}{entry}\n", String::from_utf8_lossy(index)).into();
+
+        let url = format!("{UPSTREAM_DL_URL}/{name}/{version}/download");
+        files.insert(url, Bytes::from_static(content));
+    }
+
+    /// Returns the URLs of all requests that were sent to the upstream
+    /// registry so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl UpstreamRegistryClient for MockUpstreamRegistry {
+    async fn get(&self, url: &str) -> anyhow::Result<Option<Bytes>> {
+        self.requests.lock().unwrap().push(url.to_string());
+        Ok(self.files.lock().unwrap().get(url).cloned())
+    }
+}
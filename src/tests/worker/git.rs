@@ -1,9 +1,15 @@
-use crate::builders::PublishBuilder;
+use crate::builders::{CrateBuilder, PublishBuilder, VersionBuilder};
 use crate::util::{RequestHelper, TestApp};
+use chrono::NaiveDate;
+use crates_io::index_signing::IndexSigner;
 use crates_io::models::Crate;
+use crates_io::schema::{background_jobs, crates};
 use crates_io::worker::jobs;
+use crates_io_worker::BackgroundJob;
 use diesel::prelude::*;
+use googletest::prelude::*;
 use http::StatusCode;
+use sha2::{Digest, Sha256};
 
 #[test]
 fn index_smoke_test() {
@@ -66,3 +72,276 @@ fn index_smoke_test() {
     );
     assert_ok_eq!(upstream.crate_exists("serde"), false);
 }
+
+#[test]
+fn index_syncs_are_batched() {
+    let (app, _, user) = TestApp::full()
+        .with_config(|config| config.git_index_sync_batch_size = 10)
+        .with_user();
+
+    let user = user.as_model();
+    let upstream = app.upstream_index();
+
+    app.db(|conn| {
+        for name in ["foo", "bar", "baz"] {
+            CrateBuilder::new(name, user.id)
+                .version(VersionBuilder::new("1.0.0"))
+                .expect_build(conn);
+
+            assert_ok!(jobs::SyncToGitIndex::new(name).enqueue(conn));
+        }
+
+        // Duplicate syncs of the same crate are merged into the batch
+        assert_ok!(jobs::SyncToGitIndex::new("foo").enqueue(conn));
+    });
+
+    app.run_pending_background_jobs();
+    let batch_message = "Sync 3 crates\n\n\
+        Create crate `foo`\n\
+        Create crate `bar`\n\
+        Create crate `baz`";
+
+    assert_ok_eq!(
+        upstream.list_commits(),
+        vec!["Initial Commit", batch_message]
+    );
+    assert_ok_eq!(upstream.crate_exists("foo"), true);
+    assert_ok_eq!(upstream.crate_exists("bar"), true);
+    assert_ok_eq!(upstream.crate_exists("baz"), true);
+
+    let remaining_jobs: i64 =
+        app.db(|conn| assert_ok!(background_jobs::table.count().get_result(conn)));
+    assert_eq!(remaining_jobs, 0);
+
+    // A batch with a single change uses the regular commit message
+
+    app.db(|conn| {
+        assert_ok!(diesel::delete(crates::table.filter(crates::name.eq("bar"))).execute(conn));
+        assert_ok!(jobs::SyncToGitIndex::new("foo").enqueue(conn));
+        assert_ok!(jobs::SyncToGitIndex::new("bar").enqueue(conn));
+    });
+
+    app.run_pending_background_jobs();
+    assert_ok_eq!(
+        upstream.list_commits(),
+        vec!["Initial Commit", batch_message, "Delete crate `bar`"]
+    );
+    assert_ok_eq!(upstream.crate_exists("bar"), false);
+}
+
+#[test]
+fn backfill_index_pubtime() {
+    let (app, _, user) = TestApp::full().with_user();
+    let user = user.as_model();
+    let upstream = app.upstream_index();
+
+    let created_at = NaiveDate::from_ymd_opt(2020, 1, 2)
+        .unwrap()
+        .and_hms_opt(3, 4, 5)
+        .unwrap();
+
+    let mut versions = app.db(|conn| {
+        let krate = CrateBuilder::new("foo", user.id)
+            .version(VersionBuilder::new("1.0.0").created_at(created_at))
+            .expect_build(conn);
+
+        assert_ok!(krate.index_metadata(conn))
+    });
+
+    // Simulate an index file that was written before `pubtime` was introduced
+    for version in &mut versions {
+        version.pubtime = None;
+    }
+
+    let mut content = Vec::new();
+    assert_ok!(crates_io_index::write_crates(&versions, &mut content));
+    let content = assert_ok!(String::from_utf8(content));
+    assert_ok!(upstream.write_index_file("foo", &content));
+
+    app.db(|conn| {
+        assert_ok!(jobs::BackfillIndexPubtime::new(false).enqueue(conn));
+    });
+
+    app.run_pending_background_jobs();
+
+    // The sparse index jobs are enqueued by the backfill job, which might be
+    // after the worker of the default queue has already shut down.
+    app.run_pending_background_jobs();
+
+    assert_ok_eq!(
+        upstream.list_commits(),
+        vec![
+            "Initial Commit",
+            "Write index file of `foo`",
+            "Backfill `pubtime` of index entries\n",
+        ]
+    );
+
+    let versions = app.crates_from_index_head("foo");
+    assert_eq!(versions.len(), 1);
+    assert_some_eq!(&versions[0].pubtime, "2020-01-02T03:04:05Z");
+
+    assert_eq!(app.stored_files(), vec!["index/3/f/foo"]);
+}
+
+#[test]
+fn index_is_pushed_to_mirrors() {
+    let (app, _, _, token) = TestApp::full()
+        .with_git_mirror("backup")
+        .with_git_mirror("secondary")
+        .with_token();
+
+    let upstream = app.upstream_index();
+
+    token
+        .publish_crate(PublishBuilder::new("serde", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let expected_commits = vec!["Initial Commit", "Create crate `serde`"];
+    assert_ok_eq!(upstream.list_commits(), expected_commits);
+
+    for name in ["backup", "secondary"] {
+        let mirror = app.git_mirror(name);
+        assert_ok_eq!(mirror.list_commits(), expected_commits);
+        assert_ok_eq!(mirror.crate_exists("serde"), true);
+    }
+}
+
+#[test]
+fn check_git_mirrors() {
+    let (app, _, _, token) = TestApp::full().with_git_mirror("backup").with_token();
+
+    let upstream = app.upstream_index();
+    let mirror = app.git_mirror("backup");
+
+    token
+        .publish_crate(PublishBuilder::new("serde", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+    assert_ok_eq!(mirror.list_commits(), upstream.list_commits().unwrap());
+
+    // Make the mirror diverge from the primary repository
+    mirror.create_empty_commit().unwrap();
+    assert_eq!(mirror.list_commits().unwrap().len(), 3);
+
+    app.db(|conn| assert_ok!(jobs::CheckGitMirrors.enqueue(conn)));
+    app.run_pending_background_jobs();
+
+    assert_ok_eq!(mirror.list_commits(), upstream.list_commits().unwrap());
+}
+
+#[test]
+fn sparse_index_metadata_is_signed() {
+    let signer = IndexSigner::from_seed(&[42; 32]).unwrap();
+    let signer_key_id = signer.key_id().to_string();
+    let (app, _, _, token) = TestApp::full().with_index_signer(signer).with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("serde", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let storage = &app.as_inner().storage;
+    let read = |path: &str| {
+        let file = app.runtime().block_on(storage.read_index_file(path));
+        assert_some!(file.unwrap()).0
+    };
+    let read_json =
+        |path: &str| -> serde_json::Value { serde_json::from_slice(&read(path)).unwrap() };
+
+    // Checks that the `targets`, `snapshot` and `timestamp` documents match
+    // the stored index file, and returns the version of the `targets` document
+    let check_documents = || {
+        let content = read("se/rd/serde");
+        let targets = read_json("_tuf/targets/se/rd/serde.json");
+        let target = &targets["signed"]["targets"]["se/rd/serde"];
+        assert_eq!(target["length"], content.len());
+        assert_eq!(
+            target["hashes"]["sha256"],
+            hex::encode(Sha256::digest(&content))
+        );
+
+        let snapshot = read("_tuf/snapshot.json");
+        let snapshot_json: serde_json::Value = serde_json::from_slice(&snapshot).unwrap();
+        assert_eq!(
+            snapshot_json["signed"]["meta"]["targets/se/rd/serde.json"]["version"],
+            targets["signed"]["version"]
+        );
+
+        let timestamp = read_json("_tuf/timestamp.json");
+        let snapshot_meta = &timestamp["signed"]["meta"]["snapshot.json"];
+        assert_eq!(snapshot_meta["length"], snapshot.len());
+        assert_eq!(
+            snapshot_meta["hashes"]["sha256"],
+            hex::encode(Sha256::digest(&snapshot))
+        );
+        assert_eq!(snapshot_meta["version"], snapshot_json["signed"]["version"]);
+
+        targets["signed"]["version"].as_i64().unwrap()
+    };
+
+    // The sync uploads the `targets` document of the index file, and updates
+    // the `snapshot` and `timestamp` documents
+    assert_eq!(check_documents(), 1);
+    assert_that!(
+        app.stored_files(),
+        not(contains(eq("index/_tuf/root.json")))
+    );
+
+    // Every sync increments the version of the `targets` document
+    token
+        .publish_crate(PublishBuilder::new("serde", "1.1.0"))
+        .good();
+    app.run_pending_background_jobs();
+    assert_eq!(check_documents(), 2);
+
+    // The periodic job re-signs everything and uploads the `root` document
+    app.db(|conn| assert_ok!(jobs::ResignIndexMetadata.enqueue(conn)));
+    app.run_pending_background_jobs();
+    assert_eq!(check_documents(), 3);
+    assert_eq!(read_json("_tuf/snapshot.json")["signed"]["version"], 3);
+    assert_eq!(read_json("_tuf/timestamp.json")["signed"]["version"], 3);
+
+    let root = read("_tuf/root.json");
+    let root_json: serde_json::Value = serde_json::from_slice(&root).unwrap();
+    assert_eq!(root_json["signed"]["version"], 1);
+    let roles = root_json["signed"]["roles"].as_object().unwrap();
+    let roles = roles.keys().collect::<Vec<_>>();
+    assert_eq!(roles, ["root", "snapshot", "targets", "timestamp"]);
+
+    // The `root` document is only uploaded if it needs to be updated
+    app.db(|conn| assert_ok!(jobs::ResignIndexMetadata.enqueue(conn)));
+    app.run_pending_background_jobs();
+    assert_eq!(read("_tuf/root.json"), root);
+
+    // A `root` document with a different key is replaced with the next version
+    let other_signer = IndexSigner::from_seed(&[7; 32]).unwrap();
+    let other_root = other_signer.root(4, chrono::Utc::now()).unwrap();
+    let future = storage.sync_index_file("_tuf/root.json", Some(other_root));
+    assert_ok!(app.runtime().block_on(future));
+    app.db(|conn| assert_ok!(jobs::ResignIndexMetadata.enqueue(conn)));
+    app.run_pending_background_jobs();
+
+    let root = read_json("_tuf/root.json");
+    assert_eq!(root["signed"]["version"], 5);
+    assert_eq!(root["signatures"][0]["keyid"], signer_key_id);
+
+    // Deleting the crate also removes the signed metadata of its index file
+    app.db(|conn| {
+        let krate: Crate = assert_ok!(Crate::by_name("serde").first(conn));
+        assert_ok!(diesel::delete(crates::table.find(krate.id)).execute(conn));
+        assert_ok!(jobs::enqueue_sync_to_index("serde", conn));
+    });
+    app.run_pending_background_jobs();
+
+    let stored_files = app.stored_files();
+    assert_that!(stored_files, not(contains(eq("index/se/rd/serde"))));
+    assert_that!(
+        stored_files,
+        not(contains(eq("index/_tuf/targets/se/rd/serde.json")))
+    );
+
+    let snapshot = read_json("_tuf/snapshot.json");
+    assert_none!(snapshot["signed"]["meta"].get("targets/se/rd/serde.json"));
+}
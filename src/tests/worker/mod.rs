@@ -1,2 +1,5 @@
 mod git;
+mod replicate_storage;
 mod sync_admins;
+mod verify_index;
+mod verify_storage;
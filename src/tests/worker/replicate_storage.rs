@@ -0,0 +1,67 @@
+use crate::builders::PublishBuilder;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::config;
+use crates_io::storage::{StorageBucket, StorageConfig};
+use crates_io::worker::jobs;
+use crates_io_worker::BackgroundJob;
+use futures_util::TryStreamExt;
+
+fn replicated_files(app: &TestApp) -> Vec<String> {
+    let store = app.as_inner().storage.as_replica_inner().unwrap();
+
+    let list = app.runtime().block_on(async {
+        let stream = store.list(None);
+        stream.try_collect::<Vec<_>>().await.unwrap()
+    });
+
+    let mut paths: Vec<_> = list.into_iter().map(|m| m.location.to_string()).collect();
+    paths.sort();
+    paths
+}
+
+fn replicated_storage(config: &mut config::Server) {
+    let cdn_prefix = config.storage.cdn_prefix.take();
+    config.storage = StorageConfig::in_memory_replicated();
+    config.storage.cdn_prefix = cdn_prefix;
+}
+
+#[test]
+fn uploads_are_replicated() {
+    let (app, _, _, token) = TestApp::full().with_config(replicated_storage).with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let expected_files = vec!["crates/foo/foo-1.0.0.crate", "index/3/f/foo"];
+    assert_eq!(app.stored_files(), expected_files);
+    assert_eq!(replicated_files(&app), expected_files);
+}
+
+#[test]
+fn replicate_storage_object_repairs_secondary() {
+    let (app, _, _, token) = TestApp::full().with_config(replicated_storage).with_token();
+
+    token
+        .publish_crate(PublishBuilder::new("foo", "1.0.0"))
+        .good();
+    app.run_pending_background_jobs();
+
+    let secondary = app.as_inner().storage.as_replica_inner().unwrap();
+    let rt = app.runtime();
+    for path in ["crates/foo/foo-1.0.0.crate", "index/3/f/foo"] {
+        assert_ok!(rt.block_on(secondary.delete(&path.into())));
+    }
+
+    app.db(|conn| {
+        let path = "crates/foo/foo-1.0.0.crate";
+        assert_ok!(jobs::ReplicateStorageObject::new(StorageBucket::Default, path).enqueue(conn));
+        assert_ok!(
+            jobs::ReplicateStorageObject::new(StorageBucket::Index, "3/f/foo").enqueue(conn)
+        );
+    });
+    app.run_pending_background_jobs();
+
+    assert_eq!(replicated_files(&app), app.stored_files());
+}
@@ -0,0 +1,68 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::util::TestApp;
+use crates_io::schema::versions;
+use crates_io::worker::jobs;
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+
+#[test]
+fn verify_index_repairs_discrepancies() {
+    let (app, _, user) = TestApp::full().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        for name in ["foo", "bar"] {
+            CrateBuilder::new(name, user.id)
+                .version(VersionBuilder::new("1.0.0"))
+                .expect_build(conn);
+
+            assert_ok!(jobs::enqueue_sync_to_index(name, conn));
+        }
+    });
+
+    app.run_pending_background_jobs();
+
+    // The repair jobs are enqueued by the `VerifyIndex` job, which might be
+    // after the workers of the other queues have already shut down.
+    app.run_pending_background_jobs();
+
+    let expected_files = vec!["index/3/b/bar", "index/3/f/foo"];
+    assert_eq!(app.stored_files(), expected_files);
+    assert_ok_eq!(app.upstream_index().crate_exists("foo"), true);
+
+    // Remove a file from the sparse index and add an orphaned one
+
+    let storage = &app.as_inner().storage;
+    let rt = app.runtime();
+    assert_ok!(rt.block_on(storage.sync_index("foo", None)));
+    assert_ok!(rt.block_on(storage.sync_index("baz", Some("{}".to_string()))));
+
+    let expected_files = vec!["index/3/b/bar", "index/3/b/baz"];
+    assert_eq!(app.stored_files(), expected_files);
+
+    // Yank a version without syncing the index
+
+    app.db(|conn| {
+        assert_ok!(diesel::update(versions::table)
+            .set(versions::yanked.eq(true))
+            .execute(conn));
+
+        assert_ok!(jobs::VerifyIndex::new(true).enqueue(conn));
+    });
+
+    app.run_pending_background_jobs();
+
+    // The repair jobs are enqueued by the `VerifyIndex` job, which might be
+    // after the workers of the other queues have already shut down.
+    app.run_pending_background_jobs();
+
+    let expected_files = vec!["index/3/b/bar", "index/3/f/foo"];
+    assert_eq!(app.stored_files(), expected_files);
+
+    let bar = app.crates_from_index_head("bar");
+    assert!(bar.iter().all(|version| version.yanked == Some(true)));
+
+    let content = assert_some!(assert_ok!(rt.block_on(storage.read_index("bar"))));
+    let content = String::from_utf8_lossy(&content);
+    assert!(content.contains(r#""yanked":true"#));
+}
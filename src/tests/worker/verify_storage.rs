@@ -0,0 +1,113 @@
+use crate::builders::PublishBuilder;
+use crate::util::{RequestHelper, TestApp};
+use crates_io::models::{StorageAuditFailure, StorageAuditFailureKind};
+use crates_io::schema::{storage_audit_failures, versions};
+use crates_io::worker::jobs;
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use hyper::body::Bytes;
+
+fn audit_failures(app: &TestApp) -> Vec<(String, StorageAuditFailureKind)> {
+    app.db(|conn| {
+        storage_audit_failures::table
+            .inner_join(versions::table)
+            .select((versions::num, StorageAuditFailure::as_select()))
+            .order(versions::num)
+            .load::<(String, StorageAuditFailure)>(conn)
+            .unwrap()
+            .into_iter()
+            .map(|(num, failure)| (num, failure.kind))
+            .collect()
+    })
+}
+
+#[test]
+fn verify_storage_records_failures() {
+    let (app, _, _, token) = TestApp::full().with_token();
+
+    for version in ["1.0.0", "1.1.0", "1.2.0", "1.3.0"] {
+        token
+            .publish_crate(PublishBuilder::new("foo", version))
+            .good();
+    }
+    app.run_pending_background_jobs();
+
+    app.db(|conn| assert_ok!(jobs::VerifyStorage::all(0).enqueue(conn)));
+    app.run_pending_background_jobs();
+    assert_eq!(audit_failures(&app), vec![]);
+
+    // Delete, truncate and replace some of the crate files
+
+    let store = app.as_inner().storage.as_inner();
+    let rt = app.runtime();
+
+    let path = "crates/foo/foo-1.0.0.crate".into();
+    assert_ok!(rt.block_on(store.delete(&path)));
+
+    let path = "crates/foo/foo-1.1.0.crate".into();
+    let content = assert_ok!(rt.block_on(async { store.get(&path).await?.bytes().await }));
+    let truncated = content.slice(..content.len() / 2);
+    assert_ok!(rt.block_on(store.put(&path, truncated)));
+
+    let path = "crates/foo/foo-1.2.0.crate".into();
+    let mut tampered = content.to_vec();
+    tampered[0] ^= 0xff;
+    assert_ok!(rt.block_on(store.put(&path, Bytes::from(tampered))));
+
+    app.db(|conn| assert_ok!(jobs::VerifyStorage::all(0).enqueue(conn)));
+    app.run_pending_background_jobs();
+
+    let expected = vec![
+        ("1.0.0".to_string(), StorageAuditFailureKind::Missing),
+        ("1.1.0".to_string(), StorageAuditFailureKind::Truncated),
+        ("1.2.0".to_string(), StorageAuditFailureKind::Mismatched),
+    ];
+    assert_eq!(audit_failures(&app), expected);
+
+    // Restoring a crate file resolves its failure
+
+    let path = "crates/foo/foo-1.1.0.crate".into();
+    assert_ok!(rt.block_on(store.put(&path, content)));
+
+    app.db(|conn| assert_ok!(jobs::VerifyStorage::sample(10).enqueue(conn)));
+    app.run_pending_background_jobs();
+
+    let expected = vec![
+        ("1.0.0".to_string(), StorageAuditFailureKind::Missing),
+        ("1.2.0".to_string(), StorageAuditFailureKind::Mismatched),
+    ];
+    assert_eq!(audit_failures(&app), expected);
+}
+
+#[test]
+fn verify_storage_resumes_after_cursor() {
+    let (app, _, _, token) = TestApp::full().with_token();
+
+    for version in ["1.0.0", "1.1.0"] {
+        token
+            .publish_crate(PublishBuilder::new("foo", version))
+            .good();
+    }
+    app.run_pending_background_jobs();
+
+    let store = app.as_inner().storage.as_inner();
+    let rt = app.runtime();
+    for version in ["1.0.0", "1.1.0"] {
+        let path = format!("crates/foo/foo-{version}.crate").into();
+        assert_ok!(rt.block_on(store.delete(&path)));
+    }
+
+    let first_version_id = app.db(|conn| {
+        versions::table
+            .select(versions::id)
+            .order(versions::id)
+            .first::<i32>(conn)
+            .unwrap()
+    });
+
+    app.db(|conn| assert_ok!(jobs::VerifyStorage::all(first_version_id).enqueue(conn)));
+    app.run_pending_background_jobs();
+
+    let expected = vec![("1.1.0".to_string(), StorageAuditFailureKind::Missing)];
+    assert_eq!(audit_failures(&app), expected);
+}
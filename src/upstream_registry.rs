@@ -0,0 +1,356 @@
+//! Pull-through mirror mode, in which this registry acts as a caching proxy
+//! of an upstream registry.
+//!
+//! If a crate has not been published to this registry, its sparse index file
+//! and `.crate` files are fetched from the upstream registry on demand, and
+//! stored in the [Storage](crate::storage::Storage) so that they can be
+//! served as if they had been published locally. The `.crate` files are
+//! verified against the `cksum` field of the index file before they are
+//! stored.
+
+use crate::config::UpstreamRegistryConfig;
+use crate::models::Crate;
+use anyhow::{anyhow, ensure, Context};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crates_io_index::Repository;
+use hyper::body::Bytes;
+use parking_lot::Mutex;
+use reqwest::{header, Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::time::Instant;
+
+/// A minimal HTTP client for the upstream registry.
+///
+/// This is abstracted into a trait so that the tests can use a local stub
+/// instead of sending requests over the network.
+#[async_trait]
+pub trait UpstreamRegistryClient: Send + Sync {
+    /// Sends a `GET` request to the given URL and returns the response body,
+    /// or `None` if the upstream registry responded with `404 Not Found`.
+    async fn get(&self, url: &str) -> anyhow::Result<Option<Bytes>>;
+}
+
+pub struct RealUpstreamRegistryClient {
+    client: Client,
+}
+
+impl RealUpstreamRegistryClient {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl UpstreamRegistryClient for RealUpstreamRegistryClient {
+    async fn get(&self, url: &str) -> anyhow::Result<Option<Bytes>> {
+        let response = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, "crates.io (https://crates.io)")
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bytes = response.error_for_status()?.bytes().await?;
+        Ok(Some(bytes))
+    }
+}
+
+pub struct UpstreamRegistry {
+    config: UpstreamRegistryConfig,
+    client: Box<dyn UpstreamRegistryClient>,
+    /// The `dl` template of the upstream registry, and the time it was
+    /// fetched at. It is fetched again once it is older than the index TTL.
+    download_template: Mutex<Option<(String, Instant)>>,
+}
+
+impl UpstreamRegistry {
+    pub fn new(config: UpstreamRegistryConfig, client: Box<dyn UpstreamRegistryClient>) -> Self {
+        Self {
+            config,
+            client,
+            download_template: Mutex::new(None),
+        }
+    }
+
+    /// Returns `true` if the crate matches the allow-list of the upstream
+    /// registry configuration.
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let allowed_crates = &self.config.allowed_crates;
+        allowed_crates.iter().any(|pattern| pattern.matches(&name))
+    }
+
+    /// Returns the name of the crate that the sparse index file at `path`
+    /// belongs to, if the crate may be fetched from the upstream registry.
+    ///
+    /// Paths of names that are not valid crate names never belong to a
+    /// mirrored crate.
+    pub fn mirrored_crate_name<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let (_, name) = path.rsplit_once('/')?;
+        Crate::validate_crate_name("crate", name).ok()?;
+        let is_index_file = Repository::relative_index_file_for_url(name) == path;
+        (is_index_file && self.is_allowed(name)).then_some(name)
+    }
+
+    /// Returns `true` if a mirrored index file that was last fetched at
+    /// `last_modified` needs to be fetched from the upstream registry again.
+    pub fn is_expired(&self, last_modified: DateTime<Utc>) -> bool {
+        let age = (Utc::now() - last_modified).to_std().unwrap_or_default();
+        age >= self.config.index_ttl
+    }
+
+    /// Fetches the sparse index file at `path` from the upstream registry.
+    ///
+    /// Returns an error if the file contains entries of other crates than
+    /// `name`, or `None` if the crate does not exist upstream.
+    pub async fn fetch_index_file(&self, path: &str, name: &str) -> anyhow::Result<Option<String>> {
+        let url = format!("{}/{path}", self.index_url());
+        let Some(bytes) = self.client.get(&url).await? else {
+            return Ok(None);
+        };
+
+        let content = String::from_utf8(bytes.into())
+            .with_context(|| format!("Upstream index file {path} is not valid UTF-8"))?;
+
+        let crates = crates_io_index::read_crates(&content)
+            .with_context(|| format!("Failed to parse upstream index file {path}"))?;
+
+        for krate in &crates {
+            ensure!(
+                Crate::validate_crate_name("crate", &krate.name).is_ok(),
+                "Upstream index file {path} contains an invalid crate name: {}",
+                krate.name
+            );
+        }
+
+        let name = name.to_lowercase();
+        ensure!(
+            crates.iter().all(|krate| krate.name.to_lowercase() == name),
+            "Upstream index file {path} contains entries of other crates"
+        );
+
+        Ok(Some(content))
+    }
+
+    /// Fetches the `.crate` file of the given index entry from the upstream
+    /// registry, and verifies it against the `cksum` field of the entry.
+    ///
+    /// Returns `None` if the file does not exist upstream.
+    pub async fn fetch_crate_file(
+        &self,
+        krate: &crates_io_index::Crate,
+    ) -> anyhow::Result<Option<Bytes>> {
+        let url = self.download_url(krate).await?;
+        let Some(bytes) = self.client.get(&url).await? else {
+            return Ok(None);
+        };
+
+        let cksum = hex::encode(Sha256::digest(&bytes));
+        ensure!(
+            cksum == krate.cksum,
+            "Checksum mismatch for {} v{}: expected {}, got {cksum}",
+            krate.name,
+            krate.vers,
+            krate.cksum
+        );
+
+        Ok(Some(bytes))
+    }
+
+    /// Returns the download URL of the given index entry, based on the `dl`
+    /// field in the `config.json` file of the upstream registry.
+    async fn download_url(&self, krate: &crates_io_index::Crate) -> anyhow::Result<String> {
+        let dl = self.download_template().await?;
+        Ok(expand_download_url(&dl, krate))
+    }
+
+    /// Returns the `dl` field of the `config.json` file of the upstream
+    /// registry, which is only fetched again once the cached copy is older
+    /// than the index TTL.
+    async fn download_template(&self) -> anyhow::Result<String> {
+        #[derive(Deserialize)]
+        struct IndexConfig {
+            dl: String,
+        }
+
+        if let Some((dl, fetched_at)) = &*self.download_template.lock() {
+            if fetched_at.elapsed() < self.config.index_ttl {
+                return Ok(dl.clone());
+            }
+        }
+
+        let url = format!("{}/config.json", self.index_url());
+        let config = self.client.get(&url).await?;
+        let config = config.ok_or_else(|| anyhow!("Upstream registry has no config.json"))?;
+        let config: IndexConfig = serde_json::from_slice(&config)
+            .context("Failed to parse config.json of the upstream registry")?;
+
+        *self.download_template.lock() = Some((config.dl.clone(), Instant::now()));
+
+        Ok(config.dl)
+    }
+
+    fn index_url(&self) -> &str {
+        self.config.index_url.trim_end_matches('/')
+    }
+}
+
+/// Expands the markers in the `dl` template of a registry, as described in
+/// <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration>.
+fn expand_download_url(dl: &str, krate: &crates_io_index::Crate) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        let dl = dl.trim_end_matches('/');
+        return format!("{dl}/{}/{}/download", krate.name, krate.vers);
+    }
+
+    let prefix = prefix(&krate.name);
+    dl.replace("{crate}", &krate.name)
+        .replace("{version}", &krate.vers)
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{sha256-checksum}", &krate.cksum)
+}
+
+/// Returns the directory of the crate's index file, without changing the
+/// case of the crate name.
+///
+/// The name is split on `char` boundaries, so that names that are not valid
+/// crate names can not cause a panic.
+fn prefix(name: &str) -> String {
+    let chars = name.chars().take(4).collect::<Vec<_>>();
+    match chars.len() {
+        0 => String::new(),
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", chars[0]),
+        _ => format!("{}{}/{}{}", chars[0], chars[1], chars[2], chars[3]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::token::CrateScope;
+    use std::time::Duration;
+
+    fn upstream(allowed_crates: &[&str]) -> UpstreamRegistry {
+        struct NoopClient;
+
+        #[async_trait]
+        impl UpstreamRegistryClient for NoopClient {
+            async fn get(&self, _url: &str) -> anyhow::Result<Option<Bytes>> {
+                Ok(None)
+            }
+        }
+
+        let allowed_crates = allowed_crates
+            .iter()
+            .map(|pattern| CrateScope::try_from(*pattern).unwrap())
+            .collect();
+
+        let config = UpstreamRegistryConfig {
+            index_url: "https://index.example.com/".into(),
+            allowed_crates,
+            index_ttl: Duration::from_secs(60),
+        };
+
+        UpstreamRegistry::new(config, Box::new(NoopClient))
+    }
+
+    fn krate(name: &str, vers: &str) -> crates_io_index::Crate {
+        crates_io_index::Crate {
+            name: name.into(),
+            vers: vers.into(),
+            deps: vec![],
+            cksum: "0123abcd".into(),
+            features: Default::default(),
+            features2: None,
+            yanked: None,
+            links: None,
+            rust_version: None,
+            pubtime: None,
+            v: None,
+        }
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let upstream = upstream(&["serde", "tokio-*"]);
+        assert!(upstream.is_allowed("serde"));
+        assert!(upstream.is_allowed("Serde"));
+        assert!(upstream.is_allowed("tokio-util"));
+        assert!(!upstream.is_allowed("serde_json"));
+        assert!(!upstream.is_allowed("tokio"));
+
+        assert!(!self::upstream(&[]).is_allowed("serde"));
+        assert!(self::upstream(&["*"]).is_allowed("serde"));
+    }
+
+    #[test]
+    fn test_mirrored_crate_name() {
+        let upstream = upstream(&["*"]);
+        assert_eq!(upstream.mirrored_crate_name("se/rd/serde"), Some("serde"));
+        assert_eq!(upstream.mirrored_crate_name("3/f/foo"), Some("foo"));
+        assert_eq!(upstream.mirrored_crate_name("1/a"), Some("a"));
+        assert_eq!(upstream.mirrored_crate_name("3/b/foo"), None);
+        assert_eq!(upstream.mirrored_crate_name("_tuf/root.json"), None);
+        assert_eq!(upstream.mirrored_crate_name("config.json"), None);
+        assert_eq!(upstream.mirrored_crate_name("fe/e/fée"), None);
+        assert_eq!(upstream.mirrored_crate_name("3/f/f.o"), None);
+
+        let upstream = self::upstream(&["foo"]);
+        assert_eq!(upstream.mirrored_crate_name("se/rd/serde"), None);
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let upstream = upstream(&["*"]);
+        assert!(!upstream.is_expired(Utc::now()));
+        assert!(upstream.is_expired(Utc::now() - chrono::Duration::seconds(61)));
+    }
+
+    #[test]
+    fn test_expand_download_url() {
+        let krate = krate("Serde", "1.0.0");
+
+        let dl = "https://example.com/api/v1/crates";
+        let expected = "https://example.com/api/v1/crates/Serde/1.0.0/download";
+        assert_eq!(expand_download_url(dl, &krate), expected);
+
+        let dl = "https://example.com/{prefix}/{lowerprefix}/{crate}-{version}.crate";
+        let expected = "https://example.com/Se/rd/se/rd/Serde-1.0.0.crate";
+        assert_eq!(expand_download_url(dl, &krate), expected);
+
+        let dl = "https://example.com/{sha256-checksum}";
+        assert_eq!(
+            expand_download_url(dl, &krate),
+            "https://example.com/0123abcd"
+        );
+
+        let krate = self::krate("foo", "1.0.0");
+        let dl = "https://example.com/{prefix}/{crate}";
+        assert_eq!(
+            expand_download_url(dl, &krate),
+            "https://example.com/3/f/foo"
+        );
+
+        let krate = self::krate("fööbar", "1.0.0");
+        assert_eq!(
+            expand_download_url(dl, &krate),
+            "https://example.com/fö/öb/fööbar"
+        );
+    }
+}
@@ -60,6 +60,13 @@ pub fn forbidden() -> BoxedAppError {
     custom(StatusCode::FORBIDDEN, detail)
 }
 
+/// Return an error with status 401, which instructs cargo to retry the
+/// request with the credentials of the registry.
+pub fn unauthorized() -> BoxedAppError {
+    let detail = "this registry requires authentication";
+    custom(StatusCode::UNAUTHORIZED, detail)
+}
+
 pub fn not_found() -> BoxedAppError {
     custom(StatusCode::NOT_FOUND, "Not Found")
 }
@@ -4,8 +4,8 @@ use secrecy::ExposeSecret;
 use crate::external_urls::remove_blocked_urls;
 use crate::models::{
     ApiToken, Category, Crate, CrateOwnerInvitation, CreatedApiToken, Dependency, DependencyKind,
-    Keyword, Owner, ReverseDependency, Team, TopVersions, User, Version, VersionDownload,
-    VersionOwnerAction,
+    IndexChange, IndexChangeKind, Keyword, Owner, ReverseDependency, Team, TopVersions, User,
+    Version, VersionClientDownload, VersionDownload, VersionOwnerAction,
 };
 use crate::util::rfc3339;
 use crates_io_github as github;
@@ -155,6 +155,10 @@ pub struct EncodableVersionDownload {
     pub version: i32,
     pub downloads: i32,
     pub date: String,
+    /// The kind of client, only included when the downloads are broken
+    /// down by client via `?by=client`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
 }
 
 impl From<VersionDownload> for EncodableVersionDownload {
@@ -163,6 +167,43 @@ impl From<VersionDownload> for EncodableVersionDownload {
             version: download.version_id,
             downloads: download.downloads,
             date: download.date.to_string(),
+            client: None,
+        }
+    }
+}
+
+impl From<VersionClientDownload> for EncodableVersionDownload {
+    fn from(download: VersionClientDownload) -> Self {
+        Self {
+            version: download.version_id,
+            downloads: download.downloads,
+            date: download.date.to_string(),
+            client: Some(download.client),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncodableIndexChange {
+    /// The cursor of this change, which can be passed as `?since=` to only
+    /// receive subsequent changes.
+    pub id: i64,
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: Option<String>,
+    pub kind: IndexChangeKind,
+    #[serde(with = "rfc3339")]
+    pub timestamp: NaiveDateTime,
+}
+
+impl From<IndexChange> for EncodableIndexChange {
+    fn from(change: IndexChange) -> Self {
+        Self {
+            id: change.id,
+            krate: change.crate_name,
+            version: change.version,
+            kind: change.kind,
+            timestamp: change.created_at,
         }
     }
 }
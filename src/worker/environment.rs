@@ -1,11 +1,11 @@
-use crate::cloudfront::CloudFront;
+use crate::cdn_invalidation::InvalidationQueue;
 use crate::db::DieselPool;
-use crate::fastly::Fastly;
+use crate::index_signing::IndexSigner;
 use crate::storage::Storage;
 use crate::team_repo::TeamRepo;
 use crate::typosquat;
 use crate::Emails;
-use crates_io_index::{Repository, RepositoryConfig};
+use crates_io_index::{MirrorConfig, Repository, RepositoryConfig};
 use derive_builder::Builder;
 use diesel::PgConnection;
 use parking_lot::{Mutex, MutexGuard};
@@ -22,9 +22,13 @@ pub struct Environment {
     #[builder(default, setter(skip))]
     repository: Mutex<Option<Repository>>,
     #[builder(default)]
-    cloudfront: Option<CloudFront>,
+    cloudfront: Option<InvalidationQueue>,
     #[builder(default)]
-    fastly: Option<Fastly>,
+    fastly: Option<InvalidationQueue>,
+    #[builder(default)]
+    index_signer: Option<IndexSigner>,
+    #[builder(default, setter(skip))]
+    index_metadata: Mutex<()>,
     pub storage: Arc<Storage>,
     pub connection_pool: DieselPool,
     pub emails: Emails,
@@ -59,14 +63,35 @@ impl Environment {
         Ok(repo_lock)
     }
 
-    pub(crate) fn cloudfront(&self) -> Option<&CloudFront> {
+    /// Returns the configured mirrors of the git index.
+    pub(crate) fn git_mirrors(&self) -> &[MirrorConfig] {
+        &self.repository_config.mirrors
+    }
+
+    /// Returns the git index mirror with the given name, if it is configured.
+    pub(crate) fn git_mirror(&self, name: &str) -> Option<&MirrorConfig> {
+        self.git_mirrors().iter().find(|mirror| mirror.name == name)
+    }
+
+    pub(crate) fn cloudfront(&self) -> Option<&InvalidationQueue> {
         self.cloudfront.as_ref()
     }
 
-    pub(crate) fn fastly(&self) -> Option<&Fastly> {
+    pub(crate) fn fastly(&self) -> Option<&InvalidationQueue> {
         self.fastly.as_ref()
     }
 
+    pub(crate) fn index_signer(&self) -> Option<&IndexSigner> {
+        self.index_signer.as_ref()
+    }
+
+    /// Locks the signed metadata of the sparse index, so that the index files
+    /// and their `targets`, `snapshot` and `timestamp` documents are not
+    /// updated by multiple jobs at the same time.
+    pub(crate) fn lock_index_metadata(&self) -> MutexGuard<'_, ()> {
+        self.index_metadata.lock()
+    }
+
     /// Returns the typosquatting cache, initialising it if required.
     pub(crate) fn typosquat_cache(
         &self,
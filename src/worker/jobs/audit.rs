@@ -0,0 +1,86 @@
+//! Shared infrastructure of the audits that compare stored data against the
+//! database, like [VerifyIndex](super::VerifyIndex) and
+//! [VerifyStorage](super::VerifyStorage).
+//!
+//! The audits themselves are synchronous, since they mostly iterate over
+//! database rows. They access the [Storage](crate::storage::Storage) via
+//! [Handle::block_on], which can only drive IO on a multi-threaded runtime,
+//! like the one of the background worker or the one returned by
+//! [block_on_runtime](crate::tasks::block_on_runtime) in admin commands.
+
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use diesel::PgConnection;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+/// A problem that was found by an audit.
+pub trait Finding {
+    /// Describes the audited object, e.g. "git index file of `foo`".
+    fn subject(&self) -> String;
+
+    /// Describes the problem, e.g. "missing".
+    fn kind(&self) -> &'static str;
+
+    fn describe(&self) -> String {
+        format!("{} is {}", self.subject(), self.kind())
+    }
+}
+
+/// Runs `audit` on a thread where blocking is acceptable, with a database
+/// connection and a [Handle] of the current runtime.
+pub async fn run_audit<F, R>(env: Arc<Environment>, audit: F) -> anyhow::Result<R>
+where
+    F: FnOnce(&Environment, &mut PgConnection, &Handle) -> anyhow::Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_blocking(move || {
+        let conn = &mut *env.connection_pool.get()?;
+        audit(&env, conn, &Handle::current())
+    })
+    .await
+}
+
+/// The result of an audit run.
+#[derive(Debug)]
+pub struct AuditReport<F> {
+    /// The number of audited objects.
+    pub num_checked: usize,
+    pub findings: Vec<F>,
+}
+
+impl<F> Default for AuditReport<F> {
+    fn default() -> Self {
+        Self {
+            num_checked: 0,
+            findings: Vec::new(),
+        }
+    }
+}
+
+impl<F: Finding> AuditReport<F> {
+    /// Logs all findings of the audit `name` as warnings, followed by a
+    /// summary.
+    pub fn log(&self, name: &str) {
+        for finding in &self.findings {
+            let kind = finding.kind();
+            warn!(audit = name, kind, "{}", finding.describe());
+        }
+
+        let num_checked = self.num_checked;
+        let num_findings = self.findings.len();
+        info!(audit = name, num_checked, num_findings, "Audit finished");
+    }
+
+    /// Prints all findings of an audit that was run by an admin command,
+    /// followed by a summary, which refers to the audited objects as `what`.
+    pub fn print(&self, what: &str) {
+        for finding in &self.findings {
+            println!("{}", finding.describe());
+        }
+
+        let num_checked = self.num_checked;
+        let num_findings = self.findings.len();
+        println!("checked {num_checked} {what}, found {num_findings} problems");
+    }
+}
@@ -18,9 +18,9 @@ impl BackgroundJob for DailyDbMaintenance {
     /// Because the `version_downloads` table includes years of historical data, we can accumulate
     /// a *lot* of garbage before an auto-vacuum is run.
     ///
-    /// We only need to keep 90 days of entries in `version_downloads`. Once we have a mechanism to
-    /// archive daily download counts and drop historical data, we can drop this task and rely on
-    /// auto-vacuum again.
+    /// Older entries in `version_downloads` are moved to archive files by the
+    /// `ArchiveVersionDownloads` job. Once the historical data has been archived, we can drop this
+    /// task and rely on auto-vacuum again.
     async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
         spawn_blocking(move || {
             let mut conn = env.connection_pool.get()?;
@@ -0,0 +1,434 @@
+use crate::models::{DownloadAnomalyKind, NewDownloadAnomaly};
+use crate::schema::{version_downloads, versions};
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The number of most recent days (including today) whose daily downloads
+/// are scanned for anomalies.
+pub const SCAN_DAYS: u64 = 3;
+
+/// The number of days before a scanned day that are used as its history.
+pub const HISTORY_DAYS: u64 = 28;
+
+/// Daily downloads below this threshold are never flagged, since small
+/// numbers are too noisy to be meaningful.
+const MIN_DOWNLOADS: i32 = 1000;
+
+/// The minimum number of days a version must have existed before a scanned
+/// day to compare the day against the history of the version.
+const MIN_HISTORY_DAYS: usize = 7;
+
+/// The z-score above which the daily downloads of a version are flagged.
+const Z_SCORE_THRESHOLD: f64 = 6.0;
+
+/// The minimum share of the downloads of a version within the history
+/// window that have to happen on a single day to be flagged as a spike.
+const SPIKE_SHARE: f64 = 0.8;
+
+/// The minimum ratio of the downloads of a spike to the average daily
+/// downloads of the whole crate.
+const SPIKE_FACTOR: f64 = 10.0;
+
+/// Daily downloads keyed by date.
+type Series = BTreeMap<NaiveDate, i64>;
+
+/// A background job that scans the daily downloads of the last [SCAN_DAYS]
+/// days for statistical outliers, which are usually caused by scripted
+/// download loops.
+///
+/// Two kinds of anomalies are detected:
+///
+/// - [DownloadAnomalyKind::ZScore]: the downloads of a version on a day are
+///   far above the downloads of the same version in the [HISTORY_DAYS] days
+///   before.
+/// - [DownloadAnomalyKind::SingleDaySpike]: most of the downloads of a
+///   version happened on a single day, and that day is far above the
+///   average daily downloads of the whole crate. This also covers new
+///   versions that do not have a history yet.
+///
+/// The anomalies are recorded in the `download_anomalies` table for review
+/// with `crates-admin download-anomalies`. They are not excluded from
+/// `recent_crate_downloads` until they have been reviewed, and dismissed
+/// anomalies are not flagged again.
+#[derive(Serialize, Deserialize)]
+pub struct DetectDownloadAnomalies;
+
+impl BackgroundJob for DetectDownloadAnomalies {
+    const JOB_NAME: &'static str = "detect_download_anomalies";
+
+    type Context = Arc<Environment>;
+
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        spawn_blocking(move || {
+            let conn = &mut *env.connection_pool.get()?;
+
+            let anomalies = detect_anomalies(conn, Utc::now().date_naive())?;
+            for anomaly in &anomalies {
+                warn!(
+                    version_id = anomaly.version_id,
+                    date = %anomaly.date,
+                    kind = %anomaly.kind,
+                    downloads = anomaly.downloads,
+                    expected_downloads = anomaly.expected_downloads,
+                    "Found download anomaly"
+                );
+            }
+
+            info!(
+                num_anomalies = anomalies.len(),
+                "Download anomaly detection finished"
+            );
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Scans the daily downloads of the [SCAN_DAYS] days up to `today` for
+/// anomalies, and records them in the `download_anomalies` table.
+///
+/// Anomalies that have been dismissed before are neither updated nor
+/// returned.
+pub fn detect_anomalies(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> QueryResult<Vec<NewDownloadAnomaly>> {
+    let scan_start = today - Days::new(SCAN_DAYS - 1);
+    let history_start = scan_start - Days::new(HISTORY_DAYS);
+
+    // Only versions with enough downloads on one of the scanned days can be
+    // flagged, so the history is only loaded for those.
+    let candidates = version_downloads::table
+        .select(version_downloads::version_id)
+        .filter(version_downloads::date.ge(scan_start))
+        .filter(version_downloads::downloads.ge(MIN_DOWNLOADS));
+
+    let candidates: Vec<(i32, i32, NaiveDateTime)> = versions::table
+        .select((versions::id, versions::crate_id, versions::created_at))
+        .filter(versions::id.eq_any(candidates))
+        .load(conn)?;
+
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let version_ids = candidates.iter().map(|&(id, _, _)| id).collect::<Vec<_>>();
+    let mut crate_ids = candidates.iter().map(|&(_, id, _)| id).collect::<Vec<_>>();
+    crate_ids.sort_unstable();
+    crate_ids.dedup();
+
+    let rows: Vec<(i32, NaiveDate, i32)> = version_downloads::table
+        .select((
+            version_downloads::version_id,
+            version_downloads::date,
+            version_downloads::downloads,
+        ))
+        .filter(version_downloads::version_id.eq_any(&version_ids))
+        .filter(version_downloads::date.ge(history_start))
+        .load(conn)?;
+
+    let mut version_series = HashMap::<i32, Series>::new();
+    for (version_id, date, downloads) in rows {
+        let series = version_series.entry(version_id).or_default();
+        series.insert(date, downloads.into());
+    }
+
+    let rows: Vec<(i32, NaiveDate, i32)> = version_downloads::table
+        .inner_join(versions::table)
+        .select((
+            versions::crate_id,
+            version_downloads::date,
+            version_downloads::downloads,
+        ))
+        .filter(versions::crate_id.eq_any(&crate_ids))
+        .filter(version_downloads::date.ge(history_start))
+        .load(conn)?;
+
+    let mut crate_series = HashMap::<i32, Series>::new();
+    for (crate_id, date, downloads) in rows {
+        let series = crate_series.entry(crate_id).or_default();
+        *series.entry(date).or_default() += i64::from(downloads);
+    }
+
+    let mut anomalies = Vec::new();
+    for (version_id, crate_id, created_at) in candidates {
+        let version = &version_series[&version_id];
+        let krate = &crate_series[&crate_id];
+
+        for (&date, &downloads) in version.range(scan_start..) {
+            if downloads < MIN_DOWNLOADS.into() {
+                continue;
+            }
+
+            let Some((kind, expected_downloads, score)) =
+                detect(date, created_at.date(), version, krate)
+            else {
+                continue;
+            };
+
+            let anomaly = NewDownloadAnomaly {
+                version_id,
+                date,
+                kind,
+                downloads: downloads as i32,
+                expected_downloads: expected_downloads.round() as i32,
+                score,
+            };
+
+            if anomaly.upsert(conn)? {
+                anomalies.push(anomaly);
+            }
+        }
+    }
+
+    anomalies.sort_by_key(|anomaly| (anomaly.date, anomaly.version_id));
+    Ok(anomalies)
+}
+
+/// Checks whether the downloads of a version on `date` are an anomaly, and
+/// returns the kind of the anomaly, the expected downloads and the score.
+///
+/// `version` contains the daily downloads of the version and `krate` the
+/// daily downloads of all versions of its crate, both starting at least
+/// [HISTORY_DAYS] before `date`. Missing days count as zero downloads.
+fn detect(
+    date: NaiveDate,
+    created: NaiveDate,
+    version: &Series,
+    krate: &Series,
+) -> Option<(DownloadAnomalyKind, f64, f64)> {
+    let downloads = *version.get(&date)? as f64;
+    let history_start = date - Days::new(HISTORY_DAYS);
+
+    // Days before the version was published would drag the mean down, so
+    // only the days since then are part of its history.
+    let history = history_start
+        .iter_days()
+        .take_while(|day| *day < date)
+        .filter(|day| *day >= created)
+        .map(|day| version.get(&day).copied().unwrap_or_default() as f64)
+        .collect::<Vec<_>>();
+
+    if history.len() >= MIN_HISTORY_DAYS {
+        let (mean, std_dev) = mean_and_std_dev(&history);
+
+        // Download counts fluctuate at least like a Poisson distribution,
+        // so a flatter history must not turn small deviations into huge
+        // z-scores.
+        let z_score = (downloads - mean) / std_dev.max(mean.sqrt()).max(1.0);
+        if z_score >= Z_SCORE_THRESHOLD {
+            return Some((DownloadAnomalyKind::ZScore, mean, z_score));
+        }
+    }
+
+    let version_total = version.range(history_start..).map(|(_, &d)| d).sum::<i64>();
+    let share = downloads / version_total as f64;
+
+    let crate_total = krate
+        .range(history_start..date)
+        .map(|(_, &d)| d)
+        .sum::<i64>();
+    let crate_mean = crate_total as f64 / HISTORY_DAYS as f64;
+    let ratio = downloads / crate_mean.max(1.0);
+
+    if share >= SPIKE_SHARE && ratio >= SPIKE_FACTOR {
+        return Some((DownloadAnomalyKind::SingleDaySpike, crate_mean, ratio));
+    }
+
+    None
+}
+
+fn mean_and_std_dev(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{DownloadAnomaly, NewCrate, NewUser, NewVersion};
+    use crate::schema::{download_anomalies, recent_crate_downloads};
+    use crate::test_util::test_db_connection;
+    use claims::{assert_none, assert_ok, assert_some};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    /// Returns a series with `downloads` on each of the `days` days before
+    /// `end`, followed by the given values starting at `end`.
+    fn series(end: &str, days: u64, downloads: i64, values: &[i64]) -> Series {
+        let end = date(end);
+        let history = (1..=days).map(|i| (end - Days::new(i), downloads));
+        let values = values.iter().enumerate();
+        let values = values.map(|(i, &d)| (end + Days::new(i as u64), d));
+        history.chain(values).collect()
+    }
+
+    #[test]
+    fn test_detect_z_score() {
+        let version = series("2024-03-01", 28, 100, &[150, 5000]);
+        let krate = series("2024-03-01", 28, 1000, &[1050, 5900]);
+        let created = date("2023-01-01");
+
+        assert_none!(detect(date("2024-03-01"), created, &version, &krate));
+
+        let (kind, expected, score) =
+            assert_some!(detect(date("2024-03-02"), created, &version, &krate));
+        assert_eq!(kind, DownloadAnomalyKind::ZScore);
+        assert_eq!(expected, 50. / 28. + 100.);
+        assert!(score > 100.);
+
+        // a noisy history raises the bar
+        let mut version = version;
+        for (i, downloads) in version.values_mut().enumerate().take(28) {
+            *downloads = if i % 2 == 0 { 0 } else { 2000 };
+        }
+        assert_none!(detect(date("2024-03-02"), created, &version, &krate));
+    }
+
+    #[test]
+    fn test_detect_single_day_spike() {
+        let krate = series("2024-03-01", 28, 100, &[20100, 200]);
+        let created = date("2024-03-01");
+
+        // a new version without a history
+        let version = series("2024-03-01", 0, 0, &[20000, 100]);
+        let (kind, expected, score) =
+            assert_some!(detect(date("2024-03-01"), created, &version, &krate));
+        assert_eq!(kind, DownloadAnomalyKind::SingleDaySpike);
+        assert_eq!(expected, 100.);
+        assert_eq!(score, 200.);
+
+        // a release of a popular crate is not a spike
+        let krate = series("2024-03-01", 28, 10000, &[30000, 20000]);
+        assert_none!(detect(date("2024-03-01"), created, &version, &krate));
+
+        // neither are downloads that are spread over several days
+        let krate = series("2024-03-01", 28, 100, &[20100, 20100]);
+        let version = series("2024-03-01", 0, 0, &[20000, 20000]);
+        assert_none!(detect(date("2024-03-01"), created, &version, &krate));
+    }
+
+    #[test]
+    fn test_mean_and_std_dev() {
+        assert_eq!(
+            mean_and_std_dev(&[2., 4., 4., 4., 5., 5., 7., 9.]),
+            (5., 2.)
+        );
+    }
+
+    #[test]
+    fn test_detect_anomalies() {
+        let (_test_db, mut conn) = test_db_connection();
+        let conn = &mut conn;
+
+        let user = NewUser::new(2, "login", None, None, "access_token")
+            .create_or_update(None, &Emails::new_in_memory(), conn)
+            .unwrap();
+        let krate = NewCrate {
+            name: "foo",
+            ..Default::default()
+        }
+        .create(conn, user.id)
+        .unwrap();
+
+        let version_id = NewVersion::new(
+            krate.id,
+            &semver::Version::parse("1.0.0").unwrap(),
+            &BTreeMap::new(),
+            None,
+            0,
+            user.id,
+            "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            None,
+            None,
+        )
+        .unwrap()
+        .save(conn, "someone@example.com")
+        .unwrap()
+        .id;
+
+        // the version has to be older than the history window
+        let created_at = Utc::now().naive_utc() - Days::new(60);
+        diesel::update(versions::table.find(version_id))
+            .set(versions::created_at.eq(created_at))
+            .execute(conn)
+            .unwrap();
+
+        let today = Utc::now().date_naive();
+        let rows = series(&today.to_string(), 28, 100, &[5000]);
+        for (date, downloads) in rows {
+            diesel::insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version_id),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(downloads as i32),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        let anomalies = assert_ok!(detect_anomalies(conn, today));
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].date, today);
+        assert_eq!(anomalies[0].kind, DownloadAnomalyKind::ZScore);
+        assert_eq!(anomalies[0].expected_downloads, 100);
+
+        let recent_downloads = |conn: &mut PgConnection| {
+            diesel::sql_query("REFRESH MATERIALIZED VIEW recent_crate_downloads")
+                .execute(conn)
+                .unwrap();
+
+            recent_crate_downloads::table
+                .find(krate.id)
+                .select(recent_crate_downloads::downloads)
+                .first::<i64>(conn)
+                .unwrap()
+        };
+
+        // anomalies are only excluded from the ranking after a review
+        assert_eq!(recent_downloads(conn), 28 * 100 + 5000);
+
+        assert!(assert_ok!(DownloadAnomaly::set_excluded(
+            conn, version_id, today, true
+        )));
+        assert_eq!(recent_downloads(conn), 29 * 100);
+
+        // detecting the anomaly again keeps the review decision
+        assert_ok!(detect_anomalies(conn, today));
+        let excluded = download_anomalies::table
+            .select(download_anomalies::excluded)
+            .load::<bool>(conn)
+            .unwrap();
+        assert_eq!(excluded, vec![true]);
+
+        assert!(assert_ok!(DownloadAnomaly::dismiss(
+            conn, version_id, today
+        )));
+        assert!(!assert_ok!(DownloadAnomaly::dismiss(
+            conn, version_id, today
+        )));
+        assert_eq!(recent_downloads(conn), 28 * 100 + 5000);
+
+        // dismissed anomalies are kept and not flagged again
+        assert_eq!(assert_ok!(detect_anomalies(conn, today)), vec![]);
+        let dismissed = download_anomalies::table
+            .select((
+                download_anomalies::excluded,
+                download_anomalies::dismissed_at.is_not_null(),
+            ))
+            .load::<(bool, bool)>(conn)
+            .unwrap();
+        assert_eq!(dismissed, vec![(false, true)]);
+        assert_eq!(recent_downloads(conn), 28 * 100 + 5000);
+    }
+}
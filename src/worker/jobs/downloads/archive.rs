@@ -0,0 +1,1187 @@
+use crate::models::{DownloadGranularity, NewVersionDownloadsArchive, VersionDownloadsArchive};
+use crate::schema::{
+    version_downloads, version_downloads_archives, version_downloads_by_client, versions,
+};
+use crate::storage::{version_downloads_archive_path, Storage};
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use anyhow::{anyhow, ensure, Context};
+use chrono::{Days, Months, NaiveDate, Utc};
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Integer, Text};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use tokio::runtime::Handle;
+
+/// The number of days of daily downloads that are kept in the database, which
+/// matches the range of the download endpoints.
+pub const RETENTION_DAYS: u64 = 90;
+
+/// The number of rows that are loaded from the database or the archive files
+/// at once. Postgres supports at most 65535 bind parameters per query.
+const BATCH_SIZE: usize = 10_000;
+
+/// A background job that moves all complete months of daily downloads that
+/// are older than [RETENTION_DAYS] from the `version_downloads` and
+/// `version_downloads_by_client` tables to gzipped CSV files in the
+/// [Storage].
+///
+/// Before the rows of a month are deleted, the job ensures that the weekly
+/// and monthly rollups of that month exist, so that the download history
+/// stays available through the rollups. The archived months are recorded in
+/// the `version_downloads_archives` table, which is part of the database
+/// dump, and can be restored with `crates-admin version-downloads-archive
+/// rehydrate`.
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveVersionDownloads;
+
+impl BackgroundJob for ArchiveVersionDownloads {
+    const JOB_NAME: &'static str = "archive_version_downloads";
+
+    type Context = Arc<Environment>;
+
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        spawn_blocking(move || {
+            let conn = &mut *env.connection_pool.get()?;
+
+            let cutoff = archive_cutoff(Utc::now().date_naive());
+            let oldest = version_downloads::table
+                .select(diesel::dsl::min(version_downloads::date))
+                .filter(version_downloads::date.lt(cutoff))
+                .first::<Option<NaiveDate>>(conn)?;
+
+            let Some(oldest) = oldest else {
+                info!("No daily downloads before {cutoff} to archive");
+                return Ok(());
+            };
+
+            let mut month = DownloadGranularity::Month.period_start(oldest);
+            while month < cutoff {
+                archive_month(conn, &env.storage, &Handle::current(), month)?;
+                month = month + Months::new(1);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Returns the first day of the oldest month that is kept in the database.
+fn archive_cutoff(today: NaiveDate) -> NaiveDate {
+    DownloadGranularity::Month.period_start(today - Days::new(RETENTION_DAYS))
+}
+
+/// Moves the frozen daily downloads of the month starting at `month` to
+/// archive files and returns the number of moved rows of `version_downloads`.
+///
+/// If the month has been archived before, the rows that have been added to
+/// the database since then (e.g. by reprocessing old CDN logs) are merged
+/// with the previous archive files into new ones.
+pub fn archive_month(
+    conn: &mut PgConnection,
+    storage: &Storage,
+    rt: &Handle,
+    month: NaiveDate,
+) -> anyhow::Result<usize> {
+    match export_month(conn, storage, rt, month)? {
+        Some(export) => commit_month(conn, storage, rt, export),
+        None => Ok(0),
+    }
+}
+
+/// The archive files of a month, which have been written to temporary files
+/// but not uploaded yet.
+struct MonthExport {
+    month: NaiveDate,
+    previous: Option<VersionDownloadsArchive>,
+    daily: TableExport,
+    by_client: TableExport,
+}
+
+/// Exports the rows of the month starting at `month` that are ready to be
+/// archived, merged with the previous archive files of the month, if any.
+///
+/// Returns `None` if there is nothing to archive.
+fn export_month(
+    conn: &mut PgConnection,
+    storage: &Storage,
+    rt: &Handle,
+    month: NaiveDate,
+) -> anyhow::Result<Option<MonthExport>> {
+    let name = month.format("%Y-%m");
+    let end = month + Months::new(1);
+
+    // The previous archive files are downloaded before the export, since no
+    // transaction should be kept open while the storage is accessed.
+    let previous = VersionDownloadsArchive::find(conn, month)?;
+    let previous_daily = match &previous {
+        Some(archive) => Some(download_archive(storage, rt, &archive.path)?),
+        None => None,
+    };
+    let previous_by_client = match previous.as_ref().and_then(|a| a.client_path.as_ref()) {
+        Some(path) => Some(download_archive(storage, rt, path)?),
+        None => None,
+    };
+
+    // The exports of both tables have to see the same snapshot, since they
+    // are compared with the deleted rows in `commit_month()`.
+    let (daily, by_client) =
+        conn.build_transaction()
+            .repeatable_read()
+            .run(|conn| -> anyhow::Result<_> {
+                ensure_rollups(conn, month, end)?;
+
+                let previous_daily = previous_daily.as_ref().map(|file| file.path());
+                let daily = export_table(conn, ArchivedTable::Daily, month, previous_daily)?;
+
+                let previous_by_client = previous_by_client.as_ref().map(|file| file.path());
+                let by_client =
+                    export_table(conn, ArchivedTable::ByClient, month, previous_by_client)?;
+
+                Ok((daily, by_client))
+            })?;
+
+    // The rows of `version_downloads_by_client` are only archived together
+    // with the daily downloads, which are frozen a bit later.
+    if daily.num_rows == 0 || (daily.num_archived == 0 && by_client.num_archived == 0) {
+        info!("No daily downloads of {name} to archive");
+        return Ok(None);
+    }
+
+    Ok(Some(MonthExport {
+        month,
+        previous,
+        daily,
+        by_client,
+    }))
+}
+
+/// Uploads the archive files of `export` and deletes the archived rows from
+/// the database.
+///
+/// Every run uploads new files, and `version_downloads_archives` only
+/// refers to them once the archived rows have been deleted. If anything
+/// fails, the previous files stay in place, so that the archived rows are
+/// not counted twice by the next run.
+fn commit_month(
+    conn: &mut PgConnection,
+    storage: &Storage,
+    rt: &Handle,
+    export: MonthExport,
+) -> anyhow::Result<usize> {
+    let MonthExport {
+        month,
+        previous,
+        daily,
+        by_client,
+    } = export;
+
+    let name = month.format("%Y-%m").to_string();
+    let end = month + Months::new(1);
+    let run = format!(
+        "{}-{:08x}",
+        Utc::now().format("%Y%m%dT%H%M%S"),
+        rand::random::<u32>()
+    );
+
+    let mut uploaded = Vec::new();
+    let result = (|| -> anyhow::Result<()> {
+        let daily_path = daily.upload(storage, rt, &name, &run)?;
+        uploaded.extend(daily_path.clone());
+        let client_path = by_client.upload(storage, rt, &name, &run)?;
+        uploaded.extend(client_path.clone());
+
+        let daily_path = daily_path.ok_or_else(|| anyhow!("{name} has no daily downloads"))?;
+
+        conn.transaction(|conn| {
+            // The rollups of a month that has been archived before do not
+            // contain the new rows yet, see `update_rollups.sql`.
+            if previous.is_some() {
+                add_to_monthly_rollups(conn, month, end)?;
+            }
+
+            daily.delete_archived_rows(conn, month)?;
+            by_client.delete_archived_rows(conn, month)?;
+
+            NewVersionDownloadsArchive {
+                month,
+                path: &daily_path,
+                num_rows: daily.num_rows,
+                client_path: client_path.as_deref(),
+                num_client_rows: by_client.num_rows,
+            }
+            .record(conn)?;
+
+            Ok(())
+        })
+    })();
+
+    if let Err(error) = result {
+        delete_archives(storage, rt, &uploaded);
+        return Err(error);
+    }
+
+    // The previous files have been replaced by the new ones.
+    if let Some(previous) = previous {
+        let paths = [Some(previous.path), previous.client_path];
+        delete_archives(
+            storage,
+            rt,
+            &paths.into_iter().flatten().collect::<Vec<_>>(),
+        );
+    }
+
+    let num_rows = daily.num_archived;
+    let num_client_rows = by_client.num_archived;
+    info!(
+        num_rows,
+        num_client_rows, "Archived daily downloads of {name}"
+    );
+    Ok(num_rows as usize)
+}
+
+/// Restores the daily downloads of the month starting at `month` from its
+/// archive files and returns the number of restored rows of
+/// `version_downloads`.
+///
+/// The month is removed from `version_downloads_archives` and its archive
+/// files are deleted, so that the month is archived into new files again.
+/// Rows of versions that have been deleted in the meantime are skipped.
+pub fn rehydrate_month(
+    conn: &mut PgConnection,
+    storage: &Storage,
+    rt: &Handle,
+    month: NaiveDate,
+) -> anyhow::Result<usize> {
+    let name = month.format("%Y-%m");
+
+    let archive = VersionDownloadsArchive::find(conn, month)?
+        .ok_or_else(|| anyhow!("{name} has not been archived"))?;
+
+    let daily = download_archive(storage, rt, &archive.path)?;
+    let by_client = match &archive.client_path {
+        Some(path) => Some(download_archive(storage, rt, path)?),
+        None => None,
+    };
+
+    let (num_rows, num_client_rows) = conn.transaction(|conn| -> anyhow::Result<_> {
+        let deleted =
+            diesel::delete(version_downloads_archives::table.find(month)).execute(conn)?;
+        ensure!(deleted == 1, "{name} has not been archived");
+
+        let num_rows = restore_table(conn, ArchivedTable::Daily, daily.path())?;
+        let num_client_rows = match &by_client {
+            Some(file) => restore_table(conn, ArchivedTable::ByClient, file.path())?,
+            None => 0,
+        };
+
+        Ok((num_rows, num_client_rows))
+    })?;
+
+    let paths = [Some(archive.path), archive.client_path];
+    delete_archives(
+        storage,
+        rt,
+        &paths.into_iter().flatten().collect::<Vec<_>>(),
+    );
+
+    info!(
+        num_rows,
+        num_client_rows, "Rehydrated daily downloads of {name}"
+    );
+    Ok(num_rows)
+}
+
+/// A table whose rows are moved to archive files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchivedTable {
+    /// `version_downloads`, of which only the frozen rows are archived.
+    Daily,
+    /// `version_downloads_by_client`
+    ByClient,
+}
+
+impl ArchivedTable {
+    fn table_name(self) -> &'static str {
+        match self {
+            Self::Daily => "version_downloads",
+            Self::ByClient => "version_downloads_by_client",
+        }
+    }
+
+    /// The directory of the archive files in the public storage bucket.
+    ///
+    /// Both tables are public in the database dump as well, see
+    /// `dump-db.toml`.
+    fn directory(self) -> &'static str {
+        match self {
+            Self::Daily => "version-downloads",
+            Self::ByClient => "version-downloads-by-client",
+        }
+    }
+
+    /// The header line of the archive files.
+    fn csv_header(self) -> &'static str {
+        match self {
+            Self::Daily => "version_id,date,downloads",
+            Self::ByClient => "version_id,date,client,downloads",
+        }
+    }
+
+    /// The SQL expression of the `client` of a row, which is empty for the
+    /// daily downloads.
+    fn client_column(self) -> &'static str {
+        match self {
+            Self::Daily => "''",
+            Self::ByClient => "client",
+        }
+    }
+
+    /// The SQL condition that selects the archived rows of the month
+    /// starting at `month`.
+    fn condition(self, month: NaiveDate) -> String {
+        let end = month + Months::new(1);
+        let condition = format!("date >= '{month}' AND date < '{end}'");
+        match self {
+            Self::Daily => format!("{condition} AND processed"),
+            Self::ByClient => condition,
+        }
+    }
+}
+
+/// A row of an archive file.
+///
+/// The archive files are ordered by [ArchiveRow::key], which allows merging
+/// them with the rows from the database without loading them into memory.
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+struct ArchiveRow {
+    #[diesel(sql_type = Date)]
+    date: NaiveDate,
+    #[diesel(sql_type = Integer)]
+    version_id: i32,
+    /// Empty for the rows of `version_downloads`.
+    #[diesel(sql_type = Text)]
+    client: String,
+    #[diesel(sql_type = Integer)]
+    downloads: i32,
+}
+
+impl ArchiveRow {
+    fn key(&self) -> (NaiveDate, i32, &str) {
+        (self.date, self.version_id, &self.client)
+    }
+}
+
+/// The archive file of a table, which has been written to a temporary file.
+struct TableExport {
+    table: ArchivedTable,
+    file: NamedTempFile,
+    /// The number of rows in the archive file.
+    num_rows: i64,
+    /// The number of rows that are moved from the database to the archive
+    /// file.
+    num_archived: i64,
+    /// The sum of the downloads of the rows that are moved from the database.
+    archived_downloads: i64,
+}
+
+impl TableExport {
+    /// Uploads the archive file to a new path and returns the path, unless
+    /// the archive file is empty.
+    fn upload(
+        &self,
+        storage: &Storage,
+        rt: &Handle,
+        name: &str,
+        run: &str,
+    ) -> anyhow::Result<Option<String>> {
+        if self.num_rows == 0 {
+            return Ok(None);
+        }
+
+        let path = version_downloads_archive_path(self.table.directory(), name, run);
+        rt.block_on(storage.upload_version_downloads_archive(&path, self.file.path()))?;
+        Ok(Some(path))
+    }
+
+    /// Deletes the rows that have been exported to the archive file.
+    ///
+    /// Fails if the rows have changed since the export, e.g. because more
+    /// rows have been frozen in the meantime, since those would be lost.
+    fn delete_archived_rows(
+        &self,
+        conn: &mut PgConnection,
+        month: NaiveDate,
+    ) -> anyhow::Result<()> {
+        #[derive(QueryableByName)]
+        struct Deleted {
+            #[diesel(sql_type = BigInt)]
+            num_rows: i64,
+            #[diesel(sql_type = BigInt)]
+            downloads: i64,
+        }
+
+        let deleted: Deleted = diesel::sql_query(format!(
+            "WITH deleted AS (DELETE FROM {table} WHERE {condition} RETURNING downloads) \
+             SELECT COUNT(*) AS num_rows, COALESCE(SUM(downloads), 0)::BIGINT AS downloads \
+             FROM deleted",
+            table = self.table.table_name(),
+            condition = self.table.condition(month),
+        ))
+        .get_result(conn)?;
+
+        ensure!(
+            deleted.num_rows == self.num_archived && deleted.downloads == self.archived_downloads,
+            "The rows of {} have changed since they were exported",
+            self.table.table_name()
+        );
+
+        Ok(())
+    }
+}
+
+/// Writes the archived rows of `table` of the month starting at `month`,
+/// merged with the `previous` archive file, to a temporary file.
+fn export_table(
+    conn: &mut PgConnection,
+    table: ArchivedTable,
+    month: NaiveDate,
+    previous: Option<&Path>,
+) -> anyhow::Result<TableExport> {
+    let file = NamedTempFile::new()?;
+    let mut writer = ArchiveWriter::new(table, file.reopen()?)?;
+
+    let mut previous = match previous {
+        Some(path) => Some(ArchiveReader::open(table, path)?),
+        None => None,
+    };
+    let mut next_previous = || -> anyhow::Result<Option<ArchiveRow>> {
+        previous
+            .as_mut()
+            .and_then(|reader| reader.next())
+            .transpose()
+    };
+
+    let mut rows = Cursor::declare(conn, table, month)?;
+
+    let mut export = TableExport {
+        table,
+        file,
+        num_rows: 0,
+        num_archived: 0,
+        archived_downloads: 0,
+    };
+
+    let mut previous_row = next_previous()?;
+    let mut new_row = rows.next()?;
+    loop {
+        let (old, new) = match (previous_row.take(), new_row.take()) {
+            (None, None) => break,
+            (Some(old), Some(new)) => match old.key().cmp(&new.key()) {
+                Ordering::Less => {
+                    new_row = Some(new);
+                    (Some(old), None)
+                }
+                Ordering::Greater => {
+                    previous_row = Some(old);
+                    (None, Some(new))
+                }
+                Ordering::Equal => (Some(old), Some(new)),
+            },
+            (old, new) => (old, new),
+        };
+
+        if old.is_some() {
+            previous_row = next_previous()?;
+        }
+
+        if let Some(new) = &new {
+            export.num_archived += 1;
+            export.archived_downloads += i64::from(new.downloads);
+            new_row = rows.next()?;
+        }
+
+        let row = match (old, new) {
+            (Some(old), Some(new)) => ArchiveRow {
+                downloads: old.downloads + new.downloads,
+                ..new
+            },
+            (Some(row), None) | (None, Some(row)) => row,
+            (None, None) => unreachable!(),
+        };
+
+        writer.write(&row)?;
+        export.num_rows += 1;
+    }
+
+    rows.close()?;
+    writer.finish().context("Failed to write archive file")?;
+
+    Ok(export)
+}
+
+/// Inserts the rows of the archive file at `path` into `table` and returns
+/// the number of inserted rows.
+fn restore_table(
+    conn: &mut PgConnection,
+    table: ArchivedTable,
+    path: &Path,
+) -> anyhow::Result<usize> {
+    use diesel::upsert::excluded;
+
+    let mut reader = ArchiveReader::open(table, path)?;
+    let mut num_rows = 0;
+
+    loop {
+        let chunk = reader
+            .by_ref()
+            .take(BATCH_SIZE)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let version_ids = chunk.iter().map(|row| row.version_id).collect::<Vec<_>>();
+        let existing: HashSet<i32> = versions::table
+            .select(versions::id)
+            .filter(versions::id.eq_any(&version_ids))
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+
+        let rows = chunk
+            .into_iter()
+            .filter(|row| existing.contains(&row.version_id));
+
+        num_rows += match table {
+            ArchivedTable::Daily => {
+                let rows = rows
+                    .map(|row| {
+                        (
+                            version_downloads::version_id.eq(row.version_id),
+                            version_downloads::date.eq(row.date),
+                            version_downloads::downloads.eq(row.downloads),
+                            version_downloads::counted.eq(row.downloads),
+                            version_downloads::processed.eq(true),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(version_downloads::table)
+                    .values(&rows)
+                    .on_conflict((version_downloads::version_id, version_downloads::date))
+                    .do_update()
+                    .set((
+                        version_downloads::downloads
+                            .eq(version_downloads::downloads
+                                + excluded(version_downloads::downloads)),
+                        version_downloads::counted
+                            .eq(version_downloads::counted + excluded(version_downloads::counted)),
+                    ))
+                    .execute(conn)?
+            }
+            ArchivedTable::ByClient => {
+                let rows = rows
+                    .map(|row| {
+                        (
+                            version_downloads_by_client::version_id.eq(row.version_id),
+                            version_downloads_by_client::date.eq(row.date),
+                            version_downloads_by_client::client.eq(row.client),
+                            version_downloads_by_client::downloads.eq(row.downloads),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                diesel::insert_into(version_downloads_by_client::table)
+                    .values(&rows)
+                    .on_conflict((
+                        version_downloads_by_client::version_id,
+                        version_downloads_by_client::date,
+                        version_downloads_by_client::client,
+                    ))
+                    .do_update()
+                    .set(
+                        version_downloads_by_client::downloads
+                            .eq(version_downloads_by_client::downloads
+                                + excluded(version_downloads_by_client::downloads)),
+                    )
+                    .execute(conn)?
+            }
+        };
+    }
+
+    Ok(num_rows)
+}
+
+/// A server-side cursor over the archived rows of a table, which are loaded
+/// in batches of [BATCH_SIZE] rows, ordered by [ArchiveRow::key].
+///
+/// Cursors only exist within a transaction.
+struct Cursor<'a> {
+    conn: &'a mut PgConnection,
+    batch: VecDeque<ArchiveRow>,
+    done: bool,
+}
+
+impl<'a> Cursor<'a> {
+    const NAME: &'static str = "archived_rows";
+
+    fn declare(
+        conn: &'a mut PgConnection,
+        table: ArchivedTable,
+        month: NaiveDate,
+    ) -> QueryResult<Self> {
+        // Cursors do not support bind parameters, but the condition only
+        // contains formatted dates.
+        diesel::sql_query(format!(
+            "DECLARE {name} NO SCROLL CURSOR FOR \
+             SELECT date, version_id, {client} AS client, downloads FROM {table} \
+             WHERE {condition} \
+             ORDER BY date, version_id, {client} COLLATE \"C\"",
+            name = Self::NAME,
+            client = table.client_column(),
+            table = table.table_name(),
+            condition = table.condition(month),
+        ))
+        .execute(conn)?;
+
+        Ok(Self {
+            conn,
+            batch: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn next(&mut self) -> QueryResult<Option<ArchiveRow>> {
+        if self.batch.is_empty() && !self.done {
+            let query = format!("FETCH {BATCH_SIZE} FROM {}", Self::NAME);
+            let rows = diesel::sql_query(query).load::<ArchiveRow>(self.conn)?;
+            self.done = rows.len() < BATCH_SIZE;
+            self.batch = rows.into();
+        }
+
+        Ok(self.batch.pop_front())
+    }
+
+    fn close(self) -> QueryResult<()> {
+        diesel::sql_query(format!("CLOSE {}", Self::NAME)).execute(self.conn)?;
+        Ok(())
+    }
+}
+
+/// Writes the rows of an archive file, which have to be ordered by
+/// [ArchiveRow::key].
+struct ArchiveWriter {
+    table: ArchivedTable,
+    encoder: GzEncoder<BufWriter<File>>,
+}
+
+impl ArchiveWriter {
+    fn new(table: ArchivedTable, file: File) -> std::io::Result<Self> {
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        writeln!(encoder, "{}", table.csv_header())?;
+        Ok(Self { table, encoder })
+    }
+
+    fn write(&mut self, row: &ArchiveRow) -> std::io::Result<()> {
+        let ArchiveRow {
+            date,
+            version_id,
+            client,
+            downloads,
+        } = row;
+
+        match self.table {
+            ArchivedTable::Daily => writeln!(self.encoder, "{version_id},{date},{downloads}"),
+            ArchivedTable::ByClient => {
+                writeln!(self.encoder, "{version_id},{date},{client},{downloads}")
+            }
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        self.encoder.finish()?.flush()
+    }
+}
+
+/// Reads the rows of an archive file.
+struct ArchiveReader {
+    table: ArchivedTable,
+    lines: Lines<BufReader<GzDecoder<File>>>,
+}
+
+impl ArchiveReader {
+    fn open(table: ArchivedTable, path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        let mut lines = BufReader::new(GzDecoder::new(file)).lines();
+
+        let header = lines.next().transpose();
+        let header = header.context("Failed to decompress archive file")?;
+        ensure!(
+            header.as_deref() == Some(table.csv_header()),
+            "Archive file has an unexpected header"
+        );
+
+        Ok(Self { table, lines })
+    }
+
+    fn parse(&self, line: &str) -> Option<ArchiveRow> {
+        let mut fields = line.split(',');
+        let version_id = fields.next()?.parse().ok()?;
+        let date = fields.next()?.parse().ok()?;
+        let client = match self.table {
+            ArchivedTable::Daily => String::new(),
+            ArchivedTable::ByClient => fields.next()?.to_string(),
+        };
+        let downloads = fields.next()?.parse().ok()?;
+
+        fields.next().is_none().then_some(ArchiveRow {
+            date,
+            version_id,
+            client,
+            downloads,
+        })
+    }
+}
+
+impl Iterator for ArchiveReader {
+    type Item = anyhow::Result<ArchiveRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(error) => return Some(Err(error).context("Failed to decompress archive file")),
+        };
+
+        let row = self.parse(&line);
+        Some(row.ok_or_else(|| anyhow!("Invalid archive line: {line}")))
+    }
+}
+
+/// Downloads the archive file at `path` to a temporary file.
+fn download_archive(storage: &Storage, rt: &Handle, path: &str) -> anyhow::Result<NamedTempFile> {
+    let file = NamedTempFile::new()?;
+    let found = rt.block_on(storage.download_version_downloads_archive(path, file.path()))?;
+    ensure!(found, "Archive file {path} is missing");
+    Ok(file)
+}
+
+/// Deletes the archive files at `paths`, which are no longer referenced.
+///
+/// Failures are only logged, since the files are not needed anymore.
+fn delete_archives(storage: &Storage, rt: &Handle, paths: &[String]) {
+    for path in paths {
+        if let Err(error) = rt.block_on(storage.delete_version_downloads_archive(path)) {
+            warn!("Failed to delete archive file {path}: {error}");
+        }
+    }
+}
+
+/// Inserts the missing weekly and monthly rollups of all periods that
+/// overlap with the month starting at `month` from the daily downloads.
+///
+/// This is used by `crates-admin backfill-download-rollups` to create the
+/// rollups of the daily downloads that predate the rollup tables, one month
+/// at a time.
+pub fn backfill_rollups(conn: &mut PgConnection, month: NaiveDate) -> QueryResult<()> {
+    ensure_rollups(conn, month, month + Months::new(1))
+}
+
+/// Inserts the weekly and monthly rollups of all periods that overlap with
+/// the dates between `start` and `end` (exclusive), unless they exist already.
+///
+/// Existing rollups are left untouched, since the daily downloads of weeks
+/// that overlap with an archived month are incomplete.
+fn ensure_rollups(conn: &mut PgConnection, start: NaiveDate, end: NaiveDate) -> QueryResult<()> {
+    for granularity in DownloadGranularity::ALL {
+        let last_period = granularity.period_start(end - Days::new(1));
+
+        diesel::sql_query(format!(
+            include_str!("ensure_rollups.sql"),
+            table = granularity.table_name(),
+            unit = granularity,
+        ))
+        .bind::<Date, _>(granularity.period_start(start))
+        .bind::<Date, _>(granularity.next_period_start(last_period))
+        .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Adds the totals of the archived rows of `version_downloads` to the
+/// monthly rollups of a month that has been archived before.
+fn add_to_monthly_rollups(
+    conn: &mut PgConnection,
+    month: NaiveDate,
+    end: NaiveDate,
+) -> QueryResult<()> {
+    diesel::sql_query(
+        "INSERT INTO version_downloads_monthly (version_id, date, downloads) \
+         SELECT version_id, $1, SUM(downloads) FROM version_downloads \
+         WHERE date >= $1 AND date < $2 AND processed \
+         GROUP BY version_id \
+         ON CONFLICT (version_id, date) DO UPDATE \
+         SET downloads = version_downloads_monthly.downloads + EXCLUDED.downloads",
+    )
+    .bind::<Date, _>(month)
+    .bind::<Date, _>(end)
+    .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email::Emails;
+    use crate::models::{NewUser, NewVersion};
+    use crate::schema::{version_downloads_monthly, version_downloads_weekly};
+    use crate::storage::StorageConfig;
+    use crate::test_util::test_db_connection;
+    use claims::{assert_err, assert_ok, assert_some};
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    fn version(conn: &mut PgConnection) -> i32 {
+        use crate::models::NewCrate;
+
+        let user = NewUser::new(2, "login", None, None, "access_token")
+            .create_or_update(None, &Emails::new_in_memory(), conn)
+            .unwrap();
+        let krate = NewCrate {
+            name: "foo",
+            ..Default::default()
+        }
+        .create(conn, user.id)
+        .unwrap();
+
+        NewVersion::new(
+            krate.id,
+            &semver::Version::parse("1.0.0").unwrap(),
+            &BTreeMap::new(),
+            None,
+            0,
+            user.id,
+            "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            None,
+            None,
+        )
+        .unwrap()
+        .save(conn, "someone@example.com")
+        .unwrap()
+        .id
+    }
+
+    fn insert(conn: &mut PgConnection, version_id: i32, rows: &[(&str, i32)]) {
+        for &(day, downloads) in rows {
+            diesel::insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version_id),
+                    version_downloads::date.eq(date(day)),
+                    version_downloads::downloads.eq(downloads),
+                    version_downloads::counted.eq(downloads),
+                    version_downloads::processed.eq(true),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    }
+
+    fn insert_by_client(conn: &mut PgConnection, version_id: i32, rows: &[(&str, &str, i32)]) {
+        for &(day, client, downloads) in rows {
+            diesel::insert_into(version_downloads_by_client::table)
+                .values((
+                    version_downloads_by_client::version_id.eq(version_id),
+                    version_downloads_by_client::date.eq(date(day)),
+                    version_downloads_by_client::client.eq(client),
+                    version_downloads_by_client::downloads.eq(downloads),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+    }
+
+    fn row(day: &str, version_id: i32, client: &str, downloads: i32) -> ArchiveRow {
+        ArchiveRow {
+            date: date(day),
+            version_id,
+            client: client.to_string(),
+            downloads,
+        }
+    }
+
+    fn read_archive(
+        storage: &Storage,
+        rt: &Handle,
+        table: ArchivedTable,
+        path: &str,
+    ) -> Vec<ArchiveRow> {
+        let file = assert_ok!(download_archive(storage, rt, path));
+        let reader = assert_ok!(ArchiveReader::open(table, file.path()));
+        assert_ok!(reader.collect())
+    }
+
+    fn archive_exists(storage: &Storage, rt: &Handle, path: &str) -> bool {
+        let file = NamedTempFile::new().unwrap();
+        let found = rt.block_on(storage.download_version_downloads_archive(path, file.path()));
+        assert_ok!(found)
+    }
+
+    fn daily_downloads(conn: &mut PgConnection) -> Vec<(NaiveDate, i32)> {
+        version_downloads::table
+            .select((version_downloads::date, version_downloads::downloads))
+            .order(version_downloads::date)
+            .load(conn)
+            .unwrap()
+    }
+
+    fn client_downloads(conn: &mut PgConnection) -> Vec<(NaiveDate, i32)> {
+        version_downloads_by_client::table
+            .select((
+                version_downloads_by_client::date,
+                version_downloads_by_client::downloads,
+            ))
+            .order(version_downloads_by_client::date)
+            .load(conn)
+            .unwrap()
+    }
+
+    fn monthly_downloads(conn: &mut PgConnection) -> Vec<(NaiveDate, i64)> {
+        version_downloads_monthly::table
+            .select((
+                version_downloads_monthly::date,
+                version_downloads_monthly::downloads,
+            ))
+            .order(version_downloads_monthly::date)
+            .load(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_archive_cutoff() {
+        assert_eq!(archive_cutoff(date("2024-05-15")), date("2024-02-01"));
+        assert_eq!(archive_cutoff(date("2024-04-30")), date("2024-01-01"));
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let rows = [
+            row("2024-01-01", 1, "cargo/1.74", 5),
+            row("2024-01-01", 1, "cargo/nightly", 10),
+            row("2024-01-02", 2, "other", 7),
+        ];
+
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = assert_ok!(ArchiveWriter::new(
+            ArchivedTable::ByClient,
+            file.reopen().unwrap()
+        ));
+        for row in &rows {
+            assert_ok!(writer.write(row));
+        }
+        assert_ok!(writer.finish());
+
+        let mut content = String::new();
+        GzDecoder::new(file.reopen().unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        insta::assert_snapshot!(content, @r###"
+        version_id,date,client,downloads
+        1,2024-01-01,cargo/1.74,5
+        1,2024-01-01,cargo/nightly,10
+        2,2024-01-02,other,7
+        "###);
+
+        let reader = assert_ok!(ArchiveReader::open(ArchivedTable::ByClient, file.path()));
+        assert_eq!(assert_ok!(reader.collect::<anyhow::Result<Vec<_>>>()), rows);
+
+        assert!(ArchiveReader::open(ArchivedTable::Daily, file.path()).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_archive_and_rehydrate() {
+        let (_test_db, mut conn) = test_db_connection();
+        let storage = Storage::from_config(&StorageConfig::in_memory());
+        let rt = Handle::current();
+
+        tokio::task::block_in_place(|| {
+            let conn = &mut conn;
+            let version_id = version(conn);
+            insert(
+                conn,
+                version_id,
+                &[("2023-12-31", 1), ("2024-01-01", 2), ("2024-01-31", 3)],
+            );
+            insert(conn, version_id, &[("2024-02-01", 4)]);
+            insert_by_client(
+                conn,
+                version_id,
+                &[("2024-01-01", "cargo/1.74", 2), ("2024-02-01", "other", 4)],
+            );
+
+            assert_eq!(
+                assert_ok!(archive_month(conn, &storage, &rt, date("2024-01-01"))),
+                2
+            );
+            assert_eq!(
+                daily_downloads(conn),
+                vec![(date("2023-12-31"), 1), (date("2024-02-01"), 4)]
+            );
+            assert_eq!(client_downloads(conn), vec![(date("2024-02-01"), 4)]);
+
+            let archives = assert_ok!(VersionDownloadsArchive::all(conn));
+            assert_eq!(archives.len(), 1);
+            let first = archives[0].clone();
+            assert!(first.path.starts_with("archive/version-downloads/2024-01/"));
+            assert_eq!(first.num_rows, 2);
+            let client_path = assert_some!(first.client_path.as_deref());
+            assert!(client_path.starts_with("archive/version-downloads-by-client/2024-01/"));
+            assert_eq!(first.num_client_rows, 1);
+
+            assert_eq!(
+                read_archive(&storage, &rt, ArchivedTable::ByClient, client_path),
+                vec![row("2024-01-01", version_id, "cargo/1.74", 2)]
+            );
+
+            // the rollups have been created before the rows were deleted
+            assert_eq!(monthly_downloads(conn), vec![(date("2024-01-01"), 5)]);
+
+            // including the weeks that overlap with the neighboring months
+            let weekly: Vec<(NaiveDate, i64)> = version_downloads_weekly::table
+                .select((
+                    version_downloads_weekly::date,
+                    version_downloads_weekly::downloads,
+                ))
+                .order(version_downloads_weekly::date)
+                .load(conn)
+                .unwrap();
+            assert_eq!(
+                weekly,
+                vec![(date("2024-01-01"), 2), (date("2024-01-29"), 7)]
+            );
+
+            // rows that are added to an archived month are merged with the
+            // previous archive files into new ones
+            insert(conn, version_id, &[("2024-01-01", 10), ("2024-01-15", 6)]);
+            insert_by_client(conn, version_id, &[("2024-01-01", "cargo/1.74", 10)]);
+            assert_eq!(
+                assert_ok!(archive_month(conn, &storage, &rt, date("2024-01-01"))),
+                2
+            );
+
+            let archive = assert_some!(assert_ok!(VersionDownloadsArchive::find(
+                conn,
+                date("2024-01-01")
+            )));
+            assert_ne!(archive.path, first.path);
+            assert_eq!(
+                read_archive(&storage, &rt, ArchivedTable::Daily, &archive.path),
+                vec![
+                    row("2024-01-01", version_id, "", 12),
+                    row("2024-01-15", version_id, "", 6),
+                    row("2024-01-31", version_id, "", 3),
+                ]
+            );
+            let client_path = assert_some!(archive.client_path.as_deref());
+            assert_eq!(
+                read_archive(&storage, &rt, ArchivedTable::ByClient, client_path),
+                vec![row("2024-01-01", version_id, "cargo/1.74", 12)]
+            );
+
+            // the previous files have been deleted
+            assert!(!archive_exists(&storage, &rt, &first.path));
+
+            assert_eq!(monthly_downloads(conn), vec![(date("2024-01-01"), 21)]);
+
+            // archiving a month without rows does nothing
+            assert_eq!(
+                assert_ok!(archive_month(conn, &storage, &rt, date("2024-03-01"))),
+                0
+            );
+
+            assert_eq!(
+                assert_ok!(rehydrate_month(conn, &storage, &rt, date("2024-01-01"))),
+                3
+            );
+            assert_eq!(
+                daily_downloads(conn),
+                vec![
+                    (date("2023-12-31"), 1),
+                    (date("2024-01-01"), 12),
+                    (date("2024-01-15"), 6),
+                    (date("2024-01-31"), 3),
+                    (date("2024-02-01"), 4),
+                ]
+            );
+            assert_eq!(
+                client_downloads(conn),
+                vec![(date("2024-01-01"), 12), (date("2024-02-01"), 4)]
+            );
+            assert_eq!(assert_ok!(VersionDownloadsArchive::all(conn)).len(), 0);
+            assert!(!archive_exists(&storage, &rt, &archive.path));
+
+            assert_err!(rehydrate_month(conn, &storage, &rt, date("2024-01-01")));
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_failed_archival_keeps_previous_archive() {
+        let (_test_db, mut conn) = test_db_connection();
+        let storage = Storage::from_config(&StorageConfig::in_memory());
+        let rt = Handle::current();
+
+        tokio::task::block_in_place(|| {
+            let conn = &mut conn;
+            let version_id = version(conn);
+            let month = date("2024-01-01");
+
+            insert(conn, version_id, &[("2024-01-01", 2)]);
+            assert_eq!(assert_ok!(archive_month(conn, &storage, &rt, month)), 1);
+            let first = assert_some!(assert_ok!(VersionDownloadsArchive::find(conn, month)));
+
+            // rows that are frozen after the export would be lost, so the
+            // rows are not deleted and the new files are discarded
+            insert(conn, version_id, &[("2024-01-02", 3)]);
+            let export = assert_some!(assert_ok!(export_month(conn, &storage, &rt, month)));
+            insert(conn, version_id, &[("2024-01-03", 4)]);
+            assert_err!(commit_month(conn, &storage, &rt, export));
+
+            let archive = assert_some!(assert_ok!(VersionDownloadsArchive::find(conn, month)));
+            assert_eq!(archive.path, first.path);
+            assert_eq!(
+                daily_downloads(conn),
+                vec![(date("2024-01-02"), 3), (date("2024-01-03"), 4)]
+            );
+            assert_eq!(monthly_downloads(conn), vec![(month, 2)]);
+
+            // the next run archives all rows exactly once
+            assert_eq!(assert_ok!(archive_month(conn, &storage, &rt, month)), 2);
+            let archive = assert_some!(assert_ok!(VersionDownloadsArchive::find(conn, month)));
+            assert_eq!(
+                read_archive(&storage, &rt, ArchivedTable::Daily, &archive.path),
+                vec![
+                    row("2024-01-01", version_id, "", 2),
+                    row("2024-01-02", version_id, "", 3),
+                    row("2024-01-03", version_id, "", 4),
+                ]
+            );
+            assert_eq!(monthly_downloads(conn), vec![(month, 9)]);
+            assert!(!archive_exists(&storage, &rt, &first.path));
+        });
+    }
+}
@@ -0,0 +1,8 @@
+-- Insert the missing rollups of all periods between $1 and $2 from the daily
+-- downloads, without touching the rollups that already exist.
+INSERT INTO {table} (version_id, date, downloads)
+SELECT version_id, date_trunc('{unit}', date)::date AS period, SUM(downloads)
+FROM version_downloads
+WHERE date >= $1 AND date < $2
+GROUP BY version_id, period
+ON CONFLICT (version_id, date) DO NOTHING
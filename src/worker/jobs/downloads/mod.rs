@@ -1,7 +1,11 @@
+mod anomalies;
+mod archive;
 mod process_log;
 mod queue;
 mod update_metadata;
 
+pub use anomalies::{detect_anomalies, DetectDownloadAnomalies};
+pub use archive::{archive_month, backfill_rollups, rehydrate_month, ArchiveVersionDownloads};
 pub use process_log::ProcessCdnLog;
 pub use queue::ProcessCdnLogQueue;
 pub use update_metadata::UpdateDownloads;
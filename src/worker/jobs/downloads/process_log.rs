@@ -1,10 +1,11 @@
 use crate::config::CdnLogStorageConfig;
 use crate::db::DieselPool;
+use crate::models::{NewProcessedCdnLog, ProcessedCdnLog, ProcessedCdnLogStatus};
 use crate::tasks::spawn_blocking;
 use crate::worker::Environment;
 use anyhow::Context;
 use chrono::NaiveDate;
-use crates_io_cdn_logs::{count_downloads, Decompressor, DownloadsMap};
+use crates_io_cdn_logs::{count_downloads, ClientClass, Decompressor, DownloadsMap};
 use crates_io_worker::BackgroundJob;
 use diesel::prelude::*;
 use diesel::{PgConnection, QueryResult};
@@ -12,7 +13,7 @@ use object_store::aws::AmazonS3Builder;
 use object_store::local::LocalFileSystem;
 use object_store::memory::InMemory;
 use object_store::path::Path;
-use object_store::ObjectStore;
+use object_store::{ObjectMeta, ObjectStore};
 use semver::Version;
 use std::cmp::Reverse;
 use std::fmt::Debug;
@@ -22,11 +23,18 @@ use tokio::io::BufReader;
 /// A background job that loads a CDN log file from an object store (aka. S3),
 /// counts the number of downloads for each crate and version, and then inserts
 /// the results into the database.
+///
+/// Every processed log file is recorded in the `processed_cdn_logs` ledger,
+/// and log files that are already in the ledger are skipped, unless `force`
+/// is set. This makes sure that redelivered queue messages do not count the
+/// same downloads twice.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessCdnLog {
     pub region: String,
     pub bucket: String,
     pub path: String,
+    #[serde(default)]
+    pub force: bool,
 }
 
 impl ProcessCdnLog {
@@ -35,6 +43,16 @@ impl ProcessCdnLog {
             region,
             bucket,
             path,
+            force: false,
+        }
+    }
+
+    /// Processes the log file even if it has already been processed before,
+    /// which counts its downloads again.
+    pub fn forced(self) -> Self {
+        Self {
+            force: true,
+            ..self
         }
     }
 }
@@ -53,7 +71,7 @@ impl BackgroundJob for ProcessCdnLog {
 
         let db_pool = ctx.connection_pool.clone();
         let writing_enabled = ctx.config.cdn_log_counting_enabled;
-        run(store, &self.path, db_pool, writing_enabled).await
+        run(store, self, db_pool, writing_enabled).await
     }
 }
 
@@ -96,32 +114,75 @@ fn build_store(
 /// This function is separate from the [`BackgroundJob`] trait method so that
 /// it can be tested without having to construct a full [`Environment`]
 /// struct.
-#[instrument(skip_all, fields(cdn_log_store.path = %path))]
+#[instrument(skip_all, fields(cdn_log_store.path = %job.path))]
 async fn run(
     store: Arc<dyn ObjectStore>,
-    path: &str,
+    job: &ProcessCdnLog,
     db_pool: DieselPool,
     writing_enabled: bool,
 ) -> anyhow::Result<()> {
+    let path = &job.path;
     let path = Path::parse(path).with_context(|| format!("Failed to parse path: {path:?}"))?;
 
-    let downloads = load_and_count(&path, store).await?;
+    let meta = store.head(&path).await;
+    let meta = meta.with_context(|| format!("Failed to request metadata for {path:?}"))?;
+
+    if writing_enabled && !job.force {
+        let db_pool = db_pool.clone();
+        let bucket = job.bucket.clone();
+        let path = job.path.clone();
+        let processed = spawn_blocking(move || {
+            let mut conn = db_pool.get()?;
+            Ok::<_, anyhow::Error>(ProcessedCdnLog::find(&mut conn, &bucket, &path)?)
+        });
+
+        if let Some(processed) = processed.await? {
+            if processed.checksum != meta.e_tag {
+                warn!("Log file has changed since it was processed");
+            }
+
+            info!(processed_at = %processed.processed_at, "Log file was already processed");
+            return Ok(());
+        }
+    }
+
+    let downloads = load_and_count(&path, &meta, store).await?;
     if downloads.is_empty() {
         info!("No downloads found in log file");
-        return Ok(());
+    } else {
+        log_stats(&downloads);
     }
 
-    log_stats(&downloads);
-
     if writing_enabled {
-        spawn_blocking(move || {
-            let mut conn = db_pool.get()?;
-            conn.transaction(|conn| save_downloads(downloads, conn))?;
+        let region = job.region.clone();
+        let bucket = job.bucket.clone();
+        let path = job.path.clone();
+        let checksum = meta.e_tag;
+        let force = job.force;
+
+        let saved = spawn_blocking(move || {
+            let entry = NewProcessedCdnLog {
+                bucket: &bucket,
+                path: &path,
+                region: &region,
+                status: match downloads.is_empty() {
+                    true => ProcessedCdnLogStatus::Empty,
+                    false => ProcessedCdnLogStatus::Counted,
+                },
+                checksum: checksum.as_deref(),
+                num_downloads: downloads.sum_downloads() as i64,
+                num_inserts: downloads.unique_version_dates().len() as i32,
+            };
 
-            Ok::<_, anyhow::Error>(())
+            let mut conn = db_pool.get()?;
+            conn.transaction(|conn| save_downloads(downloads, &entry, force, conn))
         })
         .await?;
-    } else {
+
+        if !saved {
+            info!("Log file was already processed by another job");
+        }
+    } else if !downloads.is_empty() {
         log_top_downloads(downloads, 30);
     }
 
@@ -130,11 +191,12 @@ async fn run(
 
 /// Loads the given log file from the object store and counts the number of
 /// downloads for each crate and version.
-async fn load_and_count(path: &Path, store: Arc<dyn ObjectStore>) -> anyhow::Result<DownloadsMap> {
-    let meta = store.head(path).await;
-    let meta = meta.with_context(|| format!("Failed to request metadata for {path:?}"))?;
-
-    let reader = object_store::buffered::BufReader::new(store, &meta);
+async fn load_and_count(
+    path: &Path,
+    meta: &ObjectMeta,
+    store: Arc<dyn ObjectStore>,
+) -> anyhow::Result<DownloadsMap> {
+    let reader = object_store::buffered::BufReader::new(store, meta);
     let decompressor = Decompressor::from_extension(reader, path.extension())?;
     let reader = BufReader::new(decompressor);
 
@@ -150,20 +212,23 @@ fn log_stats(downloads: &DownloadsMap) {
     let num_crates = downloads.unique_crates().len();
     info!("Number of crates: {num_crates}");
 
-    let total_inserts = downloads.len();
+    let total_inserts = downloads.unique_version_dates().len();
     info!("Number of needed inserts: {total_inserts}");
+
+    let num_clients = downloads.len();
+    info!("Number of needed client breakdown inserts: {num_clients}");
 }
 
 /// Prints the top `num` downloads from the given [`DownloadsMap`] map to the log.
 fn log_top_downloads(downloads: DownloadsMap, num: usize) {
     let mut downloads = downloads.into_vec();
-    downloads.sort_by_key(|(_, _, _, downloads)| Reverse(*downloads));
+    downloads.sort_by_key(|(_, _, _, _, downloads)| Reverse(*downloads));
 
     let top_downloads = downloads
         .into_iter()
         .take(num)
-        .map(|(krate, version, date, downloads)| {
-            format!("{date}  {krate}@{version} .. {downloads}")
+        .map(|(krate, version, date, client, downloads)| {
+            format!("{date}  {krate}@{version} ({client}) .. {downloads}")
         })
         .collect::<Vec<_>>();
 
@@ -176,10 +241,11 @@ table! {
     ///
     /// The primary key does not actually exist, but specifying one is
     /// required by Diesel.
-    temp_downloads (name, version, date) {
+    temp_downloads (name, version, date, client) {
         name -> Text,
         version -> Text,
         date -> Date,
+        client -> Text,
         downloads -> BigInt,
     }
 }
@@ -191,31 +257,56 @@ struct NewDownload {
     name: String,
     version: String,
     date: NaiveDate,
+    client: String,
     downloads: i64,
 }
 
-impl From<(String, Version, NaiveDate, u64)> for NewDownload {
-    fn from((name, version, date, downloads): (String, Version, NaiveDate, u64)) -> Self {
+impl From<(String, Version, NaiveDate, ClientClass, u64)> for NewDownload {
+    fn from(
+        (name, version, date, client, downloads): (String, Version, NaiveDate, ClientClass, u64),
+    ) -> Self {
         Self {
             name,
             version: version.to_string(),
             date,
+            client: client.to_string(),
             downloads: downloads as i64,
         }
     }
 }
 
 /// Saves the downloads from the given [`DownloadsMap`] to the database into
-/// the `version_downloads` table.
+/// the `version_downloads` and `version_downloads_by_client` tables, and records the log file in the
+/// `processed_cdn_logs` ledger.
+///
+/// If the log file has already been recorded in the ledger, and `force` is
+/// not set, nothing is saved and `false` is returned.
 ///
 /// This function **should be run inside a transaction** to ensure that the
 /// temporary `temp_downloads` table is dropped after the inserts are
-/// completed!
+/// completed, and that the downloads are saved if and only if the ledger
+/// entry is saved!
 ///
 /// The temporary table only exists on the current connection, but if a
 /// connection pool is used, the temporary table will not be dropped when
 /// the connection is returned to the pool.
-pub fn save_downloads(downloads: DownloadsMap, conn: &mut PgConnection) -> anyhow::Result<()> {
+pub fn save_downloads(
+    downloads: DownloadsMap,
+    entry: &NewProcessedCdnLog<'_>,
+    force: bool,
+    conn: &mut PgConnection,
+) -> anyhow::Result<bool> {
+    debug!("Recording log file in processed_cdn_logs table");
+    if force {
+        entry.upsert(conn)?;
+    } else if !entry.insert(conn)? {
+        return Ok(false);
+    }
+
+    if downloads.is_empty() {
+        return Ok(true);
+    }
+
     debug!("Creating temp_downloads table");
     create_temp_downloads_table(conn).context("Failed to create temp_downloads table")?;
 
@@ -232,7 +323,7 @@ pub fn save_downloads(downloads: DownloadsMap, conn: &mut PgConnection) -> anyho
         );
     }
 
-    Ok(())
+    Ok(true)
 }
 
 /// Creates the temporary `temp_downloads` table that is used to store the
@@ -250,6 +341,7 @@ fn create_temp_downloads_table(conn: &mut PgConnection) -> QueryResult<usize> {
                 name VARCHAR NOT NULL,
                 version VARCHAR NOT NULL,
                 date DATE NOT NULL,
+                client VARCHAR NOT NULL,
                 downloads INTEGER NOT NULL
             ) ON COMMIT DROP;
         "#,
@@ -280,8 +372,8 @@ fn fill_temp_downloads_table(
 }
 
 /// Saves the downloads from the temporary `temp_downloads` table to the
-/// `version_downloads` table and returns the name/version combinations that
-/// were not found in the database.
+/// `version_downloads` and `version_downloads_by_client` tables and returns
+/// the name/version combinations that were not found in the database.
 #[instrument(
     "db.query",
     skip_all,
@@ -297,14 +389,23 @@ fn save_to_version_downloads(conn: &mut PgConnection) -> QueryResult<Vec<NameAnd
                 LEFT JOIN versions ON versions.num = temp_downloads.version AND versions.crate_id = crates.id
             ), inserted AS (
                 INSERT INTO version_downloads (version_id, date, downloads)
-                SELECT joined_data.id, joined_data.date, joined_data.downloads
+                SELECT joined_data.id, joined_data.date, SUM(joined_data.downloads)::INTEGER
                 FROM joined_data
                 WHERE joined_data.id IS NOT NULL
+                GROUP BY joined_data.id, joined_data.date
                 ON CONFLICT (version_id, date)
                 DO UPDATE SET downloads = version_downloads.downloads + EXCLUDED.downloads
                 RETURNING version_downloads.version_id
+            ), inserted_by_client AS (
+                INSERT INTO version_downloads_by_client (version_id, date, client, downloads)
+                SELECT joined_data.id, joined_data.date, joined_data.client, joined_data.downloads
+                FROM joined_data
+                WHERE joined_data.id IS NOT NULL
+                ON CONFLICT (version_id, date, client)
+                DO UPDATE SET downloads = version_downloads_by_client.downloads + EXCLUDED.downloads
+                RETURNING version_downloads_by_client.version_id
             )
-            SELECT joined_data.name, joined_data.version
+            SELECT DISTINCT joined_data.name, joined_data.version
             FROM joined_data
             WHERE joined_data.id IS NULL;
         "#,
@@ -341,7 +442,7 @@ impl Debug for NameAndVersion {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::schema::{crates, version_downloads, versions};
+    use crate::schema::{crates, version_downloads, version_downloads_by_client, versions};
     use crates_io_test_db::TestDatabase;
     use diesel::r2d2::{ConnectionManager, Pool};
     use insta::assert_debug_snapshot;
@@ -360,7 +461,43 @@ mod tests {
         let store = build_dummy_store().await;
 
         let writing_enabled = true;
-        assert_ok!(run(store, CLOUDFRONT_PATH, db_pool.clone(), writing_enabled).await);
+        assert_ok!(run(store, &job(), db_pool.clone(), writing_enabled).await);
+        assert_debug_snapshot!(all_version_downloads(db_pool.clone()).await, @r###"
+        [
+            "bindgen | 0.65.1 | 1 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 2 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 1 | 0 | 2024-01-17 | false",
+            "tracing-core | 0.1.32 | 1 | 0 | 2024-01-16 | false",
+        ]
+        "###);
+        assert_debug_snapshot!(all_client_downloads(db_pool.clone()).await, @r###"
+        [
+            "bindgen | 0.65.1 | 2024-01-16 | cargo/1.74 | 1",
+            "quick-error | 1.2.3 | 2024-01-16 | cargo/1.74 | 2",
+            "quick-error | 1.2.3 | 2024-01-17 | cargo/1.74 | 1",
+            "tracing-core | 0.1.32 | 2024-01-16 | cargo/1.74 | 1",
+        ]
+        "###);
+        assert_debug_snapshot!(all_processed_logs(db_pool).await, @r###"
+        [
+            "cloudfront/static.crates.io/E35K556QRQDZXW.2024-01-16-16.d01d5f13.gz | Counted | 20 | 19",
+        ]
+        "###);
+    }
+
+    #[tokio::test]
+    async fn test_process_cdn_log_twice() {
+        let _guard = crate::util::tracing::init_for_test();
+
+        let test_database = TestDatabase::new();
+        let db_pool = build_connection_pool(test_database.url());
+        create_dummy_crates_and_versions(db_pool.clone()).await;
+
+        let store = build_dummy_store().await;
+
+        let writing_enabled = true;
+        assert_ok!(run(store.clone(), &job(), db_pool.clone(), writing_enabled).await);
+        assert_ok!(run(store, &job(), db_pool.clone(), writing_enabled).await);
         assert_debug_snapshot!(all_version_downloads(db_pool).await, @r###"
         [
             "bindgen | 0.65.1 | 1 | 0 | 2024-01-16 | false",
@@ -371,6 +508,34 @@ mod tests {
         "###);
     }
 
+    #[tokio::test]
+    async fn test_process_cdn_log_forced() {
+        let _guard = crate::util::tracing::init_for_test();
+
+        let test_database = TestDatabase::new();
+        let db_pool = build_connection_pool(test_database.url());
+        create_dummy_crates_and_versions(db_pool.clone()).await;
+
+        let store = build_dummy_store().await;
+
+        let writing_enabled = true;
+        assert_ok!(run(store.clone(), &job(), db_pool.clone(), writing_enabled).await);
+        assert_ok!(run(store, &job().forced(), db_pool.clone(), writing_enabled).await);
+        assert_debug_snapshot!(all_version_downloads(db_pool.clone()).await, @r###"
+        [
+            "bindgen | 0.65.1 | 2 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 4 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 2 | 0 | 2024-01-17 | false",
+            "tracing-core | 0.1.32 | 2 | 0 | 2024-01-16 | false",
+        ]
+        "###);
+        assert_debug_snapshot!(all_processed_logs(db_pool).await, @r###"
+        [
+            "cloudfront/static.crates.io/E35K556QRQDZXW.2024-01-16-16.d01d5f13.gz | Counted | 20 | 19",
+        ]
+        "###);
+    }
+
     #[tokio::test]
     async fn test_process_cdn_log_report_only() {
         let _guard = crate::util::tracing::init_for_test();
@@ -382,8 +547,54 @@ mod tests {
         let store = build_dummy_store().await;
 
         let writing_enabled = false;
-        assert_ok!(run(store, CLOUDFRONT_PATH, db_pool.clone(), writing_enabled).await);
-        assert_debug_snapshot!(all_version_downloads(db_pool).await, @"[]");
+        assert_ok!(run(store, &job(), db_pool.clone(), writing_enabled).await);
+        assert_debug_snapshot!(all_version_downloads(db_pool.clone()).await, @"[]");
+        assert_debug_snapshot!(all_processed_logs(db_pool).await, @"[]");
+    }
+
+    #[tokio::test]
+    async fn test_process_local_logs() {
+        let _guard = crate::util::tracing::init_for_test();
+
+        let test_database = TestDatabase::new();
+        let db_pool = build_connection_pool(test_database.url());
+        create_dummy_crates_and_versions(db_pool.clone()).await;
+
+        // Self-hosted deployments drop their access logs into the local CDN
+        // log storage, where the `region` and `bucket` are ignored.
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        std::fs::create_dir(&logs).unwrap();
+
+        let common = include_bytes!("../../../../crates_io_cdn_logs/test_data/common/basic.log");
+        std::fs::write(logs.join("access.log"), common).unwrap();
+
+        let caddy = include_bytes!("../../../../crates_io_cdn_logs/test_data/caddy/basic.log");
+        std::fs::write(logs.join("caddy.log"), caddy).unwrap();
+
+        let config = CdnLogStorageConfig::local(dir.path().to_path_buf());
+        let store = build_store(&config, "local", "local").unwrap();
+
+        let writing_enabled = true;
+        for path in ["logs/access.log", "logs/caddy.log"] {
+            let job = ProcessCdnLog::new("local".into(), "local".into(), path.into());
+            assert_ok!(run(store.clone(), &job, db_pool.clone(), writing_enabled).await);
+        }
+
+        assert_debug_snapshot!(all_version_downloads(db_pool.clone()).await, @r###"
+        [
+            "bindgen | 0.65.1 | 2 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 4 | 0 | 2024-01-16 | false",
+            "quick-error | 1.2.3 | 2 | 0 | 2024-01-17 | false",
+            "tracing-core | 0.1.32 | 2 | 0 | 2024-01-17 | false",
+        ]
+        "###);
+        assert_debug_snapshot!(all_processed_logs(db_pool).await, @r###"
+        [
+            "logs/caddy.log | Counted | 6 | 5",
+            "logs/access.log | Counted | 6 | 5",
+        ]
+        "###);
     }
 
     #[test]
@@ -407,6 +618,12 @@ mod tests {
         assert_ok!(build_store(&config, "us-west-1", "bucket"));
     }
 
+    fn job() -> ProcessCdnLog {
+        let region = "us-west-1".to_string();
+        let bucket = "bucket".to_string();
+        ProcessCdnLog::new(region, bucket, CLOUDFRONT_PATH.to_string())
+    }
+
     /// Builds a dummy object store with a log file in it.
     async fn build_dummy_store() -> Arc<dyn ObjectStore> {
         let store = InMemory::new();
@@ -498,4 +715,67 @@ mod tests {
             .load(conn)
             .unwrap()
     }
+
+    /// Queries the client breakdown of all version downloads from the
+    /// database and returns them as a [`Vec`] of strings for use with
+    /// [`assert_debug_snapshot!()`].
+    async fn all_client_downloads(db_pool: DieselPool) -> Vec<String> {
+        let downloads = spawn_blocking(move || {
+            let mut conn = db_pool.get().unwrap();
+
+            let downloads = version_downloads_by_client::table
+                .inner_join(versions::table)
+                .inner_join(crates::table.on(versions::crate_id.eq(crates::id)))
+                .select((
+                    crates::name,
+                    versions::num,
+                    version_downloads_by_client::date,
+                    version_downloads_by_client::client,
+                    version_downloads_by_client::downloads,
+                ))
+                .order((
+                    crates::name,
+                    versions::num,
+                    version_downloads_by_client::date,
+                    version_downloads_by_client::client,
+                ))
+                .load::<(String, String, NaiveDate, String, i32)>(&mut conn)?;
+
+            Ok::<_, anyhow::Error>(downloads)
+        })
+        .await
+        .unwrap();
+
+        downloads
+            .into_iter()
+            .map(|(name, version, date, client, downloads)| {
+                format!("{name} | {version} | {date} | {client} | {downloads}")
+            })
+            .collect()
+    }
+
+    /// Queries all entries of the `processed_cdn_logs` ledger and returns
+    /// them as a [`Vec`] of strings for use with [`assert_debug_snapshot!()`].
+    async fn all_processed_logs(db_pool: DieselPool) -> Vec<String> {
+        let processed = spawn_blocking(move || {
+            let mut conn = db_pool.get().unwrap();
+            Ok::<_, anyhow::Error>(ProcessedCdnLog::recent(&mut conn, None, 100)?)
+        })
+        .await
+        .unwrap();
+
+        processed
+            .into_iter()
+            .map(|log| {
+                let ProcessedCdnLog {
+                    path,
+                    status,
+                    num_downloads,
+                    num_inserts,
+                    ..
+                } = log;
+                format!("{path} | {status:?} | {num_downloads} | {num_inserts}")
+            })
+            .collect()
+    }
 }
@@ -2,13 +2,14 @@ use crate::config::CdnLogQueueConfig;
 use crate::db::DieselPool;
 use crate::sqs::{MockSqsQueue, SqsQueue, SqsQueueImpl};
 use crate::tasks::spawn_blocking;
-use crate::worker::jobs::ProcessCdnLog;
+use crate::worker::jobs::{ProcessCdnLog, UpdateDownloads};
 use crate::worker::Environment;
 use anyhow::Context;
 use aws_credential_types::Credentials;
 use aws_sdk_sqs::config::Region;
 use aws_sdk_sqs::types::Message;
-use crates_io_worker::BackgroundJob;
+use crates_io_worker::{current_job_id, BackgroundJob};
+use diesel::PgConnection;
 use std::sync::Arc;
 
 /// A background job that processes messages from the CDN log queue.
@@ -17,6 +18,10 @@ use std::sync::Arc;
 /// message to an SQS queue. This job processes those messages, extracting the
 /// log file path from each message and enqueuing a `ProcessCdnLog` job for each
 /// path.
+///
+/// If any `ProcessCdnLog` jobs are enqueued, an `UpdateDownloads` follow-up job
+/// is enqueued too, which only runs once this job and all of the
+/// `ProcessCdnLog` jobs have completed successfully.
 #[derive(Debug, Serialize, Deserialize, clap::Parser)]
 pub struct ProcessCdnLogQueue {
     /// The maximum number of messages to receive from the queue and process.
@@ -33,7 +38,14 @@ impl BackgroundJob for ProcessCdnLogQueue {
         info!("Processing messages from the CDN log queue…");
 
         let queue = build_queue(&ctx.config.cdn_log_queue);
-        run(&queue, self.max_messages, &ctx.connection_pool).await
+        let parent_job_id = current_job_id();
+        run(
+            &queue,
+            self.max_messages,
+            &ctx.connection_pool,
+            parent_job_id,
+        )
+        .await
     }
 }
 
@@ -63,13 +75,20 @@ fn build_queue(config: &CdnLogQueueConfig) -> Box<dyn SqsQueue + Send + Sync> {
 ///
 /// This function is separate from the [BackgroundJob] implementation so that it
 /// can be tested without needing to construct a full [Environment] struct.
+///
+/// If `parent_job_id` is set, the `UpdateDownloads` follow-up job depends on
+/// the job with this ID, so that it can't run before all `ProcessCdnLog` jobs
+/// have been enqueued.
 async fn run(
     queue: &impl SqsQueue,
     max_messages: usize,
     connection_pool: &DieselPool,
+    parent_job_id: Option<i64>,
 ) -> anyhow::Result<()> {
     const MAX_BATCH_SIZE: usize = 10;
 
+    let mut follow_up = FollowUp::new(parent_job_id);
+
     let mut num_remaining = max_messages;
     while num_remaining > 0 {
         let batch_size = num_remaining.min(MAX_BATCH_SIZE);
@@ -89,7 +108,7 @@ async fn run(
         }
 
         for message in messages {
-            process_message(message, queue, connection_pool).await?;
+            follow_up = process_message(message, queue, connection_pool, follow_up).await?;
         }
     }
 
@@ -102,16 +121,17 @@ async fn process_message(
     message: &Message,
     queue: &impl SqsQueue,
     connection_pool: &DieselPool,
-) -> anyhow::Result<()> {
+    mut follow_up: FollowUp,
+) -> anyhow::Result<FollowUp> {
     debug!("Processing message…");
 
     let Some(receipt_handle) = message.receipt_handle() else {
         warn!("Message has no receipt handle; skipping");
-        return Ok(());
+        return Ok(follow_up);
     };
 
     if let Some(body) = message.body() {
-        process_body(body, connection_pool).await?;
+        follow_up = process_body(body, connection_pool, follow_up).await?;
         debug!("Processed message");
     } else {
         warn!("Message has no body; skipping");
@@ -123,7 +143,7 @@ async fn process_message(
         .await
         .context("Failed to delete message from the CDN log queue")?;
 
-    Ok(())
+    Ok(follow_up)
 }
 
 /// Processes a single message body from the CDN log queue.
@@ -133,27 +153,31 @@ async fn process_message(
 /// warning and returns `Ok(())` instead. This is because we don't want to
 /// requeue the message in the case of a parsing error, as it would just be
 /// retried indefinitely.
-async fn process_body(body: &str, connection_pool: &DieselPool) -> anyhow::Result<()> {
+async fn process_body(
+    body: &str,
+    connection_pool: &DieselPool,
+    follow_up: FollowUp,
+) -> anyhow::Result<FollowUp> {
     let message = match serde_json::from_str::<super::message::Message>(body) {
         Ok(message) => message,
         Err(err) => {
             warn!(%body, "Failed to parse message: {err}");
-            return Ok(());
+            return Ok(follow_up);
         }
     };
 
     if message.records.is_empty() {
         warn!("Message has no records; skipping");
-        return Ok(());
+        return Ok(follow_up);
     }
 
     let jobs = jobs_from_message(message);
     if jobs.is_empty() {
-        return Ok(());
+        return Ok(follow_up);
     }
 
     let pool = connection_pool.clone();
-    spawn_blocking(move || enqueue_jobs(jobs, &pool)).await
+    spawn_blocking(move || enqueue_jobs(jobs, &pool, follow_up)).await
 }
 
 /// Extracts a list of [`ProcessCdnLog`] jobs from a message.
@@ -199,22 +223,71 @@ fn is_ignored_path(path: &str) -> bool {
     path.contains("/index.staging.crates.io/") || path.contains("/index.crates.io/")
 }
 
-fn enqueue_jobs(jobs: Vec<ProcessCdnLog>, pool: &DieselPool) -> anyhow::Result<()> {
+/// The state of the `UpdateDownloads` follow-up job of a [ProcessCdnLogQueue]
+/// run.
+#[derive(Debug, Clone, Copy)]
+struct FollowUp {
+    /// The ID of the running [ProcessCdnLogQueue] job, if any.
+    parent_job_id: Option<i64>,
+    /// The ID of the `UpdateDownloads` job, once it has been enqueued.
+    job_id: Option<i64>,
+}
+
+impl FollowUp {
+    fn new(parent_job_id: Option<i64>) -> Self {
+        let job_id = None;
+        Self {
+            parent_job_id,
+            job_id,
+        }
+    }
+
+    /// Returns the ID of the `UpdateDownloads` follow-up job, enqueueing it
+    /// first if necessary. Returns `None` if there is no parent job that the
+    /// follow-up job could depend on.
+    fn get_or_enqueue(&mut self, conn: &mut PgConnection) -> anyhow::Result<Option<i64>> {
+        let Some(parent_job_id) = self.parent_job_id else {
+            return Ok(None);
+        };
+
+        if self.job_id.is_none() {
+            info!("Enqueuing `UpdateDownloads` follow-up job…");
+            let job_id = UpdateDownloads
+                .enqueue_with_dependencies(conn, &[parent_job_id])
+                .context("Failed to enqueue `UpdateDownloads` follow-up job")?;
+
+            self.job_id = Some(job_id);
+        }
+
+        Ok(self.job_id)
+    }
+}
+
+fn enqueue_jobs(
+    jobs: Vec<ProcessCdnLog>,
+    pool: &DieselPool,
+    mut follow_up: FollowUp,
+) -> anyhow::Result<FollowUp> {
     let mut conn = pool
         .get()
         .context("Failed to acquire database connection")?;
 
+    let follow_up_id = follow_up.get_or_enqueue(&mut conn)?;
+
     for job in jobs {
         let path = &job.path;
 
         info!("Enqueuing processing job… ({path})");
-        job.enqueue(&mut conn)
-            .context("Failed to enqueue processing job")?;
+        match follow_up_id {
+            Some(follow_up_id) => job.enqueue_as_dependency_of(&mut conn, follow_up_id),
+            None => job.enqueue(&mut conn),
+        }
+        .context("Failed to enqueue processing job")?;
 
         debug!("Enqueued processing job");
     }
 
-    Ok(())
+    Ok(follow_up)
 }
 
 #[cfg(test)]
@@ -224,7 +297,7 @@ mod tests {
     use aws_sdk_sqs::types::builders::MessageBuilder;
     use aws_sdk_sqs::types::Message;
     use crates_io_test_db::TestDatabase;
-    use crates_io_worker::schema::background_jobs;
+    use crates_io_worker::schema::{background_job_dependencies, background_jobs};
     use diesel::prelude::*;
     use diesel::r2d2::{ConnectionManager, Pool};
     use diesel::QueryDsl;
@@ -255,7 +328,7 @@ mod tests {
         let test_database = TestDatabase::new();
         let connection_pool = build_connection_pool(test_database.url());
 
-        assert_ok!(run(&queue, 100, &connection_pool).await);
+        assert_ok!(run(&queue, 100, &connection_pool, None).await);
 
         assert_snapshot!(deleted_handles.lock().join(","), @"123");
         assert_snapshot!(open_jobs(&mut test_database.connect()), @"us-west-1 | bucket | path");
@@ -303,7 +376,7 @@ mod tests {
         let test_database = TestDatabase::new();
         let connection_pool = build_connection_pool(test_database.url());
 
-        assert_ok!(run(&queue, 100, &connection_pool).await);
+        assert_ok!(run(&queue, 100, &connection_pool, None).await);
 
         assert_snapshot!(deleted_handles.lock().join(","), @"1,2,3,4,5,6,7,8,9,10,11");
         assert_snapshot!(open_jobs(&mut test_database.connect()), @r###"
@@ -351,12 +424,78 @@ mod tests {
         let test_database = TestDatabase::new();
         let connection_pool = build_connection_pool(test_database.url());
 
-        assert_ok!(run(&queue, 100, &connection_pool).await);
+        assert_ok!(run(&queue, 100, &connection_pool, None).await);
 
         assert_snapshot!(deleted_handles.lock().join(","), @"1");
         assert_snapshot!(open_jobs(&mut test_database.connect()), @"");
     }
 
+    #[tokio::test]
+    async fn test_process_cdn_log_queue_follow_up() {
+        let _guard = crate::util::tracing::init_for_test();
+
+        let mut queue = Box::new(MockSqsQueue::new());
+        queue
+            .expect_receive_messages()
+            .once()
+            .returning(|_max_messages| {
+                Ok(ReceiveMessageOutputBuilder::default()
+                    .messages(message("1", "us-west-1", "bucket", "path1"))
+                    .messages(message("2", "us-west-1", "bucket", "path2"))
+                    .build())
+            });
+
+        queue
+            .expect_receive_messages()
+            .once()
+            .returning(|_max_messages| Ok(ReceiveMessageOutputBuilder::default().build()));
+
+        let deleted_handles = record_deleted_handles(&mut queue);
+
+        let test_database = TestDatabase::new();
+        let connection_pool = build_connection_pool(test_database.url());
+
+        let mut conn = test_database.connect();
+        let job = ProcessCdnLogQueue { max_messages: 100 };
+        let parent_job_id = job.enqueue(&mut conn).unwrap();
+
+        assert_ok!(run(&queue, 100, &connection_pool, Some(parent_job_id)).await);
+
+        assert_snapshot!(deleted_handles.lock().join(","), @"1,2");
+        assert_snapshot!(open_jobs(&mut conn), @r###"
+        us-west-1 | bucket | path1
+        us-west-1 | bucket | path2
+        "###);
+
+        // The follow-up job depends on the parent job and on all of the
+        // `ProcessCdnLog` jobs.
+        let follow_up_id = background_jobs::table
+            .select(background_jobs::id)
+            .filter(background_jobs::job_type.eq(UpdateDownloads::JOB_NAME))
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+
+        let dependencies = background_job_dependencies::table
+            .inner_join(
+                background_jobs::table
+                    .on(background_jobs::id.eq(background_job_dependencies::dependency_id)),
+            )
+            .select(background_jobs::job_type)
+            .filter(background_job_dependencies::job_id.eq(follow_up_id))
+            .order(background_jobs::id)
+            .load::<String>(&mut conn)
+            .unwrap();
+
+        assert_eq!(
+            dependencies,
+            [
+                ProcessCdnLogQueue::JOB_NAME,
+                ProcessCdnLog::JOB_NAME,
+                ProcessCdnLog::JOB_NAME
+            ]
+        );
+    }
+
     #[test]
     fn test_ignored_path() {
         let valid_paths = vec![
@@ -417,13 +556,13 @@ mod tests {
 
     fn open_jobs(conn: &mut PgConnection) -> String {
         let jobs = background_jobs::table
-            .select((background_jobs::job_type, background_jobs::data))
-            .load::<(String, serde_json::Value)>(conn)
+            .select(background_jobs::data)
+            .filter(background_jobs::job_type.eq(ProcessCdnLog::JOB_NAME))
+            .order(background_jobs::id)
+            .load::<serde_json::Value>(conn)
             .unwrap();
 
         jobs.into_iter()
-            .inspect(|(job_type, _data)| assert_eq!(job_type, ProcessCdnLog::JOB_NAME))
-            .map(|(_job_type, data)| data)
             .map(|data| serde_json::from_value::<ProcessCdnLog>(data).unwrap())
             .map(|job| format!("{} | {} | {}", job.region, job.bucket, job.path))
             .collect::<Vec<_>>()
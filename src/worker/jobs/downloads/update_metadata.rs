@@ -1,4 +1,4 @@
-use crate::models::VersionDownload;
+use crate::models::{DownloadGranularity, VersionDownload};
 use crate::schema::{crates, metadata, version_downloads, versions};
 use crate::tasks::spawn_blocking;
 use crate::worker::Environment;
@@ -37,6 +37,11 @@ fn update(conn: &mut PgConnection) -> QueryResult<()> {
     collect(conn, &rows)?;
     info!("Finished updating versions");
 
+    // The rollups have to be updated before the rows are frozen below,
+    // because only the periods of unfrozen rows are recalculated.
+    update_rollups(conn)?;
+    info!("Finished updating download rollups");
+
     // Anything older than 24 hours ago will be frozen and will not be queried
     // against again.
     diesel::update(version_downloads::table)
@@ -54,6 +59,20 @@ fn update(conn: &mut PgConnection) -> QueryResult<()> {
     Ok(())
 }
 
+/// Updates the weekly and monthly download rollups from the daily downloads.
+fn update_rollups(conn: &mut PgConnection) -> QueryResult<()> {
+    for granularity in DownloadGranularity::ALL {
+        diesel::sql_query(format!(
+            include_str!("update_rollups.sql"),
+            table = granularity.table_name(),
+            unit = granularity,
+        ))
+        .execute(conn)?;
+    }
+
+    Ok(())
+}
+
 fn collect(conn: &mut PgConnection, rows: &[VersionDownload]) -> QueryResult<()> {
     use diesel::update;
 
@@ -97,6 +116,7 @@ mod test {
     use crate::email::Emails;
     use crate::models::{Crate, NewCrate, NewUser, NewVersion, User, Version};
     use crate::test_util::test_db_connection;
+    use chrono::NaiveDate;
     use std::collections::BTreeMap;
 
     fn user(conn: &mut PgConnection) -> User {
@@ -314,4 +334,63 @@ mod test {
         assert_eq!(versions_changed, Ok(false));
         assert_eq!(crates_changed, Ok(false));
     }
+
+    #[test]
+    fn update_rollups() {
+        use crate::schema::{version_downloads_monthly, version_downloads_weekly};
+        use diesel::dsl::*;
+
+        let (_test_db, conn) = &mut test_db_connection();
+        let user = user(conn);
+        let (_, version) = crate_and_version(conn, user.id);
+
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%F").unwrap();
+        let rows = [
+            ("2023-12-01", 7, true),
+            ("2024-01-29", 5, true),
+            ("2024-01-30", 3, false),
+            ("2024-02-01", 4, false),
+        ];
+        for (day, downloads, processed) in rows {
+            insert_into(version_downloads::table)
+                .values((
+                    version_downloads::version_id.eq(version.id),
+                    version_downloads::date.eq(date(day)),
+                    version_downloads::downloads.eq(downloads),
+                    version_downloads::processed.eq(processed),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        // Running the update twice must not change the rollups, even though
+        // all rows have been frozen by the first run.
+        for _ in 0..2 {
+            super::update(conn).unwrap();
+
+            let weekly: Vec<(NaiveDate, i64)> = version_downloads_weekly::table
+                .select((
+                    version_downloads_weekly::date,
+                    version_downloads_weekly::downloads,
+                ))
+                .order(version_downloads_weekly::date)
+                .load(conn)
+                .unwrap();
+            assert_eq!(weekly, vec![(date("2024-01-29"), 12)]);
+
+            // December was already frozen and is not recalculated.
+            let monthly: Vec<(NaiveDate, i64)> = version_downloads_monthly::table
+                .select((
+                    version_downloads_monthly::date,
+                    version_downloads_monthly::downloads,
+                ))
+                .order(version_downloads_monthly::date)
+                .load(conn)
+                .unwrap();
+            assert_eq!(
+                monthly,
+                vec![(date("2024-01-01"), 8), (date("2024-02-01"), 4)]
+            );
+        }
+    }
 }
@@ -0,0 +1,24 @@
+-- Recalculate the rollups of all periods that contain daily downloads which
+-- have not been frozen yet, since only those can still change. Periods that
+-- overlap with archived months are skipped, because their daily downloads
+-- are not complete anymore.
+WITH affected AS (
+    SELECT DISTINCT version_id, date_trunc('{unit}', date)::date AS period
+    FROM version_downloads
+    WHERE NOT processed
+      AND date_trunc('{unit}', date) >= (
+        SELECT COALESCE(MAX(month) + INTERVAL '1 month', '-infinity')
+        FROM version_downloads_archives
+      )
+)
+INSERT INTO {table} (version_id, date, downloads)
+SELECT version_id, date_trunc('{unit}', date)::date AS period, SUM(downloads)
+FROM version_downloads
+-- Only the affected periods have to be scanned, instead of the whole history
+-- of the affected versions.
+WHERE date >= (SELECT MIN(period) FROM affected)
+  AND (version_id, date_trunc('{unit}', date)::date) IN (
+    SELECT version_id, period FROM affected
+  )
+GROUP BY version_id, period
+ON CONFLICT (version_id, date) DO UPDATE SET downloads = EXCLUDED.downloads
@@ -52,15 +52,11 @@ impl BackgroundJob for DumpDb {
 
         info!("Invalidating CDN caches");
         if let Some(cloudfront) = env.cloudfront() {
-            if let Err(error) = cloudfront.invalidate(&self.target_name).await {
-                warn!("failed to invalidate CloudFront cache: {}", error);
-            }
+            cloudfront.invalidate([&self.target_name]);
         }
 
         if let Some(fastly) = env.fastly() {
-            if let Err(error) = fastly.invalidate(&self.target_name).await {
-                warn!("failed to invalidate Fastly cache: {}", error);
-            }
+            fastly.invalidate([&self.target_name]);
         }
 
         Ok(())
@@ -1,19 +1,35 @@
-use crate::models;
+use crate::index_signing;
+use crate::models::{self, CrateVersions};
 use crate::tasks::spawn_blocking;
+use crate::worker::jobs::git_mirrors::enqueue_sync_git_mirrors;
+use crate::worker::jobs::resign_index::{sync_targets_document, update_snapshot};
 use crate::worker::Environment;
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use chrono::Utc;
 use crates_io_env_vars::var_parsed;
-use crates_io_index::{Crate, Repository};
-use crates_io_worker::BackgroundJob;
+use crates_io_index::{parse_crates, read_crates, Repository};
+use crates_io_worker::{
+    cancellation_token, claim_jobs, complete_claimed_jobs, release_claimed_jobs, BackgroundJob,
+};
 use diesel::prelude::*;
 use sentry::Level;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
 use std::process::Command;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
 use url::Url;
 
+/// The time that `SyncToGitIndex` jobs claimed by a batched sync are
+/// rescheduled by. This needs to be longer than the [TIMEOUT] of the job, so
+/// that a claimed job does not run concurrently with the batch.
+///
+/// [TIMEOUT]: BackgroundJob::TIMEOUT
+const CLAIM_LEASE: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Serialize, Deserialize)]
 pub struct SyncToGitIndex {
     krate: String,
@@ -30,54 +46,166 @@ impl BackgroundJob for SyncToGitIndex {
     const JOB_NAME: &'static str = "sync_to_git_index";
     const PRIORITY: i16 = 100;
     const QUEUE: &'static str = "repository";
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(5 * 60));
 
     type Context = Arc<Environment>;
 
-    /// Regenerates or removes an index file for a single crate
+    /// Regenerates or removes the index file for a single crate.
+    ///
+    /// If batching is enabled via
+    /// [`git_index_sync_batch_size`](crate::config::Server::git_index_sync_batch_size),
+    /// other pending `SyncToGitIndex` jobs are claimed as well, and all of
+    /// their changes are committed and pushed together. The claimed jobs are
+    /// only removed from the queue once the push has succeeded, so that they
+    /// can be retried individually otherwise.
     #[instrument(skip_all, fields(krate.name = ? self.krate))]
     async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
         info!("Syncing to git index");
 
         let crate_name = self.krate.clone();
+        let cancellation_token = cancellation_token();
         spawn_blocking(move || {
             let mut conn = env.connection_pool.get()?;
-            let new = get_index_data(&crate_name, &mut conn).context("Failed to get index data")?;
+
+            // Dropping the job future on timeout does not stop this blocking
+            // closure, so the token is checked between the individual steps
+            // to release the index lock as early as possible.
+            let ensure_not_cancelled = |step: &str| match cancellation_token.is_cancelled() {
+                true => Err(anyhow!("Job was cancelled {step}")),
+                false => Ok(()),
+            };
 
             let repo = env.lock_index()?;
-            let dst = repo.index_file(&crate_name);
+            ensure_not_cancelled("while waiting for the index lock")?;
 
-            // Read the previous crate contents
-            let old = match fs::read_to_string(&dst) {
-                Ok(content) => Some(content),
-                Err(error) if error.kind() == ErrorKind::NotFound => None,
-                Err(error) => return Err(error.into()),
-            };
+            let batch_size = env.config.git_index_sync_batch_size;
+            let claimed_jobs =
+                claim_jobs::<SyncToGitIndex>(&mut conn, batch_size.saturating_sub(1), CLAIM_LEASE)?;
+            let claimed_ids = claimed_jobs.iter().map(|(id, _)| *id).collect::<Vec<_>>();
 
-            match (old, new) {
-                (None, Some(new)) => {
-                    fs::create_dir_all(dst.parent().unwrap())?;
-                    let mut file = File::create(&dst)?;
-                    file.write_all(new.as_bytes())?;
-                    repo.commit_and_push(&format!("Create crate `{}`", &crate_name), &dst)?;
-                }
-                (Some(old), Some(new)) if old != new => {
-                    let mut file = File::create(&dst)?;
-                    file.write_all(new.as_bytes())?;
-                    repo.commit_and_push(&format!("Update crate `{}`", &crate_name), &dst)?;
+            let mut crate_names = vec![crate_name];
+            for (_, job) in claimed_jobs {
+                if !crate_names.contains(&job.krate) {
+                    crate_names.push(job.krate);
                 }
-                (Some(_old), None) => {
-                    fs::remove_file(&dst)?;
-                    repo.commit_and_push(&format!("Delete crate `{}`", &crate_name), &dst)?;
+            }
+
+            let result = sync_crates(&repo, &mut conn, crate_names, &ensure_not_cancelled);
+            if let Err(error) = result {
+                if let Err(release_error) = release_claimed_jobs(&mut conn, &claimed_ids) {
+                    warn!("Failed to release claimed `SyncToGitIndex` jobs: {release_error}");
                 }
-                _ => debug!("Skipping sync because index is up-to-date"),
+
+                return Err(error);
             }
 
+            complete_claimed_jobs(&mut conn, &claimed_ids)?;
+
             Ok(())
         })
         .await
     }
 }
 
+/// Regenerates the index files of the given crates, and commits and pushes
+/// all of the resulting changes together.
+fn sync_crates(
+    repo: &Repository,
+    conn: &mut PgConnection,
+    crate_names: Vec<String>,
+    ensure_not_cancelled: &dyn Fn(&str) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    // All index data is gathered before any file is modified, so
+    // that a database error can't leave the checkout in a state
+    // that doesn't match `HEAD`.
+    let mut index_data = Vec::with_capacity(crate_names.len());
+    for crate_name in crate_names {
+        ensure_not_cancelled("while gathering index data")?;
+
+        let new = get_index_data(&crate_name, conn)
+            .with_context(|| format!("Failed to get index data for `{crate_name}`"))?;
+
+        index_data.push((crate_name, new));
+    }
+
+    ensure_not_cancelled("before writing the index files")?;
+
+    let mut changes = Vec::new();
+    for (crate_name, new) in index_data {
+        if let Some(change) = write_index_file(repo, &crate_name, new)? {
+            changes.push(change);
+        }
+    }
+
+    // The checkout is reset to `HEAD` by the next `lock_index()`
+    // call, so bailing out here does not leave stale changes.
+    if !changes.is_empty() {
+        ensure_not_cancelled("before pushing the index changes")?;
+    }
+
+    match changes.as_slice() {
+        [] => debug!("Skipping sync because index is up-to-date"),
+        [(message, path)] => repo.commit_and_push(message, path)?,
+        changes => {
+            let mut message = format!("Sync {} crates\n", changes.len());
+            for (change, _) in changes {
+                message.push('\n');
+                message.push_str(change);
+            }
+
+            let paths = changes.iter().map(|(_, path)| path.as_path());
+            repo.commit_files_and_push(&message, &paths.collect::<Vec<_>>())?;
+        }
+    }
+
+    if !changes.is_empty() {
+        enqueue_sync_git_mirrors(repo, conn)?;
+    }
+
+    Ok(())
+}
+
+/// Writes or removes the index file of the given crate in the local checkout
+/// of the index.
+///
+/// Returns the commit message and the absolute path of the file if it has
+/// been modified, or `None` if the file is already up-to-date.
+fn write_index_file(
+    repo: &Repository,
+    crate_name: &str,
+    new: Option<String>,
+) -> anyhow::Result<Option<(String, PathBuf)>> {
+    let dst = repo.index_file(crate_name);
+
+    // Read the previous crate contents
+    let old = match fs::read_to_string(&dst) {
+        Ok(content) => Some(content),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    let message = match (old, new) {
+        (None, Some(new)) => {
+            fs::create_dir_all(dst.parent().unwrap())?;
+            let mut file = File::create(&dst)?;
+            file.write_all(new.as_bytes())?;
+            format!("Create crate `{crate_name}`")
+        }
+        (Some(old), Some(new)) if old != new => {
+            let mut file = File::create(&dst)?;
+            file.write_all(new.as_bytes())?;
+            format!("Update crate `{crate_name}`")
+        }
+        (Some(_old), None) => {
+            fs::remove_file(&dst)?;
+            format!("Delete crate `{crate_name}`")
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some((message, dst)))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SyncToSparseIndex {
     krate: String,
@@ -102,22 +230,52 @@ impl BackgroundJob for SyncToSparseIndex {
         info!("Syncing to sparse index");
 
         let crate_name = self.krate.clone();
-        let pool = env.connection_pool.clone();
-        let content = spawn_blocking(move || {
-            let mut conn = pool.get()?;
-            get_index_data(&crate_name, &mut conn).context("Failed to get index data")
+        let paths = spawn_blocking({
+            let env = env.clone();
+            move || {
+                let mut conn = env.connection_pool.get()?;
+                let rt = Handle::current();
+
+                // The lock ensures that concurrent syncs of the same crate
+                // can't interleave, so that the signed metadata always
+                // matches the uploaded index file.
+                let _lock = env.lock_index_metadata();
+
+                let content =
+                    get_index_data(&crate_name, &mut conn).context("Failed to get index data")?;
+
+                let path = Repository::relative_index_file_for_url(&crate_name);
+                let mut paths = vec![path.clone()];
+
+                // The signed metadata is uploaded before the index file
+                // itself, so that clients never see an index file without
+                // matching metadata.
+                if let Some(signer) = env.index_signer() {
+                    let now = Utc::now();
+                    let content = content.as_ref().map(|content| content.as_bytes());
+                    let version =
+                        sync_targets_document(signer, &env.storage, &rt, &path, content, now)
+                            .context("Failed to sync signed index metadata")?;
+
+                    let targets = BTreeMap::from([(path.clone(), version)]);
+                    let snapshot_paths = update_snapshot(signer, &env.storage, &rt, targets, now)
+                        .context("Failed to sync signed index metadata")?;
+
+                    paths.push(index_signing::targets_path(&path));
+                    paths.extend(snapshot_paths);
+                }
+
+                rt.block_on(env.storage.sync_index(&crate_name, content))
+                    .context("Failed to sync index data")?;
+
+                Ok::<_, anyhow::Error>(paths)
+            }
         })
         .await?;
 
-        let future = env.storage.sync_index(&self.krate, content);
-        future.await.context("Failed to sync index data")?;
-
         if let Some(cloudfront) = env.cloudfront() {
-            let path = Repository::relative_index_file_for_url(&self.krate);
-
-            info!(%path, "Invalidating index file on CloudFront");
-            let future = cloudfront.invalidate(&path);
-            future.await.context("Failed to invalidate CloudFront")?;
+            info!(?paths, "Invalidating index files on CloudFront");
+            cloudfront.invalidate(&paths);
         }
         Ok(())
     }
@@ -209,6 +367,9 @@ impl BackgroundJob for SquashIndex {
                 ]))?;
             }
 
+            let mut conn = env.connection_pool.get()?;
+            enqueue_sync_git_mirrors(&repo, &mut conn)?;
+
             info!("The index has been successfully squashed.");
 
             Ok(())
@@ -256,16 +417,10 @@ impl BackgroundJob for NormalizeIndex {
                 }
 
                 let mut body: Vec<u8> = Vec::new();
-                let file = fs::File::open(&path)?;
-                let reader = BufReader::new(file);
+                let content = fs::read_to_string(&path)?;
                 let mut versions = Vec::new();
-                for line in reader.lines() {
-                    let line = line?;
-                    if line.is_empty() {
-                        continue;
-                    }
-
-                    let mut krate: Crate = serde_json::from_str(&line)?;
+                for krate in parse_crates(&content) {
+                    let mut krate = krate?;
                     for dep in &mut krate.deps {
                         // Remove deps with empty features
                         dep.features.retain(|d| !d.is_empty());
@@ -300,6 +455,11 @@ impl BackgroundJob for NormalizeIndex {
                 &format!("HEAD:{branch}"),
             ]))?;
 
+            if !dry_run {
+                let mut conn = env.connection_pool.get()?;
+                enqueue_sync_git_mirrors(&repo, &mut conn)?;
+            }
+
             info!("Index normalization completed");
 
             Ok(())
@@ -307,3 +467,136 @@ impl BackgroundJob for NormalizeIndex {
         .await
     }
 }
+
+/// Adds the `pubtime` field to all entries in the git index that were
+/// published before the field was introduced.
+///
+/// Similar to [NormalizeIndex], all changes are committed and pushed in a
+/// single commit. The sparse index is then updated by enqueueing
+/// [SyncToSparseIndex] jobs for all modified crates.
+#[derive(Serialize, Deserialize)]
+pub struct BackfillIndexPubtime {
+    dry_run: bool,
+}
+
+impl BackfillIndexPubtime {
+    pub fn new(dry_run: bool) -> Self {
+        Self { dry_run }
+    }
+}
+
+impl BackgroundJob for BackfillIndexPubtime {
+    const JOB_NAME: &'static str = "backfill_index_pubtime";
+    const QUEUE: &'static str = "repository";
+
+    type Context = Arc<Environment>;
+
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        info!("Backfilling the `pubtime` field of the index");
+
+        let dry_run = self.dry_run;
+        spawn_blocking(move || {
+            let mut conn = env.connection_pool.get()?;
+            let repo = env.lock_index()?;
+
+            let files = repo.get_files_modified_since(None)?;
+            let num_files = files.len();
+
+            let mut modified_crates = Vec::new();
+            for (i, file) in files.iter().enumerate() {
+                if i % 50 == 0 {
+                    info!(num_files, i, ?file);
+                }
+
+                let crate_name = file.file_name().unwrap().to_str().unwrap();
+                let path = repo.index_file(crate_name);
+                if !path.exists() {
+                    continue;
+                }
+
+                let content = fs::read_to_string(&path)?;
+                let new_content = backfill_pubtime(&content, crate_name, &mut conn)
+                    .with_context(|| format!("Failed to backfill `{crate_name}`"))?;
+
+                if new_content != content {
+                    fs::write(path, new_content)?;
+                    modified_crates.push(crate_name.to_string());
+                }
+            }
+
+            let num_crates = modified_crates.len();
+            if num_crates == 0 {
+                info!("All index entries already have a `pubtime`");
+                return Ok(());
+            }
+
+            info!(num_crates, "Committing `pubtime` backfill");
+            let msg = "Backfill `pubtime` of index entries";
+            repo.run_command(Command::new("git").args(["commit", "-am", msg]))?;
+
+            let branch = match dry_run {
+                false => "master",
+                true => "pubtime-backfill-dry-run",
+            };
+
+            info!(?branch, "Pushing to upstream repository");
+            repo.run_command(Command::new("git").args([
+                "push",
+                "origin",
+                &format!("HEAD:{branch}"),
+            ]))?;
+
+            if !dry_run {
+                enqueue_sync_git_mirrors(&repo, &mut conn)?;
+
+                info!(num_crates, "Enqueueing sparse index updates");
+                for crate_name in modified_crates {
+                    SyncToSparseIndex::new(crate_name).enqueue(&mut conn)?;
+                }
+            }
+
+            info!("`pubtime` backfill completed");
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Sets the `pubtime` field of all entries in the given index file content
+/// that don't have one yet, and returns the new content.
+///
+/// Entries of versions that can't be found in the database are left as-is.
+fn backfill_pubtime(
+    content: &str,
+    crate_name: &str,
+    conn: &mut PgConnection,
+) -> anyhow::Result<String> {
+    let mut versions = read_crates(content)?;
+
+    if versions.iter().all(|version| version.pubtime.is_some()) {
+        return Ok(content.to_string());
+    }
+
+    let krate: Option<models::Crate> = models::Crate::by_name(crate_name).first(conn).optional()?;
+
+    let pubtimes: HashMap<String, String> = match krate {
+        Some(krate) => krate
+            .all_versions()
+            .load::<models::Version>(conn)?
+            .into_iter()
+            .map(|version| (version.num.clone(), version.pubtime()))
+            .collect(),
+        None => return Ok(content.to_string()),
+    };
+
+    for version in &mut versions {
+        if version.pubtime.is_none() {
+            version.pubtime = pubtimes.get(&version.vers).cloned();
+        }
+    }
+
+    let mut body = Vec::new();
+    crates_io_index::write_crates(&versions, &mut body)?;
+    Ok(String::from_utf8(body)?)
+}
@@ -0,0 +1,140 @@
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use crates_io_index::Repository;
+use crates_io_worker::schema::background_jobs;
+use crates_io_worker::{BackgroundJob, EnqueueError};
+use diesel::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The queue of the git mirror jobs, which is separate from the `repository`
+/// queue so that slow or unavailable mirrors don't delay index updates.
+pub const GIT_MIRRORS_QUEUE: &str = "git_mirrors";
+
+/// A background job that force-pushes the current `HEAD` of the git index to
+/// one of the configured mirrors.
+///
+/// Every mirror is synced by its own job, so that an unavailable mirror is
+/// retried independently and never blocks the primary repository or the
+/// other mirrors. The index lock is only held while a [snapshot] of the
+/// current `HEAD` is created, and the push happens from the snapshot.
+///
+/// [snapshot]: crates_io_index::RepositorySnapshot
+#[derive(Serialize, Deserialize)]
+pub struct SyncGitMirror {
+    mirror: String,
+}
+
+impl SyncGitMirror {
+    pub fn new(mirror: impl Into<String>) -> Self {
+        let mirror = mirror.into();
+        Self { mirror }
+    }
+}
+
+impl BackgroundJob for SyncGitMirror {
+    const JOB_NAME: &'static str = "sync_git_mirror";
+    const QUEUE: &'static str = GIT_MIRRORS_QUEUE;
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(30 * 60));
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all, fields(mirror = %self.mirror))]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        let mirror_name = self.mirror.clone();
+        spawn_blocking(move || {
+            let Some(mirror) = env.git_mirror(&mirror_name) else {
+                warn!("Skipping sync of unknown git index mirror");
+                return Ok(());
+            };
+
+            let snapshot = env.lock_index()?.snapshot()?;
+
+            info!(head = %snapshot.head_oid(), "Pushing the index to the mirror…");
+            snapshot.push_to_mirror(mirror)?;
+            info!("Index pushed to the mirror");
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// A background job that compares the `HEAD` of each configured mirror with
+/// the `HEAD` of the primary repository, and enqueues [SyncGitMirror] jobs
+/// for all mirrors that are out of sync.
+#[derive(Serialize, Deserialize)]
+pub struct CheckGitMirrors;
+
+impl BackgroundJob for CheckGitMirrors {
+    const JOB_NAME: &'static str = "check_git_mirrors";
+    const QUEUE: &'static str = GIT_MIRRORS_QUEUE;
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(5 * 60));
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all)]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        spawn_blocking(move || {
+            let mut conn = env.connection_pool.get()?;
+            let head = env.lock_index()?.head_oid()?;
+
+            for mirror in env.git_mirrors() {
+                let mirror_head = match mirror.head_oid() {
+                    Ok(mirror_head) => mirror_head,
+                    Err(error) => {
+                        warn!(mirror = %mirror.name, "Failed to read HEAD of git index mirror: {error}");
+                        continue;
+                    }
+                };
+
+                if mirror_head == Some(head) {
+                    info!(mirror = %mirror.name, %head, "Git index mirror is up-to-date");
+                } else {
+                    let mirror_head = mirror_head.map(|oid| oid.to_string());
+                    warn!(mirror = %mirror.name, %head, ?mirror_head, "Git index mirror is out of sync");
+                    enqueue_sync_git_mirror(&mirror.name, &mut conn)?;
+                }
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Enqueues [SyncGitMirror] jobs for all mirrors of the given repository.
+pub fn enqueue_sync_git_mirrors(
+    repo: &Repository,
+    conn: &mut PgConnection,
+) -> Result<(), EnqueueError> {
+    for mirror in repo.mirrors() {
+        enqueue_sync_git_mirror(&mirror.name, conn)?;
+    }
+
+    Ok(())
+}
+
+/// Enqueues a [SyncGitMirror] job for the given mirror, unless there already
+/// is a pending job for it that has not been picked up by a worker yet.
+///
+/// Since the job always pushes the latest `HEAD`, a single pending job is
+/// enough to bring the mirror up-to-date.
+fn enqueue_sync_git_mirror(mirror: &str, conn: &mut PgConnection) -> Result<(), EnqueueError> {
+    let job = SyncGitMirror::new(mirror);
+
+    let pending_job: Option<i64> = background_jobs::table
+        .select(background_jobs::id)
+        .filter(background_jobs::job_type.eq(SyncGitMirror::JOB_NAME))
+        .filter(background_jobs::data.eq(serde_json::to_value(&job)?))
+        .for_update()
+        .skip_locked()
+        .first(conn)
+        .optional()?;
+
+    if pending_job.is_none() {
+        job.enqueue(conn)?;
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::models::NewIndexChange;
 use crates_io_worker::schema::background_jobs;
 use crates_io_worker::{BackgroundJob, EnqueueError};
 use diesel::dsl::{exists, not};
@@ -5,21 +6,50 @@ use diesel::prelude::*;
 use diesel::sql_types::{Int2, Jsonb, Text};
 use std::fmt::Display;
 
+pub mod audit;
 mod daily_db_maintenance;
 mod downloads;
 pub mod dump_db;
 mod git;
+mod git_mirrors;
 mod readmes;
+mod replicate_storage;
+mod resign_index;
 mod sync_admins;
 mod typosquat;
+pub mod verify_index;
+pub mod verify_storage;
 
 pub use self::daily_db_maintenance::DailyDbMaintenance;
-pub use self::downloads::{ProcessCdnLog, ProcessCdnLogQueue, UpdateDownloads};
+pub use self::downloads::{
+    archive_month, backfill_rollups, detect_anomalies, rehydrate_month, ArchiveVersionDownloads,
+    DetectDownloadAnomalies, ProcessCdnLog, ProcessCdnLogQueue, UpdateDownloads,
+};
 pub use self::dump_db::DumpDb;
-pub use self::git::{NormalizeIndex, SquashIndex, SyncToGitIndex, SyncToSparseIndex};
+pub use self::git::{
+    BackfillIndexPubtime, NormalizeIndex, SquashIndex, SyncToGitIndex, SyncToSparseIndex,
+};
+pub use self::git_mirrors::{CheckGitMirrors, SyncGitMirror, GIT_MIRRORS_QUEUE};
 pub use self::readmes::RenderAndUploadReadme;
+pub use self::replicate_storage::{EnqueueReplicateStorageObject, ReplicateStorageObject};
+pub use self::resign_index::ResignIndexMetadata;
 pub use self::sync_admins::SyncAdmins;
 pub use self::typosquat::CheckTyposquat;
+pub use self::verify_index::VerifyIndex;
+pub use self::verify_storage::VerifyStorage;
+
+/// Record a change of a crate in the `index_changes` log, which backs the
+/// changes feed API, and enqueue both index sync jobs for the crate.
+///
+/// This should be used instead of [enqueue_sync_to_index] whenever the sync
+/// is caused by a user-visible change of a crate or version.
+pub fn enqueue_index_change(
+    change: &NewIndexChange<'_>,
+    conn: &mut PgConnection,
+) -> Result<(), EnqueueError> {
+    change.insert(conn)?;
+    enqueue_sync_to_index(change.crate_name, conn)
+}
 
 /// Enqueue both index sync jobs (git and sparse) for a crate, unless they
 /// already exist in the background job queue.
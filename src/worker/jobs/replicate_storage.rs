@@ -0,0 +1,74 @@
+use crate::db::DieselPool;
+use crate::storage::{ReplicationFailureHandler, StorageBucket};
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use async_trait::async_trait;
+use crates_io_worker::BackgroundJob;
+use std::sync::Arc;
+
+/// A background job that copies an object from the primary to the secondary
+/// store of a replicated [Storage](crate::storage::Storage), after the
+/// original write could not be replicated.
+///
+/// If the object does not exist in the primary store anymore, it is deleted
+/// from the secondary store instead.
+#[derive(Serialize, Deserialize)]
+pub struct ReplicateStorageObject {
+    bucket: StorageBucket,
+    path: String,
+}
+
+impl ReplicateStorageObject {
+    pub fn new(bucket: StorageBucket, path: impl Into<String>) -> Self {
+        let path = path.into();
+        Self { bucket, path }
+    }
+}
+
+impl BackgroundJob for ReplicateStorageObject {
+    const JOB_NAME: &'static str = "replicate_storage_object";
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all, fields(bucket = %self.bucket, path = %self.path))]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        info!("Replicating object to secondary storage…");
+        env.storage
+            .replicate_object(self.bucket, &self.path)
+            .await?;
+        info!("Object replicated to secondary storage");
+
+        Ok(())
+    }
+}
+
+/// A [ReplicationFailureHandler] that enqueues a [ReplicateStorageObject]
+/// job for every object that could not be replicated.
+pub struct EnqueueReplicateStorageObject {
+    connection_pool: DieselPool,
+}
+
+impl EnqueueReplicateStorageObject {
+    pub fn new(connection_pool: DieselPool) -> Self {
+        Self { connection_pool }
+    }
+}
+
+#[async_trait]
+impl ReplicationFailureHandler for EnqueueReplicateStorageObject {
+    async fn on_failure(&self, bucket: StorageBucket, path: &str) {
+        let job = ReplicateStorageObject::new(bucket, path);
+        let connection_pool = self.connection_pool.clone();
+
+        let result = spawn_blocking(move || {
+            let conn = &mut *connection_pool.get()?;
+            job.enqueue(conn)?;
+            anyhow::Ok(())
+        })
+        .await;
+
+        if let Err(error) = result {
+            error!(%bucket, %path, "Failed to enqueue storage replication job: {error}");
+        }
+    }
+}
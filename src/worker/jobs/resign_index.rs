@@ -0,0 +1,243 @@
+use crate::index_signing::{self, IndexSigner};
+use crate::schema::crates;
+use crate::storage::Storage;
+use crate::tasks::spawn_blocking;
+use crate::worker::Environment;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use crates_io_index::Repository;
+use crates_io_worker::{cancellation_token, BackgroundJob};
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+/// A background job that re-signs the metadata documents of the sparse
+/// index.
+///
+/// The `targets` documents of all index files are regenerated with a fresh
+/// expiry, and the `snapshot` document listing them is updated together with
+/// the `timestamp` document that references it. The `root` document is only
+/// replaced if it is about to expire or lists a different key.
+///
+/// The crates are processed in chunks, and the `snapshot` document is
+/// updated after every chunk, so that it never lists outdated versions of
+/// the `targets` documents for longer than it takes to re-sign a chunk.
+///
+/// This job needs to run at least daily, since the `timestamp` document
+/// expires after two days.
+#[derive(Serialize, Deserialize)]
+pub struct ResignIndexMetadata;
+
+/// The number of crates whose `targets` documents are re-signed before the
+/// `snapshot` document is updated.
+const CHUNK_SIZE: usize = 1000;
+
+impl BackgroundJob for ResignIndexMetadata {
+    const JOB_NAME: &'static str = "resign_index_metadata";
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(3 * 60 * 60));
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all)]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        if env.index_signer().is_none() {
+            info!("Skipping re-signing of the index metadata since signing is not configured");
+            return Ok(());
+        }
+
+        info!("Re-signing the index metadata…");
+
+        let cancellation_token = cancellation_token();
+        let paths = spawn_blocking({
+            let env = env.clone();
+            move || {
+                let conn = &mut *env.connection_pool.get()?;
+                let signer = env.index_signer().expect("checked above");
+                let storage = &env.storage;
+                let rt = Handle::current();
+                let now = Utc::now();
+
+                let mut paths = update_root(signer, storage, &rt, now)?;
+
+                let crate_names: Vec<String> = crates::table
+                    .select(crates::name)
+                    .order(crates::name)
+                    .load(conn)
+                    .context("Failed to load crate names")?;
+
+                let num_crates = crate_names.len();
+                for (i, chunk) in crate_names.chunks(CHUNK_SIZE).enumerate() {
+                    info!(
+                        num_crates,
+                        i = i * CHUNK_SIZE,
+                        "Re-signing targets documents…"
+                    );
+
+                    if cancellation_token.is_cancelled() {
+                        return Err(anyhow!("Re-signing of the index metadata was cancelled"));
+                    }
+
+                    let _lock = env.lock_index_metadata();
+
+                    let mut targets = BTreeMap::new();
+                    for crate_name in chunk {
+                        // The stored index file is signed instead of the data
+                        // from the database, since that is what clients will
+                        // download.
+                        let Some(content) = rt.block_on(storage.read_index(crate_name))? else {
+                            continue;
+                        };
+
+                        let path = Repository::relative_index_file_for_url(crate_name);
+                        let version = sync_targets_document(
+                            signer,
+                            storage,
+                            &rt,
+                            &path,
+                            Some(&content),
+                            now,
+                        )?;
+                        targets.insert(path, version);
+                    }
+
+                    for path in update_snapshot(signer, storage, &rt, targets, now)? {
+                        if !paths.contains(&path) {
+                            paths.push(path);
+                        }
+                    }
+                }
+
+                Ok::<_, anyhow::Error>(paths)
+            }
+        })
+        .await?;
+
+        if let Some(cloudfront) = env.cloudfront() {
+            info!(?paths, "Invalidating index metadata on CloudFront");
+            cloudfront.invalidate(&paths);
+        }
+
+        info!("Index metadata re-signed");
+
+        Ok(())
+    }
+}
+
+/// Uploads a new `root` document if the existing one needs to be updated,
+/// and returns the paths of the uploaded documents.
+///
+/// The storage requests are run via [Handle::block_on], which requires a
+/// multi-threaded runtime.
+fn update_root(
+    signer: &IndexSigner,
+    storage: &Storage,
+    rt: &Handle,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<String>> {
+    let existing_root = rt.block_on(storage.read_index_file(index_signing::ROOT_PATH))?;
+    let existing_root = existing_root.as_ref().map(|(content, _)| content.as_ref());
+    if !signer.root_needs_update(existing_root, now) {
+        return Ok(vec![]);
+    }
+
+    info!("Updating the root document");
+    let version = index_signing::existing_version(existing_root) + 1;
+    let root = signer.root(version, now)?;
+    rt.block_on(storage.sync_index_file(index_signing::ROOT_PATH, Some(root)))
+        .context("Failed to upload root document")?;
+
+    Ok(vec![index_signing::ROOT_PATH.to_string()])
+}
+
+/// Regenerates the `targets` document of the index file at `path` for the
+/// given `content`, or removes it if the index file was removed. Returns the
+/// version of the new `targets` document.
+///
+/// This needs to be called while holding
+/// [Environment::lock_index_metadata], and the returned version needs to be
+/// passed to [update_snapshot] before the lock is released.
+pub(crate) fn sync_targets_document(
+    signer: &IndexSigner,
+    storage: &Storage,
+    rt: &Handle,
+    path: &str,
+    content: Option<&[u8]>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Option<i64>> {
+    let targets_path = index_signing::targets_path(path);
+
+    let Some(content) = content else {
+        rt.block_on(storage.sync_index_file(&targets_path, None))
+            .context("Failed to remove targets document")?;
+        return Ok(None);
+    };
+
+    let existing = rt.block_on(storage.read_index_file(&targets_path))?;
+    let existing = existing.as_ref().map(|(content, _)| content.as_ref());
+    let version = index_signing::existing_version(existing) + 1;
+
+    let document = signer.targets(path, content, version, now)?;
+    rt.block_on(storage.sync_index_file(&targets_path, Some(document)))
+        .context("Failed to upload targets document")?;
+
+    Ok(Some(version))
+}
+
+/// Updates the versions of the `targets` documents of the given index files
+/// in the `snapshot` document, and uploads it together with a new `timestamp`
+/// document. Index files with a version of `None` are removed from the
+/// `snapshot` document. Returns the paths of the uploaded documents.
+///
+/// This needs to be called while holding [Environment::lock_index_metadata].
+pub(crate) fn update_snapshot(
+    signer: &IndexSigner,
+    storage: &Storage,
+    rt: &Handle,
+    targets: BTreeMap<String, Option<i64>>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<String>> {
+    let existing_snapshot = rt.block_on(storage.read_index_file(index_signing::SNAPSHOT_PATH))?;
+    let existing_snapshot = existing_snapshot
+        .as_ref()
+        .map(|(content, _)| content.as_ref());
+
+    let mut snapshot_targets = index_signing::existing_snapshot_targets(existing_snapshot);
+    for (path, version) in targets {
+        let targets_path = index_signing::targets_path(&path);
+        let meta_path = targets_path.trim_start_matches("_tuf/").to_string();
+        match version {
+            Some(version) => snapshot_targets.insert(meta_path, version),
+            None => snapshot_targets.remove(&meta_path),
+        };
+    }
+
+    let existing_timestamp = rt.block_on(storage.read_index_file(index_signing::TIMESTAMP_PATH))?;
+    let existing_timestamp = existing_timestamp
+        .as_ref()
+        .map(|(content, _)| content.as_ref());
+
+    let snapshot_version = index_signing::existing_version(existing_snapshot) + 1;
+    let timestamp_version = index_signing::existing_version(existing_timestamp) + 1;
+
+    // The `snapshot` document is uploaded before the `timestamp` document
+    // that references it, so that clients never see a `timestamp` document
+    // without the matching `snapshot` document.
+    let snapshot = signer.snapshot(snapshot_targets, snapshot_version, now)?;
+    let timestamp = signer.timestamp(&snapshot, snapshot_version, timestamp_version, now)?;
+
+    let documents = [
+        (index_signing::SNAPSHOT_PATH, snapshot),
+        (index_signing::TIMESTAMP_PATH, timestamp),
+    ];
+
+    let mut paths = Vec::new();
+    for (path, document) in documents {
+        rt.block_on(storage.sync_index_file(path, Some(document)))
+            .context("Failed to upload signed index metadata")?;
+        paths.push(path.to_string());
+    }
+
+    Ok(paths)
+}
@@ -0,0 +1,317 @@
+use crate::schema::crates;
+use crate::storage::Storage;
+use crate::worker::jobs::audit::{run_audit, AuditReport, Finding};
+use crate::worker::jobs::git::get_index_data;
+use crate::worker::jobs::{SyncToGitIndex, SyncToSparseIndex};
+use crate::worker::Environment;
+use anyhow::{anyhow, Context};
+use crates_io_index::{Repository, RepositorySnapshot};
+use crates_io_worker::{cancellation_token, BackgroundJob, CancellationToken};
+use diesel::prelude::*;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+/// A background job that compares the git index and the sparse index against
+/// the index data that is generated from the database.
+///
+/// All found discrepancies are logged, and if `repair` is set, the
+/// corresponding [SyncToGitIndex] and [SyncToSparseIndex] jobs are enqueued
+/// to fix them.
+///
+/// The git index is read from a [RepositorySnapshot], so that the index lock
+/// is only held while the snapshot is created and the verification does not
+/// block the `repository` queue.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyIndex {
+    repair: bool,
+}
+
+impl VerifyIndex {
+    pub fn new(repair: bool) -> Self {
+        Self { repair }
+    }
+}
+
+impl BackgroundJob for VerifyIndex {
+    const JOB_NAME: &'static str = "verify_index";
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(3 * 60 * 60));
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all, fields(repair = self.repair))]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        info!("Verifying the index…");
+
+        let repair = self.repair;
+        let cancellation_token = cancellation_token();
+        run_audit(env, move |env, conn, rt| {
+            let snapshot = env.lock_index()?.snapshot()?;
+            info!(head = %snapshot.head_oid(), "Created git index snapshot");
+
+            let mut git = GitIndexFiles::new(&snapshot);
+            let mut sparse = SparseIndexFiles::new(&env.storage, rt.clone());
+            let mut sources: [&mut dyn IndexFiles; 2] = [&mut git, &mut sparse];
+            let report = verify_index(conn, &mut sources, &cancellation_token)?;
+            report.log(Self::JOB_NAME);
+
+            if repair {
+                let num_jobs = enqueue_repair_jobs(conn, &report.findings)?;
+                info!(num_jobs, "Enqueued index repair jobs");
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The index that contains a [Discrepancy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexLocation {
+    Git,
+    Sparse,
+}
+
+impl Display for IndexLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexLocation::Git => f.write_str("git"),
+            IndexLocation::Sparse => f.write_str("sparse"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscrepancyKind {
+    /// The crate has an index file in the database, but not in the index.
+    Missing,
+    /// The content of the index file differs from the database.
+    Stale,
+    /// The index contains a file for a crate without index data in the database.
+    Orphaned,
+}
+
+impl DiscrepancyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscrepancyKind::Missing => "missing",
+            DiscrepancyKind::Stale => "stale",
+            DiscrepancyKind::Orphaned => "orphaned",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub crate_name: String,
+    pub location: IndexLocation,
+    pub kind: DiscrepancyKind,
+}
+
+impl Finding for Discrepancy {
+    fn subject(&self) -> String {
+        format!("{} index file of `{}`", self.location, self.crate_name)
+    }
+
+    fn kind(&self) -> &'static str {
+        self.kind.as_str()
+    }
+}
+
+/// A set of index files that can be compared against the database.
+pub trait IndexFiles {
+    fn location(&self) -> IndexLocation;
+
+    /// Returns the (lowercase) names of all crates that have an index file.
+    fn crate_names(&mut self) -> anyhow::Result<Vec<String>>;
+
+    /// Returns the content of the index file of the given crate, or `None` if
+    /// the file does not exist.
+    fn read(&mut self, name: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// The index files of a [RepositorySnapshot] of the git index.
+pub struct GitIndexFiles<'a> {
+    snapshot: &'a RepositorySnapshot,
+}
+
+impl<'a> GitIndexFiles<'a> {
+    pub fn new(snapshot: &'a RepositorySnapshot) -> Self {
+        Self { snapshot }
+    }
+}
+
+impl IndexFiles for GitIndexFiles<'_> {
+    fn location(&self) -> IndexLocation {
+        IndexLocation::Git
+    }
+
+    fn crate_names(&mut self) -> anyhow::Result<Vec<String>> {
+        self.snapshot.crate_names()
+    }
+
+    fn read(&mut self, name: &str) -> anyhow::Result<Option<String>> {
+        self.snapshot.read_index_file(name)
+    }
+}
+
+/// The index files of the sparse index in the [Storage] index bucket.
+///
+/// The [Storage] requests are run via [Handle::block_on], like in all
+/// [audits](crate::worker::jobs::audit).
+pub struct SparseIndexFiles<'a> {
+    storage: &'a Storage,
+    rt: Handle,
+}
+
+impl<'a> SparseIndexFiles<'a> {
+    pub fn new(storage: &'a Storage, rt: Handle) -> Self {
+        Self { storage, rt }
+    }
+}
+
+impl IndexFiles for SparseIndexFiles<'_> {
+    fn location(&self) -> IndexLocation {
+        IndexLocation::Sparse
+    }
+
+    fn crate_names(&mut self) -> anyhow::Result<Vec<String>> {
+        let files = self.rt.block_on(self.storage.list_index_files())?;
+        let names = files.into_iter().filter_map(|path| {
+            let name = path.rsplit('/').next()?;
+            (Repository::relative_index_file_for_url(name) == path).then(|| name.to_string())
+        });
+
+        Ok(names.collect())
+    }
+
+    fn read(&mut self, name: &str) -> anyhow::Result<Option<String>> {
+        let content = self.rt.block_on(self.storage.read_index(name))?;
+        Ok(content.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+}
+
+/// Compares the index files of all `sources` against the index data that is
+/// generated from the database, and returns all found discrepancies.
+///
+/// The number of checked objects in the report is the number of crates.
+///
+/// The verification is aborted with an error once `cancellation_token` has
+/// been cancelled.
+pub fn verify_index(
+    conn: &mut PgConnection,
+    sources: &mut [&mut dyn IndexFiles],
+    cancellation_token: &CancellationToken,
+) -> anyhow::Result<AuditReport<Discrepancy>> {
+    let crate_names: Vec<String> = crates::table
+        .select(crates::name)
+        .order(crates::name)
+        .load(conn)
+        .context("Failed to load crate names")?;
+
+    let num_crates = crate_names.len();
+    info!(num_crates, "Comparing index files…");
+
+    let mut report = AuditReport::default();
+    for (i, crate_name) in crate_names.iter().enumerate() {
+        if i % 1000 == 0 {
+            info!(num_crates, i, "Comparing index files…");
+        }
+
+        if cancellation_token.is_cancelled() {
+            return Err(anyhow!("Index verification was cancelled"));
+        }
+
+        let expected = get_index_data(crate_name, conn)
+            .with_context(|| format!("Failed to get index data for `{crate_name}`"))?;
+
+        for source in sources.iter_mut() {
+            let actual = source.read(crate_name)?;
+            if let Some(kind) = compare(expected.as_deref(), actual.as_deref()) {
+                let crate_name = crate_name.clone();
+                let location = source.location();
+                report.findings.push(Discrepancy {
+                    crate_name,
+                    location,
+                    kind,
+                });
+            }
+        }
+
+        report.num_checked += 1;
+    }
+
+    info!("Looking for orphaned index files…");
+    let known_names = crate_names
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<HashSet<_>>();
+
+    for source in sources.iter_mut() {
+        let location = source.location();
+        for crate_name in source.crate_names()? {
+            if !known_names.contains(&crate_name) {
+                let kind = DiscrepancyKind::Orphaned;
+                report.findings.push(Discrepancy {
+                    crate_name,
+                    location,
+                    kind,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn compare(expected: Option<&str>, actual: Option<&str>) -> Option<DiscrepancyKind> {
+    match (expected, actual) {
+        (Some(_), None) => Some(DiscrepancyKind::Missing),
+        (Some(expected), Some(actual)) if expected != actual => Some(DiscrepancyKind::Stale),
+        (None, Some(_)) => Some(DiscrepancyKind::Orphaned),
+        _ => None,
+    }
+}
+
+/// Enqueues [SyncToGitIndex] and [SyncToSparseIndex] jobs for all crates with
+/// discrepancies in the respective index, and returns the number of enqueued
+/// jobs.
+pub fn enqueue_repair_jobs(
+    conn: &mut PgConnection,
+    discrepancies: &[Discrepancy],
+) -> anyhow::Result<usize> {
+    let mut enqueued = HashSet::new();
+    for discrepancy in discrepancies {
+        let crate_name = &discrepancy.crate_name;
+        if !enqueued.insert((crate_name, discrepancy.location)) {
+            continue;
+        }
+
+        match discrepancy.location {
+            IndexLocation::Git => SyncToGitIndex::new(crate_name).enqueue(conn)?,
+            IndexLocation::Sparse => SyncToSparseIndex::new(crate_name).enqueue(conn)?,
+        };
+    }
+
+    Ok(enqueued.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(None, None), None);
+        assert_eq!(compare(Some("foo"), Some("foo")), None);
+        assert_eq!(compare(Some("foo"), None), Some(DiscrepancyKind::Missing));
+        assert_eq!(
+            compare(Some("foo"), Some("bar")),
+            Some(DiscrepancyKind::Stale)
+        );
+        assert_eq!(compare(None, Some("foo")), Some(DiscrepancyKind::Orphaned));
+    }
+}
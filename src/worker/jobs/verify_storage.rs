@@ -0,0 +1,334 @@
+use crate::models::{NewStorageAuditFailure, StorageAuditFailure, StorageAuditFailureKind};
+use crate::schema::{crates, versions};
+use crate::storage::Storage;
+use crate::worker::jobs::audit::{run_audit, AuditReport, Finding};
+use crate::worker::Environment;
+use anyhow::Context;
+use crates_io_worker::BackgroundJob;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Integer};
+use futures_util::TryStreamExt;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+/// The number of versions that are verified by a single [VerifyStorage] job
+/// when auditing all versions.
+pub const BATCH_SIZE: i64 = 1000;
+
+/// A background job that streams `.crate` files from the [Storage] and
+/// compares their SHA256 checksums against the ones recorded in the
+/// `versions` table.
+///
+/// All missing, truncated and mismatched files are recorded in the
+/// `storage_audit_failures` table, and failures of files that pass the audit
+/// again are removed.
+///
+/// If `sample` is set, a random sample of that many versions is verified.
+/// Otherwise the job verifies [BATCH_SIZE] versions with an `id` greater
+/// than `after`, and enqueues itself again for the next batch, so that an
+/// interrupted audit can be resumed from the last processed version.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyStorage {
+    sample: Option<i64>,
+    after: i32,
+}
+
+impl VerifyStorage {
+    /// Verifies a random sample of `size` versions.
+    pub fn sample(size: i64) -> Self {
+        Self {
+            sample: Some(size),
+            after: 0,
+        }
+    }
+
+    /// Verifies all versions with an `id` greater than `after`.
+    pub fn all(after: i32) -> Self {
+        Self {
+            sample: None,
+            after,
+        }
+    }
+}
+
+impl BackgroundJob for VerifyStorage {
+    const JOB_NAME: &'static str = "verify_storage";
+
+    type Context = Arc<Environment>;
+
+    #[instrument(skip_all, fields(sample = self.sample, after = self.after))]
+    async fn run(&self, env: Self::Context) -> anyhow::Result<()> {
+        let selection = match self.sample {
+            Some(size) => Selection::Sample(size),
+            None => Selection::After(self.after, BATCH_SIZE),
+        };
+
+        run_audit(env, move |env, conn, rt| {
+            let versions = load_versions(conn, selection)?;
+            let report = verify_storage(conn, &env.storage, rt, &versions)?;
+            report.log(Self::JOB_NAME);
+
+            let last_version_id = versions.last().map(|version| version.id);
+            if let (Selection::After(..), Some(last_version_id)) = (selection, last_version_id) {
+                if versions.len() as i64 == BATCH_SIZE {
+                    info!(
+                        last_version_id,
+                        "Enqueueing next storage verification batch"
+                    );
+                    VerifyStorage::all(last_version_id).enqueue(conn)?;
+                }
+            }
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The versions that are verified by [verify_storage].
+#[derive(Debug, Clone, Copy)]
+pub enum Selection {
+    /// A random sample of the given number of versions.
+    Sample(i64),
+    /// Up to the given number of versions with an `id` greater than the
+    /// given `id`, in ascending order.
+    After(i32, i64),
+}
+
+/// A version whose `.crate` file is verified against its recorded checksum.
+#[derive(Debug, Clone, Queryable)]
+pub struct StoredVersion {
+    pub id: i32,
+    pub crate_name: String,
+    pub num: String,
+    pub checksum: String,
+    pub crate_size: Option<i32>,
+}
+
+/// Loads the versions that should be verified.
+pub fn load_versions(
+    conn: &mut PgConnection,
+    selection: Selection,
+) -> QueryResult<Vec<StoredVersion>> {
+    let query = versions::table
+        .inner_join(crates::table)
+        .select((
+            versions::id,
+            crates::name,
+            versions::num,
+            versions::checksum,
+            versions::crate_size,
+        ))
+        .into_boxed();
+
+    match selection {
+        Selection::Sample(size) => {
+            let ids = sample_version_ids(conn, size)?;
+            query.filter(versions::id.eq_any(ids)).load(conn)
+        }
+        Selection::After(after, limit) => query
+            .filter(versions::id.gt(after))
+            .order(versions::id)
+            .limit(limit)
+            .load(conn),
+    }
+}
+
+/// Returns the ids of a random sample of up to `size` versions.
+///
+/// Sorting the whole `versions` table by `random()` would require a full
+/// scan, so only a random set of table pages is read via `TABLESAMPLE
+/// SYSTEM`, based on the estimated number of rows in the table. Twice the
+/// required percentage of pages is sampled, since the pages might be only
+/// partially filled.
+fn sample_version_ids(conn: &mut PgConnection, size: i64) -> QueryResult<Vec<i32>> {
+    #[derive(QueryableByName)]
+    struct SampledVersion {
+        #[diesel(sql_type = Integer)]
+        id: i32,
+    }
+
+    let estimated_rows = diesel::select(diesel::dsl::sql::<Double>(
+        "(SELECT reltuples::float8 FROM pg_class WHERE oid = 'versions'::regclass)",
+    ))
+    .get_result::<f64>(conn)?;
+
+    let percentage = sample_percentage(size, estimated_rows);
+    let sample = diesel::sql_query(
+        "SELECT id FROM versions TABLESAMPLE SYSTEM ($1) ORDER BY random() LIMIT $2",
+    )
+    .bind::<Double, _>(percentage)
+    .bind::<BigInt, _>(size)
+    .load::<SampledVersion>(conn)?;
+
+    Ok(sample.into_iter().map(|version| version.id).collect())
+}
+
+/// Returns the percentage of table pages that should be sampled to find
+/// `size` rows in a table with `estimated_rows` rows.
+///
+/// Tables that have not been analyzed yet have an estimate of `-1` or `0`
+/// rows, in which case the whole table is sampled.
+fn sample_percentage(size: i64, estimated_rows: f64) -> f64 {
+    if estimated_rows <= 0. {
+        return 100.;
+    }
+
+    (size as f64 * 2. / estimated_rows * 100.).clamp(0., 100.)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub crate_name: String,
+    pub version: String,
+    pub kind: StorageAuditFailureKind,
+}
+
+impl Finding for Failure {
+    fn subject(&self) -> String {
+        format!("crate file of `{}` v{}", self.crate_name, self.version)
+    }
+
+    fn kind(&self) -> &'static str {
+        self.kind.as_str()
+    }
+}
+
+/// Verifies the `.crate` files of the given versions, and records the
+/// results in the `storage_audit_failures` table.
+///
+/// The [Storage] requests are run via [Handle::block_on], like in all
+/// [audits](crate::worker::jobs::audit).
+pub fn verify_storage(
+    conn: &mut PgConnection,
+    storage: &Storage,
+    rt: &Handle,
+    versions: &[StoredVersion],
+) -> anyhow::Result<AuditReport<Failure>> {
+    let num_versions = versions.len();
+    info!(num_versions, "Verifying crate files…");
+
+    let mut report = AuditReport::default();
+    let mut verified_ids = Vec::new();
+    for (i, version) in versions.iter().enumerate() {
+        if i % 1000 == 0 {
+            info!(num_versions, i, "Verifying crate files…");
+        }
+
+        let result = rt.block_on(verify_crate_file(storage, version));
+        let failure = result.with_context(|| {
+            let StoredVersion {
+                crate_name, num, ..
+            } = version;
+            format!("Failed to verify crate file of `{crate_name}` v{num}")
+        })?;
+
+        match failure {
+            Some(failure) => {
+                failure.upsert(conn)?;
+                report.findings.push(Failure {
+                    crate_name: version.crate_name.clone(),
+                    version: version.num.clone(),
+                    kind: failure.kind,
+                });
+            }
+            None => verified_ids.push(version.id),
+        }
+
+        report.num_checked += 1;
+    }
+
+    StorageAuditFailure::resolve(conn, &verified_ids)?;
+
+    Ok(report)
+}
+
+/// Streams the `.crate` file of the given version through a SHA256 hasher,
+/// and returns the failure that should be recorded, or `None` if the file
+/// matches the recorded checksum.
+async fn verify_crate_file(
+    storage: &Storage,
+    version: &StoredVersion,
+) -> anyhow::Result<Option<NewStorageAuditFailure>> {
+    let stream = storage
+        .stream_crate_file(&version.crate_name, &version.num)
+        .await?;
+
+    let Some(mut stream) = stream else {
+        return Ok(Some(NewStorageAuditFailure {
+            version_id: version.id,
+            kind: StorageAuditFailureKind::Missing,
+            actual_checksum: None,
+            actual_size: None,
+        }));
+    };
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = stream.try_next().await? {
+        hasher.update(&chunk);
+        size += chunk.len() as i64;
+    }
+
+    let checksum = hex::encode(hasher.finalize());
+    let kind = classify(version, &checksum, size);
+
+    Ok(kind.map(|kind| NewStorageAuditFailure {
+        version_id: version.id,
+        kind,
+        actual_checksum: Some(checksum),
+        actual_size: Some(size),
+    }))
+}
+
+fn classify(version: &StoredVersion, checksum: &str, size: i64) -> Option<StorageAuditFailureKind> {
+    if checksum == version.checksum {
+        return None;
+    }
+
+    match version.crate_size {
+        Some(expected_size) if size < expected_size as i64 => {
+            Some(StorageAuditFailureKind::Truncated)
+        }
+        _ => Some(StorageAuditFailureKind::Mismatched),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(checksum: &str, crate_size: Option<i32>) -> StoredVersion {
+        StoredVersion {
+            id: 1,
+            crate_name: "foo".into(),
+            num: "1.0.0".into(),
+            checksum: checksum.into(),
+            crate_size,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        use StorageAuditFailureKind::*;
+
+        let v = version("abc", Some(10));
+        assert_eq!(classify(&v, "abc", 10), None);
+        assert_eq!(classify(&v, "def", 5), Some(Truncated));
+        assert_eq!(classify(&v, "def", 10), Some(Mismatched));
+        assert_eq!(classify(&v, "def", 15), Some(Mismatched));
+
+        let v = version("abc", None);
+        assert_eq!(classify(&v, "abc", 10), None);
+        assert_eq!(classify(&v, "def", 5), Some(Mismatched));
+    }
+
+    #[test]
+    fn test_sample_percentage() {
+        assert_eq!(sample_percentage(100, -1.), 100.);
+        assert_eq!(sample_percentage(100, 0.), 100.);
+        assert_eq!(sample_percentage(100, 150.), 100.);
+        assert_eq!(sample_percentage(100, 1_000_000.), 0.02);
+    }
+}
@@ -19,17 +19,26 @@ pub trait RunnerExt {
 
 impl RunnerExt for Runner<Arc<Environment>> {
     fn register_crates_io_job_types(self) -> Self {
-        self.register_job_type::<jobs::CheckTyposquat>()
+        self.register_job_type::<jobs::ArchiveVersionDownloads>()
+            .register_job_type::<jobs::BackfillIndexPubtime>()
+            .register_job_type::<jobs::CheckGitMirrors>()
+            .register_job_type::<jobs::CheckTyposquat>()
             .register_job_type::<jobs::DailyDbMaintenance>()
+            .register_job_type::<jobs::DetectDownloadAnomalies>()
             .register_job_type::<jobs::DumpDb>()
             .register_job_type::<jobs::NormalizeIndex>()
             .register_job_type::<jobs::ProcessCdnLog>()
             .register_job_type::<jobs::ProcessCdnLogQueue>()
             .register_job_type::<jobs::RenderAndUploadReadme>()
+            .register_job_type::<jobs::ReplicateStorageObject>()
+            .register_job_type::<jobs::ResignIndexMetadata>()
             .register_job_type::<jobs::SquashIndex>()
             .register_job_type::<jobs::SyncAdmins>()
+            .register_job_type::<jobs::SyncGitMirror>()
             .register_job_type::<jobs::SyncToGitIndex>()
             .register_job_type::<jobs::SyncToSparseIndex>()
             .register_job_type::<jobs::UpdateDownloads>()
+            .register_job_type::<jobs::VerifyIndex>()
+            .register_job_type::<jobs::VerifyStorage>()
     }
 }